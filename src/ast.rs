@@ -91,6 +91,11 @@
 //! [1]: https://www.cs.cornell.edu/~asampson/blog/flattening.html
 
 use core::fmt;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+use crate::limits::LimitError;
+use crate::token::{self, Span};
 
 /// Node references are represented as `usize` handles to the AST arena entries
 /// if space is a concern smaller handles can be used `u32` for example if you
@@ -109,7 +114,7 @@ pub trait Ref: Sized {
 /// Since our pool is generic over either `Expr` or `Stmt` and we want to have
 /// one reference kind of each, `NodeRef` acts as a phantom type that will be
 /// later marked to reference either expressions or statements.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct NodeRef<T> {
     inner: usize,
     _marker: std::marker::PhantomData<T>,
@@ -135,6 +140,9 @@ impl<T> Ref for NodeRef<T> {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct NodePool<T, R: Ref> {
     nodes: Vec<T>,
+    // Source location of each node, nodes built without source locations
+    // have an empty span.
+    spans: Vec<Span>,
     _marker: std::marker::PhantomData<R>,
 }
 
@@ -146,6 +154,7 @@ impl<T, R: Ref> NodePool<T, R> {
     pub fn new() -> Self {
         Self {
             nodes: Vec::with_capacity(4096),
+            spans: Vec::with_capacity(4096),
             _marker: std::marker::PhantomData,
         }
     }
@@ -156,12 +165,63 @@ impl<T, R: Ref> NodePool<T, R> {
         self.nodes.get(node_ref.get())
     }
 
+    /// Return the source location of a node given its `NodeRef`.
+    #[must_use]
+    pub fn span(&self, node_ref: R) -> Option<Span> {
+        self.spans.get(node_ref.get()).copied()
+    }
+
     /// Push a new expression into the pool.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a `LimitError` if the pool can't grow, instead of aborting
+    /// the process.
     fn put(&mut self, expr: T) -> R {
         let node_ref = self.nodes.len();
+        if self.nodes.try_reserve(1).is_err()
+            || self.spans.try_reserve(1).is_err()
+        {
+            LimitError::out_of_memory("AST nodes").raise();
+        }
         self.nodes.push(expr);
+        self.spans.push(Span::default());
         R::new(node_ref)
     }
+
+    /// Drop the nodes that aren't `live` and return the new index of every
+    /// node, `None` for dropped nodes. Surviving nodes keep their relative
+    /// order so children are still located before their parents.
+    fn retain(&mut self, live: &[bool]) -> Vec<Option<usize>> {
+        let mut remap = Vec::with_capacity(self.nodes.len());
+        let mut next = 0;
+        for is_live in live {
+            if *is_live {
+                remap.push(Some(next));
+                next += 1;
+            } else {
+                remap.push(None);
+            }
+        }
+        let mut index = 0;
+        self.nodes.retain(|_| {
+            index += 1;
+            live[index - 1]
+        });
+        let mut index = 0;
+        self.spans.retain(|_| {
+            index += 1;
+            live[index - 1]
+        });
+        remap
+    }
+
+    /// Set the source location of a node.
+    fn set_span(&mut self, node_ref: R, span: Span) {
+        if let Some(node_span) = self.spans.get_mut(node_ref.get()) {
+            *node_span = span;
+        }
+    }
 }
 
 impl<T, R: Ref> Default for NodePool<T, R> {
@@ -172,15 +232,15 @@ impl<T, R: Ref> Default for NodePool<T, R> {
 
 /// `ExprRefMarker` is a type marker for node references that reference
 /// expressions.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ExprRefMarker;
 /// `StmtRefMarker` is a type marker for node references that reference
 /// statements.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct StmtRefMarker;
 /// `DeclRefMarker` is a type marker for node references that reference
 /// declarations.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct DeclRefMarker;
 
 /// `ExprRef` is a reference to an `Expr` node in the expression pool.
@@ -190,6 +250,14 @@ pub type StmtRef = NodeRef<StmtRefMarker>;
 /// `DeclRef` is a reference to `Decl` node in the declarations pool.
 pub type DeclRef = NodeRef<DeclRefMarker>;
 
+/// `AnyRef` is a reference to a node of any kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AnyRef {
+    Decl(DeclRef),
+    Stmt(StmtRef),
+    Expr(ExprRef),
+}
+
 /// `ExprPool` is a node pool that holds `Expr` nodes.
 type ExprPool = NodePool<Expr, ExprRef>;
 /// `StmtPool` is a node pool that holds `Stmt` nodes.
@@ -210,7 +278,7 @@ type DeclPool = NodePool<Decl, DeclRef>;
 pub struct SymbolPool {}
 
 /// Binary operators.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum BinaryOperator {
     // Arithmetic operators.
     Add,
@@ -227,6 +295,11 @@ pub enum BinaryOperator {
     // Logical operators.
     And,
     Or,
+    // Bitwise operators, the language has no syntax for them yet, they're
+    // only built when decompiling the shifts and masks of strength reduction.
+    Shl,
+    Shr,
+    BitAnd,
 }
 
 impl fmt::Display for BinaryOperator {
@@ -244,12 +317,15 @@ impl fmt::Display for BinaryOperator {
             Self::Lte => write!(f, "LTE"),
             Self::And => write!(f, "AND"),
             Self::Or => write!(f, "OR"),
+            Self::Shl => write!(f, "SHL"),
+            Self::Shr => write!(f, "SHR"),
+            Self::BitAnd => write!(f, "BITAND"),
         }
     }
 }
 
 /// Unary operators
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum UnaryOperator {
     Neg,
     Not,
@@ -294,10 +370,55 @@ impl fmt::Display for DeclType {
     }
 }
 
+/// Linkage of a function declaration, functions declared `static` have
+/// internal linkage and are only visible in the file declaring them.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Linkage {
+    #[default]
+    External,
+    Internal,
+}
+
+/// Attributes of a function declaration, written in the C23 syntax before
+/// the declaration as in `[[optnone]] int f() { ... }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Attribute {
+    // The function is never optimized.
+    OptNone,
+    // The function is optimized for size, transforms growing the code skip
+    // it.
+    OptSize,
+    // The function never returns to its caller, it exits or traps.
+    NoReturn,
+}
+
+impl Attribute {
+    /// Returns the attribute named `name`.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "optnone" => Some(Self::OptNone),
+            "optsize" => Some(Self::OptSize),
+            "noreturn" => Some(Self::NoReturn),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OptNone => write!(f, "optnone"),
+            Self::OptSize => write!(f, "optsize"),
+            Self::NoReturn => write!(f, "noreturn"),
+        }
+    }
+}
+
 /// Expression nodes are used to represent expressions.
 /// TODO make Expr homogenous by storing `LiteralRef`, `StringRef` and so on
 /// in a separate storage array stored in the AST.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Expr {
     // Named values (variables),
     Named(String),
@@ -307,8 +428,8 @@ pub enum Expr {
     BoolLiteral(bool),
     // Char literal values.
     CharLiteral(char),
-    // Grouping expressions (parenthesised expressions).
-    Grouping(ExprRef),
+    // String literal values, only used as `printf` arguments.
+    StringLiteral(String),
     // Assignment expressions.
     Assignment {
         name: ExprRef,
@@ -330,6 +451,47 @@ pub enum Expr {
         name: ExprRef,
         args: Vec<ExprRef>,
     },
+    // Explicit conversions, inserted by the type checker for implicit
+    // conversions (see `conversions`).
+    Cast {
+        to: DeclType,
+        operand: ExprRef,
+    },
+}
+
+impl Expr {
+    /// Call `f` on every expression reference held by the expression.
+    fn for_each_ref(&mut self, mut f: impl FnMut(&mut ExprRef)) {
+        match self {
+            Self::Named(_)
+            | Self::IntLiteral(_)
+            | Self::BoolLiteral(_)
+            | Self::CharLiteral(_)
+            | Self::StringLiteral(_) => (),
+            Self::UnaryOp { operand: expr, .. }
+            | Self::Cast { operand: expr, .. } => f(expr),
+            Self::Assignment { name, value } => {
+                f(name);
+                f(value);
+            }
+            Self::BinOp { left, right, .. } => {
+                f(left);
+                f(right);
+            }
+            Self::Call { name, args } => {
+                f(name);
+                args.iter_mut().for_each(f);
+            }
+        }
+    }
+
+    /// Returns `true` if evaluating the expression has no side effects,
+    /// assuming its operands don't. Pure expressions can be shared between
+    /// several parents.
+    #[must_use]
+    pub const fn is_pure(&self) -> bool {
+        !matches!(self, Self::Assignment { .. } | Self::Call { .. })
+    }
 }
 
 /// Statement nodes are used to represent statements.
@@ -370,10 +532,82 @@ pub enum Stmt {
         condition: Option<ExprRef>,
         body: Option<StmtRef>,
     },
+    // Switch statements, the body is a block whose top level statements
+    // include the `case` and `default` labels.
+    Switch {
+        scrutinee: ExprRef,
+        body: StmtRef,
+    },
+    // Case labels of a switch statement.
+    Case(i32),
+    // Default label of a switch statement.
+    Default,
+    // Exits the innermost loop or switch statement.
+    Break,
+    // Unconditional jumps to a user-defined label.
+    Goto(String),
+    // User-defined labels, targets of `goto` statements.
+    Label(String),
+    // Inline assembly, the assembly string is opaque to the compiler.
+    Asm(String),
     // Empty statement.
     Empty,
 }
 
+impl Stmt {
+    /// Call `on_stmt` and `on_expr` on every statement and expression
+    /// reference held by the statement.
+    fn for_each_ref(
+        &mut self,
+        mut on_stmt: impl FnMut(&mut StmtRef),
+        mut on_expr: impl FnMut(&mut ExprRef),
+    ) {
+        match self {
+            Self::Return(expr)
+            | Self::Expr(expr)
+            | Self::LocalVariable { value: expr, .. } => on_expr(expr),
+            Self::Block(stmts) => stmts.iter_mut().for_each(on_stmt),
+            Self::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                on_expr(condition);
+                on_stmt(then_block);
+                else_block.iter_mut().for_each(on_stmt);
+            }
+            Self::For {
+                init,
+                condition,
+                iteration,
+                body,
+            } => {
+                init.iter_mut()
+                    .chain(condition.iter_mut())
+                    .chain(iteration.iter_mut())
+                    .for_each(on_expr);
+                on_stmt(body);
+            }
+            Self::While { condition, body } => {
+                condition.iter_mut().for_each(on_expr);
+                body.iter_mut().for_each(on_stmt);
+            }
+            Self::Switch { scrutinee, body } => {
+                on_expr(scrutinee);
+                on_stmt(body);
+            }
+            Self::Argument { .. }
+            | Self::Case(_)
+            | Self::Default
+            | Self::Break
+            | Self::Goto(_)
+            | Self::Label(_)
+            | Self::Asm(_)
+            | Self::Empty => (),
+        }
+    }
+}
+
 /// Declaration nodes are used to represent declarations.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Decl {
@@ -389,9 +623,142 @@ pub enum Decl {
         return_type: DeclType,
         args: Vec<StmtRef>,
         body: StmtRef,
+        linkage: Linkage,
+        attributes: Vec<Attribute>,
+    },
+    // External function declarations, defined outside the program.
+    Extern {
+        name: String,
+        return_type: DeclType,
+        args: Vec<StmtRef>,
     },
 }
 
+impl Decl {
+    /// Call `on_stmt` and `on_expr` on every statement and expression
+    /// reference held by the declaration.
+    fn for_each_ref(
+        &mut self,
+        mut on_stmt: impl FnMut(&mut StmtRef),
+        mut on_expr: impl FnMut(&mut ExprRef),
+    ) {
+        match self {
+            Self::GlobalVariable { value, .. } => on_expr(value),
+            Self::Function { args, body, .. } => {
+                args.iter_mut().for_each(&mut on_stmt);
+                on_stmt(body);
+            }
+            Self::Extern { args, .. } => args.iter_mut().for_each(on_stmt),
+        }
+    }
+}
+
+/// `SpanIndex` maps source locations back to the AST nodes covering them,
+/// editors use it to find the node under the cursor. Positions are byte
+/// offsets in the source, the character offsets of spans are converted when
+/// the index is built.
+///
+/// Spans of a syntax tree are either nested or disjoint, the index keeps
+/// them sorted by start (outer spans first) along with the closest enclosing
+/// span of each. The innermost node covering a position is found by a
+/// binary search for the last span starting before it, then by walking up
+/// the enclosing spans until one covers the position.
+///
+/// Nodes without a source location (built by rewrites) aren't indexed.
+#[derive(Debug, Clone, Default)]
+pub struct SpanIndex {
+    // Indexed spans sorted by start then by decreasing end.
+    spans: Vec<(Span, AnyRef)>,
+    // Position of the closest enclosing span of each span.
+    parents: Vec<Option<usize>>,
+}
+
+impl SpanIndex {
+    fn new(ast: &AST, source: &str) -> Self {
+        let offsets = token::byte_offsets(source);
+        let decls = ast
+            .declarations
+            .spans
+            .iter()
+            .enumerate()
+            .map(|(i, span)| (*span, AnyRef::Decl(DeclRef::new(i))));
+        let stmts = ast
+            .statements
+            .spans
+            .iter()
+            .enumerate()
+            .map(|(i, span)| (*span, AnyRef::Stmt(StmtRef::new(i))));
+        let exprs = ast
+            .expressions
+            .spans
+            .iter()
+            .enumerate()
+            .map(|(i, span)| (*span, AnyRef::Expr(ExprRef::new(i))));
+        let mut spans = decls
+            .chain(stmts)
+            .chain(exprs)
+            .filter(|(span, _)| !span.is_empty())
+            .map(|(span, node)| (span.to_bytes(&offsets), node))
+            .collect::<Vec<_>>();
+        // Nodes sharing a span are ordered from the outermost kind, children
+        // are pushed before their parents so they have a lower index.
+        let rank = |node: &AnyRef| match node {
+            AnyRef::Decl(decl_ref) => (0, usize::MAX - decl_ref.get()),
+            AnyRef::Stmt(stmt_ref) => (1, usize::MAX - stmt_ref.get()),
+            AnyRef::Expr(expr_ref) => (2, usize::MAX - expr_ref.get()),
+        };
+        spans.sort_by_key(|(span, node)| {
+            (span.start, usize::MAX - span.end, rank(node))
+        });
+        let mut parents = Vec::with_capacity(spans.len());
+        let mut enclosing: Vec<usize> = vec![];
+        for (i, (span, _)) in spans.iter().enumerate() {
+            while let Some(&top) = enclosing.last() {
+                if spans[top].0.end >= span.end {
+                    break;
+                }
+                enclosing.pop();
+            }
+            parents.push(enclosing.last().copied());
+            enclosing.push(i);
+        }
+        Self { spans, parents }
+    }
+
+    /// Returns the innermost node covering the byte offset `position`.
+    #[must_use]
+    pub fn node_at(&self, position: usize) -> Option<AnyRef> {
+        self.enclosing(position).next()
+    }
+
+    /// Returns the innermost expression covering the byte offset `position`.
+    #[must_use]
+    pub fn expr_at(&self, position: usize) -> Option<ExprRef> {
+        self.enclosing(position).find_map(|node| match node {
+            AnyRef::Expr(expr_ref) => Some(expr_ref),
+            _ => None,
+        })
+    }
+
+    /// Returns the nodes covering `position` from the innermost outwards.
+    fn enclosing(&self, position: usize) -> impl Iterator<Item = AnyRef> + '_ {
+        let last = self
+            .spans
+            .partition_point(|(span, _)| span.start <= position)
+            .checked_sub(1);
+        std::iter::successors(last, |i| self.parents[*i])
+            .filter(move |i| position < self.spans[*i].0.end)
+            .map(|i| self.spans[i].1)
+    }
+}
+
+/// Number of nodes reclaimed by `AST::compact`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Reclaimed {
+    pub statements: usize,
+    pub expressions: usize,
+}
+
 /// `AST` represents the AST generated by the parser when processing a list
 /// of tokens.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -399,6 +766,55 @@ pub struct AST {
     declarations: DeclPool,
     statements: StmtPool,
     expressions: ExprPool,
+    // Pure expressions already in the expression pool, only present when
+    // expressions are hash-consed (see `AST::with_interning`).
+    interned: Option<Interner>,
+    // Local variable declarations without an initializer, their value is
+    // the default value of their type.
+    default_initialized: HashSet<StmtRef>,
+    // Global and local variable declarations qualified `const`.
+    constants: HashSet<AnyRef>,
+    // Local variable declarations with static storage duration.
+    statics: HashSet<StmtRef>,
+    // Global and local variable declarations qualified `volatile`.
+    volatiles: HashSet<AnyRef>,
+    // Sections of the declarations placed with a section attribute.
+    sections: HashMap<DeclRef, String>,
+    // Expressions written between parentheses, the parser doesn't wrap them
+    // in a node since the tree already encodes their precedence, they're
+    // only kept to print the AST.
+    groupings: HashSet<ExprRef>,
+}
+
+/// `Interner` tracks the hash-consed expressions of an AST.
+///
+/// Spans and groupings belong to an occurrence of an expression rather than
+/// to its structure, they're left out of the key. An occurrence carrying
+/// either gets its own node, linked to the node shared by the structurally
+/// identical expressions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Interner {
+    // Shared node of each pure expression, keyed by the expression with its
+    // operands replaced by their shared nodes.
+    nodes: HashMap<Expr, ExprRef>,
+    // Shared node of the occurrences that have their own node.
+    occurrences: HashMap<ExprRef, ExprRef>,
+    // Nodes referenced by more than one occurrence.
+    shared: HashSet<ExprRef>,
+}
+
+impl Interner {
+    /// Returns the shared node of the expression at `expr_ref`.
+    fn canonical(&self, expr_ref: ExprRef) -> ExprRef {
+        self.occurrences.get(&expr_ref).copied().unwrap_or(expr_ref)
+    }
+
+    /// Returns the key of `expr` in the interned nodes.
+    fn key(&self, expr: &Expr) -> Expr {
+        let mut key = expr.clone();
+        key.for_each_ref(|expr_ref| *expr_ref = self.canonical(*expr_ref));
+        key
+    }
 }
 
 /// AST visitor trait exposes the set of behaviors to be implemented by AST
@@ -443,7 +859,84 @@ impl AST {
             declarations: DeclPool::new(),
             statements: StmtPool::new(),
             expressions: ExprPool::new(),
+            interned: None,
+            default_initialized: HashSet::new(),
+            constants: HashSet::new(),
+            statics: HashSet::new(),
+            volatiles: HashSet::new(),
+            sections: HashMap::new(),
+            groupings: HashSet::new(),
+        }
+    }
+
+    /// Hash-cons expressions pushed to the AST, pushing a pure expression
+    /// structurally identical to one already in the pool returns the
+    /// existing reference. Occurrences with a source location or written
+    /// between parentheses keep their own node, `AST::canonical` maps them
+    /// to the shared one so comparing canonical references is enough to
+    /// detect identical subexpressions.
+    #[must_use]
+    pub fn with_interning(mut self) -> Self {
+        let mut interner = Interner::default();
+        for (index, expr) in self.expressions.nodes.iter().enumerate() {
+            if expr.is_pure() {
+                let key = interner.key(expr);
+                match interner.nodes.get(&key) {
+                    Some(shared) => {
+                        interner
+                            .occurrences
+                            .insert(ExprRef::new(index), *shared);
+                    }
+                    None => {
+                        interner.nodes.insert(key, ExprRef::new(index));
+                    }
+                }
+            }
+        }
+        let mut referenced = HashSet::new();
+        let mut count = |expr_ref: &mut ExprRef| {
+            if !referenced.insert(*expr_ref) {
+                interner.shared.insert(*expr_ref);
+            }
+        };
+        for decl in &self.declarations.nodes {
+            decl.clone().for_each_ref(|_| {}, &mut count);
+        }
+        for stmt in &self.statements.nodes {
+            stmt.clone().for_each_ref(|_| {}, &mut count);
+        }
+        for expr in &self.expressions.nodes {
+            expr.clone().for_each_ref(&mut count);
+        }
+        self.interned = Some(interner);
+        self
+    }
+
+    /// Returns the node shared by the expressions structurally identical to
+    /// the one at `expr_ref` when interning is enabled, `expr_ref` otherwise.
+    #[must_use]
+    pub fn canonical(&self, expr_ref: ExprRef) -> ExprRef {
+        self.interned
+            .as_ref()
+            .map_or(expr_ref, |interner| interner.canonical(expr_ref))
+    }
+
+    /// Returns a reference to the expression at `expr_ref` that no other
+    /// occurrence shares, so a span or a grouping can be recorded for it.
+    pub fn unshare(&mut self, expr_ref: ExprRef) -> ExprRef {
+        let Some(interner) = self.interned.as_mut() else {
+            return expr_ref;
+        };
+        if !interner.shared.contains(&expr_ref) {
+            return expr_ref;
         }
+        let Some(expr) = self.expressions.get(expr_ref).cloned() else {
+            return expr_ref;
+        };
+        let canonical = interner.canonical(expr_ref);
+        let occurrence = self.expressions.put(expr);
+        interner.occurrences.insert(occurrence, canonical);
+        occurrence
     }
 
     /// Return a non-mutable reference to the declaration pool.
@@ -464,6 +957,14 @@ impl AST {
         &self.expressions.nodes
     }
 
+    /// Returns the number of declarations, statements and expressions.
+    #[must_use]
+    pub fn node_count(&self) -> usize {
+        self.declarations.nodes.len()
+            + self.statements.nodes.len()
+            + self.expressions.nodes.len()
+    }
+
     /// Push a new declaration node to the AST returning a reference to it.
     pub fn push_decl(&mut self, decl: Decl) -> DeclRef {
         self.declarations.put(decl)
@@ -474,9 +975,347 @@ impl AST {
         self.statements.put(stmt)
     }
 
-    /// Push a new expression node to the AST returning a reference to it.
+    /// Push a new expression node to the AST returning a reference to it,
+    /// when interning is enabled pure expressions are deduplicated.
     pub fn push_expr(&mut self, expr: Expr) -> ExprRef {
-        self.expressions.put(expr)
+        match self.interned.as_mut() {
+            Some(interner) if expr.is_pure() => {
+                let key = interner.key(&expr);
+                let Some(&shared) = interner.nodes.get(&key) else {
+                    let expr_ref = self.expressions.put(expr);
+                    interner.nodes.insert(key, expr_ref);
+                    return expr_ref;
+                };
+                // The shared node is only reused when it's identical to this
+                // occurrence, operands included, and carries no span or
+                // grouping of its own.
+                let annotated = self
+                    .expressions
+                    .span(shared)
+                    .is_some_and(|span| !span.is_empty())
+                    || self.groupings.contains(&shared);
+                if !annotated && self.expressions.get(shared) == Some(&expr) {
+                    interner.shared.insert(shared);
+                    return shared;
+                }
+                let expr_ref = self.expressions.put(expr);
+                interner.occurrences.insert(expr_ref, shared);
+                expr_ref
+            }
+            _ => self.expressions.put(expr),
+        }
+    }
+
+    /// Set the source location of a declaration.
+    pub fn set_decl_span(&mut self, decl_ref: DeclRef, span: Span) {
+        self.declarations.set_span(decl_ref, span)
+    }
+
+    /// Set the source location of a statement.
+    pub fn set_stmt_span(&mut self, stmt_ref: StmtRef, span: Span) {
+        self.statements.set_span(stmt_ref, span)
+    }
+
+    /// Return the source location of a declaration.
+    #[must_use]
+    pub fn decl_span(&self, decl_ref: DeclRef) -> Option<Span> {
+        self.declarations.span(decl_ref)
+    }
+
+    /// Return the source location of a statement.
+    #[must_use]
+    pub fn stmt_span(&self, stmt_ref: StmtRef) -> Option<Span> {
+        self.statements.span(stmt_ref)
+    }
+
+    /// Set the source location of an expression.
+    pub fn set_expr_span(&mut self, expr_ref: ExprRef, span: Span) {
+        self.expressions.set_span(expr_ref, span)
+    }
+
+    /// Return the source location of an expression.
+    #[must_use]
+    pub fn expr_span(&self, expr_ref: ExprRef) -> Option<Span> {
+        self.expressions.span(expr_ref)
+    }
+
+    /// Build the index of the nodes by source location in `source`, the
+    /// text the AST was parsed from.
+    #[must_use]
+    pub fn span_index(&self, source: &str) -> SpanIndex {
+        SpanIndex::new(self, source)
+    }
+
+    /// Returns the innermost node covering the byte offset `position` of
+    /// `source`.
+    ///
+    /// The index is rebuilt on every call, callers querying many positions
+    /// should build it once with `span_index`.
+    #[must_use]
+    pub fn node_at(&self, source: &str, position: usize) -> Option<AnyRef> {
+        self.span_index(source).node_at(position)
+    }
+
+    /// Mark a local variable declaration as declared without an initializer.
+    pub fn set_default_initialized(&mut self, stmt_ref: StmtRef) {
+        self.default_initialized.insert(stmt_ref);
+    }
+
+    /// Returns `true` if the local variable declaration at `stmt_ref` was
+    /// declared without an initializer.
+    #[must_use]
+    pub fn is_default_initialized(&self, stmt_ref: StmtRef) -> bool {
+        self.default_initialized.contains(&stmt_ref)
+    }
+
+    /// Mark a global or local variable declaration as `const` qualified.
+    pub fn set_const(&mut self, node: AnyRef) {
+        self.constants.insert(node);
+    }
+
+    /// Returns `true` if the variable declaration at `node` is `const`
+    /// qualified.
+    #[must_use]
+    pub fn is_const(&self, node: AnyRef) -> bool {
+        self.constants.contains(&node)
+    }
+
+    /// Place the declaration at `decl_ref` in the section `section`.
+    pub fn set_section(&mut self, decl_ref: DeclRef, section: String) {
+        self.sections.insert(decl_ref, section);
+    }
+
+    /// Returns the section the declaration at `decl_ref` is placed in, if it
+    /// has a section attribute.
+    #[must_use]
+    pub fn section(&self, decl_ref: DeclRef) -> Option<&str> {
+        self.sections.get(&decl_ref).map(String::as_str)
+    }
+
+    /// Mark a global or local variable declaration as `volatile` qualified.
+    pub fn set_volatile(&mut self, node: AnyRef) {
+        self.volatiles.insert(node);
+    }
+
+    /// Returns `true` if the variable declaration at `node` is `volatile`
+    /// qualified, its accesses can't be removed or reordered.
+    #[must_use]
+    pub fn is_volatile(&self, node: AnyRef) -> bool {
+        self.volatiles.contains(&node)
+    }
+
+    /// Mark a local variable declaration as `static`.
+    pub fn set_static(&mut self, stmt_ref: StmtRef) {
+        self.statics.insert(stmt_ref);
+    }
+
+    /// Returns `true` if the local variable declaration at `stmt_ref` is
+    /// `static`, its value persists across calls to the function.
+    #[must_use]
+    pub fn is_static(&self, stmt_ref: StmtRef) -> bool {
+        self.statics.contains(&stmt_ref)
+    }
+
+    /// Mark the expression at `expr_ref` as written between parentheses.
+    pub fn set_grouped(&mut self, expr_ref: ExprRef) {
+        self.groupings.insert(expr_ref);
+    }
+
+    /// Returns `true` if the expression at `expr_ref` was written between
+    /// parentheses.
+    #[must_use]
+    pub fn is_grouped(&self, expr_ref: ExprRef) -> bool {
+        self.groupings.contains(&expr_ref)
+    }
+
+    /// Sweep the statements and expressions that aren't reachable from a
+    /// declaration and rewrite the references of the surviving nodes,
+    /// returns the number of reclaimed nodes.
+    ///
+    /// Rewrites orphan the nodes they replace, compacting after them keeps
+    /// the pools of long lived ASTs (an LSP server re-parsing on every edit)
+    /// from growing without bound. Statement and expression references held
+    /// outside the AST are invalidated.
+    pub fn compact(&mut self) -> Reclaimed {
+        let mut live_stmts = vec![false; self.statements.nodes.len()];
+        let mut live_exprs = vec![false; self.expressions.nodes.len()];
+        let mut stmts = vec![];
+        let mut exprs = vec![];
+        for decl in &self.declarations.nodes {
+            decl.clone().for_each_ref(
+                |stmt_ref| stmts.push(*stmt_ref),
+                |expr_ref| exprs.push(*expr_ref),
+            );
+        }
+        while let Some(stmt_ref) = stmts.pop() {
+            if std::mem::replace(&mut live_stmts[stmt_ref.get()], true) {
+                continue;
+            }
+            self.statements.nodes[stmt_ref.get()].clone().for_each_ref(
+                |stmt_ref| stmts.push(*stmt_ref),
+                |expr_ref| exprs.push(*expr_ref),
+            );
+        }
+        while let Some(expr_ref) = exprs.pop() {
+            if std::mem::replace(&mut live_exprs[expr_ref.get()], true) {
+                continue;
+            }
+            self.expressions.nodes[expr_ref.get()]
+                .clone()
+                .for_each_ref(|expr_ref| exprs.push(*expr_ref));
+        }
+
+        let stmt_remap = self.statements.retain(&live_stmts);
+        let expr_remap = self.expressions.retain(&live_exprs);
+        let remap_stmt = |stmt_ref: &mut StmtRef| {
+            *stmt_ref = StmtRef::new(
+                stmt_remap[stmt_ref.get()].expect("live statement"),
+            );
+        };
+        let remap_expr = |expr_ref: &mut ExprRef| {
+            *expr_ref = ExprRef::new(
+                expr_remap[expr_ref.get()].expect("live expression"),
+            );
+        };
+        for decl in &mut self.declarations.nodes {
+            decl.for_each_ref(remap_stmt, remap_expr);
+        }
+        for stmt in &mut self.statements.nodes {
+            stmt.for_each_ref(remap_stmt, remap_expr);
+        }
+        for expr in &mut self.expressions.nodes {
+            expr.for_each_ref(remap_expr);
+        }
+        self.default_initialized =
+            std::mem::take(&mut self.default_initialized)
+                .into_iter()
+                .filter_map(|stmt_ref| {
+                    stmt_remap[stmt_ref.get()].map(StmtRef::new)
+                })
+                .collect();
+        self.statics = std::mem::take(&mut self.statics)
+            .into_iter()
+            .filter_map(|stmt_ref| stmt_remap[stmt_ref.get()].map(StmtRef::new))
+            .collect();
+        let remap_node = |node: AnyRef| match node {
+            AnyRef::Stmt(stmt_ref) => stmt_remap[stmt_ref.get()]
+                .map(|index| AnyRef::Stmt(StmtRef::new(index))),
+            node => Some(node),
+        };
+        self.constants = std::mem::take(&mut self.constants)
+            .into_iter()
+            .filter_map(remap_node)
+            .collect();
+        self.volatiles = std::mem::take(&mut self.volatiles)
+            .into_iter()
+            .filter_map(remap_node)
+            .collect();
+        self.groupings = std::mem::take(&mut self.groupings)
+            .into_iter()
+            .filter_map(|expr_ref| expr_remap[expr_ref.get()].map(ExprRef::new))
+            .collect();
+        if self.interned.is_some() {
+            self.interned = None;
+            *self = std::mem::take(self).with_interning();
+        }
+
+        Reclaimed {
+            statements: live_stmts.iter().filter(|live| !**live).count(),
+            expressions: live_exprs.iter().filter(|live| !**live).count(),
+        }
+    }
+
+    /// Replace the declaration at `decl_ref` with `decl`.
+    pub fn replace_decl(&mut self, decl_ref: DeclRef, decl: Decl) {
+        if let Some(node) = self.declarations.nodes.get_mut(decl_ref.get()) {
+            *node = decl;
+        }
+    }
+
+    /// Replace the statement at `stmt_ref` with `stmt`.
+    pub fn replace_stmt(&mut self, stmt_ref: StmtRef, stmt: Stmt) {
+        if let Some(node) = self.statements.nodes.get_mut(stmt_ref.get()) {
+            *node = stmt;
+        }
+    }
+
+    /// Replace every expression referenced by a declaration or a statement
+    /// with the expression returned by `f`, expressions nested in other
+    /// expressions are left to `f`.
+    pub fn rewrite_exprs(
+        &mut self,
+        mut f: impl FnMut(&mut Self, ExprRef) -> ExprRef,
+    ) {
+        for index in 0..self.declarations.nodes.len() {
+            let mut decl = self.declarations.nodes[index].clone();
+            let mut exprs = vec![];
+            decl.for_each_ref(|_| (), |expr_ref| exprs.push(*expr_ref));
+            let mut rewritten = exprs
+                .into_iter()
+                .map(|expr_ref| f(self, expr_ref))
+                .collect::<Vec<_>>()
+                .into_iter();
+            decl.for_each_ref(
+                |_| (),
+                |expr_ref| *expr_ref = rewritten.next().unwrap_or(*expr_ref),
+            );
+            self.declarations.nodes[index] = decl;
+        }
+        for index in 0..self.statements.nodes.len() {
+            let mut stmt = self.statements.nodes[index].clone();
+            let mut exprs = vec![];
+            stmt.for_each_ref(|_| (), |expr_ref| exprs.push(*expr_ref));
+            let mut rewritten = exprs
+                .into_iter()
+                .map(|expr_ref| f(self, expr_ref))
+                .collect::<Vec<_>>()
+                .into_iter();
+            stmt.for_each_ref(
+                |_| (),
+                |expr_ref| *expr_ref = rewritten.next().unwrap_or(*expr_ref),
+            );
+            self.statements.nodes[index] = stmt;
+        }
+    }
+
+    /// Rename the local variable or argument declared at `decl` to
+    /// `new_name` along with every reference to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `decl` isn't a variable declaration or if the
+    /// new name would change what a reference resolves to, either because
+    /// `new_name` is already visible where the variable is used or because a
+    /// declaration of `new_name` would shadow the variable. The AST is left
+    /// untouched on error.
+    pub fn rename(
+        &mut self,
+        decl: StmtRef,
+        new_name: &str,
+    ) -> Result<(), RenameError> {
+        if !matches!(
+            self.get_stmt(decl),
+            Some(Stmt::LocalVariable { .. } | Stmt::Argument { .. })
+        ) {
+            return Err(RenameError::new(format!(
+                "statement at ref {} is not a variable declaration",
+                decl.get()
+            )));
+        }
+        let mut ast = self.clone();
+        let mut renamer = Renamer {
+            ast: &mut ast,
+            decl,
+            new_name,
+            scopes: vec![],
+        };
+        for function in self.declarations() {
+            if let Decl::Function { args, body, .. } = function {
+                renamer.function(args, *body)?;
+            }
+        }
+        *self = ast;
+        Ok(())
     }
 
     /// Return an immutable reference to `Decl` by its handle.
@@ -498,6 +1337,189 @@ impl AST {
     }
 }
 
+/// Rename error type is used to report variables that can't be renamed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenameError {
+    details: String,
+}
+
+impl RenameError {
+    fn new(details: String) -> Self {
+        Self { details }
+    }
+}
+
+impl fmt::Display for RenameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for RenameError {}
+
+/// `Renamer` walks the functions of an AST resolving names with the scoping
+/// rules of semantic analysis and rewrites the references to a variable.
+struct Renamer<'a> {
+    ast: &'a mut AST,
+    // Declaration of the renamed variable.
+    decl: StmtRef,
+    new_name: &'a str,
+    // Stack of scopes, variables are bound under their original name.
+    scopes: Vec<Vec<(String, StmtRef)>>,
+}
+
+impl<'a> Renamer<'a> {
+    /// Returns the declaration `name` resolves to.
+    fn resolve(&self, name: &str) -> Option<StmtRef> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(bound, _)| bound == name)
+            .map(|(_, decl)| *decl)
+    }
+
+    /// Returns `true` if the renamed variable is in scope.
+    fn in_scope(&self) -> bool {
+        self.scopes
+            .iter()
+            .flatten()
+            .any(|(_, decl)| *decl == self.decl)
+    }
+
+    fn declare(
+        &mut self,
+        name: &str,
+        decl: StmtRef,
+    ) -> Result<(), RenameError> {
+        let scope = self.scopes.last().map_or(&[][..], Vec::as_slice);
+        if decl == self.decl
+            && scope.iter().any(|(bound, _)| bound == self.new_name)
+        {
+            return Err(RenameError::new(format!(
+                "`{}` is already declared in the same scope",
+                self.new_name
+            )));
+        }
+        if name == self.new_name && self.in_scope() {
+            return Err(RenameError::new(format!(
+                "a declaration of `{name}` would shadow the renamed variable"
+            )));
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push((name.to_string(), decl));
+        }
+        Ok(())
+    }
+
+    fn function(
+        &mut self,
+        args: &[StmtRef],
+        body: StmtRef,
+    ) -> Result<(), RenameError> {
+        // Arguments and the function body share a scope.
+        self.scopes.push(vec![]);
+        for arg in args {
+            self.stmt(*arg)?;
+        }
+        if let Some(Stmt::Block(stmts)) = self.ast.get_stmt(body).cloned() {
+            for stmt in stmts {
+                self.stmt(stmt)?;
+            }
+        }
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt_ref: StmtRef) -> Result<(), RenameError> {
+        let Some(mut stmt) = self.ast.get_stmt(stmt_ref).cloned() else {
+            return Ok(());
+        };
+        match &mut stmt {
+            Stmt::LocalVariable { name, value, .. } => {
+                *value = self.expr(*value)?;
+                self.declare(name, stmt_ref)?;
+                if stmt_ref == self.decl {
+                    *name = self.new_name.to_string();
+                }
+            }
+            Stmt::Argument { name, .. } => {
+                self.declare(name, stmt_ref)?;
+                if stmt_ref == self.decl {
+                    *name = self.new_name.to_string();
+                }
+            }
+            Stmt::Block(stmts) => {
+                self.scopes.push(vec![]);
+                for stmt in stmts.iter() {
+                    self.stmt(*stmt)?;
+                }
+                self.scopes.pop();
+            }
+            stmt => {
+                // Expressions are evaluated in the enclosing scope, before
+                // the nested statements.
+                let (mut stmts, mut exprs) = (vec![], vec![]);
+                stmt.for_each_ref(
+                    |stmt_ref| stmts.push(*stmt_ref),
+                    |expr_ref| exprs.push(*expr_ref),
+                );
+                let mut renamed = exprs
+                    .into_iter()
+                    .map(|expr| self.expr(expr))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter();
+                stmt.for_each_ref(
+                    |_| (),
+                    |expr_ref| {
+                        *expr_ref = renamed.next().unwrap_or(*expr_ref);
+                    },
+                );
+                for stmt in stmts {
+                    self.stmt(stmt)?;
+                }
+            }
+        }
+        self.ast.statements.nodes[stmt_ref.get()] = stmt;
+        Ok(())
+    }
+
+    /// Rename the references in an expression, returns the reference of the
+    /// renamed expression. Since expressions can be shared when interned
+    /// they are never modified in place, renamed expressions are new nodes.
+    fn expr(&mut self, expr_ref: ExprRef) -> Result<ExprRef, RenameError> {
+        let Some(mut expr) = self.ast.get_expr(expr_ref).cloned() else {
+            return Ok(expr_ref);
+        };
+        if let Expr::Named(name) = &expr {
+            if self.resolve(name) == Some(self.decl) {
+                let renamed = Expr::Named(self.new_name.to_string());
+                return Ok(self.ast.push_expr(renamed));
+            }
+            if name == self.new_name && self.in_scope() {
+                return Err(RenameError::new(format!(
+                    "references to `{name}` would resolve to the renamed variable"
+                )));
+            }
+            return Ok(expr_ref);
+        }
+        let mut children = vec![];
+        expr.for_each_ref(|expr_ref| children.push(*expr_ref));
+        let renamed = children
+            .iter()
+            .map(|child| self.expr(*child))
+            .collect::<Result<Vec<_>, _>>()?;
+        if renamed == children {
+            return Ok(expr_ref);
+        }
+        let mut renamed = renamed.into_iter();
+        expr.for_each_ref(|expr_ref| {
+            *expr_ref = renamed.next().unwrap_or(*expr_ref);
+        });
+        Ok(self.ast.push_expr(expr))
+    }
+}
+
 /// `ASTDisplayer` walks the AST nodes and displays the individual expressions.
 pub struct ASTDisplayer<'a> {
     ast: &'a AST,
@@ -508,6 +1530,20 @@ impl<'a> ASTDisplayer<'a> {
     pub const fn new(ast: &'a AST) -> Self {
         Self { ast }
     }
+
+    /// Returns the textual representation of the expression at `expr_ref`,
+    /// wrapped in `Grouping` if it was written between parentheses.
+    fn expr(&mut self, expr_ref: ExprRef) -> String {
+        let expr = self.ast.get_expr(expr_ref).map_or_else(
+            || unreachable!("missing expression at ref {}", expr_ref.get()),
+            |expr| self.visit_expr(expr),
+        );
+        if self.ast.is_grouped(expr_ref) {
+            format!("Grouping({expr})")
+        } else {
+            expr
+        }
+    }
 }
 
 impl<'a> Visitor<String> for ASTDisplayer<'a> {
@@ -515,135 +1551,42 @@ impl<'a> Visitor<String> for ASTDisplayer<'a> {
     fn visit_expr(&mut self, expr: &Expr) -> String {
         match expr {
             &Expr::Assignment { name, value } => {
-                let name = self.ast.get_expr(name).map_or_else(
-                    || unreachable!("missing named expression for assignment"),
-                    |name| self.visit_expr(name),
-                );
-                let value = self.ast.get_expr(value).map_or_else(
-                    || unreachable!("missing named expression for assignment"),
-                    |value| self.visit_expr(value),
-                );
-                format!("Assign({name}, {value})")
+                format!("Assign({}, {})", self.expr(name), self.expr(value))
             }
             &Expr::IntLiteral(value) => value.to_string(),
             &Expr::BoolLiteral(value) => value.to_string(),
             &Expr::CharLiteral(value) => value.to_string(),
-            &Expr::UnaryOp { operator, operand } => {
-                self.ast.get_expr(operand).map_or_else(
-                    || unreachable!("unary node is missing operand"),
-                    |operand| match operator {
-                        UnaryOperator::Neg => {
-                            format!("Neg({})", self.visit_expr(operand))
-                        }
-                        UnaryOperator::Not => {
-                            format!("Not({})", self.visit_expr(operand))
-                        }
-                    },
-                )
-            }
+            Expr::StringLiteral(value) => format!("\"{value}\""),
+            &Expr::UnaryOp { operator, operand } => match operator {
+                UnaryOperator::Neg => format!("Neg({})", self.expr(operand)),
+                UnaryOperator::Not => format!("Not({})", self.expr(operand)),
+            },
             &Expr::BinOp {
                 left,
                 operator,
                 right,
             } => {
-                if let (Some(left), Some(right)) =
-                    (self.ast.get_expr(left), self.ast.get_expr(right))
-                {
-                    match operator {
-                        BinaryOperator::Add => {
-                            format!(
-                                "Add({}, {})",
-                                self.visit_expr(left),
-                                self.visit_expr(right)
-                            )
-                        }
-                        BinaryOperator::Sub => {
-                            format!(
-                                "Sub({}, {})",
-                                self.visit_expr(left),
-                                self.visit_expr(right)
-                            )
-                        }
-                        BinaryOperator::Mul => {
-                            format!(
-                                "Mul({}, {})",
-                                self.visit_expr(left),
-                                self.visit_expr(right)
-                            )
-                        }
-                        BinaryOperator::Div => {
-                            format!(
-                                "Div({}, {})",
-                                self.visit_expr(left),
-                                self.visit_expr(right)
-                            )
-                        }
-                        BinaryOperator::Eq => {
-                            format!(
-                                "Equal({}, {})",
-                                self.visit_expr(left),
-                                self.visit_expr(right)
-                            )
-                        }
-                        BinaryOperator::Neq => {
-                            format!(
-                                "NotEqual({}, {})",
-                                self.visit_expr(left),
-                                self.visit_expr(right)
-                            )
-                        }
-                        BinaryOperator::Gt => {
-                            format!(
-                                "Greater({}, {})",
-                                self.visit_expr(left),
-                                self.visit_expr(right)
-                            )
-                        }
-                        BinaryOperator::Gte => {
-                            format!(
-                                "GreaterEqual({}, {})",
-                                self.visit_expr(left),
-                                self.visit_expr(right)
-                            )
-                        }
-                        BinaryOperator::Lt => {
-                            format!(
-                                "Lesser({}, {})",
-                                self.visit_expr(left),
-                                self.visit_expr(right)
-                            )
-                        }
-                        BinaryOperator::Lte => {
-                            format!(
-                                "LesserEqual({}, {})",
-                                self.visit_expr(left),
-                                self.visit_expr(right)
-                            )
-                        }
-                        BinaryOperator::And => {
-                            format!(
-                                "And({}, {})",
-                                self.visit_expr(left),
-                                self.visit_expr(right)
-                            )
-                        }
-                        BinaryOperator::Or => {
-                            format!(
-                                "Or({}, {})",
-                                self.visit_expr(left),
-                                self.visit_expr(right)
-                            )
-                        }
-                    }
-                } else {
-                    unreachable!("binary node is missing operand")
-                }
+                let name = match operator {
+                    BinaryOperator::Add => "Add",
+                    BinaryOperator::Sub => "Sub",
+                    BinaryOperator::Mul => "Mul",
+                    BinaryOperator::Div => "Div",
+                    BinaryOperator::Eq => "Equal",
+                    BinaryOperator::Neq => "NotEqual",
+                    BinaryOperator::Gt => "Greater",
+                    BinaryOperator::Gte => "GreaterEqual",
+                    BinaryOperator::Lt => "Lesser",
+                    BinaryOperator::Lte => "LesserEqual",
+                    BinaryOperator::And => "And",
+                    BinaryOperator::Shl => "Shl",
+                    BinaryOperator::Shr => "Shr",
+                    BinaryOperator::BitAnd => "BitAnd",
+                    BinaryOperator::Or => "Or",
+                };
+                format!("{name}({}, {})", self.expr(left), self.expr(right))
             }
-            &Expr::Grouping(expr_ref) => {
-                self.ast.get_expr(expr_ref).map_or_else(
-                    || unreachable!("unary node is missing operand"),
-                    |expr| format!("Grouping({})", self.visit_expr(expr)),
-                )
+            &Expr::Cast { to, operand } => {
+                format!("Cast({to}, {})", self.expr(operand))
             }
             Expr::Named(ref name) => {
                 format!("Named({name})")
@@ -652,49 +1595,27 @@ impl<'a> Visitor<String> for ASTDisplayer<'a> {
                 name: name_ref,
                 args,
             } => {
-                if let Some(name) = self.ast.get_expr(*name_ref) {
-                    let mut call_str =
-                        format!("Call({}, Args(", self.visit_expr(name));
-                    for arg_ref in args.iter() {
-                        if let Some(arg) = self.ast.get_expr(*arg_ref) {
-                            let formatted_arg = self.visit_expr(arg);
-                            call_str += &format!("{formatted_arg}, ");
-                        }
-                    }
-                    call_str = call_str.trim_end_matches(", ").to_string();
-                    call_str += "))";
-                    call_str
-                } else {
-                    unreachable!("expected call expression to have at least one named value")
-                }
+                let args = args
+                    .iter()
+                    .map(|arg_ref| self.expr(*arg_ref))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("Call({}, Args({args}))", self.expr(*name_ref))
             }
         }
     }
     /// Visit a statement and return its textual representation.
     fn visit_stmt(&mut self, stmt: &Stmt) -> String {
         match stmt {
-            Stmt::Return(expr_ref) => self.ast.get_expr(*expr_ref).map_or_else(
-                || unreachable!("Return statement is missing expression ref"),
-                |expr| format!("Return({})", self.visit_expr(expr)),
-            ),
+            Stmt::Return(expr_ref) => {
+                format!("Return({})", self.expr(*expr_ref))
+            }
             Stmt::LocalVariable {
                 decl_type,
                 name,
                 value,
-            } => {
-                let mut s = format!("VAR({decl_type}, {name}");
-                self.ast.get_expr(*value).map_or_else(
-                    || unreachable!("Missing expression in assignment"),
-                    |expr| {
-                        s += &format!(", {})", self.visit_expr(expr));
-                    },
-                );
-                s
-            }
-            Stmt::Expr(expr_ref) => self.ast.get_expr(*expr_ref).map_or_else(
-                || unreachable!("Expr statement is missing expression ref"),
-                |expr| format!("Expr({})", self.visit_expr(expr)),
-            ),
+            } => format!("VAR({decl_type}, {name}, {})", self.expr(*value)),
+            Stmt::Expr(expr_ref) => format!("Expr({})", self.expr(*expr_ref)),
             Stmt::Block(stmts) => {
                 let mut s = "Block {\n".to_string();
                 for stmt_ref in stmts.iter() {
@@ -716,10 +1637,7 @@ impl<'a> Visitor<String> for ASTDisplayer<'a> {
                 then_block: then_ref,
                 else_block: else_ref,
             } => {
-                let cond = self.ast.get_expr(*condition_ref).map_or_else(
-                    || unreachable!("missing condition for if statement"),
-                    |cond_expr| self.visit_expr(cond_expr),
-                );
+                let cond = self.expr(*condition_ref);
                 let then_block = self
                     .ast
                     .get_stmt(*then_ref)
@@ -730,7 +1648,9 @@ impl<'a> Visitor<String> for ASTDisplayer<'a> {
                         let else_block = self
                             .ast
                             .get_stmt(*else_ref)
-                            .map_or_else(String::new, |else_block| self.visit_stmt(else_block));
+                            .map_or_else(String::new, |else_block| {
+                                self.visit_stmt(else_block)
+                            });
                         format!("IF({cond}, {then_block}, {else_block})")
                     }
 
@@ -745,26 +1665,17 @@ impl<'a> Visitor<String> for ASTDisplayer<'a> {
             } => {
                 let init = match init_ref {
                     None => String::new(),
-                    Some(expr_ref) => self.ast.get_expr(*expr_ref).map_or_else(
-                        || unreachable!("missing expression in `for` statement"),
-                        |init_expr| self.visit_expr(init_expr),
-                    ),
+                    Some(expr_ref) => self.expr(*expr_ref),
                 };
 
                 let cond = match cond_ref {
                     None => String::new(),
-                    Some(expr_ref) => self.ast.get_expr(*expr_ref).map_or_else(
-                        || unreachable!("missing expression in `for` statement"),
-                        |cond_expr| self.visit_expr(cond_expr),
-                    ),
+                    Some(expr_ref) => self.expr(*expr_ref),
                 };
 
                 let iter = match iter_ref {
                     None => String::new(),
-                    Some(expr_ref) => self.ast.get_expr(*expr_ref).map_or_else(
-                        || unreachable!("missing expression in `for` statement"),
-                        |iter_expr| self.visit_expr(iter_expr),
-                    ),
+                    Some(expr_ref) => self.expr(*expr_ref),
                 };
 
                 let body = self.ast.get_stmt(*body_ref).map_or_else(
@@ -774,9 +1685,21 @@ impl<'a> Visitor<String> for ASTDisplayer<'a> {
 
                 format!("FOR(INIT({init}), COND({cond}), ITER({iter}), {body})")
             }
-            Stmt::Empty => unreachable!(
-                "empty statement is a temporary placeholder and should not be in the ast"
-            ),
+            Stmt::Switch { scrutinee, body } => {
+                let scrutinee = self.expr(*scrutinee);
+                let body = self.ast.get_stmt(*body).map_or_else(
+                    || unreachable!("expected `switch` statement to have body"),
+                    |body| self.visit_stmt(body),
+                );
+                format!("SWITCH({scrutinee}, {body})")
+            }
+            Stmt::Case(value) => format!("CASE({value})"),
+            Stmt::Default => "DEFAULT".to_string(),
+            Stmt::Break => "BREAK".to_string(),
+            Stmt::Goto(label) => format!("GOTO({label})"),
+            Stmt::Label(label) => format!("LABEL({label})"),
+            Stmt::Asm(code) => format!("ASM({code})"),
+            Stmt::Empty => "EMPTY".to_string(),
             _ => todo!("Unimplemented display trait for function declaration"),
         }
     }
@@ -788,6 +1711,8 @@ impl<'a> Visitor<String> for ASTDisplayer<'a> {
                 return_type,
                 args,
                 body,
+                linkage,
+                attributes,
             } => {
                 let mut args_str = String::new();
                 for arg_ref in args.iter() {
@@ -804,28 +1729,45 @@ impl<'a> Visitor<String> for ASTDisplayer<'a> {
                     || unreachable!("function is missing body"),
                     |body| body,
                 );
+                let mut prefix = match linkage {
+                    Linkage::External => String::new(),
+                    Linkage::Internal => "STATIC ".to_string(),
+                };
+                if !attributes.is_empty() {
+                    let attributes = attributes
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    prefix = format!("[[{attributes}]] {prefix}");
+                }
                 format!(
-                    "FUNCTION({}, {}, ARGS({}), {}",
+                    "{}FUNCTION({}, {}, ARGS({}), {}",
+                    prefix,
                     name,
                     return_type,
                     args_str,
                     self.visit_stmt(body)
                 )
             }
+            Decl::Extern {
+                name,
+                return_type,
+                args,
+            } => {
+                let args = args
+                    .iter()
+                    .filter_map(|arg_ref| self.ast.get_stmt(*arg_ref))
+                    .map(|arg| self.visit_stmt(arg))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("EXTERN({name}, {return_type}, ARGS({args}))")
+            }
             Decl::GlobalVariable {
                 decl_type,
                 name,
                 value,
-            } => {
-                let mut s = format!("VAR({decl_type}, {name}");
-                self.ast.get_expr(*value).map_or_else(
-                    || unreachable!("Missing expression in assignment"),
-                    |expr| {
-                        s += &format!(", {})", self.visit_expr(expr));
-                    },
-                );
-                s
-            }
+            } => format!("VAR({decl_type}, {name}, {})", self.expr(*value)),
         }
     }
 }
@@ -849,4 +1791,176 @@ mod tests {
             assert_eq!(stmt_pool.get(node_ref), Some(&Stmt::Return(expr_ref)));
         }
     }
+
+    #[test]
+    fn can_intern_pure_expressions() {
+        use super::{BinaryOperator, AST};
+        let mut ast = AST::new().with_interning();
+        let a = ast.push_expr(Expr::Named("a".to_string()));
+        let one = ast.push_expr(Expr::IntLiteral(1));
+        let lhs = ast.push_expr(Expr::BinOp {
+            left: a,
+            operator: BinaryOperator::Add,
+            right: one,
+        });
+        let left = ast.push_expr(Expr::Named("a".to_string()));
+        let right = ast.push_expr(Expr::IntLiteral(1));
+        let rhs = ast.push_expr(Expr::BinOp {
+            left,
+            operator: BinaryOperator::Add,
+            right,
+        });
+        assert_eq!(lhs, rhs);
+        assert_eq!(ast.expressions().len(), 3);
+
+        // Assignments and calls have side effects and are never shared.
+        let assign = Expr::Assignment {
+            name: a,
+            value: one,
+        };
+        let first = ast.push_expr(assign.clone());
+        let second = ast.push_expr(assign);
+        assert_ne!(first, second);
+        assert_eq!(ast.expressions().len(), 5);
+    }
+
+    #[test]
+    fn can_remap_references_when_compacting() {
+        use super::{Decl, DeclType, Linkage, AST};
+        let mut ast = AST::new();
+        // Orphans located before the live nodes shift their references.
+        ast.push_expr(Expr::IntLiteral(0));
+        let orphan = ast.push_expr(Expr::IntLiteral(1));
+        ast.push_stmt(Stmt::Expr(orphan));
+        let value = ast.push_expr(Expr::IntLiteral(42));
+        let ret = ast.push_stmt(Stmt::Return(value));
+        let body = ast.push_stmt(Stmt::Block(vec![ret]));
+        ast.push_decl(Decl::Function {
+            name: "main".to_string(),
+            return_type: DeclType::Int,
+            args: vec![],
+            body,
+            linkage: Linkage::External,
+            attributes: vec![],
+        });
+        let expected = ast.to_string();
+
+        let reclaimed = ast.compact();
+        assert_eq!((reclaimed.statements, reclaimed.expressions), (1, 2));
+        assert_eq!(ast.expressions(), &vec![Expr::IntLiteral(42)]);
+        assert_eq!(ast.to_string(), expected);
+    }
+
+    #[test]
+    fn can_rename_variables_across_their_scope() {
+        use super::{Decl, Ref, StmtRef};
+        use crate::parser::Parser;
+        use crate::scanner::Scanner;
+
+        let source = "int main(int n) { int c = n; { int c = 2; c = c + 1; } c = c + n; return c; }";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let mut ast = parser.ast().clone();
+        let decl = |ast: &super::AST, index| match ast.declarations().first() {
+            Some(Decl::Function { body, .. }) => match ast.get_stmt(*body) {
+                Some(Stmt::Block(stmts)) => stmts[index],
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        let outer = decl(&ast, 0);
+        ast.rename(outer, "total").unwrap();
+        let renamed = ast.to_string();
+        assert!(renamed
+            .contains("Assign(Named(total), Add(Named(total), Named(n)))"));
+        assert!(renamed.contains("Assign(Named(c), Add(Named(c), 1))"));
+
+        // Renaming to a name that is visible at a reference fails.
+        assert!(ast.clone().rename(outer, "n").is_err());
+        let before = ast.clone();
+        assert!(ast.rename(outer, "c").is_err());
+        assert_eq!(ast, before);
+        assert!(ast.rename(StmtRef::new(10_000), "x").is_err());
+    }
+
+    #[test]
+    fn can_find_nodes_at_positions() {
+        use super::{AnyRef, Decl};
+        use crate::parser::Parser;
+        use crate::scanner::Scanner;
+
+        let source = "int main() {\n    return (1 + f(2)) * 3;\n}\nint f(int a) { return a; }";
+        let tokens = Scanner::new(source).scan_with_spans().unwrap();
+        let mut parser = Parser::with_spans(&tokens);
+        parser.parse();
+        let ast = parser.ast();
+        let index = ast.span_index(source);
+        let text = |node| {
+            let span = match node {
+                AnyRef::Decl(decl_ref) => ast.decl_span(decl_ref),
+                AnyRef::Stmt(stmt_ref) => ast.stmt_span(stmt_ref),
+                AnyRef::Expr(expr_ref) => ast.expr_span(expr_ref),
+            }
+            .unwrap();
+            source
+                .chars()
+                .skip(span.start)
+                .take(span.len())
+                .collect::<String>()
+        };
+        let position = |needle: &str| source.find(needle).unwrap();
+
+        assert_eq!(
+            index.node_at(position("2)")).map(text),
+            Some("2".to_string())
+        );
+        assert_eq!(
+            index.node_at(position("+ f")).map(text),
+            Some("1 + f(2)".to_string())
+        );
+        assert_eq!(
+            index.node_at(position("* 3")).map(text),
+            Some("(1 + f(2)) * 3".to_string())
+        );
+        assert_eq!(
+            index.node_at(position("return (")).map(text),
+            Some("return (1 + f(2)) * 3;".to_string())
+        );
+        assert!(matches!(
+            ast.node_at(source, position("int f")),
+            Some(AnyRef::Decl(decl_ref))
+                if matches!(ast.get_decl(decl_ref), Some(Decl::Function { name, .. }) if name == "f")
+        ));
+        assert_eq!(
+            index
+                .expr_at(position(";\n}"))
+                .map(|expr| text(AnyRef::Expr(expr))),
+            None
+        );
+        assert_eq!(
+            index
+                .expr_at(position("(2"))
+                .map(|expr| text(AnyRef::Expr(expr))),
+            Some("f(2)".to_string())
+        );
+        assert!(index
+            .node_at(position("\n}"))
+            .map(text)
+            .is_some_and(|body| body.starts_with('{')));
+        assert_eq!(index.node_at(source.len()), None);
+
+        // Positions are byte offsets, the comment takes more bytes than
+        // characters.
+        let source = "// naïve café\nint main() { return 1 + 2; }";
+        let tokens = Scanner::new(source).scan_with_spans().unwrap();
+        let mut parser = Parser::with_spans(&tokens);
+        parser.parse();
+        let ast = parser.ast();
+        let node = ast.node_at(source, source.find('2').unwrap()).unwrap();
+        let AnyRef::Expr(expr_ref) = node else {
+            panic!("expected an expression at {node:?}");
+        };
+        assert_eq!(ast.get_expr(expr_ref), Some(&super::Expr::IntLiteral(2)));
+    }
 }
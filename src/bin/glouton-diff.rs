@@ -0,0 +1,73 @@
+//! `glouton-diff` compiles a C source file under two optimization levels and
+//! prints the instruction level diff of every function along with the change
+//! in instruction and block counts, the diff goes from the first level to the
+//! second.
+//!
+//! Usage: `glouton-diff LEVEL LEVEL FILE` where `LEVEL` is `-O0` or `-Oz`.
+use glouton::diff::{delta, FunctionDiff};
+use glouton::optim::{OptLevel, PassManager};
+use glouton::{ir, parser, scanner, sema};
+
+const USAGE: &str = "usage: glouton-diff [-O0|-Oz] [-O0|-Oz] FILE";
+
+/// Parse an optimization level flag.
+fn opt_level(flag: &str) -> Option<OptLevel> {
+    match flag {
+        "-O0" => Some(OptLevel::O0),
+        "-Oz" => Some(OptLevel::Oz),
+        _ => None,
+    }
+}
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let (old, new, path) = match args.as_slice() {
+        [old, new, path] => match (opt_level(old), opt_level(new)) {
+            (Some(old), Some(new)) => (old, new, path),
+            _ => {
+                eprintln!("glouton-diff: unknown optimization level\n{USAGE}");
+                std::process::exit(1);
+            }
+        },
+        _ => {
+            eprintln!("{USAGE}");
+            std::process::exit(1);
+        }
+    };
+    let source = std::fs::read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("glouton-diff: failed to read {path}: {err}");
+        std::process::exit(1);
+    });
+    let tokens = scanner::Scanner::new(&source).scan().unwrap_or_else(|err| {
+        eprintln!("glouton-diff: {err}");
+        std::process::exit(1);
+    });
+    let mut parser = parser::Parser::new(&tokens);
+    parser.parse();
+    let symbol_table = sema::analyze(parser.ast());
+    let mut irgen = ir::IRBuilder::new(parser.ast(), &symbol_table);
+    irgen.build();
+
+    let compile = |level: OptLevel| {
+        let pipeline = PassManager::with_level(level);
+        let mut functions = irgen.functions().clone();
+        for function in &mut functions {
+            pipeline.run(function);
+        }
+        functions
+    };
+    let (mut instructions, mut blocks) = ((0, 0), (0, 0));
+    for (old, new) in compile(old).iter().zip(compile(new).iter()) {
+        let diff = FunctionDiff::new(old, new);
+        print!("{diff}");
+        instructions.0 += diff.instructions().0;
+        instructions.1 += diff.instructions().1;
+        blocks.0 += diff.blocks().0;
+        blocks.1 += diff.blocks().1;
+    }
+    println!(
+        "total: instructions {}, blocks {}",
+        delta(instructions),
+        delta(blocks)
+    );
+}
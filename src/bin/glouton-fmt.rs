@@ -0,0 +1,30 @@
+//! `glouton-fmt` formats C source files, the formatted source is written
+//! to standard output.
+//!
+//! Usage: `glouton-fmt [FILE]`, reads from standard input when no file
+//! is given.
+use std::io::Read;
+
+fn main() {
+    let source = match std::env::args().nth(1) {
+        Some(path) => std::fs::read_to_string(&path).unwrap_or_else(|err| {
+            eprintln!("glouton-fmt: failed to read {path}: {err}");
+            std::process::exit(1);
+        }),
+        None => {
+            let mut source = String::new();
+            if let Err(err) = std::io::stdin().read_to_string(&mut source) {
+                eprintln!("glouton-fmt: failed to read stdin: {err}");
+                std::process::exit(1);
+            }
+            source
+        }
+    };
+    match glouton::formatter::format(&source) {
+        Ok(formatted) => print!("{formatted}"),
+        Err(err) => {
+            eprintln!("glouton-fmt: {err}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,512 @@
+//! Registry of the compiler builtin functions.
+//!
+//! Builtins are called like regular functions but they are never declared,
+//! semantic analysis type checks them against their signature here and the
+//! IR represents them as intrinsic calls which optimizations can reason about
+//! and backends lower specially (for example `__builtin_abs` to a branchless
+//! sequence and `__builtin_trap` to `ud2` on x86-64).
+//!
+//! `printf` isn't a builtin itself, calls with a constant format string are
+//! type checked against the format and lowered to the print builtins. `exit`
+//! is implicitly declared as well but is lowered to an instruction, so are
+//! the atomic operations which take a variable rather than a value.
+use std::error::Error;
+use std::fmt;
+
+use crate::ast::DeclType;
+use crate::ir::Literal;
+use crate::parser::Lang;
+
+/// Prefix reserved for builtin function names.
+pub const BUILTIN_PREFIX: &str = "__builtin_";
+
+/// Name of the formatted output function.
+pub const PRINTF: &str = "printf";
+
+/// Name of the program termination function, `int exit(int code)` never
+/// returns and is lowered to an `exit` instruction.
+pub const EXIT: &str = "exit";
+
+/// Atomic operations on `int` variables, the language has no pointers so
+/// the variable is named directly as in `__atomic_add(counter, 1, 5)`. The
+/// last argument is the memory order as an integer literal, with the values
+/// of the C `__ATOMIC_*` macros.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AtomicOp {
+    // `int __atomic_load(int x, int order)` reads `x`.
+    Load,
+    // `int __atomic_store(int x, int value, int order)` writes `value` to `x`
+    // and evaluates to `value`.
+    Store,
+    // `int __atomic_add(int x, int value, int order)` adds `value` to `x` and
+    // evaluates to the previous value of `x`.
+    Add,
+}
+
+impl AtomicOp {
+    /// Returns the atomic operation with the given name if there's one.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Load, Self::Store, Self::Add]
+            .into_iter()
+            .find(|op| op.name() == name)
+    }
+
+    /// Returns the name the operation is called with in source code.
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Load => "__atomic_load",
+            Self::Store => "__atomic_store",
+            Self::Add => "__atomic_add",
+        }
+    }
+
+    /// Returns the number of arguments of the operation, including the
+    /// variable and the memory order.
+    #[must_use]
+    pub const fn arity(&self) -> usize {
+        match self {
+            Self::Load => 2,
+            Self::Store | Self::Add => 3,
+        }
+    }
+
+    /// Returns `true` if the operation can be performed with `order`, loads
+    /// can't release and stores can't acquire.
+    #[must_use]
+    pub const fn accepts(&self, order: MemoryOrder) -> bool {
+        match self {
+            Self::Load => {
+                !matches!(order, MemoryOrder::Release | MemoryOrder::AcqRel)
+            }
+            Self::Store => !matches!(
+                order,
+                MemoryOrder::Consume
+                    | MemoryOrder::Acquire
+                    | MemoryOrder::AcqRel
+            ),
+            Self::Add => true,
+        }
+    }
+}
+
+impl fmt::Display for AtomicOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Load => write!(f, "atomic_load"),
+            Self::Store => write!(f, "atomic_store"),
+            Self::Add => write!(f, "atomic_add"),
+        }
+    }
+}
+
+/// Memory order of an atomic operation, from the weakest to the strongest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MemoryOrder {
+    Relaxed,
+    Consume,
+    Acquire,
+    Release,
+    AcqRel,
+    SeqCst,
+}
+
+impl MemoryOrder {
+    /// Returns the memory order of the `__ATOMIC_*` macro value `value`.
+    #[must_use]
+    pub const fn from_value(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(Self::Relaxed),
+            1 => Some(Self::Consume),
+            2 => Some(Self::Acquire),
+            3 => Some(Self::Release),
+            4 => Some(Self::AcqRel),
+            5 => Some(Self::SeqCst),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the `__ATOMIC_*` macro of the memory order.
+    #[must_use]
+    pub const fn value(self) -> i32 {
+        self as i32
+    }
+}
+
+impl fmt::Display for MemoryOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Relaxed => write!(f, "relaxed"),
+            Self::Consume => write!(f, "consume"),
+            Self::Acquire => write!(f, "acquire"),
+            Self::Release => write!(f, "release"),
+            Self::AcqRel => write!(f, "acq_rel"),
+            Self::SeqCst => write!(f, "seq_cst"),
+        }
+    }
+}
+
+/// Builtin functions known to the compiler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Builtin {
+    // `int __builtin_abs(int x)` absolute value of `x`.
+    Abs,
+    // `int __builtin_min(int a, int b)` minimum of `a` and `b`.
+    Min,
+    // `bool __builtin_expect(bool cond, bool expected)` evaluates to `cond`
+    // and hints that it's likely equal to `expected`.
+    Expect,
+    // `int __builtin_trap()` aborts the program, it never returns so the
+    // `int` type is only there since the language has no `void` values.
+    Trap,
+    // `int __builtin_assume(bool cond)` tells the optimizer `cond` holds, it
+    // generates no code and the result is meaningless like `__builtin_trap`.
+    Assume,
+    // `int __builtin_print_int(int x)` prints `x` in decimal and returns the
+    // number of characters printed.
+    PrintInt,
+    // `int __builtin_print_char(char c)` prints `c` and returns 1.
+    PrintChar,
+}
+
+/// All the builtins in the registry.
+pub const BUILTINS: &[Builtin] = &[
+    Builtin::Abs,
+    Builtin::Min,
+    Builtin::Expect,
+    Builtin::Trap,
+    Builtin::Assume,
+    Builtin::PrintInt,
+    Builtin::PrintChar,
+];
+
+impl Builtin {
+    /// Returns the builtin with the given name if there's one.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        BUILTINS
+            .iter()
+            .find(|builtin| builtin.name() == name)
+            .copied()
+    }
+
+    /// Returns the name the builtin is called with in source code.
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Abs => "__builtin_abs",
+            Self::Min => "__builtin_min",
+            Self::Expect => "__builtin_expect",
+            Self::Trap => "__builtin_trap",
+            Self::Assume => "__builtin_assume",
+            Self::PrintInt => "__builtin_print_int",
+            Self::PrintChar => "__builtin_print_char",
+        }
+    }
+
+    /// Returns the types of the builtin parameters.
+    #[must_use]
+    pub const fn params(&self) -> &'static [DeclType] {
+        match self {
+            Self::Abs => &[DeclType::Int],
+            Self::Min => &[DeclType::Int, DeclType::Int],
+            Self::Expect => &[DeclType::Bool, DeclType::Bool],
+            Self::Trap => &[],
+            Self::Assume => &[DeclType::Bool],
+            Self::PrintInt => &[DeclType::Int],
+            Self::PrintChar => &[DeclType::Char],
+        }
+    }
+
+    /// Returns the builtin return type.
+    #[must_use]
+    pub const fn return_type(&self) -> DeclType {
+        match self {
+            Self::Abs
+            | Self::Min
+            | Self::Trap
+            | Self::Assume
+            | Self::PrintInt
+            | Self::PrintChar => DeclType::Int,
+            Self::Expect => DeclType::Bool,
+        }
+    }
+
+    /// Returns `true` if calling the builtin has side effects, such calls
+    /// can't be removed even when their result is unused.
+    ///
+    /// Assumptions have no effect at runtime but must stay alive until the
+    /// facts they carry are used, they're dropped before emission.
+    #[must_use]
+    pub const fn has_side_effects(&self) -> bool {
+        matches!(
+            self,
+            Self::Trap | Self::Assume | Self::PrintInt | Self::PrintChar
+        )
+    }
+
+    /// Returns the first language level providing the builtin.
+    #[must_use]
+    pub const fn lang(&self) -> Lang {
+        match self {
+            Self::PrintInt | Self::PrintChar => Lang::Min,
+            Self::Abs | Self::Min => Lang::C89Subset,
+            Self::Expect | Self::Trap | Self::Assume => Lang::Gnu,
+        }
+    }
+
+    /// Returns `true` if the builtin is lowered to a call into the C library
+    /// by native backends, such builtins are unavailable in freestanding
+    /// mode.
+    #[must_use]
+    pub const fn needs_runtime(&self) -> bool {
+        matches!(self, Self::PrintInt | Self::PrintChar)
+    }
+
+    /// Evaluate the builtin on constant arguments, returns `None` if the
+    /// call can't be folded.
+    #[must_use]
+    pub fn fold(&self, args: &[Literal]) -> Option<Literal> {
+        match (self, args) {
+            (Self::Abs, [Literal::Int(x)]) => {
+                Some(Literal::Int(x.wrapping_abs()))
+            }
+            (Self::Min, [Literal::Int(a), Literal::Int(b)]) => {
+                Some(Literal::Int(*a.min(b)))
+            }
+            (Self::Expect, [cond @ Literal::Bool(_), Literal::Bool(_)]) => {
+                Some(*cond)
+            }
+            // Assuming `true` carries no information.
+            (Self::Assume, [Literal::Bool(true)]) => Some(Literal::Int(0)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Directive of a `printf` format string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Directive {
+    // Text printed as is, escape sequences are already replaced.
+    Text(String),
+    // `%d` prints an `int` argument.
+    Int,
+    // `%c` prints a `char` argument.
+    Char,
+    // `%s` prints a string literal argument.
+    Str,
+}
+
+impl Directive {
+    /// Returns the type of the argument the directive prints, string
+    /// arguments have no type since they're only literals.
+    #[must_use]
+    pub const fn arg_type(&self) -> Option<DeclType> {
+        match self {
+            Self::Int => Some(DeclType::Int),
+            Self::Char => Some(DeclType::Char),
+            Self::Text(_) | Self::Str => None,
+        }
+    }
+
+    /// Returns `true` if the directive prints an argument.
+    #[must_use]
+    pub const fn takes_arg(&self) -> bool {
+        !matches!(self, Self::Text(_))
+    }
+}
+
+/// Format error type is used to report invalid `printf` format strings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatError {
+    details: String,
+}
+
+impl FormatError {
+    fn new(details: String) -> Self {
+        Self { details }
+    }
+
+    /// Returns the error message without the `invalid format string` prefix.
+    #[must_use]
+    pub fn details(&self) -> &str {
+        &self.details
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid format string: {}", self.details)
+    }
+}
+
+impl Error for FormatError {}
+
+/// Returns the character literal of `ch` as written in source, quotes
+/// included, the inverse of `unescape` on characters.
+#[must_use]
+pub fn escape_char(ch: char) -> String {
+    match ch {
+        '\n' => "'\\n'".to_string(),
+        '\t' => "'\\t'".to_string(),
+        '\0' => "'\\0'".to_string(),
+        '\\' => "'\\\\'".to_string(),
+        '\'' => "'\\''".to_string(),
+        ch => format!("'{ch}'"),
+    }
+}
+
+/// Replace the escape sequences (`\n`, `\t`, `\0`, `\\` and `\'`) of a
+/// string literal by the characters they stand for.
+///
+/// # Errors
+///
+/// Returns an error on unknown escape sequences.
+pub fn unescape(literal: &str) -> Result<String, FormatError> {
+    let mut unescaped = String::with_capacity(literal.len());
+    let mut chars = literal.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('0') => unescaped.push('\0'),
+            Some('\\') => unescaped.push('\\'),
+            Some('\'') => unescaped.push('\''),
+            Some(c) => {
+                return Err(FormatError::new(format!(
+                    "unknown escape sequence `\\{c}`"
+                )))
+            }
+            None => {
+                return Err(FormatError::new(
+                    "unterminated escape sequence".to_string(),
+                ))
+            }
+        }
+    }
+    Ok(unescaped)
+}
+
+/// Parse a `printf` format string, `%d`, `%c`, `%s` and `%%` are the only
+/// supported conversions.
+///
+/// # Errors
+///
+/// Returns an error on unsupported conversions and escape sequences.
+pub fn parse_format(format: &str) -> Result<Vec<Directive>, FormatError> {
+    let mut directives = vec![];
+    let mut text = String::new();
+    let format = unescape(format)?;
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+        let directive = match chars.next() {
+            Some('%') => {
+                text.push('%');
+                continue;
+            }
+            Some('d') => Directive::Int,
+            Some('c') => Directive::Char,
+            Some('s') => Directive::Str,
+            Some(c) => {
+                return Err(FormatError::new(format!(
+                    "unsupported conversion `%{c}`"
+                )))
+            }
+            None => {
+                return Err(FormatError::new(
+                    "incomplete conversion at the end".to_string(),
+                ))
+            }
+        };
+        if !text.is_empty() {
+            directives.push(Directive::Text(std::mem::take(&mut text)));
+        }
+        directives.push(directive);
+    }
+    if !text.is_empty() {
+        directives.push(Directive::Text(text));
+    }
+    Ok(directives)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builtins::{parse_format, Builtin, Directive};
+    use crate::ir::Literal;
+
+    // Macro to generate test cases.
+    macro_rules! test_builtin_fold {
+        ($name:ident, $builtin:expr, $args:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                assert_eq!($builtin.fold($args), $expected);
+            }
+        };
+    }
+
+    test_builtin_fold!(
+        can_fold_abs,
+        Builtin::Abs,
+        &[Literal::Int(-42)],
+        Some(Literal::Int(42))
+    );
+    test_builtin_fold!(
+        can_fold_min,
+        Builtin::Min,
+        &[Literal::Int(3), Literal::Int(-1)],
+        Some(Literal::Int(-1))
+    );
+    test_builtin_fold!(
+        can_fold_expect_to_condition,
+        Builtin::Expect,
+        &[Literal::Bool(false), Literal::Bool(true)],
+        Some(Literal::Bool(false))
+    );
+    test_builtin_fold!(can_not_fold_trap, Builtin::Trap, &[], None);
+    test_builtin_fold!(
+        can_not_fold_false_assumption,
+        Builtin::Assume,
+        &[Literal::Bool(false)],
+        None
+    );
+
+    #[test]
+    fn can_parse_format_strings() {
+        assert_eq!(
+            parse_format("x = %d, c = %c%%\\n"),
+            Ok(vec![
+                Directive::Text("x = ".to_string()),
+                Directive::Int,
+                Directive::Text(", c = ".to_string()),
+                Directive::Char,
+                Directive::Text("%\n".to_string()),
+            ])
+        );
+        assert_eq!(parse_format("%s"), Ok(vec![Directive::Str]));
+        assert_eq!(
+            parse_format("%f").unwrap_err().to_string(),
+            "invalid format string: unsupported conversion `%f`"
+        );
+    }
+
+    #[test]
+    fn can_lookup_builtins_by_name() {
+        assert_eq!(Builtin::from_name("__builtin_min"), Some(Builtin::Min));
+        assert_eq!(Builtin::from_name("min"), None);
+    }
+}
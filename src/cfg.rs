@@ -1,56 +1,197 @@
 //! Implementation of control flow graphs over Glouton IR.
+use crate::builtins::Builtin;
 use crate::ir;
 use crate::ir::{BasicBlock, BlockRef};
 use core::fmt;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::sync::OnceLock;
 
-/// Edges in the control flow graph connect basic blocks and hold control
-/// flow instructions and a target label.
+/// Kind of control flow transfer an edge represents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    // Edge to the target of a conditional branch taken when the condition
+    // is true.
+    Then,
+    // Edge to the target of a conditional branch taken when the condition
+    // is false.
+    Else,
+    // Edge to the next block when a block doesn't end with a control flow
+    // instruction.
+    Fallthrough,
+    // Edge to the target of an unconditional jump.
+    Jump,
+}
+
+impl fmt::Display for EdgeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Then => write!(f, "then"),
+            Self::Else => write!(f, "else"),
+            Self::Fallthrough => write!(f, "fallthrough"),
+            Self::Jump => write!(f, "jump"),
+        }
+    }
+}
+
+/// `EdgeRef` is a handle to an edge in the graph, handles stay valid until
+/// the edge they reference is removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EdgeRef(pub usize);
+
+/// Edges in the control flow graph connect a source block to a target block.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub enum Edge {
-    Cond {
-        target_if_true: BlockRef,
-        target_if_false: BlockRef,
-        cond: ir::Instruction,
-    },
-    Jump {
-        target: BlockRef,
-    },
-    Ret {
-        target: BlockRef,
-    },
+pub struct Edge {
+    source: BlockRef,
+    target: BlockRef,
+    kind: EdgeKind,
+    // Position of the edge in the successor list of its source and the
+    // predecessor list of its target, used to unlink it in constant time.
+    succ_index: usize,
+    pred_index: usize,
+}
+
+impl Edge {
+    /// Returns the block the edge leaves from.
+    #[must_use]
+    pub const fn source(&self) -> BlockRef {
+        self.source
+    }
+
+    /// Returns the block the edge enters.
+    #[must_use]
+    pub const fn target(&self) -> BlockRef {
+        self.target
+    }
+
+    /// Returns the kind of the edge.
+    #[must_use]
+    pub const fn kind(&self) -> EdgeKind {
+        self.kind
+    }
+}
+
+/// CFG error type is used to report inconsistencies found by
+/// `Graph::validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CfgError {
+    function: String,
+    // Label of the offending block, empty for function level errors.
+    block: String,
+    details: String,
+}
+
+impl fmt::Display for CfgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.block.is_empty() {
+            write!(f, "invalid CFG in @{}: {}", self.function, self.details)
+        } else {
+            write!(
+                f,
+                "invalid CFG in @{} at block {}: {}",
+                self.function, self.block, self.details
+            )
+        }
+    }
+}
+
+impl Error for CfgError {}
+
+/// Orders in which the blocks reachable from the entry are visited by a
+/// depth first traversal, successors are visited in edge order.
+#[derive(Debug, Clone, Default)]
+struct BlockOrder {
+    // Blocks in the order they're first visited.
+    preorder: Vec<BlockRef>,
+    // Blocks in the order their traversal completes.
+    postorder: Vec<BlockRef>,
+    // Position of each block in reverse post-order, `None` for blocks that
+    // are unreachable from the entry.
+    rpo_numbers: Vec<Option<usize>>,
+}
+
+/// Dominator tree of a control flow graph, blocks are keyed by their
+/// `BlockRef`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dominators {
+    // Immediate dominator of each block.
+    idoms: Vec<Option<BlockRef>>,
+    // Blocks immediately dominated by each block.
+    children: Vec<Vec<BlockRef>>,
+}
+
+impl Dominators {
+    /// Returns the immediate dominator of the block at `block_ref`, `None`
+    /// for the entry block and the blocks unreachable from it.
+    #[must_use]
+    pub fn idom(&self, block_ref: BlockRef) -> Option<BlockRef> {
+        self.idoms[block_ref.0]
+    }
+
+    /// Returns the blocks immediately dominated by the block at `block_ref`.
+    #[must_use]
+    pub fn children(&self, block_ref: BlockRef) -> &[BlockRef] {
+        &self.children[block_ref.0]
+    }
+
+    /// Returns `true` if every path from the entry to `block` goes through
+    /// `dominator`, blocks dominate themselves.
+    #[must_use]
+    pub fn dominates(&self, dominator: BlockRef, mut block: BlockRef) -> bool {
+        loop {
+            if dominator == block {
+                return true;
+            }
+            match self.idoms[block.0] {
+                Some(idom) => block = idom,
+                None => return false,
+            }
+        }
+    }
 }
 
 /// A control flow graph constructed from a linear representation.
+///
+/// Blocks and edges live in arenas and are referenced with stable handles
+/// (`BlockRef` and `EdgeRef`), every block keeps the list of its incoming
+/// and outgoing edges so passes restructuring control flow can insert and
+/// remove edges in constant time.
 pub struct Graph {
     /// Nodes in the CFG.
     blocks: Vec<BasicBlock>,
-    /// Edges in the CFG.
-    edges: Vec<Edge>,
+    /// Edges in the CFG, removed edges leave an empty slot which is reused
+    /// by the next insertion.
+    edges: Vec<Option<Edge>>,
+    // Slots of removed edges.
+    free_edges: Vec<EdgeRef>,
+    // Incoming edges of each basic block.
+    predecessors: Vec<Vec<EdgeRef>>,
+    // Outgoing edges of each basic block.
+    successors: Vec<Vec<EdgeRef>>,
     // Mapping from labels to blocks built during the initial basic blocks
-    // construction pass and then updated during the control flow graph pass
-    // where blocks that don't have a label originally (non-target blocks) are
-    // assigned a label.
-    // TODO: Since we already have the notion of `BlockRef` refactor this
-    // to get rid of `String` in both the keys and values.
+    // construction pass, blocks that don't have a label originally
+    // (non-target blocks) are assigned one.
     labels: HashMap<String, BlockRef>,
-    // Successors of each basic block.
-    // TODO: Since we already have the notion of `BlockRef` refactor this
-    // to get rid of `String` in both the keys and values.
-    successors: HashMap<String, Vec<String>>,
+    // Label of each basic block.
+    names: Vec<String>,
+    // Traversal orders computed on demand and invalidated whenever blocks
+    // or edges are inserted or removed, `OnceLock` keeps the graph `Sync`.
+    order: OnceLock<BlockOrder>,
 }
 
 impl fmt::Display for Graph {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "CFG:  {{")?;
-        for (label, _) in &self.labels {
+        for label in &self.names {
             writeln!(f, "      {};", label)?;
         }
 
-        for (label, succs) in &self.successors {
-            for succ in succs {
-                writeln!(f, "      {} -> {};", label, succ)?;
-            }
+        for edge in self.edges.iter().flatten() {
+            writeln!(
+                f,
+                "      {} -> {}; // {}",
+                self.names[edge.source.0], self.names[edge.target.0], edge.kind
+            )?;
         }
 
         writeln!(f, "}}")
@@ -64,39 +205,44 @@ impl Graph {
     /// list of basic blocks.
     ///
     /// Given the list of basic blocks and labels mappings we construct
-    /// the control flow graph by building a dictionary of labels to successors
-    /// where each successor is one of many possible control flow targets.
+    /// the control flow graph by connecting each block to its possible
+    /// control flow targets.
     ///
     /// Algorithm to form control flow graph, proceeds by connecting the basic
     /// blocks with each other using either :
     ///
     /// 1. Branch targets if the last instruction is a conditional branch.
-    /// 2. Jump target if the last instruction is a conditional branch.
+    /// 2. Jump target if the last instruction is an unconditional jump.
     /// 3. The next block in the list if the next instruction is neither of
     ///    the previous two.
     ///
     /// for block in blocks:
     ///     if block.last().is_branch():
-    ///         add_edge(block, block.last().then_target())
-    ///         add_edge(block, block.last().else_target())
+    ///         add_edge(block, block.last().then_target(), Then)
+    ///         add_edge(block, block.last().else_target(), Else)
     ///     if block.last().is_jump():
-    ///         add_edge(block, block.last().target())
+    ///         add_edge(block, block.last().target(), Jump)
     ///     else:
-    ///         add_edge(block, blocks.next())?
+    ///         add_edge(block, blocks.next(), Fallthrough)?
     pub fn new(program: &Vec<ir::Function>) -> Self {
-        let mut blocks: Vec<BasicBlock> = Vec::new();
+        let mut graph = Self {
+            blocks: Vec::new(),
+            edges: Vec::new(),
+            free_edges: Vec::new(),
+            predecessors: Vec::new(),
+            successors: Vec::new(),
+            labels: HashMap::new(),
+            names: Vec::new(),
+            order: OnceLock::new(),
+        };
 
         for function in program {
-            let mut bbs = Self::form_basic_blocks(function);
-            blocks.append(&mut bbs)
-        }
-
-        Self {
-            blocks,
-            labels: HashMap::new(),
-            successors: HashMap::new(),
-            edges: Vec::new(),
+            for block in Self::form_basic_blocks(function) {
+                graph.add_block(block);
+            }
         }
+        graph.compute_edges();
+        graph
     }
 
     /// Form a list of basic blocks from the function, the ownership of
@@ -124,89 +270,648 @@ impl Graph {
                 }
             }
         }
+        // Functions that don't end with a terminator still form a block.
+        if !current.instructions().is_empty() {
+            worklist.push(current)
+        }
         worklist
     }
 
-    /// Iterate over all the constructed basic blocks and assign them a label
-    /// if they don't have one. Blocks that are control flow target will always
-    /// have a label instruction as a leader.
-    fn assign_labels_to_blocks(&mut self) {
-        for (index, block) in self.blocks.iter().enumerate() {
-            if block.leader().is_some_and(|inst| inst.label()) {
-                let label = block.leader().unwrap();
-                self.labels.insert(format!("{label}"), ir::BlockRef(index));
-            } else {
-                let label = format!(".LABEL_{}", index);
-                self.labels.insert(label, ir::BlockRef(index));
+    /// Returns the number of blocks in the graph.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Returns `true` if the graph has no blocks.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Returns an iterator over the handles of the blocks in the graph.
+    pub fn blocks(&self) -> impl Iterator<Item = BlockRef> {
+        (0..self.blocks.len()).map(BlockRef)
+    }
+
+    /// Returns a non-mutable reference to the block at `block_ref`.
+    #[must_use]
+    pub fn block(&self, block_ref: BlockRef) -> &BasicBlock {
+        &self.blocks[block_ref.0]
+    }
+
+    /// Returns a mutable reference to the block at `block_ref`.
+    pub fn block_mut(&mut self, block_ref: BlockRef) -> &mut BasicBlock {
+        &mut self.blocks[block_ref.0]
+    }
+
+    /// Returns the label of the block at `block_ref`.
+    #[must_use]
+    pub fn label(&self, block_ref: BlockRef) -> &str {
+        &self.names[block_ref.0]
+    }
+
+    /// Returns the block starting with `label` if there's one.
+    #[must_use]
+    pub fn block_by_label(&self, label: &str) -> Option<BlockRef> {
+        self.labels.get(label).copied()
+    }
+
+    /// Returns a non-mutable reference to the edge at `edge_ref`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the edge was removed.
+    #[must_use]
+    pub fn edge(&self, edge_ref: EdgeRef) -> &Edge {
+        self.edges[edge_ref.0]
+            .as_ref()
+            .unwrap_or_else(|| panic!("edge {} was removed", edge_ref.0))
+    }
+
+    /// Returns the outgoing edges of the block at `block_ref`.
+    #[must_use]
+    pub fn succ_edges(&self, block_ref: BlockRef) -> &[EdgeRef] {
+        &self.successors[block_ref.0]
+    }
+
+    /// Returns the incoming edges of the block at `block_ref`.
+    #[must_use]
+    pub fn pred_edges(&self, block_ref: BlockRef) -> &[EdgeRef] {
+        &self.predecessors[block_ref.0]
+    }
+
+    /// Returns an iterator over the successors of the block at `block_ref`.
+    pub fn successors(
+        &self,
+        block_ref: BlockRef,
+    ) -> impl Iterator<Item = BlockRef> + '_ {
+        self.successors[block_ref.0]
+            .iter()
+            .map(|edge_ref| self.edge(*edge_ref).target)
+    }
+
+    /// Returns an iterator over the predecessors of the block at `block_ref`.
+    pub fn predecessors(
+        &self,
+        block_ref: BlockRef,
+    ) -> impl Iterator<Item = BlockRef> + '_ {
+        self.predecessors[block_ref.0]
+            .iter()
+            .map(|edge_ref| self.edge(*edge_ref).source)
+    }
+
+    /// Push a new block to the graph and return its handle, blocks that
+    /// don't start with a label are assigned one.
+    pub fn add_block(&mut self, block: BasicBlock) -> BlockRef {
+        let block_ref = BlockRef(self.blocks.len());
+        let label = match block.leader() {
+            Some(leader) if leader.label() => format!("{leader}"),
+            // Blocks without a label are named after their position, we
+            // don't use the `.LABEL_` prefix to avoid colliding with the
+            // labels emitted by the IR builder.
+            _ => format!(".BLOCK_{}", block_ref.0),
+        };
+        self.labels.insert(label.clone(), block_ref);
+        self.names.push(label);
+        self.order.take();
+        self.blocks.push(block);
+        self.predecessors.push(Vec::new());
+        self.successors.push(Vec::new());
+        block_ref
+    }
+
+    /// Connect `source` to `target` with a new edge of the given kind.
+    pub fn add_edge(
+        &mut self,
+        source: BlockRef,
+        target: BlockRef,
+        kind: EdgeKind,
+    ) -> EdgeRef {
+        let edge_ref =
+            self.free_edges.pop().unwrap_or(EdgeRef(self.edges.len()));
+        let edge = Edge {
+            source,
+            target,
+            kind,
+            succ_index: self.successors[source.0].len(),
+            pred_index: self.predecessors[target.0].len(),
+        };
+        self.successors[source.0].push(edge_ref);
+        self.predecessors[target.0].push(edge_ref);
+        self.order.take();
+        if edge_ref.0 == self.edges.len() {
+            self.edges.push(Some(edge));
+        } else {
+            self.edges[edge_ref.0] = Some(edge);
+        }
+        edge_ref
+    }
+
+    /// Remove the edge at `edge_ref` and return it, the order of the
+    /// remaining edges of its source and target isn't preserved.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the edge was already removed.
+    pub fn remove_edge(&mut self, edge_ref: EdgeRef) -> Edge {
+        let edge = self.edges[edge_ref.0]
+            .take()
+            .unwrap_or_else(|| panic!("edge {} was removed", edge_ref.0));
+        self.free_edges.push(edge_ref);
+        self.order.take();
+
+        let succs = &mut self.successors[edge.source.0];
+        succs.swap_remove(edge.succ_index);
+        if let Some(moved) = succs.get(edge.succ_index).copied() {
+            if let Some(moved) = self.edges[moved.0].as_mut() {
+                moved.succ_index = edge.succ_index;
             }
         }
+        let preds = &mut self.predecessors[edge.target.0];
+        preds.swap_remove(edge.pred_index);
+        if let Some(moved) = preds.get(edge.pred_index).copied() {
+            if let Some(moved) = self.edges[moved.0].as_mut() {
+                moved.pred_index = edge.pred_index;
+            }
+        }
+        edge
+    }
+
+    /// Returns an iterator over the blocks reachable from the entry (the
+    /// first block) in depth first pre-order.
+    pub fn preorder(&self) -> impl Iterator<Item = BlockRef> + '_ {
+        self.order().preorder.iter().copied()
+    }
+
+    /// Returns an iterator over the blocks reachable from the entry in
+    /// post-order, every block is visited after its successors except along
+    /// back edges.
+    pub fn po(&self) -> impl Iterator<Item = BlockRef> + '_ {
+        self.order().postorder.iter().copied()
     }
 
-    /// Compute a list of succesors for each basic block in the graph.
-    fn compute_successors(&mut self) {
-        let mut successors = HashMap::new();
+    /// Returns an iterator over the blocks reachable from the entry in
+    /// reverse post-order, every block is visited before its successors
+    /// except along back edges. This is the order forward dataflow analyses
+    /// and dominance computations converge fastest in.
+    pub fn rpo(&self) -> impl Iterator<Item = BlockRef> + '_ {
+        self.order().postorder.iter().rev().copied()
+    }
 
-        for (label, block_ref) in &self.labels {
-            let mut succs = vec![];
-            let last = self.blocks[block_ref.0]
-                .terminator()
-                .expect("Expected instruction found empty basic block");
+    /// Returns the position of the block at `block_ref` in reverse
+    /// post-order, `None` if the block is unreachable from the entry.
+    #[must_use]
+    pub fn rpo_number(&self, block_ref: BlockRef) -> Option<usize> {
+        self.order().rpo_numbers[block_ref.0]
+    }
 
-            match last {
-                &ir::Instruction::Jump(label) => {
-                    if self.labels.get(format!("{label}").as_str()).is_some() {
-                        succs.push(format!("{label}"))
-                    }
+    /// Returns the immediate dominator of every block, `None` for the entry
+    /// block and the blocks unreachable from it.
+    ///
+    /// Dominators are computed with the iterative algorithm of Cooper, Harvey
+    /// and Kennedy : blocks are visited in reverse post-order and the
+    /// dominator of a block is the nearest common dominator of its already
+    /// processed predecessors, until no dominator changes.
+    #[must_use]
+    pub fn immediate_dominators(&self) -> Vec<Option<BlockRef>> {
+        let mut idoms = vec![None; self.blocks.len()];
+        if self.blocks.is_empty() {
+            return idoms;
+        }
+        idoms[0] = Some(BlockRef(0));
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in self.rpo().skip(1) {
+                let idom = self
+                    .predecessors(block)
+                    .filter(|pred| idoms[pred.0].is_some())
+                    .reduce(|a, b| self.intersect(&idoms, a, b));
+                if idom.is_some() && idoms[block.0] != idom {
+                    idoms[block.0] = idom;
+                    changed = true;
+                }
+            }
+        }
+        idoms[0] = None;
+        idoms
+    }
+
+    /// Returns the dominator tree of the graph, see `immediate_dominators`.
+    #[must_use]
+    pub fn dominators(&self) -> Dominators {
+        let idoms = self.immediate_dominators();
+        let mut children = vec![vec![]; idoms.len()];
+        for (block, idom) in idoms.iter().enumerate() {
+            if let Some(idom) = idom {
+                children[idom.0].push(BlockRef(block));
+            }
+        }
+        Dominators { idoms, children }
+    }
+
+    /// Returns the dominance frontier of every block : the blocks where its
+    /// dominance stops, reached from a block it dominates without being
+    /// strictly dominated by it. Unreachable blocks have an empty frontier.
+    ///
+    /// Frontiers are computed as in Cooper, Harvey and Kennedy : every join
+    /// block is in the frontier of the blocks on the dominator tree paths
+    /// from its predecessors up to its immediate dominator.
+    #[must_use]
+    pub fn dominance_frontiers(&self) -> Vec<BTreeSet<BlockRef>> {
+        let idoms = self.immediate_dominators();
+        let mut frontiers = vec![BTreeSet::new(); self.blocks.len()];
+        for block in self.rpo() {
+            if self.predecessors[block.0].len() < 2 {
+                continue;
+            }
+            for pred in self.predecessors(block) {
+                if self.rpo_number(pred).is_none() {
+                    continue;
                 }
-                &ir::Instruction::Branch(.., then_label, else_label) => {
-                    if self
-                        .labels
-                        .get(format!("{then_label}").as_str())
-                        .is_some()
-                        && self
-                            .labels
-                            .get(format!("{else_label}").as_str())
-                            .is_some()
-                    {
-                        succs.push(format!("{then_label}"));
-                        succs.push(format!("{else_label}"));
+                let mut runner = pred;
+                while Some(runner) != idoms[block.0] {
+                    frontiers[runner.0].insert(block);
+                    match idoms[runner.0] {
+                        Some(idom) => runner = idom,
+                        None => break,
                     }
                 }
-                &ir::Instruction::Return(..) => succs = vec![],
-                _ => {
-                    if block_ref.0 == self.labels.len() - 1 {
-                        succs = vec![]
-                    } else {
-                        let block_name = self
-                            .labels
-                            .iter()
-                            .map(|(k, &v)| {
-                                if v == BlockRef(block_ref.0 + 1) {
-                                    k.clone()
-                                } else {
-                                    unreachable!(
-                                        "No label found for block {}",
-                                        block_ref.0 + 1
-                                    )
-                                }
-                            })
-                            .next()
-                            .take();
-                        if let Some(succ) = block_name {
-                            succs = vec![succ.clone()];
+            }
+        }
+        frontiers
+    }
+
+    /// Returns the nearest common dominator of `a` and `b` by walking up the
+    /// dominators of the block with the greater reverse post-order number.
+    fn intersect(
+        &self,
+        idoms: &[Option<BlockRef>],
+        mut a: BlockRef,
+        mut b: BlockRef,
+    ) -> BlockRef {
+        let number = |block: BlockRef| self.rpo_number(block);
+        while a != b {
+            while number(a) > number(b) {
+                a = idoms[a.0].expect("processed blocks have a dominator");
+            }
+            while number(b) > number(a) {
+                b = idoms[b.0].expect("processed blocks have a dominator");
+            }
+        }
+        a
+    }
+
+    /// Returns the estimated execution frequency of every block relative to
+    /// the entry block, unreachable blocks have a frequency of zero.
+    ///
+    /// Frequencies are propagated in reverse post-order, every block splits
+    /// its frequency between its successors according to the weights of its
+    /// branch hint. Branches without a hint are assumed unlikely to reach a
+    /// block calling `__builtin_trap`. Back edges are ignored so loop bodies
+    /// aren't scaled by their trip count.
+    #[must_use]
+    pub fn frequencies(&self) -> Vec<f64> {
+        let mut frequencies = vec![0.0; self.blocks.len()];
+        if let Some(entry) = frequencies.first_mut() {
+            *entry = 1.0;
+        }
+        for block in self.rpo() {
+            let (then_weight, else_weight) = self.branch_weights(block);
+            let forward = self.successors[block.0]
+                .iter()
+                .map(|edge_ref| self.edge(*edge_ref))
+                .filter(|edge| {
+                    self.rpo_number(edge.target) > self.rpo_number(block)
+                })
+                .map(|edge| match edge.kind {
+                    EdgeKind::Then => (edge.target, f64::from(then_weight)),
+                    EdgeKind::Else => (edge.target, f64::from(else_weight)),
+                    EdgeKind::Jump | EdgeKind::Fallthrough => {
+                        (edge.target, 1.0)
+                    }
+                })
+                .collect::<Vec<_>>();
+            let total = forward.iter().map(|(_, weight)| weight).sum::<f64>();
+            for (target, weight) in forward {
+                frequencies[target.0] += frequencies[block.0] * weight / total;
+            }
+        }
+        frequencies
+    }
+
+    /// Returns the `(then, else)` weights of the branch ending the block at
+    /// `block_ref`.
+    fn branch_weights(&self, block_ref: BlockRef) -> (u32, u32) {
+        let Some(ir::Instruction::Branch(_, then_label, else_label, hint)) =
+            self.blocks[block_ref.0].terminator()
+        else {
+            return (1, 1);
+        };
+        let traps = |label: &ir::Label| {
+            self.block_by_label(&label.to_string())
+                .is_some_and(|target| {
+                    self.blocks[target.0].instructions().iter().any(|inst| {
+                        matches!(
+                            inst,
+                            ir::Instruction::Intrinsic(_, Builtin::Trap, _)
+                                | ir::Instruction::Trap(..)
+                        )
+                    })
+                })
+        };
+        match (hint, traps(then_label), traps(else_label)) {
+            (ir::BranchHint::Unknown, true, false) => {
+                ir::BranchHint::Unlikely.weights()
+            }
+            (ir::BranchHint::Unknown, false, true) => {
+                ir::BranchHint::Likely.weights()
+            }
+            _ => hint.weights(),
+        }
+    }
+
+    /// Returns the cached traversal orders, computing them if the graph
+    /// changed since they were last computed.
+    fn order(&self) -> &BlockOrder {
+        self.order.get_or_init(|| {
+            let mut order = BlockOrder {
+                rpo_numbers: vec![None; self.blocks.len()],
+                ..BlockOrder::default()
+            };
+            if self.blocks.is_empty() {
+                return order;
+            }
+            let mut visited = vec![false; self.blocks.len()];
+            // Stack of blocks being visited and the index of the next
+            // successor edge to follow.
+            let mut stack = vec![(BlockRef(0), 0)];
+            visited[0] = true;
+            order.preorder.push(BlockRef(0));
+            while let Some((block, next)) = stack.last_mut() {
+                let block = *block;
+                match self.successors[block.0].get(*next) {
+                    Some(edge_ref) => {
+                        *next += 1;
+                        let target = self.edge(*edge_ref).target;
+                        if !visited[target.0] {
+                            visited[target.0] = true;
+                            order.preorder.push(target);
+                            stack.push((target, 0));
                         }
                     }
+                    None => {
+                        stack.pop();
+                        order.postorder.push(block);
+                    }
+                }
+            }
+            let len = order.postorder.len();
+            for (index, block) in order.postorder.iter().enumerate() {
+                order.rpo_numbers[block.0] = Some(len - 1 - index);
+            }
+            order
+        })
+    }
+
+    /// Connect each block to its control flow targets.
+    fn compute_edges(&mut self) {
+        for index in 0..self.blocks.len() {
+            let source = BlockRef(index);
+            for (label, kind) in self.exits(source) {
+                if let Some(target) = self.block_by_label(&label) {
+                    self.add_edge(source, target, kind);
+                }
+            }
+        }
+    }
+
+    /// Returns the labels of the blocks control can flow to from the end of
+    /// the block at `block_ref` according to its last instruction.
+    fn exits(&self, block_ref: BlockRef) -> Vec<(String, EdgeKind)> {
+        let index = block_ref.0;
+        let last = self.blocks[index]
+            .terminator()
+            .expect("Expected instruction found empty basic block");
+
+        match last {
+            &ir::Instruction::Jump(label) => {
+                vec![(format!("{label}"), EdgeKind::Jump)]
+            }
+            &ir::Instruction::Branch(_, then_label, else_label, _) => {
+                vec![
+                    (format!("{then_label}"), EdgeKind::Then),
+                    (format!("{else_label}"), EdgeKind::Else),
+                ]
+            }
+            ir::Instruction::Return(..) => vec![],
+            inst if inst.no_return() => vec![],
+            // Blocks that don't end with a control flow instruction
+            // fall through to the next block.
+            _ if index + 1 < self.blocks.len() => {
+                vec![(self.names[index + 1].clone(), EdgeKind::Fallthrough)]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Check that the graph is consistent with `function`, the function it
+    /// was built from, returns the first inconsistency found.
+    ///
+    /// The following invariants are checked :
+    ///
+    /// 1. Labels are defined once and every `jmp` and `br` target is defined.
+    /// 2. The function ends with a terminator, control can't fall off its end.
+    /// 3. Every block is non-empty and only its last instruction is a
+    ///    terminator.
+    /// 4. The outgoing edges of every block mirror its terminator and the
+    ///    entry block is only entered through its label.
+    /// 5. Every edge is linked in the successors of its source and the
+    ///    predecessors of its target.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the first invariant that doesn't hold.
+    pub fn validate(&self, function: &ir::Function) -> Result<(), CfgError> {
+        let error = |block: &str, details: String| {
+            Err(CfgError {
+                function: function.name().to_string(),
+                block: block.to_string(),
+                details,
+            })
+        };
+
+        let mut defined = BTreeSet::new();
+        for inst in function.instructions() {
+            if inst.label() && !defined.insert(inst.to_string()) {
+                return error("", format!("label {inst} defined twice"));
+            }
+        }
+        for inst in function.instructions() {
+            let targets = match inst {
+                ir::Instruction::Jump(label) => vec![label.to_string()],
+                ir::Instruction::Branch(_, then_label, else_label, _) => {
+                    vec![then_label.to_string(), else_label.to_string()]
+                }
+                _ => vec![],
+            };
+            if let Some(label) = targets.iter().find(|l| !defined.contains(*l))
+            {
+                return error("", format!("jump to undefined label {label}"));
+            }
+        }
+        if function
+            .instructions()
+            .last()
+            .is_some_and(|i| !i.terminator())
+        {
+            return error("", "control falls off the function end".to_string());
+        }
+
+        for block_ref in self.blocks() {
+            let name = self.label(block_ref);
+            let instructions = self.block(block_ref).instructions();
+            if instructions.is_empty() {
+                return error(name, "empty basic block".to_string());
+            }
+            let last = instructions.len() - 1;
+            // Labels count as terminators but lead their block.
+            if instructions[..last]
+                .iter()
+                .skip(1)
+                .any(|inst| inst.terminator() || inst.label())
+            {
+                return error(name, "terminator in the middle".to_string());
+            }
+
+            let mut expected = self.exits(block_ref);
+            let mut actual = self
+                .succ_edges(block_ref)
+                .iter()
+                .map(|edge_ref| {
+                    let edge = self.edge(*edge_ref);
+                    (self.label(edge.target).to_string(), edge.kind)
+                })
+                .collect::<Vec<_>>();
+            expected.sort_by_key(|(label, kind)| (label.clone(), *kind as u8));
+            actual.sort_by_key(|(label, kind)| (label.clone(), *kind as u8));
+            if expected != actual {
+                return error(
+                    name,
+                    format!("edges {actual:?} don't match exits {expected:?}"),
+                );
+            }
+
+            for (index, edge_ref) in
+                self.succ_edges(block_ref).iter().enumerate()
+            {
+                let edge = self.edge(*edge_ref);
+                if edge.source != block_ref || edge.succ_index != index {
+                    return error(name, "successor list corrupted".to_string());
+                }
+            }
+            for (index, edge_ref) in
+                self.pred_edges(block_ref).iter().enumerate()
+            {
+                let edge = self.edge(*edge_ref);
+                if edge.target != block_ref || edge.pred_index != index {
+                    return error(
+                        name,
+                        "predecessor list corrupted".to_string(),
+                    );
+                }
+                if block_ref.0 == 0 && edge.kind == EdgeKind::Fallthrough {
+                    return error(
+                        name,
+                        "entry block entered by fallthrough".into(),
+                    );
                 }
             }
-            successors.insert(label.clone(), succs);
         }
-        self.successors = successors
+        Ok(())
+    }
+
+    /// Returns `true` if the control flow graph is reducible, i.e every cycle
+    /// has a single entry block (its header) which dominates the rest of it.
+    ///
+    /// Structured control flow always yields reducible graphs but `goto` can
+    /// jump into the middle of a loop, analyses that rely on natural loops
+    /// (loop detection, code motion) must conservatively skip functions with
+    /// irreducible graphs.
+    ///
+    /// Reducibility is decided with the T1-T2 transformations starting from
+    /// the entry block (the first block), blocks unreachable from the entry
+    /// are ignored :
+    ///
+    /// T1: Remove self loops.
+    /// T2: Merge a block with a unique predecessor into its predecessor.
+    ///
+    /// The graph is reducible iff it collapses into a single block.
+    pub fn is_reducible(&self) -> bool {
+        if self.blocks.is_empty() {
+            return true;
+        }
+        // Ordered maps so blocks are merged in the same order on every run.
+        let mut succs: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        let mut preds: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        // Collect the blocks reachable from the entry.
+        let mut worklist = vec![0];
+        while let Some(block) = worklist.pop() {
+            if succs.contains_key(&block) {
+                continue;
+            }
+            let targets: BTreeSet<usize> = self
+                .successors(BlockRef(block))
+                .map(|succ| succ.0)
+                .collect();
+            worklist.extend(targets.iter());
+            succs.insert(block, targets);
+            preds.entry(block).or_default();
+        }
+        for (block, targets) in &succs {
+            for target in targets {
+                preds.entry(*target).or_default().insert(*block);
+            }
+        }
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            // T1: Remove self loops.
+            for (block, targets) in succs.iter_mut() {
+                if targets.remove(block) {
+                    preds.get_mut(block).map(|p| p.remove(block));
+                }
+            }
+            // T2: Merge a block with a unique predecessor into it.
+            let candidate = succs.keys().copied().find(|block| {
+                *block != 0 && preds.get(block).is_some_and(|p| p.len() == 1)
+            });
+            if let Some(block) = candidate {
+                let pred = *preds[&block].first().expect("unique predecessor");
+                let targets = succs.remove(&block).unwrap_or_default();
+                preds.remove(&block);
+                let pred_succs = succs.get_mut(&pred).expect("predecessor");
+                pred_succs.remove(&block);
+                for target in targets {
+                    pred_succs.insert(target);
+                    if let Some(target_preds) = preds.get_mut(&target) {
+                        target_preds.remove(&block);
+                        target_preds.insert(pred);
+                    }
+                }
+                changed = true;
+            }
+        }
+        succs.len() == 1
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::cfg::Graph;
+    use crate::cfg::{EdgeKind, Graph};
+    use crate::ir::BlockRef;
     use crate::ir::IRBuilder;
     use crate::parser::Parser;
     use crate::scanner::Scanner;
@@ -228,10 +933,7 @@ mod tests {
 
                 let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
                 irgen.build();
-                let mut graph = Graph::new(irgen.functions());
-                graph.assign_labels_to_blocks();
-                // Compute successors.
-                graph.compute_successors();
+                let graph = Graph::new(irgen.functions());
                 println!("{}", graph);
             }
         };
@@ -265,4 +967,501 @@ mod tests {
         "#,
         &vec![]
     );
+
+    // Macro to generate edge construction test cases.
+    macro_rules! test_cfg_edges {
+        ($name:ident, $source:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                let source = $source;
+                let mut scanner = Scanner::new(source);
+                let tokens = scanner
+                    .scan()
+                    .expect("expected test case source to be valid");
+                let mut parser = Parser::new(&tokens);
+                parser.parse();
+                let symbol_table = analyze(parser.ast());
+
+                let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+                irgen.build();
+                let graph = Graph::new(irgen.functions());
+                let edges = graph
+                    .blocks()
+                    .flat_map(|block| graph.succ_edges(block).iter())
+                    .map(|edge_ref| {
+                        let edge = graph.edge(*edge_ref);
+                        (
+                            graph.label(edge.source()),
+                            graph.label(edge.target()),
+                            edge.kind(),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(edges, $expected);
+            }
+        };
+    }
+
+    test_cfg_edges!(
+        can_build_if_else_edges,
+        r#"
+            int main() {
+                int a = 1;
+                if (a > 2) {
+                    a = 2;
+                } else {
+                    a = 3;
+                }
+                return a;
+            }
+        "#,
+        vec![
+            (".BLOCK_0", ".LABEL_0", EdgeKind::Then),
+            (".BLOCK_0", ".LABEL_1", EdgeKind::Else),
+            (".LABEL_0", ".LABEL_2", EdgeKind::Jump),
+            (".LABEL_1", ".LABEL_2", EdgeKind::Jump),
+        ]
+    );
+
+    test_cfg_edges!(
+        can_build_fallthrough_edges,
+        r#"
+            int main() {
+                int i = 0;
+                loop:
+                i = i + 1;
+                return i;
+            }
+        "#,
+        vec![(".BLOCK_0", ".LABEL_0", EdgeKind::Fallthrough)]
+    );
+
+    #[test]
+    fn can_insert_and_remove_edges() {
+        let source = r#"
+            int main() {
+                int a = 1;
+                if (a > 2) {
+                    a = 2;
+                } else {
+                    a = 3;
+                }
+                return a;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let mut graph = Graph::new(irgen.functions());
+
+        let join = graph.block_by_label(".LABEL_2").unwrap();
+        let first = graph.pred_edges(join)[0];
+        let removed = graph.remove_edge(first);
+        assert_eq!(removed.target(), join);
+        assert_eq!(
+            graph.predecessors(join).collect::<Vec<_>>(),
+            vec![BlockRef(2)]
+        );
+        assert!(graph.successors(BlockRef(1)).next().is_none());
+        // Removed slots are reused.
+        let edge =
+            graph.add_edge(BlockRef(1), BlockRef(2), EdgeKind::Fallthrough);
+        assert_eq!(edge, first);
+        assert_eq!(
+            graph.predecessors(BlockRef(2)).collect::<Vec<_>>(),
+            vec![BlockRef(0), BlockRef(1)]
+        );
+    }
+
+    // Macro to generate traversal order test cases.
+    macro_rules! test_cfg_order {
+        ($name:ident, $source:expr, $preorder:expr, $rpo:expr) => {
+            #[test]
+            fn $name() {
+                let source = $source;
+                let mut scanner = Scanner::new(source);
+                let tokens = scanner
+                    .scan()
+                    .expect("expected test case source to be valid");
+                let mut parser = Parser::new(&tokens);
+                parser.parse();
+                let symbol_table = analyze(parser.ast());
+
+                let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+                irgen.build();
+                let graph = Graph::new(irgen.functions());
+                let labels = |order: Vec<BlockRef>| {
+                    order
+                        .into_iter()
+                        .map(|block| graph.label(block).to_string())
+                        .collect::<Vec<_>>()
+                };
+                assert_eq!(labels(graph.preorder().collect()), $preorder);
+                assert_eq!(labels(graph.rpo().collect()), $rpo);
+                let mut po = graph.po().collect::<Vec<_>>();
+                po.reverse();
+                assert_eq!(labels(po), $rpo);
+                for (index, block) in graph.rpo().enumerate() {
+                    assert_eq!(graph.rpo_number(block), Some(index));
+                }
+            }
+        };
+    }
+
+    test_cfg_order!(
+        can_order_if_else_blocks,
+        r#"
+            int main() {
+                int a = 1;
+                if (a > 2) {
+                    a = 2;
+                } else {
+                    a = 3;
+                }
+                return a;
+            }
+        "#,
+        vec![".BLOCK_0", ".LABEL_0", ".LABEL_2", ".LABEL_1"],
+        vec![".BLOCK_0", ".LABEL_1", ".LABEL_0", ".LABEL_2"]
+    );
+
+    test_cfg_order!(
+        can_order_loop_blocks,
+        r#"
+            int main() {
+                int i = 0;
+                while (i < 10) {
+                    i = i + 1;
+                }
+                return i;
+            }
+        "#,
+        vec![".BLOCK_0", ".LABEL_0", ".LABEL_1"],
+        vec![".BLOCK_0", ".LABEL_0", ".LABEL_1"]
+    );
+
+    #[test]
+    fn can_skip_unreachable_blocks_in_traversals() {
+        let source = r#"
+            int main() {
+                int i = 0;
+                goto end;
+                i = 1;
+                end:
+                return i;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let mut graph = Graph::new(irgen.functions());
+
+        assert_eq!(graph.len(), 3);
+        assert_eq!(graph.rpo().collect::<Vec<_>>(), [BlockRef(0), BlockRef(2)]);
+        assert_eq!(graph.rpo_number(BlockRef(1)), None);
+        // Edge insertions invalidate the cached orders.
+        graph.add_edge(BlockRef(0), BlockRef(1), EdgeKind::Fallthrough);
+        assert_eq!(graph.rpo_number(BlockRef(1)), Some(1));
+    }
+
+    #[test]
+    fn can_estimate_block_frequencies() {
+        let source = r#"
+            int main() {
+                int a = 1;
+                if (a > 10) {
+                    __builtin_trap();
+                }
+                for (a = 0; a < 10; a = a + 1) {
+                    a = a + 1;
+                }
+                return a;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let graph = Graph::new(irgen.functions());
+
+        let frequencies = graph.frequencies();
+        let frequency =
+            |label: &str| frequencies[graph.block_by_label(label).unwrap().0];
+        assert_eq!(frequencies[0], 1.0);
+        // The trap is unlikely to be reached.
+        assert!((frequency(".LABEL_0") - 1.0 / 2001.0).abs() < 1e-9);
+        assert!((frequency(".LABEL_1") - 2000.0 / 2001.0).abs() < 1e-9);
+        // The trap never returns so only the flow skipping it reaches the
+        // loop. Loops aren't scaled by their trip count, all the flow
+        // entering the loop leaves it.
+        assert!((frequency(".LABEL_3") - 2000.0 / 2001.0).abs() < 1e-9);
+        assert!((frequency(".LABEL_4") - 2000.0 / 2001.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn can_compute_immediate_dominators() {
+        let source = r#"
+            int main() {
+                int a = 1;
+                if (a > 0) {
+                    a = 2;
+                } else {
+                    a = 3;
+                }
+                while (a < 10) {
+                    a = a + 1;
+                }
+                return a;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let graph = Graph::new(irgen.functions());
+
+        let idom = |label: &str| {
+            graph.immediate_dominators()[graph.block_by_label(label).unwrap().0]
+        };
+        // Both sides of the `if` meet at `.LABEL_2`, only the entry
+        // dominates it. The loop body dominates nothing past itself.
+        assert_eq!(graph.immediate_dominators()[0], None);
+        assert_eq!(idom(".LABEL_0"), Some(BlockRef(0)));
+        assert_eq!(idom(".LABEL_1"), Some(BlockRef(0)));
+        assert_eq!(idom(".LABEL_2"), Some(BlockRef(0)));
+        assert_eq!(idom(".LABEL_3"), graph.block_by_label(".LABEL_2"));
+        assert_eq!(idom(".LABEL_4"), graph.block_by_label(".LABEL_2"));
+    }
+
+    #[test]
+    fn can_compute_dominance_frontiers() {
+        let source = r#"
+            int main() {
+                int a = 1;
+                if (a > 0) {
+                    a = 2;
+                } else {
+                    a = 3;
+                }
+                while (a < 10) {
+                    a = a + 1;
+                }
+                return a;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let graph = Graph::new(irgen.functions());
+        let block = |label: &str| graph.block_by_label(label).unwrap();
+        let dominators = graph.dominators();
+        assert_eq!(
+            dominators.children(block(".LABEL_2")),
+            [block(".LABEL_3"), block(".LABEL_4")]
+        );
+        assert!(dominators.dominates(BlockRef(0), block(".LABEL_4")));
+        assert!(!dominators.dominates(block(".LABEL_0"), block(".LABEL_2")));
+
+        let frontier = |label: &str| {
+            graph.dominance_frontiers()[block(label).0]
+                .iter()
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        // Both sides of the `if` stop dominating where they meet, the loop
+        // body is in its own frontier through its back edge.
+        assert_eq!(frontier(".LABEL_0"), [block(".LABEL_2")]);
+        assert_eq!(frontier(".LABEL_1"), [block(".LABEL_2")]);
+        assert_eq!(frontier(".LABEL_2"), []);
+        assert_eq!(
+            frontier(".LABEL_3"),
+            [block(".LABEL_3"), block(".LABEL_4")]
+        );
+        assert_eq!(frontier(".LABEL_4"), []);
+    }
+
+    // Macro to generate CFG validation test cases.
+    macro_rules! test_cfg_validation {
+        ($name:ident, $source:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                let source = $source;
+                let mut scanner = Scanner::new(source);
+                let tokens = scanner
+                    .scan()
+                    .expect("expected test case source to be valid");
+                let mut parser = Parser::new(&tokens);
+                parser.parse();
+                let symbol_table = analyze(parser.ast());
+
+                let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+                irgen.build();
+                let graph = Graph::new(irgen.functions());
+                let result = graph
+                    .validate(&irgen.functions()[0])
+                    .map_err(|err| err.to_string());
+                assert_eq!(result, $expected);
+            }
+        };
+    }
+
+    test_cfg_validation!(
+        can_validate_structured_control_flow,
+        r#"
+            int main() {
+                int a = 1;
+                int i = 0;
+                for (i = 0; i < 10; i = i + 1) {
+                    if (i > 2) {
+                        a = a + i;
+                    } else {
+                        a = a - 1;
+                    }
+                }
+                return a;
+            }
+        "#,
+        Ok(())
+    );
+
+    test_cfg_validation!(
+        can_validate_goto_control_flow,
+        r#"
+            int main() {
+                int i = 0;
+                loop:
+                i = i + 1;
+                if (i < 10) {
+                    goto loop;
+                }
+                return i;
+            }
+        "#,
+        Ok(())
+    );
+
+    test_cfg_validation!(
+        can_find_control_falling_off_function_end,
+        r#"
+            int main() {
+                int a = 1;
+            }
+        "#,
+        Err("invalid CFG in @main: control falls off the function end"
+            .to_string())
+    );
+
+    #[test]
+    fn can_find_edges_not_matching_terminators() {
+        let source = r#"
+            int main() {
+                int a = 1;
+                if (a > 2) {
+                    a = 2;
+                }
+                return a;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let mut graph = Graph::new(irgen.functions());
+
+        let edge = graph.succ_edges(BlockRef(0))[1];
+        graph.remove_edge(edge);
+        let err = graph.validate(&irgen.functions()[0]).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("invalid CFG in @main at block .BLOCK_0: edges"));
+    }
+
+    // Macro to generate reducibility test cases.
+    macro_rules! test_reducibility {
+        ($name:ident, $source:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                let source = $source;
+                let mut scanner = Scanner::new(source);
+                let tokens = scanner
+                    .scan()
+                    .expect("expected test case source to be valid");
+                let mut parser = Parser::new(&tokens);
+                parser.parse();
+                let symbol_table = analyze(parser.ast());
+
+                let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+                irgen.build();
+                let graph = Graph::new(irgen.functions());
+                assert_eq!(graph.is_reducible(), $expected);
+            }
+        };
+    }
+
+    test_reducibility!(
+        can_find_reducible_while_loop,
+        r#"
+            int main() {
+                int i = 0;
+                while (i < 10) {
+                    i = i + 1;
+                }
+                return i;
+            }
+        "#,
+        true
+    );
+
+    test_reducibility!(
+        can_find_reducible_goto_loop,
+        r#"
+            int main() {
+                int i = 0;
+                loop:
+                i = i + 1;
+                if (i < 10) {
+                    goto loop;
+                }
+                return i;
+            }
+        "#,
+        true
+    );
+
+    test_reducibility!(
+        can_find_irreducible_goto_into_loop,
+        r#"
+            int main() {
+                int i = 0;
+                bool c = true;
+                if (c) {
+                    goto middle;
+                }
+                head:
+                i = i + 1;
+                middle:
+                i = i + 2;
+                if (i < 10) {
+                    goto head;
+                }
+                return i;
+            }
+        "#,
+        false
+    );
 }
@@ -0,0 +1,430 @@
+//! Compile time evaluation of operations on constants.
+//!
+//! Signed integer overflow is undefined behavior in C, by default the
+//! evaluator wraps around like the generated code does on every target we
+//! support. In checked mode overflowing operations are reported instead so
+//! optimizations can be validated not to change the overflow behavior of a
+//! program between optimization levels.
+use std::error::Error;
+use std::fmt;
+
+use crate::ir::{Literal, OPCode};
+
+/// How overflowing signed integer arithmetic is evaluated.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArithmeticMode {
+    // Overflowing operations wrap around (two's complement).
+    #[default]
+    Wrapping,
+    // Overflowing operations are reported as errors.
+    Checked,
+    // Overflowing operations are reported as errors, optimizations replace
+    // them with a trap instead of failing the compilation (`-ftrapv`).
+    Trapping,
+}
+
+/// Overflow error type is used to report signed overflow in checked mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverflowError {
+    opcode: OPCode,
+    operands: Vec<Literal>,
+}
+
+impl fmt::Display for OverflowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operands = self
+            .operands
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let opcode = format!("{:?}", self.opcode).to_lowercase();
+        write!(f, "signed overflow evaluating `{opcode} {operands}`")
+    }
+}
+
+impl Error for OverflowError {}
+
+/// Evaluate the binary operation `opcode` on constant operands, returns
+/// `None` if the operation can't be evaluated at compile time (operands of
+/// the wrong type or division by zero).
+///
+/// # Errors
+///
+/// Returns an error if the operation overflows in checked mode.
+pub fn binary(
+    opcode: OPCode,
+    lhs: Literal,
+    rhs: Literal,
+    mode: ArithmeticMode,
+) -> Result<Option<Literal>, OverflowError> {
+    let overflow = || OverflowError {
+        opcode,
+        operands: vec![lhs, rhs],
+    };
+    let literal = match (opcode, lhs, rhs) {
+        (OPCode::Add, Literal::Int(a), Literal::Int(b)) => {
+            Literal::Int(arithmetic(a.overflowing_add(b), mode, overflow)?)
+        }
+        (OPCode::Sub, Literal::Int(a), Literal::Int(b)) => {
+            Literal::Int(arithmetic(a.overflowing_sub(b), mode, overflow)?)
+        }
+        (OPCode::Mul, Literal::Int(a), Literal::Int(b)) => {
+            Literal::Int(arithmetic(a.overflowing_mul(b), mode, overflow)?)
+        }
+        // Division by zero traps at runtime, it's left to the program.
+        (OPCode::Div, Literal::Int(_), Literal::Int(0)) => return Ok(None),
+        (OPCode::Div, Literal::Int(a), Literal::Int(b)) => {
+            Literal::Int(arithmetic(a.overflowing_div(b), mode, overflow)?)
+        }
+        // Shift amounts out of range have no meaning, they are left alone.
+        (OPCode::Shl, Literal::Int(a), Literal::Int(b @ 0..=31)) => {
+            Literal::Int(a << b)
+        }
+        (OPCode::Shr, Literal::Int(a), Literal::Int(b @ 0..=31)) => {
+            Literal::Int(a >> b)
+        }
+        (OPCode::BitAnd, Literal::Int(a), Literal::Int(b)) => {
+            Literal::Int(a & b)
+        }
+        (OPCode::And, Literal::Bool(a), Literal::Bool(b)) => {
+            Literal::Bool(a && b)
+        }
+        (OPCode::Or, Literal::Bool(a), Literal::Bool(b)) => {
+            Literal::Bool(a || b)
+        }
+        (OPCode::Eq, a, b) if a != Literal::Empty => Literal::Bool(a == b),
+        (OPCode::Neq, a, b) if a != Literal::Empty => Literal::Bool(a != b),
+        (OPCode::Lt, Literal::Int(a), Literal::Int(b)) => Literal::Bool(a < b),
+        (OPCode::Lte, Literal::Int(a), Literal::Int(b)) => {
+            Literal::Bool(a <= b)
+        }
+        (OPCode::Gt, Literal::Int(a), Literal::Int(b)) => Literal::Bool(a > b),
+        (OPCode::Gte, Literal::Int(a), Literal::Int(b)) => {
+            Literal::Bool(a >= b)
+        }
+        (OPCode::Lt, Literal::Char(a), Literal::Char(b)) => {
+            Literal::Bool(a < b)
+        }
+        (OPCode::Lte, Literal::Char(a), Literal::Char(b)) => {
+            Literal::Bool(a <= b)
+        }
+        (OPCode::Gt, Literal::Char(a), Literal::Char(b)) => {
+            Literal::Bool(a > b)
+        }
+        (OPCode::Gte, Literal::Char(a), Literal::Char(b)) => {
+            Literal::Bool(a >= b)
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(literal))
+}
+
+/// Evaluate the unary operation `opcode` on a constant operand, returns
+/// `None` if the operation can't be evaluated at compile time.
+///
+/// # Errors
+///
+/// Returns an error if the operation overflows in checked mode.
+pub fn unary(
+    opcode: OPCode,
+    operand: Literal,
+    mode: ArithmeticMode,
+) -> Result<Option<Literal>, OverflowError> {
+    let overflow = || OverflowError {
+        opcode,
+        operands: vec![operand],
+    };
+    let literal = match (opcode, operand) {
+        (OPCode::Neg, Literal::Int(a)) => {
+            Literal::Int(arithmetic(a.overflowing_neg(), mode, overflow)?)
+        }
+        (OPCode::Not, Literal::Bool(a)) => Literal::Bool(!a),
+        _ => return Ok(None),
+    };
+    Ok(Some(literal))
+}
+
+/// `Magic` is the multiply and shift sequence replacing a signed division by
+/// a constant (Granlund-Montgomery, as derived in Hacker's Delight 10-1).
+///
+/// `n / d` is computed as the high 32 bits of `multiplier * n`, corrected by
+/// adding (resp. subtracting) `n` when the multiplier and a positive (resp.
+/// negative) divisor have different signs, shifted right by `shift` and
+/// incremented if negative so the quotient rounds toward zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Magic {
+    divisor: i32,
+    multiplier: i32,
+    shift: u32,
+}
+
+impl Magic {
+    /// Returns the magic multiplier and shift for `divisor`, returns `None`
+    /// for divisors `-1`, `0` and `1` which don't need one.
+    #[must_use]
+    pub const fn new(divisor: i32) -> Option<Self> {
+        if divisor >= -1 && divisor <= 1 {
+            return None;
+        }
+        const TWO31: u32 = 1 << 31;
+        let ad = divisor.unsigned_abs();
+        let t = TWO31 + ((divisor as u32) >> 31);
+        // Absolute value of the largest dividend `nc` such that
+        // `nc % ad == ad - 1`.
+        let anc = t - 1 - t % ad;
+        let mut p = 31;
+        let (mut q1, mut r1) = (TWO31 / anc, TWO31 % anc);
+        let (mut q2, mut r2) = (TWO31 / ad, TWO31 % ad);
+        // Find the smallest `p` such that `2^p > anc * (ad - 2^p % ad)`, with
+        // `q1, r1` (resp. `q2, r2`) the quotient and remainder of `2^p / anc`
+        // (resp. `2^p / ad`).
+        loop {
+            p += 1;
+            q1 = q1.wrapping_mul(2);
+            r1 = r1.wrapping_mul(2);
+            if r1 >= anc {
+                q1 = q1.wrapping_add(1);
+                r1 = r1.wrapping_sub(anc);
+            }
+            q2 = q2.wrapping_mul(2);
+            r2 = r2.wrapping_mul(2);
+            if r2 >= ad {
+                q2 = q2.wrapping_add(1);
+                r2 = r2.wrapping_sub(ad);
+            }
+            let delta = ad - r2;
+            if !(q1 < delta || (q1 == delta && r1 == 0)) {
+                break;
+            }
+        }
+        let multiplier = q2.wrapping_add(1) as i32;
+        Some(Self {
+            divisor,
+            multiplier: if divisor < 0 {
+                multiplier.wrapping_neg()
+            } else {
+                multiplier
+            },
+            shift: p - 32,
+        })
+    }
+
+    /// Returns the divisor.
+    #[must_use]
+    pub const fn divisor(&self) -> i32 {
+        self.divisor
+    }
+
+    /// Returns the multiplier.
+    #[must_use]
+    pub const fn multiplier(&self) -> i32 {
+        self.multiplier
+    }
+
+    /// Returns the right shift applied after the multiplication.
+    #[must_use]
+    pub const fn shift(&self) -> u32 {
+        self.shift
+    }
+
+    /// Evaluate the sequence on `dividend`, returns `dividend / divisor`
+    /// rounded toward zero.
+    #[must_use]
+    pub const fn divide(&self, dividend: i32) -> i32 {
+        let high = (self.multiplier as i64 * dividend as i64) >> 32;
+        let mut quotient = high as i32;
+        if self.divisor > 0 && self.multiplier < 0 {
+            quotient = quotient.wrapping_add(dividend);
+        } else if self.divisor < 0 && self.multiplier > 0 {
+            quotient = quotient.wrapping_sub(dividend);
+        }
+        quotient >>= self.shift;
+        quotient.wrapping_add(((quotient as u32) >> 31) as i32)
+    }
+
+    /// Evaluate the sequence on `dividend`, returns `dividend % divisor`
+    /// with the sign of the dividend.
+    #[must_use]
+    pub const fn remainder(&self, dividend: i32) -> i32 {
+        dividend.wrapping_sub(self.divide(dividend).wrapping_mul(self.divisor))
+    }
+}
+
+/// Returns the wrapped result of an overflowing operation, or an error if
+/// it overflowed in checked or trapping mode.
+fn arithmetic(
+    (value, overflowed): (i32, bool),
+    mode: ArithmeticMode,
+    overflow: impl FnOnce() -> OverflowError,
+) -> Result<i32, OverflowError> {
+    match mode {
+        ArithmeticMode::Checked | ArithmeticMode::Trapping if overflowed => {
+            Err(overflow())
+        }
+        _ => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consteval::{binary, unary, ArithmeticMode, Magic};
+    use crate::ir::{Literal, OPCode};
+
+    // Macro to generate test cases.
+    macro_rules! test_binary_eval {
+        ($name:ident, $opcode:expr, $lhs:expr, $rhs:expr, $mode:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                let result = binary($opcode, $lhs, $rhs, $mode)
+                    .map_err(|err| err.to_string());
+                assert_eq!(result, $expected);
+            }
+        };
+    }
+
+    test_binary_eval!(
+        can_evaluate_addition,
+        OPCode::Add,
+        Literal::Int(40),
+        Literal::Int(2),
+        ArithmeticMode::Checked,
+        Ok(Some(Literal::Int(42)))
+    );
+    test_binary_eval!(
+        can_wrap_overflowing_multiplication,
+        OPCode::Mul,
+        Literal::Int(i32::MAX),
+        Literal::Int(2),
+        ArithmeticMode::Wrapping,
+        Ok(Some(Literal::Int(-2)))
+    );
+    test_binary_eval!(
+        can_report_overflowing_addition,
+        OPCode::Add,
+        Literal::Int(i32::MAX),
+        Literal::Int(1),
+        ArithmeticMode::Checked,
+        Err("signed overflow evaluating `add 2147483647 1`".to_string())
+    );
+    test_binary_eval!(
+        can_report_overflowing_division,
+        OPCode::Div,
+        Literal::Int(i32::MIN),
+        Literal::Int(-1),
+        ArithmeticMode::Checked,
+        Err("signed overflow evaluating `div -2147483648 -1`".to_string())
+    );
+    test_binary_eval!(
+        can_not_evaluate_division_by_zero,
+        OPCode::Div,
+        Literal::Int(1),
+        Literal::Int(0),
+        ArithmeticMode::Wrapping,
+        Ok(None)
+    );
+    test_binary_eval!(
+        can_evaluate_comparison,
+        OPCode::Lte,
+        Literal::Int(1),
+        Literal::Int(2),
+        ArithmeticMode::Wrapping,
+        Ok(Some(Literal::Bool(true)))
+    );
+
+    #[test]
+    fn can_evaluate_negation() {
+        let min = Literal::Int(i32::MIN);
+        assert_eq!(
+            unary(OPCode::Neg, min, ArithmeticMode::Wrapping),
+            Ok(Some(min))
+        );
+        assert!(unary(OPCode::Neg, min, ArithmeticMode::Checked).is_err());
+    }
+
+    #[test]
+    fn can_fold_shifts() {
+        let fold = |opcode, a, b| match binary(
+            opcode,
+            Literal::Int(a),
+            Literal::Int(b),
+            ArithmeticMode::Checked,
+        ) {
+            Ok(Some(Literal::Int(c))) => Some(c),
+            _ => None,
+        };
+        assert_eq!(fold(OPCode::Shl, 3, 4), Some(48));
+        assert_eq!(fold(OPCode::Shr, -7, 1), Some(-4));
+        assert_eq!(fold(OPCode::BitAnd, -1, 7), Some(7));
+        assert_eq!(fold(OPCode::Shl, 1, 32), None);
+        // The sequence `StrengthReduce` emits for divisions by `2^k`.
+        for dividend in (-1000..=1000).chain([i32::MIN, i32::MAX]) {
+            for k in 1..31 {
+                let bias = fold(
+                    OPCode::BitAnd,
+                    fold(OPCode::Shr, dividend, 31).unwrap(),
+                    (1 << k) - 1,
+                )
+                .unwrap();
+                let quotient =
+                    fold(OPCode::Shr, dividend.wrapping_add(bias), k);
+                assert_eq!(quotient, Some(dividend / (1 << k)));
+            }
+        }
+    }
+
+    #[test]
+    fn can_compute_magic_numbers() {
+        let magic = |d| Magic::new(d).map(|m| (m.multiplier(), m.shift()));
+        assert_eq!(magic(3), Some((0x5555_5556, 0)));
+        assert_eq!(magic(5), Some((0x6666_6667, 1)));
+        assert_eq!(magic(7), Some((0x9249_2493_u32 as i32, 2)));
+        assert_eq!(magic(-5), Some((0x9999_9999_u32 as i32, 1)));
+        assert_eq!(magic(1), None);
+        assert_eq!(magic(0), None);
+        assert_eq!(magic(-1), None);
+    }
+
+    #[test]
+    fn can_divide_by_magic_numbers() {
+        let divisors = [
+            2,
+            3,
+            5,
+            6,
+            7,
+            10,
+            12,
+            25,
+            125,
+            641,
+            7919,
+            -3,
+            -6,
+            -7,
+            -1000,
+            i32::MAX,
+            i32::MIN,
+            i32::MIN + 1,
+        ];
+        let dividends = (i32::MIN..=i32::MAX)
+            .step_by(65_521)
+            .chain(-1000..=1000)
+            .chain([i32::MIN, i32::MIN + 1, i32::MAX - 1, i32::MAX]);
+        for dividend in dividends {
+            for divisor in divisors {
+                let magic = Magic::new(divisor).unwrap();
+                assert_eq!(
+                    magic.divide(dividend),
+                    dividend.wrapping_div(divisor),
+                    "{dividend} / {divisor}"
+                );
+                assert_eq!(
+                    magic.remainder(dividend),
+                    dividend.wrapping_rem(divisor),
+                    "{dividend} % {divisor}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,559 @@
+//! Implicit conversions and integer promotions.
+//!
+//! The usual arithmetic conversions of C applied to the integer types of the
+//! language: `bool` and `char` operands of arithmetic operators are promoted
+//! to `int`, operands of comparisons with different types are converted to
+//! their common type, conditions and operands of logical operators are
+//! converted to `bool` and values are converted to the type of the variable,
+//! parameter or function they're assigned, passed or returned to.
+//!
+//! `insert_casts` runs before semantic analysis and makes every conversion an
+//! explicit `Expr::Cast` node, so the type checker only sees operands of the
+//! expected types and IR generation emits a `cast` instruction for each
+//! conversion; backends never widen or narrow values implicitly.
+use std::collections::HashMap;
+
+use crate::ast::{
+    BinaryOperator, Decl, DeclRef, DeclType, Expr, ExprRef, Ref, Stmt, StmtRef,
+    UnaryOperator, AST,
+};
+use crate::builtins::{
+    parse_format, AtomicOp, Builtin, Directive, EXIT, PRINTF,
+};
+use crate::ir::Literal;
+use crate::sema::{Definition, XRef};
+
+/// Returns the conversion rank of `t`, values convert without loss to types
+/// of higher rank.
+#[must_use]
+pub const fn rank(t: DeclType) -> u8 {
+    match t {
+        DeclType::Bool => 0,
+        DeclType::Char => 1,
+        DeclType::Int => 2,
+    }
+}
+
+/// Returns the type of `t` after integer promotion, `bool` and `char` are
+/// promoted to `int`.
+#[must_use]
+pub const fn promote(t: DeclType) -> DeclType {
+    match t {
+        DeclType::Bool | DeclType::Char | DeclType::Int => DeclType::Int,
+    }
+}
+
+/// Returns the common type of the operands of a binary operation after the
+/// usual arithmetic conversions.
+#[must_use]
+pub const fn usual_arithmetic(lhs: DeclType, rhs: DeclType) -> DeclType {
+    let (lhs, rhs) = (promote(lhs), promote(rhs));
+    if rank(lhs) >= rank(rhs) {
+        lhs
+    } else {
+        rhs
+    }
+}
+
+/// Returns `true` if a value of type `from` implicitly converts to `to`, all
+/// the integer types convert to each other.
+#[must_use]
+pub const fn is_implicit(from: DeclType, to: DeclType) -> bool {
+    matches!(
+        (from, to),
+        (
+            DeclType::Bool | DeclType::Char | DeclType::Int,
+            DeclType::Bool | DeclType::Char | DeclType::Int
+        )
+    )
+}
+
+/// Convert a constant to `to`, conversions to `bool` compare to zero and
+/// conversions to `char` keep the low 8 bits.
+#[must_use]
+pub fn convert(literal: Literal, to: DeclType) -> Literal {
+    let value = match literal {
+        Literal::Int(value) => value,
+        Literal::Char(value) => value as u32 as i32,
+        Literal::Bool(value) => i32::from(value),
+        Literal::Empty => return Literal::Empty,
+    };
+    match to {
+        DeclType::Int => Literal::Int(value),
+        DeclType::Char => Literal::Char(char::from(value as u8)),
+        DeclType::Bool => Literal::Bool(value != 0),
+    }
+}
+
+/// Make the implicit conversions of `ast` explicit, returns the number of
+/// inserted casts.
+///
+/// Names resolve with the cross-reference index, expressions whose type
+/// can't be resolved (undeclared names) are left to semantic analysis to
+/// report.
+pub fn insert_casts(ast: &mut AST) -> usize {
+    let mut converter = Converter::new(ast);
+    for index in 0..ast.declarations().len() {
+        let Some(decl) = ast.declarations().get(index).cloned() else {
+            continue;
+        };
+        match decl {
+            Decl::GlobalVariable {
+                decl_type,
+                name,
+                value,
+            } => {
+                let value = converter.convert(ast, value, decl_type);
+                ast.replace_decl(
+                    DeclRef::new(index),
+                    Decl::GlobalVariable {
+                        decl_type,
+                        name,
+                        value,
+                    },
+                );
+            }
+            Decl::Function {
+                return_type, body, ..
+            } => converter.stmt(ast, body, return_type),
+            Decl::Extern { .. } => (),
+        }
+    }
+    converter.casts
+}
+
+/// `Converter` walks the AST inserting casts.
+struct Converter {
+    // Type of every named expression.
+    names: HashMap<ExprRef, DeclType>,
+    // Parameter and return types of every function.
+    signatures: HashMap<String, (Vec<DeclType>, DeclType)>,
+    // Number of inserted casts.
+    casts: usize,
+}
+
+impl Converter {
+    fn new(ast: &AST) -> Self {
+        let arg_types = |args: &[StmtRef]| {
+            args.iter()
+                .filter_map(|arg| match ast.get_stmt(*arg) {
+                    Some(Stmt::Argument { decl_type, .. }) => Some(*decl_type),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let signatures = ast
+            .declarations()
+            .iter()
+            .filter_map(|decl| match decl {
+                Decl::Function {
+                    name,
+                    return_type,
+                    args,
+                    ..
+                }
+                | Decl::Extern {
+                    name,
+                    return_type,
+                    args,
+                } => Some((name.clone(), (arg_types(args), *return_type))),
+                Decl::GlobalVariable { .. } => None,
+            })
+            .collect();
+        let mut names = HashMap::new();
+        for entry in XRef::new(ast).entries() {
+            let t = match entry.definition() {
+                Definition::Global(decl_ref) => match ast.get_decl(decl_ref) {
+                    Some(
+                        Decl::GlobalVariable { decl_type: t, .. }
+                        | Decl::Function { return_type: t, .. }
+                        | Decl::Extern { return_type: t, .. },
+                    ) => *t,
+                    None => continue,
+                },
+                Definition::Local(stmt_ref) => match ast.get_stmt(stmt_ref) {
+                    Some(
+                        Stmt::LocalVariable { decl_type, .. }
+                        | Stmt::Argument { decl_type, .. },
+                    ) => *decl_type,
+                    _ => continue,
+                },
+            };
+            for use_ in entry.uses() {
+                names.insert(use_.expr, t);
+            }
+        }
+        Self {
+            names,
+            signatures,
+            casts: 0,
+        }
+    }
+
+    /// Insert the casts of the statement at `stmt_ref` in a function
+    /// returning `return_type`.
+    fn stmt(
+        &mut self,
+        ast: &mut AST,
+        stmt_ref: StmtRef,
+        return_type: DeclType,
+    ) {
+        let Some(stmt) = ast.get_stmt(stmt_ref).cloned() else {
+            return;
+        };
+        let stmt = match stmt {
+            Stmt::Return(value) => {
+                Stmt::Return(self.convert(ast, value, return_type))
+            }
+            Stmt::LocalVariable {
+                decl_type,
+                name,
+                value,
+            } => Stmt::LocalVariable {
+                decl_type,
+                name,
+                value: self.convert(ast, value, decl_type),
+            },
+            Stmt::Expr(expr) => Stmt::Expr(self.expr(ast, expr).0),
+            Stmt::Block(stmts) => {
+                for stmt_ref in &stmts {
+                    self.stmt(ast, *stmt_ref, return_type);
+                }
+                return;
+            }
+            Stmt::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                self.stmt(ast, then_block, return_type);
+                if let Some(else_block) = else_block {
+                    self.stmt(ast, else_block, return_type);
+                }
+                Stmt::If {
+                    condition: self.convert(ast, condition, DeclType::Bool),
+                    then_block,
+                    else_block,
+                }
+            }
+            Stmt::For {
+                init,
+                condition,
+                iteration,
+                body,
+            } => {
+                self.stmt(ast, body, return_type);
+                Stmt::For {
+                    init: init.map(|init| self.expr(ast, init).0),
+                    condition: condition.map(|condition| {
+                        self.convert(ast, condition, DeclType::Bool)
+                    }),
+                    iteration: iteration
+                        .map(|iteration| self.expr(ast, iteration).0),
+                    body,
+                }
+            }
+            Stmt::While { condition, body } => {
+                if let Some(body) = body {
+                    self.stmt(ast, body, return_type);
+                }
+                Stmt::While {
+                    condition: condition.map(|condition| {
+                        self.convert(ast, condition, DeclType::Bool)
+                    }),
+                    body,
+                }
+            }
+            Stmt::Switch { scrutinee, body } => {
+                self.stmt(ast, body, return_type);
+                Stmt::Switch {
+                    scrutinee: self.convert(ast, scrutinee, DeclType::Int),
+                    body,
+                }
+            }
+            Stmt::Argument { .. }
+            | Stmt::Case(_)
+            | Stmt::Default
+            | Stmt::Break
+            | Stmt::Goto(_)
+            | Stmt::Label(_)
+            | Stmt::Asm(_)
+            | Stmt::Empty => return,
+        };
+        ast.replace_stmt(stmt_ref, stmt);
+    }
+
+    /// Insert the casts of the expression at `expr_ref` and convert it to
+    /// `to`, returns the reference of the converted expression.
+    fn convert(
+        &mut self,
+        ast: &mut AST,
+        expr_ref: ExprRef,
+        to: DeclType,
+    ) -> ExprRef {
+        let (expr_ref, t) = self.expr(ast, expr_ref);
+        match t {
+            Some(t) => self.cast(ast, expr_ref, t, to),
+            None => expr_ref,
+        }
+    }
+
+    /// Wrap the expression at `expr_ref` of type `from` in a cast to `to`.
+    fn cast(
+        &mut self,
+        ast: &mut AST,
+        expr_ref: ExprRef,
+        from: DeclType,
+        to: DeclType,
+    ) -> ExprRef {
+        if from == to || !is_implicit(from, to) {
+            return expr_ref;
+        }
+        self.casts += 1;
+        let span = ast.expr_span(expr_ref);
+        let mut cast = ast.push_expr(Expr::Cast {
+            to,
+            operand: expr_ref,
+        });
+        if let Some(span) = span.filter(|span| !span.is_empty()) {
+            cast = ast.unshare(cast);
+            ast.set_expr_span(cast, span);
+        }
+        cast
+    }
+
+    /// Insert the casts of the operands of the expression at `expr_ref`,
+    /// returns the reference of the rewritten expression and its type.
+    fn expr(
+        &mut self,
+        ast: &mut AST,
+        expr_ref: ExprRef,
+    ) -> (ExprRef, Option<DeclType>) {
+        let Some(node) = ast.get_expr(expr_ref).cloned() else {
+            return (expr_ref, None);
+        };
+        let (expr, t) = match node {
+            Expr::Named(_) => {
+                return (expr_ref, self.names.get(&expr_ref).copied())
+            }
+            Expr::IntLiteral(_) => return (expr_ref, Some(DeclType::Int)),
+            Expr::BoolLiteral(_) => return (expr_ref, Some(DeclType::Bool)),
+            Expr::CharLiteral(_) => return (expr_ref, Some(DeclType::Char)),
+            Expr::StringLiteral(_) => return (expr_ref, None),
+            Expr::Cast { to, operand } => {
+                let (operand, _) = self.expr(ast, operand);
+                (Expr::Cast { to, operand }, Some(to))
+            }
+            Expr::Assignment { name, value } => {
+                let t = self.names.get(&name).copied();
+                let value = match t {
+                    Some(t) => self.convert(ast, value, t),
+                    None => self.expr(ast, value).0,
+                };
+                (Expr::Assignment { name, value }, t)
+            }
+            Expr::UnaryOp { operator, operand } => {
+                let to = match operator {
+                    UnaryOperator::Neg => DeclType::Int,
+                    UnaryOperator::Not => DeclType::Bool,
+                };
+                let operand = self.convert(ast, operand, to);
+                (Expr::UnaryOp { operator, operand }, Some(to))
+            }
+            Expr::BinOp {
+                left,
+                operator,
+                right,
+            } => {
+                let (left, lhs) = self.expr(ast, left);
+                let (right, rhs) = self.expr(ast, right);
+                let (operands, t) = match operator {
+                    BinaryOperator::Add
+                    | BinaryOperator::Sub
+                    | BinaryOperator::Mul
+                    | BinaryOperator::Div
+                    | BinaryOperator::Shl
+                    | BinaryOperator::Shr
+                    | BinaryOperator::BitAnd => {
+                        (Some(DeclType::Int), DeclType::Int)
+                    }
+                    BinaryOperator::And | BinaryOperator::Or => {
+                        (Some(DeclType::Bool), DeclType::Bool)
+                    }
+                    // Operands of the same type are compared as is.
+                    _ => match (lhs, rhs) {
+                        (Some(lhs), Some(rhs)) if lhs != rhs => {
+                            (Some(usual_arithmetic(lhs, rhs)), DeclType::Bool)
+                        }
+                        _ => (None, DeclType::Bool),
+                    },
+                };
+                let (left, right) = match (operands, lhs, rhs) {
+                    (Some(to), Some(lhs), Some(rhs)) => (
+                        self.cast(ast, left, lhs, to),
+                        self.cast(ast, right, rhs, to),
+                    ),
+                    _ => (left, right),
+                };
+                (
+                    Expr::BinOp {
+                        left,
+                        operator,
+                        right,
+                    },
+                    Some(t),
+                )
+            }
+            Expr::Call { name, args } => {
+                let callee = match ast.get_expr(name) {
+                    Some(Expr::Named(callee)) => callee.clone(),
+                    _ => return (expr_ref, None),
+                };
+                // Arguments of `printf` are converted to the types of
+                // their conversion in the format.
+                if callee == PRINTF && !self.signatures.contains_key(PRINTF) {
+                    let params = printf_params(ast, &args)
+                        .filter(|params| params.len() + 1 == args.len());
+                    let args = match params {
+                        Some(params) => args
+                            .iter()
+                            .zip(std::iter::once(None).chain(params))
+                            .map(|(arg, param)| match param {
+                                Some(param) => self.convert(ast, *arg, param),
+                                None => *arg,
+                            })
+                            .collect(),
+                        None => args,
+                    };
+                    (Expr::Call { name, args }, Some(DeclType::Int))
+                } else {
+                    let signature = match Builtin::from_name(&callee) {
+                        Some(builtin) => Some((
+                            builtin.params().to_vec(),
+                            builtin.return_type(),
+                        )),
+                        None if callee == EXIT
+                            && !self.signatures.contains_key(EXIT) =>
+                        {
+                            Some((vec![DeclType::Int], DeclType::Int))
+                        }
+                        None if !self.signatures.contains_key(&callee) => {
+                            AtomicOp::from_name(&callee).map(|op| {
+                                (vec![DeclType::Int; op.arity()], DeclType::Int)
+                            })
+                        }
+                        None => self.signatures.get(&callee).cloned(),
+                    };
+                    let args = match &signature {
+                        Some((params, _)) if params.len() == args.len() => args
+                            .iter()
+                            .zip(params)
+                            .map(|(arg, param)| self.convert(ast, *arg, *param))
+                            .collect(),
+                        _ => args
+                            .iter()
+                            .map(|arg| self.expr(ast, *arg).0)
+                            .collect(),
+                    };
+                    (Expr::Call { name, args }, signature.map(|(_, t)| t))
+                }
+            }
+        };
+        if ast.get_expr(expr_ref) == Some(&expr) {
+            return (expr_ref, t);
+        }
+        let span = ast.expr_span(expr_ref);
+        let mut rewritten = ast.push_expr(expr);
+        let grouped = ast.is_grouped(expr_ref);
+        let span = span.filter(|span| !span.is_empty());
+        if span.is_some() || grouped {
+            rewritten = ast.unshare(rewritten);
+        }
+        if let Some(span) = span {
+            ast.set_expr_span(rewritten, span);
+        }
+        if grouped {
+            ast.set_grouped(rewritten);
+        }
+        (rewritten, t)
+    }
+}
+
+/// Returns the types of the arguments following the format of a `printf`
+/// call, `None` for `%s` arguments. Returns `None` if the format isn't a
+/// valid string literal.
+fn printf_params(ast: &AST, args: &[ExprRef]) -> Option<Vec<Option<DeclType>>> {
+    let Some(Expr::StringLiteral(format)) = ast.get_expr(*args.first()?) else {
+        return None;
+    };
+    let directives = parse_format(format).ok()?;
+    Some(
+        directives
+            .iter()
+            .filter(|directive| directive.takes_arg())
+            .map(Directive::arg_type)
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::DeclType;
+    use crate::conversions::{
+        convert, insert_casts, is_implicit, promote, usual_arithmetic,
+    };
+    use crate::ir::{IRBuilder, Literal};
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::sema::analyze;
+
+    #[test]
+    fn can_apply_usual_arithmetic_conversions() {
+        assert_eq!(promote(DeclType::Char), DeclType::Int);
+        assert_eq!(promote(DeclType::Bool), DeclType::Int);
+        assert_eq!(
+            usual_arithmetic(DeclType::Char, DeclType::Bool),
+            DeclType::Int
+        );
+        assert!(is_implicit(DeclType::Int, DeclType::Char));
+        assert_eq!(
+            convert(Literal::Char('a'), DeclType::Int),
+            Literal::Int(97)
+        );
+        assert_eq!(
+            convert(Literal::Int(0x141), DeclType::Char),
+            Literal::Char('A')
+        );
+        assert_eq!(
+            convert(Literal::Int(2), DeclType::Bool),
+            Literal::Bool(true)
+        );
+        assert_eq!(
+            convert(Literal::Bool(true), DeclType::Int),
+            Literal::Int(1)
+        );
+    }
+
+    #[test]
+    fn can_insert_explicit_casts() {
+        let source = "extern char next(); int main() { char c = next(); int x = c + 1; bool b = c < x; if (x) { return b; } return c; }";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        assert_eq!(insert_casts(parser.ast_mut()), 5);
+        let ast = parser.ast().to_string();
+        assert!(ast.contains("Add(Cast(INT_TYPE, Named(c)), 1)"), "{ast}");
+        assert!(
+            ast.contains("Lesser(Cast(INT_TYPE, Named(c)), Named(x))"),
+            "{ast}"
+        );
+        assert!(ast.contains("Cast(BOOL_TYPE, Named(x))"), "{ast}");
+        assert!(ast.contains("Return(Cast(INT_TYPE, Named(b)))"), "{ast}");
+
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let ir = irgen.functions()[0].to_string();
+        assert!(ir.contains("%v1: int = cast c"), "{ir}");
+    }
+}
@@ -0,0 +1,110 @@
+//! Relative cost of IR instructions.
+//!
+//! Heuristics weighing code growth against a budget (loop unswitching today,
+//! inlining, unrolling and if-conversion once they exist) ask a `CostModel`
+//! for the cost of the instructions they duplicate instead of counting them.
+//! Costs are relative, a simple ALU operation costs 1 and instructions that
+//! emit no code cost 0.
+use crate::builtins::Builtin;
+use crate::ir::Instruction;
+use crate::target::{Arch, Target};
+
+/// `CostModel` trait returns the relative cost of IR instructions.
+pub trait CostModel: Send + Sync {
+    /// Returns the cost of `inst`.
+    fn cost(&self, inst: &Instruction) -> u32;
+
+    /// Returns the cost of a sequence of instructions.
+    fn cost_of(&self, insts: &[Instruction]) -> u32 {
+        insts.iter().map(|inst| self.cost(inst)).sum()
+    }
+}
+
+/// Cost model used when no target is known, costs approximate a generic
+/// 64-bit out of order core.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenericCostModel {}
+
+impl CostModel for GenericCostModel {
+    fn cost(&self, inst: &Instruction) -> u32 {
+        match inst {
+            Instruction::Label(..) | Instruction::Nop => 0,
+            Instruction::Mul(..) => 3,
+            Instruction::Div(..) => 20,
+            // Calls pay for the argument moves and the return.
+            Instruction::Call(_, _, args) => 5 + args.len() as u32,
+            // Atomics pay for the locked bus access or the LL/SC loop.
+            Instruction::Atomic(..) => 20,
+            Instruction::Intrinsic(_, builtin, _) => match builtin {
+                // Assumptions only carry facts for the optimizer.
+                Builtin::Assume => 0,
+                Builtin::Expect => 1,
+                _ => 2,
+            },
+            _ => 1,
+        }
+    }
+}
+
+/// Cost model of a target architecture, instructions not listed cost the
+/// same as in `GenericCostModel`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TargetCostModel {
+    arch: Arch,
+}
+
+impl TargetCostModel {
+    /// Create the cost model of `target`.
+    #[must_use]
+    pub const fn new(target: &Target) -> Self {
+        Self {
+            arch: target.arch(),
+        }
+    }
+}
+
+impl CostModel for TargetCostModel {
+    fn cost(&self, inst: &Instruction) -> u32 {
+        match (self.arch, inst) {
+            // 32-bit signed division latencies.
+            (Arch::X86_64, Instruction::Div(..)) => 26,
+            (Arch::AArch64, Instruction::Div(..)) => 12,
+            (Arch::RiscV64, Instruction::Div(..)) => 20,
+            (Arch::AArch64 | Arch::RiscV64, Instruction::Mul(..)) => 4,
+            _ => GenericCostModel {}.cost(inst),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cost::{CostModel, GenericCostModel, TargetCostModel};
+    use crate::ir::{Instruction, Label, Literal, Symbol, Type, Value};
+    use crate::target::Target;
+
+    #[test]
+    fn can_estimate_instruction_costs() {
+        let x = Value::StorageLocation(Symbol::new("x", Type::Int));
+        let dst = Symbol::new("%v0", Type::Int);
+        let insts = [
+            Instruction::Label(0),
+            Instruction::Const(
+                dst.clone(),
+                Value::ConstantLiteral(Literal::Int(3)),
+            ),
+            Instruction::Add(dst.clone(), x.clone(), x.clone()),
+            Instruction::Div(dst, x.clone(), x),
+            Instruction::Jump(Label::new(0)),
+        ];
+        let generic = GenericCostModel {};
+        assert_eq!(generic.cost_of(&insts), 23);
+
+        let x86 =
+            TargetCostModel::new(&Target::from_triple("x86_64-linux").unwrap());
+        let arm = TargetCostModel::new(
+            &Target::from_triple("aarch64-linux").unwrap(),
+        );
+        assert_eq!(x86.cost(&insts[2]), generic.cost(&insts[2]));
+        assert!(arm.cost(&insts[3]) < x86.cost(&insts[3]));
+    }
+}
@@ -0,0 +1,161 @@
+//! Coverage instrumentation metadata and lcov report generation.
+//!
+//! When coverage is enabled the IR builder starts every basic block with a
+//! `count` instruction incrementing an entry of a module level counter array,
+//! `Coverage` records which counter each function and statement executes
+//! under so counts collected at runtime can be mapped back to source lines.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::token::Span;
+
+/// Symbol name of the module level counter array backends allocate.
+pub const COUNTERS_SYMBOL: &str = "__glouton_coverage";
+
+/// `Coverage` maps the counters inserted by the IR builder to source code.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    // Number of counters in the counter array.
+    counters: usize,
+    // Name, source location and entry block counter of each function.
+    functions: Vec<(String, Span, usize)>,
+    // Source location of each statement and the counter of its block.
+    statements: Vec<(Span, usize)>,
+}
+
+impl Coverage {
+    /// Returns the number of counters.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.counters
+    }
+
+    /// Returns `true` if no counters were allocated.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.counters == 0
+    }
+
+    /// Allocate a new counter.
+    pub(crate) fn next_counter(&mut self) -> usize {
+        self.counters += 1;
+        self.counters - 1
+    }
+
+    /// Allocate the entry block counter of a function.
+    pub(crate) fn enter_function(&mut self, name: &str, span: Span) -> usize {
+        let counter = self.next_counter();
+        self.functions.push((name.to_string(), span, counter));
+        counter
+    }
+
+    /// Record that the statement at `span` executes under `counter`.
+    pub(crate) fn record(&mut self, span: Span, counter: usize) {
+        self.statements.push((span, counter))
+    }
+
+    /// Build an lcov tracefile for `source` from the counter values collected
+    /// at runtime, counters missing from `counts` are considered unexecuted.
+    #[must_use]
+    pub fn lcov(&self, file: &str, source: &str, counts: &[u64]) -> String {
+        let line_starts = line_starts(source);
+        let line = |span: &Span| {
+            line_starts.partition_point(|start| *start <= span.start)
+        };
+        let count = |counter: usize| counts.get(counter).copied().unwrap_or(0);
+
+        let mut report = String::new();
+        let _ = writeln!(report, "TN:");
+        let _ = writeln!(report, "SF:{file}");
+        for (name, span, counter) in &self.functions {
+            let _ = writeln!(report, "FN:{},{name}", line(span));
+            let _ = writeln!(report, "FNDA:{},{name}", count(*counter));
+        }
+        let hit = self
+            .functions
+            .iter()
+            .filter(|(_, _, counter)| count(*counter) > 0)
+            .count();
+        let _ = writeln!(report, "FNF:{}", self.functions.len());
+        let _ = writeln!(report, "FNH:{hit}");
+        // Statements sharing a line report the highest count.
+        let mut lines = BTreeMap::new();
+        for (span, counter) in &self.statements {
+            let entry = lines.entry(line(span)).or_insert(0);
+            *entry = count(*counter).max(*entry);
+        }
+        for (line, count) in &lines {
+            let _ = writeln!(report, "DA:{line},{count}");
+        }
+        let _ = writeln!(report, "LF:{}", lines.len());
+        let _ = writeln!(
+            report,
+            "LH:{}",
+            lines.values().filter(|count| **count > 0).count()
+        );
+        let _ = writeln!(report, "end_of_record");
+        report
+    }
+}
+
+/// Returns the character offsets at which each line of `source` starts.
+fn line_starts(source: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(
+            source
+                .chars()
+                .enumerate()
+                .filter(|(_, ch)| *ch == '\n')
+                .map(|(offset, _)| offset + 1),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::IRBuilder;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::sema::analyze;
+
+    #[test]
+    fn can_generate_lcov_report() {
+        let source = r#"int main() {
+    int a = 1;
+    if (a > 2) {
+        a = 2;
+    }
+    return a;
+}
+"#;
+        let tokens = Scanner::new(source).scan_with_spans().unwrap();
+        let mut parser = Parser::with_spans(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen =
+            IRBuilder::new(parser.ast(), &symbol_table).with_coverage();
+        irgen.build();
+
+        let coverage = irgen.coverage().expect("expected coverage metadata");
+        assert_eq!(coverage.len(), 4);
+        // Entry block, then block, else block and the join block.
+        let report = coverage.lcov("main.c", source, &[1, 0, 1, 1]);
+        assert_eq!(
+            report,
+            r#"TN:
+SF:main.c
+FN:1,main
+FNDA:1,main
+FNF:1
+FNH:1
+DA:2,1
+DA:3,1
+DA:4,0
+DA:6,1
+LF:4
+LH:3
+end_of_record
+"#
+        );
+    }
+}
@@ -0,0 +1,566 @@
+//! Dataflow analysis framework.
+//!
+//! An analysis computes a fact at every program point of a function by
+//! propagating facts along the edges of its control flow graph, forwards
+//! from the entry or backwards from the exits. Where control flow joins the
+//! facts of the incoming edges are merged by the meet operator, a worklist
+//! solver revisits blocks until their facts stop changing.
+//!
+//! Analyses implement the `Analysis` trait and are solved on a `cfg::Graph`
+//! built from a single function, its entry being the first block. The
+//! builtin analyses are :
+//!
+//! * `Liveness` : variables read before being assigned on some path from a
+//!   point, backwards, union.
+//! * `ReachingDefinitions` : assignments not overwritten on some path to a
+//!   point, forwards, union.
+//! * `AvailableExpressions` : expressions computed on every path to a point
+//!   with their operands unchanged since, forwards, intersection.
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::cfg::Graph;
+use crate::ir::{BlockRef, Instruction, OPCode, Symbol, Value};
+
+/// Direction facts are propagated in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    // From the entry to the exits, along the edges.
+    Forward,
+    // From the exits to the entry, against the edges.
+    Backward,
+}
+
+/// Position of an instruction in a graph, the block holding it and its
+/// index in the block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Location {
+    pub block: BlockRef,
+    pub index: usize,
+}
+
+/// `Analysis` describes a dataflow problem solved by `solve`.
+pub trait Analysis {
+    /// Facts computed at every program point.
+    type Fact: Clone + PartialEq;
+
+    /// Returns the direction the analysis propagates facts in.
+    fn direction(&self) -> Direction;
+
+    /// Returns the fact holding at the function entry for forward analyses,
+    /// at the function exits for backward ones.
+    fn boundary(&self) -> Self::Fact;
+
+    /// Returns the initial fact of every other block, the identity of
+    /// `meet`.
+    fn top(&self) -> Self::Fact;
+
+    /// Merge `other` into `fact` where control flow joins.
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact);
+
+    /// Update `fact` across the instruction `inst` at `location`, from the
+    /// point before it to the point after it for forward analyses and the
+    /// other way around for backward ones.
+    fn transfer(
+        &self,
+        location: Location,
+        inst: &Instruction,
+        fact: &mut Self::Fact,
+    );
+}
+
+/// Facts holding at the entry and at the exit of every block, keyed by their
+/// `BlockRef`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Solution<F> {
+    entries: Vec<F>,
+    exits: Vec<F>,
+}
+
+impl<F: Clone + PartialEq> Solution<F> {
+    /// Returns the fact holding before the first instruction of `block`.
+    #[must_use]
+    pub fn entry(&self, block: BlockRef) -> &F {
+        &self.entries[block.0]
+    }
+
+    /// Returns the fact holding after the last instruction of `block`.
+    #[must_use]
+    pub fn exit(&self, block: BlockRef) -> &F {
+        &self.exits[block.0]
+    }
+
+    /// Returns the fact holding after every instruction of `block`, in
+    /// order, by replaying the transfer function of `analysis` from the
+    /// block boundary.
+    pub fn after_each<A: Analysis<Fact = F>>(
+        &self,
+        analysis: &A,
+        graph: &Graph,
+        block: BlockRef,
+    ) -> Vec<F> {
+        let insts = graph.block(block).instructions();
+        let location = |index| Location { block, index };
+        match analysis.direction() {
+            Direction::Forward => {
+                let mut fact = self.entry(block).clone();
+                insts
+                    .iter()
+                    .enumerate()
+                    .map(|(index, inst)| {
+                        analysis.transfer(location(index), inst, &mut fact);
+                        fact.clone()
+                    })
+                    .collect()
+            }
+            Direction::Backward => {
+                let mut fact = self.exit(block).clone();
+                let mut facts = insts
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(index, inst)| {
+                        let after = fact.clone();
+                        analysis.transfer(location(index), inst, &mut fact);
+                        after
+                    })
+                    .collect::<Vec<_>>();
+                facts.reverse();
+                facts
+            }
+        }
+    }
+}
+
+/// Solve `analysis` on `graph` with a worklist, blocks are visited in
+/// reverse post-order for forward analyses and in post-order for backward
+/// ones so most facts are final after a single pass. Blocks unreachable
+/// from the entry keep the `top` fact.
+pub fn solve<A: Analysis>(analysis: &A, graph: &Graph) -> Solution<A::Fact> {
+    let direction = analysis.direction();
+    let mut entries = vec![analysis.top(); graph.len()];
+    let mut exits = vec![analysis.top(); graph.len()];
+    let mut worklist = match direction {
+        Direction::Forward => graph.rpo().collect::<VecDeque<_>>(),
+        Direction::Backward => graph.po().collect::<VecDeque<_>>(),
+    };
+    let mut queued = vec![false; graph.len()];
+    for block in &worklist {
+        queued[block.0] = true;
+    }
+
+    while let Some(block) = worklist.pop_front() {
+        queued[block.0] = false;
+        let insts = graph.block(block).instructions();
+        let location = |index| Location { block, index };
+        let changed = match direction {
+            Direction::Forward => {
+                let mut fact = if block.0 == 0 {
+                    analysis.boundary()
+                } else {
+                    analysis.top()
+                };
+                for predecessor in graph.predecessors(block) {
+                    analysis.meet(&mut fact, &exits[predecessor.0]);
+                }
+                entries[block.0] = fact.clone();
+                for (index, inst) in insts.iter().enumerate() {
+                    analysis.transfer(location(index), inst, &mut fact);
+                }
+                let changed = exits[block.0] != fact;
+                exits[block.0] = fact;
+                changed
+            }
+            Direction::Backward => {
+                let mut fact = analysis.top();
+                let mut successors = graph.successors(block).peekable();
+                if successors.peek().is_none() {
+                    fact = analysis.boundary();
+                }
+                for successor in successors {
+                    analysis.meet(&mut fact, &entries[successor.0]);
+                }
+                exits[block.0] = fact.clone();
+                for (index, inst) in insts.iter().enumerate().rev() {
+                    analysis.transfer(location(index), inst, &mut fact);
+                }
+                let changed = entries[block.0] != fact;
+                entries[block.0] = fact;
+                changed
+            }
+        };
+        if !changed {
+            continue;
+        }
+        let dependents = match direction {
+            Direction::Forward => graph.successors(block).collect::<Vec<_>>(),
+            Direction::Backward => {
+                graph.predecessors(block).collect::<Vec<_>>()
+            }
+        };
+        for dependent in dependents {
+            if !queued[dependent.0] && graph.rpo_number(dependent).is_some() {
+                queued[dependent.0] = true;
+                worklist.push_back(dependent);
+            }
+        }
+    }
+    Solution { entries, exits }
+}
+
+/// Returns the variables `inst` reads.
+fn reads(inst: &Instruction) -> impl Iterator<Item = &Symbol> {
+    inst.uses().filter_map(|value| match value {
+        Value::StorageLocation(symbol) => Some(symbol),
+        Value::ConstantLiteral(..) => None,
+    })
+}
+
+/// Returns `true` if `inst` may read or write variables besides its
+/// operands and destination : calls read and write globals, inline assembly
+/// any variable.
+fn clobbers(inst: &Instruction) -> bool {
+    matches!(
+        inst,
+        Instruction::Call(..) | Instruction::Asm(..) | Instruction::Atomic(..)
+    ) || inst.barrier()
+}
+
+/// Liveness analysis computes the variables live at every point : read
+/// on some path from the point before being assigned.
+///
+/// The function doesn't know which variables are globals, the `escaping`
+/// variables are assumed to be read at the function exits and by every
+/// call, atomic and inline assembly block.
+#[derive(Debug, Clone, Default)]
+pub struct Liveness {
+    escaping: HashSet<Symbol>,
+}
+
+impl Liveness {
+    /// Create a liveness analysis where `escaping` variables are read by the
+    /// callers and callees of the function.
+    #[must_use]
+    pub const fn new(escaping: HashSet<Symbol>) -> Self {
+        Self { escaping }
+    }
+}
+
+impl Analysis for Liveness {
+    type Fact = HashSet<Symbol>;
+
+    fn direction(&self) -> Direction {
+        Direction::Backward
+    }
+
+    fn boundary(&self) -> Self::Fact {
+        self.escaping.clone()
+    }
+
+    fn top(&self) -> Self::Fact {
+        HashSet::new()
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.extend(other.iter().cloned());
+    }
+
+    fn transfer(&self, _: Location, inst: &Instruction, fact: &mut Self::Fact) {
+        if let Some(dst) = inst.destination() {
+            fact.remove(dst);
+        }
+        fact.extend(reads(inst).cloned());
+        if clobbers(inst) {
+            fact.extend(self.escaping.iter().cloned());
+        }
+    }
+}
+
+/// Reaching definitions analysis computes the assignments reaching every
+/// point : executed on some path to the point and not overwritten since.
+/// Definitions are identified by their `Location`.
+#[derive(Debug, Clone, Default)]
+pub struct ReachingDefinitions {
+    // Locations assigning each variable.
+    definitions: HashMap<Symbol, HashSet<Location>>,
+}
+
+impl ReachingDefinitions {
+    /// Create a reaching definitions analysis of the function in `graph`.
+    #[must_use]
+    pub fn new(graph: &Graph) -> Self {
+        let mut definitions: HashMap<Symbol, HashSet<Location>> =
+            HashMap::new();
+        for block in graph.blocks() {
+            for (index, inst) in
+                graph.block(block).instructions().iter().enumerate()
+            {
+                if let Some(dst) = inst.destination() {
+                    definitions
+                        .entry(dst.clone())
+                        .or_default()
+                        .insert(Location { block, index });
+                }
+            }
+        }
+        Self { definitions }
+    }
+}
+
+impl Analysis for ReachingDefinitions {
+    type Fact = HashSet<Location>;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    fn boundary(&self) -> Self::Fact {
+        HashSet::new()
+    }
+
+    fn top(&self) -> Self::Fact {
+        HashSet::new()
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.extend(other.iter().copied());
+    }
+
+    fn transfer(
+        &self,
+        location: Location,
+        inst: &Instruction,
+        fact: &mut Self::Fact,
+    ) {
+        if let Some(dst) = inst.destination() {
+            if let Some(killed) = self.definitions.get(dst) {
+                fact.retain(|definition| !killed.contains(definition));
+            }
+            fact.insert(location);
+        }
+    }
+}
+
+/// Expression computed by an instruction, its opcode, type and operands.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Expression {
+    pub opcode: OPCode,
+    pub operands: Vec<Value>,
+}
+
+impl Expression {
+    /// Returns the expression `inst` computes, `None` for instructions that
+    /// aren't pure operations on their operands.
+    #[must_use]
+    pub fn of(inst: &Instruction) -> Option<Self> {
+        match inst {
+            Instruction::Add(..)
+            | Instruction::Sub(..)
+            | Instruction::Mul(..)
+            | Instruction::Div(..)
+            | Instruction::Shl(..)
+            | Instruction::Shr(..)
+            | Instruction::BitAnd(..)
+            | Instruction::And(..)
+            | Instruction::Or(..)
+            | Instruction::Not(..)
+            | Instruction::Neg(..)
+            | Instruction::Cast(..)
+            | Instruction::Eq(..)
+            | Instruction::Neq(..)
+            | Instruction::Lt(..)
+            | Instruction::Lte(..)
+            | Instruction::Gt(..)
+            | Instruction::Gte(..) => Some(Self {
+                opcode: inst.opcode(),
+                operands: inst.uses().cloned().collect(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the expression reads `symbol`.
+    fn reads(&self, symbol: &Symbol) -> bool {
+        self.operands.iter().any(|operand| {
+            matches!(operand, Value::StorageLocation(read) if read == symbol)
+        })
+    }
+}
+
+/// Available expressions analysis computes the expressions available at
+/// every point : computed on every path to the point and none of their
+/// operands assigned since.
+///
+/// Calls, atomics and inline assembly may assign any named variable so only
+/// the expressions of temporaries stay available across them.
+#[derive(Debug, Clone, Default)]
+pub struct AvailableExpressions {
+    // Every expression computed in the function.
+    expressions: HashSet<Expression>,
+}
+
+impl AvailableExpressions {
+    /// Create an available expressions analysis of the function in `graph`.
+    #[must_use]
+    pub fn new(graph: &Graph) -> Self {
+        let expressions = graph
+            .blocks()
+            .flat_map(|block| graph.block(block).instructions())
+            .filter_map(Expression::of)
+            .collect();
+        Self { expressions }
+    }
+}
+
+impl Analysis for AvailableExpressions {
+    type Fact = HashSet<Expression>;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    fn boundary(&self) -> Self::Fact {
+        HashSet::new()
+    }
+
+    fn top(&self) -> Self::Fact {
+        self.expressions.clone()
+    }
+
+    fn meet(&self, fact: &mut Self::Fact, other: &Self::Fact) {
+        fact.retain(|expression| other.contains(expression));
+    }
+
+    fn transfer(&self, _: Location, inst: &Instruction, fact: &mut Self::Fact) {
+        if clobbers(inst) {
+            fact.retain(|expression| {
+                expression.operands.iter().all(|operand| match operand {
+                    Value::StorageLocation(symbol) => {
+                        symbol.name().starts_with('%')
+                    }
+                    Value::ConstantLiteral(..) => true,
+                })
+            });
+        }
+        if let Instruction::Atomic(_, _, Value::StorageLocation(variable), ..) =
+            inst
+        {
+            fact.retain(|expression| !expression.reads(variable));
+        }
+        let Some(dst) = inst.destination() else {
+            return;
+        };
+        fact.retain(|expression| !expression.reads(dst));
+        if let Some(expression) = Expression::of(inst) {
+            if !expression.reads(dst) {
+                fact.insert(expression);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::cfg::Graph;
+    use crate::dataflow::{
+        self, AvailableExpressions, Expression, Liveness, Location,
+        ReachingDefinitions,
+    };
+    use crate::ir::{BlockRef, IRBuilder, Instruction, OPCode, Symbol, Type};
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::sema::analyze;
+
+    // Blocks : the entry, `a = 3`, `b = a + b` then the join returning
+    // `a + b`.
+    const SOURCE: &str = r"
+        int main() {
+            int a = 1;
+            int b = 2;
+            int c = a + b;
+            if (c > 2) {
+                a = 3;
+            } else {
+                b = a + b;
+            }
+            return a + b;
+        }
+    ";
+
+    fn graph() -> Graph {
+        let tokens = Scanner::new(SOURCE).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        Graph::new(&irgen.functions().to_vec())
+    }
+
+    fn variables(names: &[&str]) -> HashSet<Symbol> {
+        names
+            .iter()
+            .map(|name| Symbol::new(name, Type::Int))
+            .collect()
+    }
+
+    #[test]
+    fn can_compute_live_variables() {
+        let graph = graph();
+        let liveness = Liveness::default();
+        let solution = dataflow::solve(&liveness, &graph);
+        assert!(solution.entry(BlockRef(0)).is_empty());
+        assert_eq!(solution.exit(BlockRef(0)), &variables(&["a", "b"]));
+        // `a` is assigned before being read.
+        assert_eq!(solution.entry(BlockRef(1)), &variables(&["b"]));
+        assert_eq!(solution.entry(BlockRef(3)), &variables(&["a", "b"]));
+
+        // `c` is dead once compared.
+        let after = solution.after_each(&liveness, &graph, BlockRef(0));
+        assert!(after[5].contains(&Symbol::new("c", Type::Int)));
+        assert!(!after[8].contains(&Symbol::new("c", Type::Int)));
+    }
+
+    #[test]
+    fn can_compute_reaching_definitions() {
+        let graph = graph();
+        let solution =
+            dataflow::solve(&ReachingDefinitions::new(&graph), &graph);
+        let location = |block, index| Location {
+            block: BlockRef(block),
+            index,
+        };
+        let join = solution.entry(BlockRef(3));
+        // Both assignments of `a` and `b` reach the join.
+        for definition in [
+            location(0, 1),
+            location(1, 2),
+            location(0, 3),
+            location(2, 2),
+        ] {
+            assert!(join.contains(&definition), "{definition:?}");
+        }
+        // `a = 3` overwrites the initializer of `a` in its block.
+        assert!(!solution.exit(BlockRef(1)).contains(&location(0, 1)));
+    }
+
+    #[test]
+    fn can_compute_available_expressions() {
+        let graph = graph();
+        let solution =
+            dataflow::solve(&AvailableExpressions::new(&graph), &graph);
+        let comparison = graph.block(BlockRef(0)).instructions()[7].clone();
+        let sum = graph.block(BlockRef(0)).instructions()[4].clone();
+        assert!(matches!(comparison, Instruction::Gt(..)));
+        assert_eq!(Expression::of(&sum).unwrap().opcode, OPCode::Add);
+
+        let join = solution.entry(BlockRef(3));
+        assert!(join.contains(&Expression::of(&comparison).unwrap()));
+        // Both branches assign an operand of `a + b`.
+        assert!(!join.contains(&Expression::of(&sum).unwrap()));
+        assert!(solution
+            .exit(BlockRef(0))
+            .contains(&Expression::of(&sum).unwrap()));
+    }
+}
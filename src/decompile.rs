@@ -0,0 +1,1258 @@
+//! Best effort decompiler from IR back to C.
+//!
+//! The decompiler rebuilds an AST from the IR of a program, after the
+//! optimizer ran, so transformations can be reviewed by reading C instead
+//! of IR. Control flow is structured by pattern matching single entry
+//! regions of the CFG :
+//!
+//! * A conditional branch whose targets meet again at a join block becomes
+//!   an `if` statement, with an `else` block when both targets hold code.
+//! * A natural loop, a header targeted by back edges, becomes a
+//!   `while (true)` loop whose exit edges are `break` statements. A loop
+//!   starting with its exit test is printed as `while (condition)`.
+//!
+//! Functions the patterns don't cover (irreducible control flow, edges that
+//! would need a `continue`) fall back to a `goto` per edge.
+//!
+//! Temporaries used once in the block defining them are folded into the
+//! expression using them, other temporaries become `_vN` variables. Calls
+//! are never folded, C leaves the evaluation order of operands unspecified
+//! so a call next to a read of a global it assigns could run first. Every
+//! variable is declared at the top of its function.
+//!
+//! Not implemented: imported functions and `printf` are called without an
+//! `extern` declaration or an `#include <stdio.h>`.
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{
+    BinaryOperator, Decl, DeclType, Expr, ExprRef, Stmt, StmtRef,
+    UnaryOperator, AST,
+};
+use crate::builtins::{escape_char, Builtin};
+use crate::cfg::{EdgeKind, Graph};
+use crate::ir::{
+    BlockRef, Function, Instruction, Literal, Program, Symbol, Type, Value,
+};
+
+/// Returns the AST of `program`, globals first then functions.
+#[must_use]
+pub fn decompile(program: &Program) -> AST {
+    let mut ast = AST::new();
+    let mut globals = HashSet::new();
+    for global in program.globals() {
+        let symbol = global.symbol();
+        let name = Decompiler::name(symbol);
+        globals.insert(name.clone());
+        let value = ast.push_expr(literal(global.value()));
+        ast.push_decl(Decl::GlobalVariable {
+            decl_type: decl_type(symbol.t()),
+            name,
+            value,
+        });
+    }
+    for function in program.functions() {
+        let decl = Decompiler::new(function, &globals, &mut ast).function();
+        ast.push_decl(decl);
+    }
+    ast
+}
+
+/// Returns the C source code of `ast`.
+#[must_use]
+pub fn print(ast: &AST) -> String {
+    let mut printer = Printer {
+        ast,
+        output: String::new(),
+        depth: 0,
+    };
+    for decl in ast.declarations() {
+        printer.decl(decl);
+    }
+    printer.output
+}
+
+/// Returns the declared type of values of type `t`.
+const fn decl_type(t: Type) -> DeclType {
+    match t {
+        Type::Unit | Type::Int => DeclType::Int,
+        Type::Bool => DeclType::Bool,
+        Type::Char => DeclType::Char,
+    }
+}
+
+/// Returns the expression of the literal `literal`.
+const fn literal(literal: Literal) -> Expr {
+    match literal {
+        Literal::Empty => Expr::IntLiteral(0),
+        Literal::Int(value) => Expr::IntLiteral(value),
+        Literal::Bool(value) => Expr::BoolLiteral(value),
+        Literal::Char(value) => Expr::CharLiteral(value),
+    }
+}
+
+/// Returns `true` if `symbol` is a temporary introduced by the IR builder.
+fn is_temporary(symbol: &Symbol) -> bool {
+    symbol.name().starts_with('%')
+}
+
+/// Loop being structured.
+#[derive(Debug, Clone, Copy)]
+struct Loop {
+    // Block targeted by the back edges.
+    header: BlockRef,
+    // Block control reaches when leaving the loop, edges to it are breaks.
+    exit: Option<BlockRef>,
+}
+
+/// `Decompiler` rebuilds the declaration of a single function.
+struct Decompiler<'a> {
+    function: &'a Function,
+    graph: Graph,
+    ast: &'a mut AST,
+    // Variables that are not declared by the function.
+    outside: HashSet<String>,
+    // Number of uses of every temporary and the block of its last use.
+    uses: HashMap<Symbol, (usize, BlockRef)>,
+    // Temporaries holding a literal, inlined at every use.
+    constants: HashMap<Symbol, ExprRef>,
+    // Expressions of temporaries waiting for their single use, along with
+    // the variables they read.
+    pending: Vec<(Symbol, ExprRef, HashSet<String>)>,
+    // Variables declared at the top of the function, in order.
+    locals: Vec<(String, Type)>,
+    // Blocks already structured.
+    visited: HashSet<BlockRef>,
+}
+
+impl<'a> Decompiler<'a> {
+    fn new(
+        function: &'a Function,
+        globals: &HashSet<String>,
+        ast: &'a mut AST,
+    ) -> Self {
+        let graph = Graph::new(&vec![function.clone()]);
+        let mut uses = HashMap::new();
+        for block in graph.blocks() {
+            for inst in graph.block(block).instructions() {
+                for value in inst.uses() {
+                    if let Value::StorageLocation(symbol) = value {
+                        let entry =
+                            uses.entry(symbol.clone()).or_insert((0, block));
+                        *entry = (entry.0 + 1, block);
+                    }
+                }
+            }
+        }
+        let outside = globals
+            .iter()
+            .cloned()
+            .chain(function.args().iter().map(Self::name))
+            .chain(function.statics().iter().map(Self::name))
+            .collect();
+        Self {
+            function,
+            graph,
+            ast,
+            outside,
+            uses,
+            constants: HashMap::new(),
+            pending: vec![],
+            locals: vec![],
+            visited: HashSet::new(),
+        }
+    }
+
+    /// Returns the function declaration, structured when possible.
+    fn function(mut self) -> Decl {
+        let entry = self.graph.blocks().next();
+        let mut stmts = vec![];
+        if self.structure(entry, None, None, &mut stmts).is_none() {
+            self.pending.clear();
+            self.constants.clear();
+            stmts = self.unstructured();
+        }
+        let mut body = self
+            .locals
+            .iter()
+            .map(|(name, t)| {
+                let value = self.ast.push_expr(decl_type(*t).default_value());
+                let local = self.ast.push_stmt(Stmt::LocalVariable {
+                    decl_type: decl_type(*t),
+                    name: name.clone(),
+                    value,
+                });
+                self.ast.set_default_initialized(local);
+                local
+            })
+            .collect::<Vec<_>>();
+        body.extend(stmts);
+        let args = self
+            .function
+            .args()
+            .iter()
+            .map(|arg| {
+                self.ast.push_stmt(Stmt::Argument {
+                    decl_type: decl_type(arg.t()),
+                    name: arg.name().to_string(),
+                })
+            })
+            .collect();
+        Decl::Function {
+            name: self.function.name().to_string(),
+            return_type: decl_type(self.function.return_type()),
+            args,
+            body: self.ast.push_stmt(Stmt::Block(body)),
+            linkage: self.function.linkage(),
+            attributes: self.function.attributes().to_vec(),
+        }
+    }
+
+    /// Structure the blocks from `block` until reaching `follow`, appending
+    /// their statements to `out`. Returns `None` if the region isn't
+    /// structured.
+    fn structure(
+        &mut self,
+        mut block: Option<BlockRef>,
+        follow: Option<BlockRef>,
+        current: Option<Loop>,
+        out: &mut Vec<StmtRef>,
+    ) -> Option<()> {
+        while let Some(b) = block {
+            if Some(b) == follow {
+                return Some(());
+            }
+            if let Some(current) = current {
+                if Some(b) == current.exit {
+                    out.push(self.ast.push_stmt(Stmt::Break));
+                    return Some(());
+                }
+                // Jumping back to the header before the end of the body
+                // needs a `continue`.
+                if b == current.header {
+                    return None;
+                }
+            }
+            if self.is_header(b) {
+                let inner = Loop {
+                    header: b,
+                    exit: self.loop_exit(b),
+                };
+                let mut body = vec![];
+                let next = self.block(b, Some(b), Some(inner), &mut body)?;
+                self.structure(next, Some(b), Some(inner), &mut body)?;
+                out.push(self.while_loop(body));
+                block = inner.exit;
+            } else {
+                block = self.block(b, follow, current, out)?;
+            }
+        }
+        Some(())
+    }
+
+    /// Append the statements of block `b` to `out`, returns the block
+    /// control continues at.
+    fn block(
+        &mut self,
+        b: BlockRef,
+        follow: Option<BlockRef>,
+        current: Option<Loop>,
+        out: &mut Vec<StmtRef>,
+    ) -> Option<Option<BlockRef>> {
+        if !self.visited.insert(b) {
+            return None;
+        }
+        let insts = self.graph.block(b).instructions().to_vec();
+        for inst in &insts {
+            self.instruction(inst, b, out);
+        }
+        let Some(Instruction::Branch(condition, ..)) = insts.last() else {
+            self.flush(out, |_| true);
+            return Some(self.graph.successors(b).next());
+        };
+        let condition = self.value(condition);
+        self.flush(out, |_| true);
+        let (mut then_target, mut else_target) = (None, None);
+        for edge in self.graph.succ_edges(b) {
+            let edge = self.graph.edge(*edge);
+            match edge.kind() {
+                EdgeKind::Then => then_target = Some(edge.target()),
+                _ => else_target = Some(edge.target()),
+            }
+        }
+        let (then_target, else_target) = (then_target?, else_target?);
+        let exit = current.and_then(|current| current.exit);
+        if Some(then_target) == exit || Some(else_target) == exit {
+            let (condition, next) = if Some(then_target) == exit {
+                (condition, else_target)
+            } else {
+                (self.negate(condition), then_target)
+            };
+            let exit = self.ast.push_stmt(Stmt::Break);
+            let then_block = self.ast.push_stmt(Stmt::Block(vec![exit]));
+            out.push(self.ast.push_stmt(Stmt::If {
+                condition,
+                then_block,
+                else_block: None,
+            }));
+            return Some(Some(next));
+        }
+        let join = self.join(b, then_target, else_target, follow, current);
+        let mut then_stmts = vec![];
+        let mut else_stmts = vec![];
+        self.structure(
+            Some(then_target),
+            join.or(follow),
+            current,
+            &mut then_stmts,
+        )?;
+        self.structure(
+            Some(else_target),
+            join.or(follow),
+            current,
+            &mut else_stmts,
+        )?;
+        let (condition, then_stmts, else_stmts) = if then_stmts.is_empty() {
+            (self.negate(condition), else_stmts, then_stmts)
+        } else {
+            (condition, then_stmts, else_stmts)
+        };
+        if !then_stmts.is_empty() {
+            let then_block = self.ast.push_stmt(Stmt::Block(then_stmts));
+            let else_block = (!else_stmts.is_empty())
+                .then(|| self.ast.push_stmt(Stmt::Block(else_stmts)));
+            out.push(self.ast.push_stmt(Stmt::If {
+                condition,
+                then_block,
+                else_block,
+            }));
+        }
+        Some(join)
+    }
+
+    /// Returns the statements of the function with every edge lowered to a
+    /// `goto`, blocks are labeled `L` followed by their index.
+    fn unstructured(&mut self) -> Vec<StmtRef> {
+        let label = |block: BlockRef| format!("L{}", block.0);
+        let mut out = vec![];
+        for b in self.graph.blocks().collect::<Vec<_>>() {
+            out.push(self.ast.push_stmt(Stmt::Label(label(b))));
+            let insts = self.graph.block(b).instructions().to_vec();
+            for inst in &insts {
+                self.instruction(inst, b, &mut out);
+            }
+            let condition = match insts.last() {
+                Some(Instruction::Branch(condition, ..)) => {
+                    Some(self.value(condition))
+                }
+                _ => None,
+            };
+            self.flush(&mut out, |_| true);
+            let edges = self
+                .graph
+                .succ_edges(b)
+                .iter()
+                .map(|edge| self.graph.edge(*edge).clone())
+                .collect::<Vec<_>>();
+            let mut gotos = edges
+                .iter()
+                .filter(|edge| edge.kind() != EdgeKind::Fallthrough)
+                .map(|edge| {
+                    (
+                        edge.kind(),
+                        self.ast.push_stmt(Stmt::Goto(label(edge.target()))),
+                    )
+                })
+                .collect::<Vec<_>>();
+            gotos.sort_by_key(|(kind, _)| *kind != EdgeKind::Then);
+            match (condition, gotos.as_slice()) {
+                (Some(condition), [(_, then_goto), (_, else_goto)]) => {
+                    out.push(self.ast.push_stmt(Stmt::If {
+                        condition,
+                        then_block: *then_goto,
+                        else_block: Some(*else_goto),
+                    }));
+                }
+                (_, gotos) => out.extend(gotos.iter().map(|(_, goto)| *goto)),
+            }
+        }
+        out
+    }
+
+    /// Returns `true` if `block` is the target of a back edge.
+    fn is_header(&self, block: BlockRef) -> bool {
+        let number = self.graph.rpo_number(block);
+        self.graph.predecessors(block).any(|pred| {
+            self.graph
+                .rpo_number(pred)
+                .is_some_and(|pred| Some(pred) >= number)
+        })
+    }
+
+    /// Returns the exit of the natural loop of `header`, the first target
+    /// outside the loop of the header, then of the blocks jumping back to
+    /// the header, then of the other blocks of the loop.
+    fn loop_exit(&self, header: BlockRef) -> Option<BlockRef> {
+        let number = self.graph.rpo_number(header);
+        let latches = self
+            .graph
+            .predecessors(header)
+            .filter(|pred| self.graph.rpo_number(*pred) >= number)
+            .collect::<Vec<_>>();
+        let mut body = HashSet::from([header]);
+        let mut order = vec![header];
+        let mut worklist = latches.clone();
+        while let Some(block) = worklist.pop() {
+            if body.insert(block) {
+                order.push(block);
+                worklist.extend(self.graph.predecessors(block));
+            }
+        }
+        let rest = order.split_off(1);
+        let (latches, mut rest): (Vec<_>, Vec<_>) =
+            rest.into_iter().partition(|block| latches.contains(block));
+        rest.sort_by_key(|block| self.graph.rpo_number(*block));
+        order
+            .into_iter()
+            .chain(latches)
+            .chain(rest)
+            .flat_map(|block| self.graph.successors(block).collect::<Vec<_>>())
+            .find(|succ| !body.contains(succ))
+    }
+
+    /// Returns the first block after `branch` in reverse post-order reached
+    /// from both `lhs` and `rhs` without leaving the current region.
+    fn join(
+        &self,
+        branch: BlockRef,
+        lhs: BlockRef,
+        rhs: BlockRef,
+        follow: Option<BlockRef>,
+        current: Option<Loop>,
+    ) -> Option<BlockRef> {
+        let stops = [
+            follow,
+            current.map(|current| current.header),
+            current.and_then(|current| current.exit),
+        ];
+        let reach = |start: BlockRef| {
+            let mut reached = HashSet::from([start]);
+            let mut worklist = vec![start];
+            while let Some(block) = worklist.pop() {
+                if stops.contains(&Some(block)) {
+                    continue;
+                }
+                for succ in self.graph.successors(block) {
+                    if reached.insert(succ) {
+                        worklist.push(succ);
+                    }
+                }
+            }
+            reached
+        };
+        let rhs = reach(rhs);
+        reach(lhs)
+            .into_iter()
+            .filter(|block| {
+                rhs.contains(block)
+                    && self.graph.rpo_number(*block)
+                        > self.graph.rpo_number(branch)
+            })
+            .min_by_key(|block| self.graph.rpo_number(*block))
+    }
+
+    /// Returns the loop with body `body`, a body starting with its exit
+    /// test becomes the loop condition.
+    fn while_loop(&mut self, mut body: Vec<StmtRef>) -> StmtRef {
+        let test = body.first().and_then(|first| {
+            let Some(Stmt::If {
+                condition,
+                then_block,
+                else_block: None,
+            }) = self.ast.get_stmt(*first)
+            else {
+                return None;
+            };
+            let Some(Stmt::Block(stmts)) = self.ast.get_stmt(*then_block)
+            else {
+                return None;
+            };
+            let exits = matches!(stmts.as_slice(),
+                [stmt] if self.ast.get_stmt(*stmt) == Some(&Stmt::Break));
+            exits.then_some(*condition)
+        });
+        let condition = match test {
+            Some(condition) => {
+                body.remove(0);
+                self.negate(condition)
+            }
+            None => self.ast.push_expr(Expr::BoolLiteral(true)),
+        };
+        let body = self.ast.push_stmt(Stmt::Block(body));
+        self.ast.push_stmt(Stmt::While {
+            condition: Some(condition),
+            body: Some(body),
+        })
+    }
+
+    /// Returns the logical negation of `condition`, comparisons are
+    /// inverted.
+    fn negate(&mut self, condition: ExprRef) -> ExprRef {
+        match self.ast.get_expr(condition).cloned() {
+            Some(Expr::UnaryOp {
+                operator: UnaryOperator::Not,
+                operand,
+            }) => operand,
+            Some(Expr::BinOp {
+                left,
+                operator,
+                right,
+            }) if inverse(operator).is_some() => {
+                self.ast.push_expr(Expr::BinOp {
+                    left,
+                    operator: inverse(operator).unwrap_or(operator),
+                    right,
+                })
+            }
+            _ => self.ast.push_expr(Expr::UnaryOp {
+                operator: UnaryOperator::Not,
+                operand: condition,
+            }),
+        }
+    }
+
+    /// Returns the name of `symbol` in the decompiled program, variables
+    /// renamed by IR generation for shadowing in a nested scope (`x.1`)
+    /// become `x_1` and the globals backing static locals (`main.count`)
+    /// become `main_count`.
+    fn name(symbol: &Symbol) -> String {
+        symbol.name().strip_prefix('%').map_or_else(
+            || symbol.name().replace('.', "_"),
+            |name| format!("_{name}"),
+        )
+    }
+
+    /// Returns the expression reading `value`, consuming the pending
+    /// expression of the temporary it names.
+    fn value(&mut self, value: &Value) -> ExprRef {
+        match value {
+            Value::ConstantLiteral(value) => {
+                self.ast.push_expr(literal(*value))
+            }
+            Value::StorageLocation(symbol) => {
+                if let Some(constant) = self.constants.get(symbol) {
+                    return *constant;
+                }
+                if let Some(index) = self
+                    .pending
+                    .iter()
+                    .position(|(pending, ..)| pending == symbol)
+                {
+                    return self.pending.remove(index).1;
+                }
+                self.ast.push_expr(Expr::Named(Self::name(symbol)))
+            }
+        }
+    }
+
+    /// Returns the call to `name` with arguments `args`.
+    fn call(&mut self, name: &str, args: &[Value]) -> ExprRef {
+        let args = args.iter().map(|arg| self.value(arg)).collect();
+        let name = self.ast.push_expr(Expr::Named(name.to_string()));
+        self.ast.push_expr(Expr::Call { name, args })
+    }
+
+    /// Returns the call to `printf` printing `args` with `format`.
+    fn printf(&mut self, format: &str, args: &[Value]) -> ExprRef {
+        let format = self.ast.push_expr(Expr::StringLiteral(format.into()));
+        let mut call_args = vec![format];
+        call_args.extend(args.iter().map(|arg| self.value(arg)));
+        let name = self.ast.push_expr(Expr::Named("printf".to_string()));
+        self.ast.push_expr(Expr::Call {
+            name,
+            args: call_args,
+        })
+    }
+
+    /// Returns the binary operation `operator` on `lhs` and `rhs`.
+    fn binary(
+        &mut self,
+        operator: BinaryOperator,
+        lhs: &Value,
+        rhs: &Value,
+    ) -> ExprRef {
+        let left = self.value(lhs);
+        let right = self.value(rhs);
+        self.ast.push_expr(Expr::BinOp {
+            left,
+            operator,
+            right,
+        })
+    }
+
+    /// Returns the unary operation `operator` on `operand`.
+    fn unary(&mut self, operator: UnaryOperator, operand: &Value) -> ExprRef {
+        let operand = self.value(operand);
+        self.ast.push_expr(Expr::UnaryOp { operator, operand })
+    }
+
+    /// Append the statements of `inst` in block `b` to `out`, control flow
+    /// instructions are left to the structuring.
+    fn instruction(
+        &mut self,
+        inst: &Instruction,
+        b: BlockRef,
+        out: &mut Vec<StmtRef>,
+    ) {
+        let (dst, expr) = match inst {
+            Instruction::Const(dst, Value::ConstantLiteral(value))
+                if is_temporary(dst) =>
+            {
+                let value = self.ast.push_expr(literal(*value));
+                self.constants.insert(dst.clone(), value);
+                return;
+            }
+            Instruction::Const(dst, value) | Instruction::Id(dst, value) => {
+                (dst, self.value(value))
+            }
+            Instruction::Add(dst, lhs, rhs) => {
+                (dst, self.binary(BinaryOperator::Add, lhs, rhs))
+            }
+            Instruction::Sub(dst, lhs, rhs) => {
+                (dst, self.binary(BinaryOperator::Sub, lhs, rhs))
+            }
+            Instruction::Mul(dst, lhs, rhs) => {
+                (dst, self.binary(BinaryOperator::Mul, lhs, rhs))
+            }
+            Instruction::Div(dst, lhs, rhs) => {
+                (dst, self.binary(BinaryOperator::Div, lhs, rhs))
+            }
+            // Shifting left by a constant multiplies by a power of two.
+            Instruction::Shl(
+                dst,
+                lhs,
+                Value::ConstantLiteral(Literal::Int(amount @ 0..=30)),
+            ) => {
+                let factor = Value::ConstantLiteral(Literal::Int(1 << amount));
+                (dst, self.binary(BinaryOperator::Mul, lhs, &factor))
+            }
+            Instruction::Shl(dst, lhs, rhs) => {
+                (dst, self.binary(BinaryOperator::Shl, lhs, rhs))
+            }
+            Instruction::Shr(dst, lhs, rhs) => {
+                (dst, self.binary(BinaryOperator::Shr, lhs, rhs))
+            }
+            Instruction::BitAnd(dst, lhs, rhs) => {
+                (dst, self.binary(BinaryOperator::BitAnd, lhs, rhs))
+            }
+            Instruction::And(dst, lhs, rhs) => {
+                (dst, self.binary(BinaryOperator::And, lhs, rhs))
+            }
+            Instruction::Or(dst, lhs, rhs) => {
+                (dst, self.binary(BinaryOperator::Or, lhs, rhs))
+            }
+            Instruction::Eq(dst, lhs, rhs) => {
+                (dst, self.binary(BinaryOperator::Eq, lhs, rhs))
+            }
+            Instruction::Neq(dst, lhs, rhs) => {
+                (dst, self.binary(BinaryOperator::Neq, lhs, rhs))
+            }
+            Instruction::Lt(dst, lhs, rhs) => {
+                (dst, self.binary(BinaryOperator::Lt, lhs, rhs))
+            }
+            Instruction::Lte(dst, lhs, rhs) => {
+                (dst, self.binary(BinaryOperator::Lte, lhs, rhs))
+            }
+            Instruction::Gt(dst, lhs, rhs) => {
+                (dst, self.binary(BinaryOperator::Gt, lhs, rhs))
+            }
+            Instruction::Gte(dst, lhs, rhs) => {
+                (dst, self.binary(BinaryOperator::Gte, lhs, rhs))
+            }
+            Instruction::Not(dst, operand) => {
+                (dst, self.unary(UnaryOperator::Not, operand))
+            }
+            Instruction::Neg(dst, operand) => {
+                (dst, self.unary(UnaryOperator::Neg, operand))
+            }
+            Instruction::Cast(dst, operand) => {
+                let operand = self.value(operand);
+                let to = decl_type(dst.t());
+                (dst, self.ast.push_expr(Expr::Cast { to, operand }))
+            }
+            Instruction::Call(dst, callee, args) => {
+                let call = self.call(callee.name(), args);
+                // Callees can assign globals.
+                let outside = self.outside.clone();
+                self.flush(out, |reads| !reads.is_disjoint(&outside));
+                self.effect(dst, call, out);
+                return;
+            }
+            Instruction::Intrinsic(dst, builtin, args) => {
+                // Both return the number of characters printed, like the
+                // `printf` calls they were lowered from.
+                let call = match builtin {
+                    Builtin::PrintInt => self.printf("%d", args),
+                    Builtin::PrintChar => self.printf("%c", args),
+                    _ => self.call(builtin.name(), args),
+                };
+                if builtin.has_side_effects() {
+                    self.flush(out, |_| true);
+                    self.effect(dst, call, out);
+                    return;
+                }
+                (dst, call)
+            }
+            Instruction::Atomic(dst, op, variable, operand, order) => {
+                let mut args = vec![variable.clone()];
+                args.extend(operand.clone());
+                args.push(Value::ConstantLiteral(Literal::Int(order.value())));
+                let call = self.call(op.name(), &args);
+                self.flush(out, |_| true);
+                self.effect(dst, call, out);
+                return;
+            }
+            Instruction::Return(value) => {
+                let value = self.value(value);
+                out.push(self.ast.push_stmt(Stmt::Return(value)));
+                return;
+            }
+            Instruction::Exit(value) => {
+                let call = self.call("exit", std::slice::from_ref(value));
+                out.push(self.ast.push_stmt(Stmt::Expr(call)));
+                return;
+            }
+            Instruction::Trap(..) => {
+                let call = self.call("abort", &[]);
+                out.push(self.ast.push_stmt(Stmt::Expr(call)));
+                return;
+            }
+            Instruction::Asm(code) => {
+                self.flush(out, |_| true);
+                out.push(self.ast.push_stmt(Stmt::Asm(code.clone())));
+                return;
+            }
+            Instruction::Jump(..)
+            | Instruction::Branch(..)
+            | Instruction::Label(..)
+            | Instruction::Count(..)
+            | Instruction::Nop => return,
+        };
+        match self.uses.get(dst) {
+            Some((1, block)) if is_temporary(dst) && *block == b => {
+                let reads = self.reads(expr);
+                self.pending.push((dst.clone(), expr, reads));
+            }
+            None if is_temporary(dst) => (),
+            _ => self.assign(dst, expr, out),
+        }
+    }
+
+    /// Append the evaluation of `expr`, which has side effects, to `out`
+    /// and store its result in `dst` when it's used.
+    fn effect(&mut self, dst: &Symbol, expr: ExprRef, out: &mut Vec<StmtRef>) {
+        match self.uses.get(dst) {
+            None if is_temporary(dst) || dst.t() == Type::Unit => {
+                out.push(self.ast.push_stmt(Stmt::Expr(expr)));
+            }
+            _ => self.assign(dst, expr, out),
+        }
+    }
+
+    /// Append the assignment of `expr` to `dst` to `out`.
+    fn assign(&mut self, dst: &Symbol, expr: ExprRef, out: &mut Vec<StmtRef>) {
+        let name = Self::name(dst);
+        self.flush(out, |reads| reads.contains(&name));
+        if !self.outside.contains(&name)
+            && !self.locals.iter().any(|(local, _)| *local == name)
+        {
+            self.locals.push((name.clone(), dst.t()));
+        }
+        let variable = self.ast.push_expr(Expr::Named(name));
+        let assignment = self.ast.push_expr(Expr::Assignment {
+            name: variable,
+            value: expr,
+        });
+        out.push(self.ast.push_stmt(Stmt::Expr(assignment)));
+    }
+
+    /// Assign the pending expressions whose read variables satisfy
+    /// `predicate` to their temporary, in order.
+    fn flush(
+        &mut self,
+        out: &mut Vec<StmtRef>,
+        predicate: impl Fn(&HashSet<String>) -> bool,
+    ) {
+        let (flushed, pending): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.pending)
+                .into_iter()
+                .partition(|(_, _, reads)| predicate(reads));
+        self.pending = pending;
+        for (dst, expr, _) in flushed {
+            self.assign(&dst, expr, out);
+        }
+    }
+
+    /// Returns the variables read by `expr`.
+    fn reads(&self, expr: ExprRef) -> HashSet<String> {
+        let mut reads = HashSet::new();
+        let mut worklist = vec![expr];
+        while let Some(expr) = worklist.pop() {
+            match self.ast.get_expr(expr) {
+                Some(Expr::Named(name)) => {
+                    reads.insert(name.clone());
+                }
+                Some(
+                    Expr::UnaryOp { operand, .. } | Expr::Cast { operand, .. },
+                ) => worklist.push(*operand),
+                Some(Expr::BinOp { left, right, .. }) => {
+                    worklist.extend([*left, *right]);
+                }
+                Some(Expr::Call { args, .. }) => worklist.extend(args),
+                _ => (),
+            }
+        }
+        reads
+    }
+}
+
+/// `Printer` prints an AST as C source code.
+struct Printer<'a> {
+    ast: &'a AST,
+    output: String,
+    // Current indentation level.
+    depth: usize,
+}
+
+impl Printer<'_> {
+    /// Print a line at the current indentation.
+    fn line(&mut self, line: &str) {
+        self.output.push_str(&"    ".repeat(self.depth));
+        self.output.push_str(line);
+        self.output.push('\n');
+    }
+
+    fn decl(&mut self, decl: &Decl) {
+        match decl {
+            Decl::GlobalVariable {
+                decl_type,
+                name,
+                value,
+            } => {
+                let value = self.expr(*value, 0);
+                self.line(&format!("{} {name} = {value};", c_type(*decl_type)));
+            }
+            Decl::Function {
+                name,
+                return_type,
+                args,
+                body,
+                ..
+            } => {
+                let signature = self.signature(*return_type, name, args);
+                self.line(&format!("{signature} {{"));
+                self.body(*body);
+                self.line("}");
+            }
+            Decl::Extern {
+                name,
+                return_type,
+                args,
+            } => {
+                let signature = self.signature(*return_type, name, args);
+                self.line(&format!("extern {signature};"));
+            }
+        }
+    }
+
+    fn signature(
+        &self,
+        return_type: DeclType,
+        name: &str,
+        args: &[StmtRef],
+    ) -> String {
+        let args = args
+            .iter()
+            .filter_map(|arg| match self.ast.get_stmt(*arg) {
+                Some(Stmt::Argument { decl_type, name }) => {
+                    Some(format!("{} {name}", c_type(*decl_type)))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        format!("{} {name}({})", c_type(return_type), args.join(", "))
+    }
+
+    /// Print the statements of the block `stmt`, one level deeper.
+    fn body(&mut self, stmt: StmtRef) {
+        self.depth += 1;
+        match self.ast.get_stmt(stmt) {
+            Some(Stmt::Block(stmts)) => {
+                for stmt in stmts {
+                    self.stmt(*stmt);
+                }
+            }
+            _ => self.stmt(stmt),
+        }
+        self.depth -= 1;
+    }
+
+    fn stmt(&mut self, stmt: StmtRef) {
+        let Some(node) = self.ast.get_stmt(stmt) else {
+            return;
+        };
+        match node {
+            Stmt::Return(value) => {
+                let value = self.expr(*value, 0);
+                self.line(&format!("return {value};"));
+            }
+            Stmt::LocalVariable {
+                decl_type,
+                name,
+                value,
+            } => {
+                let declaration = format!("{} {name}", c_type(*decl_type));
+                if self.ast.is_default_initialized(stmt) {
+                    self.line(&format!("{declaration};"));
+                } else {
+                    let value = self.expr(*value, 0);
+                    self.line(&format!("{declaration} = {value};"));
+                }
+            }
+            Stmt::Argument { decl_type, name } => {
+                self.line(&format!("{} {name};", c_type(*decl_type)));
+            }
+            Stmt::Expr(expr) => {
+                let expr = self.expr(*expr, 0);
+                self.line(&format!("{expr};"));
+            }
+            Stmt::Block(..) => {
+                self.line("{");
+                self.body(stmt);
+                self.line("}");
+            }
+            Stmt::If {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                let condition = self.expr(*condition, 0);
+                self.line(&format!("if ({condition}) {{"));
+                self.body(*then_block);
+                if let Some(else_block) = else_block {
+                    self.line("} else {");
+                    self.body(*else_block);
+                }
+                self.line("}");
+            }
+            Stmt::For {
+                init,
+                condition,
+                iteration,
+                body,
+            } => {
+                let [init, condition, iteration] = [init, condition, iteration]
+                    .map(|expr| {
+                        expr.map_or_else(String::new, |e| self.expr(e, 0))
+                    });
+                self.line(&format!(
+                    "for ({init}; {condition}; {iteration}) {{"
+                ));
+                self.body(*body);
+                self.line("}");
+            }
+            Stmt::While { condition, body } => {
+                let condition = condition
+                    .map_or_else(|| "true".to_string(), |e| self.expr(e, 0));
+                self.line(&format!("while ({condition}) {{"));
+                if let Some(body) = body {
+                    self.body(*body);
+                }
+                self.line("}");
+            }
+            Stmt::Switch { scrutinee, body } => {
+                let scrutinee = self.expr(*scrutinee, 0);
+                self.line(&format!("switch ({scrutinee}) {{"));
+                self.body(*body);
+                self.line("}");
+            }
+            Stmt::Case(value) => self.line(&format!("case {value}:")),
+            Stmt::Default => self.line("default:"),
+            Stmt::Break => self.line("break;"),
+            Stmt::Goto(label) => self.line(&format!("goto {label};")),
+            Stmt::Label(label) => self.line(&format!("{label}:")),
+            Stmt::Asm(code) => self.line(&format!("asm({code:?});")),
+            Stmt::Empty => self.line(";"),
+        }
+    }
+
+    /// Returns the source of `expr`, parenthesized if it binds looser than
+    /// an operator of precedence `parent`.
+    fn expr(&self, expr: ExprRef, parent: u8) -> String {
+        let Some(node) = self.ast.get_expr(expr) else {
+            return String::new();
+        };
+        let (source, precedence) = match node {
+            Expr::Named(name) => (name.clone(), u8::MAX),
+            Expr::IntLiteral(value) => (value.to_string(), u8::MAX),
+            Expr::BoolLiteral(value) => (value.to_string(), u8::MAX),
+            Expr::CharLiteral(value) => (escape_char(*value), u8::MAX),
+            Expr::StringLiteral(value) => (format!("{value:?}"), u8::MAX),
+            Expr::Assignment { name, value } => (
+                format!("{} = {}", self.expr(*name, 1), self.expr(*value, 1)),
+                1,
+            ),
+            Expr::BinOp {
+                left,
+                operator,
+                right,
+            } => {
+                let precedence = precedence(*operator);
+                let left = self.expr(*left, precedence);
+                let right = self.expr(*right, precedence + 1);
+                (
+                    format!("{left} {} {right}", c_operator(*operator)),
+                    precedence,
+                )
+            }
+            Expr::UnaryOp { operator, operand } => {
+                let operator = match operator {
+                    UnaryOperator::Neg => "-",
+                    UnaryOperator::Not => "!",
+                };
+                let operand = self.expr(*operand, 10);
+                // `-(-a)` written `--a` would be a decrement.
+                if operator == "-" && operand.starts_with('-') {
+                    (format!("{operator}({operand})"), 10)
+                } else {
+                    (format!("{operator}{operand}"), 10)
+                }
+            }
+            Expr::Call { name, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.expr(*arg, 0))
+                    .collect::<Vec<_>>();
+                (
+                    format!("{}({})", self.expr(*name, 0), args.join(", ")),
+                    u8::MAX,
+                )
+            }
+            Expr::Cast { to, operand } => {
+                (format!("({}){}", c_type(*to), self.expr(*operand, 10)), 10)
+            }
+        };
+        if precedence < parent {
+            format!("({source})")
+        } else {
+            source
+        }
+    }
+}
+
+/// Returns the C spelling of `decl_type`.
+const fn c_type(decl_type: DeclType) -> &'static str {
+    match decl_type {
+        DeclType::Int => "int",
+        DeclType::Char => "char",
+        DeclType::Bool => "bool",
+    }
+}
+
+/// Returns the precedence of `operator`, higher binds tighter.
+const fn precedence(operator: BinaryOperator) -> u8 {
+    match operator {
+        BinaryOperator::Or => 2,
+        BinaryOperator::And => 3,
+        BinaryOperator::BitAnd => 4,
+        BinaryOperator::Eq | BinaryOperator::Neq => 5,
+        BinaryOperator::Gt
+        | BinaryOperator::Gte
+        | BinaryOperator::Lt
+        | BinaryOperator::Lte => 6,
+        BinaryOperator::Shl | BinaryOperator::Shr => 7,
+        BinaryOperator::Add | BinaryOperator::Sub => 8,
+        BinaryOperator::Mul | BinaryOperator::Div => 9,
+    }
+}
+
+/// Returns the comparison true when `operator` is false.
+const fn inverse(operator: BinaryOperator) -> Option<BinaryOperator> {
+    match operator {
+        BinaryOperator::Eq => Some(BinaryOperator::Neq),
+        BinaryOperator::Neq => Some(BinaryOperator::Eq),
+        BinaryOperator::Lt => Some(BinaryOperator::Gte),
+        BinaryOperator::Gte => Some(BinaryOperator::Lt),
+        BinaryOperator::Gt => Some(BinaryOperator::Lte),
+        BinaryOperator::Lte => Some(BinaryOperator::Gt),
+        _ => None,
+    }
+}
+
+/// Returns the C spelling of `operator`.
+const fn c_operator(operator: BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Add => "+",
+        BinaryOperator::Sub => "-",
+        BinaryOperator::Mul => "*",
+        BinaryOperator::Div => "/",
+        BinaryOperator::Eq => "==",
+        BinaryOperator::Neq => "!=",
+        BinaryOperator::Gt => ">",
+        BinaryOperator::Gte => ">=",
+        BinaryOperator::Lt => "<",
+        BinaryOperator::Lte => "<=",
+        BinaryOperator::And => "&&",
+        BinaryOperator::Or => "||",
+        BinaryOperator::Shl => "<<",
+        BinaryOperator::Shr => ">>",
+        BinaryOperator::BitAnd => "&",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decompile::{decompile, print};
+    use crate::ir::{IRBuilder, Program};
+    use crate::optim::PassManager;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::sema::analyze;
+
+    /// Returns the unoptimized program of `source`.
+    fn program(source: &str) -> Program {
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        irgen.into_program()
+    }
+
+    #[test]
+    fn can_decompile_structured_control_flow() {
+        let source = r"
+            int total = 0;
+            int main() {
+                int i = 0;
+                while (i < 10) {
+                    if (i > 5) {
+                        total = total + i * 2;
+                    } else {
+                        total = total - 1;
+                    }
+                    i = i + 1;
+                }
+                return total;
+            }
+        ";
+        let mut program = program(source);
+        for function in program.functions_mut() {
+            PassManager::oz().run(function);
+        }
+
+        // The optimizer inverted the branch on `i > 5`.
+        let expected = r"int total = 0;
+int main() {
+    int i;
+    i = 0;
+    if (i < 10) {
+        while (true) {
+            if (i <= 5) {
+                total = total - 1;
+            } else {
+                total = total + i * 2;
+            }
+            i = i + 1;
+            if (i >= 10) {
+                break;
+            }
+        }
+    }
+    return total;
+}
+";
+        let decompiled = print(&decompile(&program));
+        assert_eq!(decompiled, expected);
+
+        // The decompiled program compiles again.
+        let tokens = Scanner::new(&decompiled).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        analyze(parser.ast());
+    }
+
+    #[test]
+    fn can_decompile_nested_negations() {
+        let source = "int f(int a) { return -(-a); }";
+        let decompiled = print(&decompile(&program(source)));
+        assert!(decompiled.contains("return -(-a);"), "{decompiled}");
+
+        // The decompiled program compiles again to the same program.
+        assert_eq!(print(&decompile(&program(&decompiled))), decompiled);
+    }
+
+    #[test]
+    fn can_decompile_static_locals() {
+        let source = r"
+            int main() {
+                static int count = 3;
+                count = count + 1;
+                return count;
+            }
+        ";
+        let decompiled = print(&decompile(&program(source)));
+        let expected = r"int main_count = 3;
+int main() {
+    main_count = main_count + 1;
+    return main_count;
+}
+";
+        assert_eq!(decompiled, expected);
+    }
+
+    #[test]
+    fn can_keep_calls_in_their_own_statement() {
+        let source = r"
+            int g = 2;
+            int h() {
+                g = g + 10;
+                return g;
+            }
+            int main() {
+                return h() + g;
+            }
+        ";
+        let decompiled = print(&decompile(&program(source)));
+        // `h() + g` could read `g` before the call assigns it.
+        let expected = r"int main() {
+    int _v3;
+    _v3 = h();
+    return _v3 + g;
+}
+";
+        assert!(decompiled.ends_with(expected), "{decompiled}");
+    }
+
+    #[test]
+    fn can_decompile_bitwise_and_print_instructions() {
+        let source = r#"
+            int f(int a) {
+                printf("%d\n", a / 8);
+                return a;
+            }
+        "#;
+        let mut program = program(source);
+        for function in program.functions_mut() {
+            PassManager::o2().run(function);
+        }
+        // Strength reduction turned the division into shifts and a mask.
+        let expected = r#"int f(int a) {
+    printf("%d", a + (a >> 31 & 7) >> 3);
+    printf("%c", '\n');
+    return a;
+}
+"#;
+        assert_eq!(print(&decompile(&program)), expected);
+    }
+}
@@ -0,0 +1,637 @@
+//! Diagnostics reported by the compiler.
+//!
+//! Every error the frontend reports is turned into a `Diagnostic` before it is
+//! printed. Diagnostics carry a stable code so tools can match on them, and
+//! can be printed either for humans or as one JSON object per line for CI
+//! systems and editors (`--diagnostics-format=json`).
+//!
+//! Warnings are grouped, emitters ask the `Warnings` registry for a diagnostic
+//! which is only returned if the group is enabled on the command line and is
+//! promoted to an error under `-Werror`.
+//!
+//! Spans are character offsets, `SourceMap` turns them into the lines and
+//! columns editors show: tabs advance to the next tab stop and wide
+//! characters take two columns.
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::limits::LimitError;
+use crate::scanner::ScanError;
+use crate::token::Span;
+
+/// Code of diagnostics reported by the scanner.
+pub const SCAN_ERROR: &str = "E0001";
+/// Code of diagnostics reported by the parser.
+pub const SYNTAX_ERROR: &str = "E0002";
+/// Code of diagnostics reported by the semantic analyzer.
+pub const SEMANTIC_ERROR: &str = "E0003";
+/// Code of diagnostics reported by IR generation and the optimizer.
+pub const INTERNAL_ERROR: &str = "E0004";
+/// Code of diagnostics reported when linking translation units.
+pub const LINK_ERROR: &str = "E0005";
+/// Code of diagnostics reported for programs exceeding a resource limit.
+pub const PROGRAM_TOO_LARGE: &str = "E0006";
+
+/// How severe a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+            Self::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// `Diagnostic` is a message reported about the compiled program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    // Stable code identifying the kind of diagnostic.
+    code: &'static str,
+    // Severity of the diagnostic.
+    severity: Severity,
+    // Main message.
+    message: String,
+    // Source file of the offending code.
+    file: Option<String>,
+    // Location of the offending code, as character offsets.
+    span: Option<Span>,
+    // Line of the offending code.
+    line: Option<usize>,
+    // Column of the offending code.
+    column: Option<usize>,
+    // Excerpt of the offending code with carets under the span.
+    snippet: Option<String>,
+    // Additional messages attached to the diagnostic.
+    notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Create a new diagnostic without location.
+    #[must_use]
+    pub fn new(code: &'static str, severity: Severity, message: &str) -> Self {
+        Self {
+            code,
+            severity,
+            message: message.to_string(),
+            file: None,
+            span: None,
+            line: None,
+            column: None,
+            snippet: None,
+            notes: vec![],
+        }
+    }
+
+    /// Create a new error diagnostic.
+    #[must_use]
+    pub fn error(code: &'static str, message: &str) -> Self {
+        Self::new(code, Severity::Error, message)
+    }
+
+    /// Attach the source file of the offending code.
+    #[must_use]
+    pub fn with_file(mut self, file: &str) -> Self {
+        self.file = Some(file.to_string());
+        self
+    }
+
+    /// Attach the span of the offending code.
+    #[must_use]
+    pub const fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    /// Attach the line of the offending code.
+    #[must_use]
+    pub const fn with_line(mut self, line: usize) -> Self {
+        self.line = Some(line);
+        self
+    }
+
+    /// Attach the column of the offending code.
+    #[must_use]
+    pub const fn with_column(mut self, column: usize) -> Self {
+        self.column = Some(column);
+        self
+    }
+
+    /// Attach the line, column and an excerpt of the code at `span`.
+    #[must_use]
+    pub fn with_source(mut self, source: &SourceMap, span: Span) -> Self {
+        let (line, column) = source.position(span.start);
+        self.line = Some(line);
+        self.column = Some(column);
+        self.snippet = Some(source.snippet(span));
+        self
+    }
+
+    /// Attach a note.
+    #[must_use]
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    /// Returns the diagnostic code.
+    #[must_use]
+    pub const fn code(&self) -> &'static str {
+        self.code
+    }
+
+    /// Returns the diagnostic severity.
+    #[must_use]
+    pub const fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Returns the source file of the offending code.
+    #[must_use]
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    /// Returns the location of the offending code.
+    #[must_use]
+    pub const fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    /// Returns the line of the offending code.
+    #[must_use]
+    pub const fn line(&self) -> Option<usize> {
+        self.line
+    }
+
+    /// Returns the column of the offending code.
+    #[must_use]
+    pub const fn column(&self) -> Option<usize> {
+        self.column
+    }
+
+    /// Returns the diagnostic message.
+    #[must_use]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the diagnostic notes.
+    #[must_use]
+    pub fn notes(&self) -> &[String] {
+        &self.notes
+    }
+
+    /// Returns the diagnostic as a single line JSON object, absent locations
+    /// are `null`.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let file = self
+            .file
+            .as_deref()
+            .map_or_else(|| "null".to_string(), escape);
+        let span = self.span.map_or_else(
+            || "null".to_string(),
+            |span| format!(r#"{{"start":{},"end":{}}}"#, span.start, span.end),
+        );
+        let line = self
+            .line
+            .map_or_else(|| "null".to_string(), |line| line.to_string());
+        let column = self
+            .column
+            .map_or_else(|| "null".to_string(), |column| column.to_string());
+        let notes = self
+            .notes
+            .iter()
+            .map(|note| escape(note))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            r#"{{"code":{},"severity":{},"file":{file},"span":{span},"line":{line},"column":{column},"message":{},"notes":[{notes}]}}"#,
+            escape(self.code),
+            escape(&self.severity.to_string()),
+            escape(&self.message),
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)?;
+        match (&self.file, self.line, self.column) {
+            (Some(file), Some(line), Some(column)) => {
+                write!(f, " at {file}:{line}:{column}")?;
+            }
+            (Some(file), Some(line), None) => write!(f, " at {file}:{line}")?,
+            (Some(file), None, _) => write!(f, " in {file}")?,
+            (None, Some(line), Some(column)) => {
+                write!(f, " at line {line}, column {column}")?;
+            }
+            (None, Some(line), None) => write!(f, " at line {line}")?,
+            (None, None, _) => (),
+        }
+        if let Some(snippet) = &self.snippet {
+            write!(f, "\n{snippet}")?;
+        }
+        for note in &self.notes {
+            write!(f, "\n  note: {note}")?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&ScanError> for Diagnostic {
+    fn from(err: &ScanError) -> Self {
+        Self::error(SCAN_ERROR, err.details()).with_line(err.line())
+    }
+}
+
+impl From<&LimitError> for Diagnostic {
+    fn from(err: &LimitError) -> Self {
+        Self::error(PROGRAM_TOO_LARGE, &err.to_string())
+    }
+}
+
+/// Default distance between tab stops.
+pub const DEFAULT_TAB_WIDTH: usize = 8;
+
+/// Maps character offsets of a source file to lines and columns.
+pub struct SourceMap {
+    source: Vec<char>,
+    // Offset of the first character of every line.
+    lines: Vec<usize>,
+    tab_width: usize,
+}
+
+impl SourceMap {
+    /// Create a source map of `source` with tab stops every
+    /// `DEFAULT_TAB_WIDTH` columns.
+    #[must_use]
+    pub fn new(source: &str) -> Self {
+        let source = source.chars().collect::<Vec<_>>();
+        let lines = std::iter::once(0)
+            .chain(
+                source
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, c)| **c == '\n')
+                    .map(|(i, _)| i + 1),
+            )
+            .collect();
+        Self {
+            source,
+            lines,
+            tab_width: DEFAULT_TAB_WIDTH,
+        }
+    }
+
+    /// Place tab stops every `tab_width` columns.
+    #[must_use]
+    pub fn with_tab_width(mut self, tab_width: usize) -> Self {
+        assert!(tab_width > 0, "Tab width must be positive.");
+        self.tab_width = tab_width;
+        self
+    }
+
+    /// Returns the index of the line containing `offset` and its first
+    /// offset.
+    fn line_start(&self, offset: usize) -> (usize, usize) {
+        let line = self.lines.partition_point(|start| *start <= offset) - 1;
+        (line, self.lines[line])
+    }
+
+    /// Returns the column reached by the characters of `line`, starting at
+    /// column 0.
+    fn width(&self, line: &[char]) -> usize {
+        line.iter().fold(0, |column, c| match c {
+            '\t' => (column / self.tab_width + 1) * self.tab_width,
+            c => column + char_width(*c),
+        })
+    }
+
+    /// Returns the 1-based line and display column of `offset`.
+    #[must_use]
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.source.len());
+        let (line, start) = self.line_start(offset);
+        (line + 1, self.width(&self.source[start..offset]) + 1)
+    }
+
+    /// Returns the 0-based line and UTF-16 code unit offset of `offset`
+    /// within its line, as used by the language server protocol.
+    #[must_use]
+    pub fn utf16_position(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.source.len());
+        let (line, start) = self.line_start(offset);
+        let character = self.source[start..offset]
+            .iter()
+            .map(|c| c.len_utf16())
+            .sum();
+        (line, character)
+    }
+
+    /// Returns the line containing the start of `span` with tabs expanded,
+    /// and carets under the characters of the span on that line.
+    #[must_use]
+    pub fn snippet(&self, span: Span) -> String {
+        let offset = span.start.min(self.source.len());
+        let (_, start) = self.line_start(offset);
+        let end = self.source[start..]
+            .iter()
+            .position(|c| *c == '\n')
+            .map_or(self.source.len(), |len| start + len);
+        let mut text = String::new();
+        for (i, c) in self.source[start..end].iter().enumerate() {
+            if *c == '\t' {
+                let column = self.width(&self.source[start..start + i]);
+                let stop = self.width(&self.source[start..=start + i]);
+                text.push_str(&" ".repeat(stop - column));
+            } else {
+                text.push(*c);
+            }
+        }
+        let before = self.width(&self.source[start..offset]);
+        let after =
+            self.width(&self.source[start..span.end.clamp(offset, end)]);
+        let carets = "^".repeat((after - before).max(1));
+        format!("  {}\n  {}{carets}", text.trim_end(), " ".repeat(before))
+    }
+}
+
+/// Returns the number of columns `c` takes in a terminal: combining marks
+/// take none and East Asian wide characters and emoji take two.
+const fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036F | 0x200B..=0x200F | 0xFE00..=0xFE0F => 0,
+        0x1100..=0x115F
+        | 0x2E80..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// Returns `text` as a quoted and escaped JSON string.
+#[must_use]
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                escaped.push_str(&format!("\\u{:04x}", c as u32));
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Warning groups, each group is enabled by `-W<name>` and disabled by
+/// `-Wno-<name>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Warning {
+    // Local variables that are never read.
+    Unused,
+    // Local variables declared without an initializer that are read before
+    // being assigned.
+    Uninitialized,
+    // Local variables hiding a variable of an enclosing scope.
+    Shadow,
+    // `if` and `else` branches whose body is an empty statement.
+    EmptyBody,
+}
+
+impl Warning {
+    /// Every warning group.
+    pub const ALL: [Self; 4] = [
+        Self::Unused,
+        Self::Uninitialized,
+        Self::Shadow,
+        Self::EmptyBody,
+    ];
+
+    /// Returns the name of the group as used in `-W<name>`.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Unused => "unused",
+            Self::Uninitialized => "uninitialized",
+            Self::Shadow => "shadow",
+            Self::EmptyBody => "empty-body",
+        }
+    }
+
+    /// Returns the code of the diagnostics in the group.
+    #[must_use]
+    pub const fn code(self) -> &'static str {
+        match self {
+            Self::Unused => "W0001",
+            Self::Uninitialized => "W0002",
+            Self::Shadow => "W0003",
+            Self::EmptyBody => "W0004",
+        }
+    }
+
+    /// Returns `true` if the group is enabled by `-Wall`.
+    #[must_use]
+    pub const fn in_all(self) -> bool {
+        !matches!(self, Self::Shadow | Self::EmptyBody)
+    }
+
+    /// Returns the group named `name`.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|warning| warning.name() == name)
+    }
+}
+
+/// `Warnings` is the registry of enabled warning groups, every group is
+/// disabled by default.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Warnings {
+    // Enabled warning groups.
+    enabled: HashSet<Warning>,
+    // Whether warnings are reported as errors.
+    werror: bool,
+}
+
+impl Warnings {
+    /// Enable a warning group.
+    pub fn enable(&mut self, warning: Warning) {
+        self.enabled.insert(warning);
+    }
+
+    /// Disable a warning group.
+    pub fn disable(&mut self, warning: Warning) {
+        self.enabled.remove(&warning);
+    }
+
+    /// Report warnings as errors.
+    pub fn set_werror(&mut self, werror: bool) {
+        self.werror = werror;
+    }
+
+    /// Returns `true` if `warning` is enabled.
+    #[must_use]
+    pub fn is_enabled(&self, warning: Warning) -> bool {
+        self.enabled.contains(&warning)
+    }
+
+    /// Apply a `-W` command line flag (`-Wall`, `-Werror`, `-W<name>` or
+    /// `-Wno-<name>`), returns `false` if the flag isn't a known warning
+    /// flag.
+    pub fn apply_flag(&mut self, flag: &str) -> bool {
+        let Some(name) = flag.strip_prefix("-W") else {
+            return false;
+        };
+        match name {
+            "all" => Warning::ALL
+                .into_iter()
+                .filter(|warning| warning.in_all())
+                .for_each(|warning| self.enable(warning)),
+            "error" => self.set_werror(true),
+            "no-error" => self.set_werror(false),
+            name => match name.strip_prefix("no-") {
+                Some(name) => match Warning::from_name(name) {
+                    Some(warning) => self.disable(warning),
+                    None => return false,
+                },
+                None => match Warning::from_name(name) {
+                    Some(warning) => self.enable(warning),
+                    None => return false,
+                },
+            },
+        }
+        true
+    }
+
+    /// Returns the diagnostic for a `warning` with the given `message`, or
+    /// `None` if the group is disabled.
+    #[must_use]
+    pub fn warn(&self, warning: Warning, message: &str) -> Option<Diagnostic> {
+        if !self.is_enabled(warning) {
+            return None;
+        }
+        let message = format!("{message} [-W{}]", warning.name());
+        let diagnostic = if self.werror {
+            Diagnostic::error(warning.code(), &message)
+                .with_note("warnings are treated as errors (-Werror)")
+        } else {
+            Diagnostic::new(warning.code(), Severity::Warning, &message)
+        };
+        Some(diagnostic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::{
+        escape, Diagnostic, Severity, SourceMap, Warning, Warnings, SCAN_ERROR,
+        SYNTAX_ERROR,
+    };
+    use crate::scanner::Scanner;
+    use crate::token::Span;
+
+    #[test]
+    fn can_format_diagnostics() {
+        let err = Scanner::new("int main() {\n  return 1 @ 2;\n}")
+            .scan()
+            .unwrap_err();
+        let diagnostic = Diagnostic::from(&err);
+        assert_eq!(diagnostic.code(), SCAN_ERROR);
+        assert_eq!(
+            diagnostic.to_string(),
+            format!("error[E0001]: {} at line 2", err.details())
+        );
+        let diagnostic = Diagnostic::error(SYNTAX_ERROR, "expected `;`")
+            .with_span(Span { start: 3, end: 5 })
+            .with_line(1)
+            .with_note("statements end with `;`");
+        assert_eq!(
+            diagnostic.to_string(),
+            "error[E0002]: expected `;` at line 1\n  note: statements end with `;`"
+        );
+        let diagnostic = diagnostic.with_file("src/main.c");
+        assert_eq!(diagnostic.file(), Some("src/main.c"));
+        assert_eq!(
+            diagnostic.to_string(),
+            "error[E0002]: expected `;` at src/main.c:1\n  note: statements end with `;`"
+        );
+        assert_eq!(
+            diagnostic.to_json(),
+            r#"{"code":"E0002","severity":"error","file":"src/main.c","span":{"start":3,"end":5},"line":1,"column":null,"message":"expected `;`","notes":["statements end with `;`"]}"#
+        );
+    }
+
+    #[test]
+    fn can_map_offsets_to_columns() {
+        let source = "int main() {\n\tint 日本 = 1; // é\n}";
+        let map = SourceMap::new(source);
+        let offset = |needle: &str| {
+            source[..source.find(needle).unwrap()].chars().count()
+        };
+        assert_eq!(map.position(offset("main")), (1, 5));
+        assert_eq!(map.position(offset("int 日")), (2, 9));
+        assert_eq!(map.position(offset("=")), (2, 18));
+        assert_eq!(map.with_tab_width(4).position(offset("=")), (2, 14));
+        let map = SourceMap::new(source);
+        assert_eq!(map.utf16_position(offset("=")), (1, 8));
+        let start = offset("日本");
+        let diagnostic = Diagnostic::error(SYNTAX_ERROR, "unknown name")
+            .with_source(&map, Span::new(start, start + 2));
+        assert_eq!(diagnostic.column(), Some(13));
+        assert_eq!(
+            diagnostic.to_string(),
+            "error[E0002]: unknown name at line 2, column 13\n          int 日本 = 1; // é\n              ^^^^"
+        );
+    }
+
+    #[test]
+    fn can_escape_json_strings() {
+        assert_eq!(escape("a \"b\"\\\n\u{1}"), r#""a \"b\"\\\n\u0001""#);
+    }
+
+    #[test]
+    fn can_configure_warning_groups() {
+        let mut warnings = Warnings::default();
+        assert!(warnings.warn(Warning::Unused, "unused").is_none());
+        assert!(warnings.apply_flag("-Wall"));
+        assert!(warnings.is_enabled(Warning::Unused));
+        assert!(warnings.is_enabled(Warning::Uninitialized));
+        assert!(!warnings.is_enabled(Warning::Shadow));
+        assert!(warnings.apply_flag("-Wshadow"));
+        assert!(warnings.apply_flag("-Wno-unused"));
+        assert!(!warnings.apply_flag("-Wpedantic"));
+        assert!(!warnings.is_enabled(Warning::Unused));
+
+        let diagnostic = warnings.warn(Warning::Shadow, "`x` shadows").unwrap();
+        assert_eq!(diagnostic.severity(), Severity::Warning);
+        assert_eq!(
+            diagnostic.to_string(),
+            "warning[W0003]: `x` shadows [-Wshadow]"
+        );
+        assert!(warnings.apply_flag("-Werror"));
+        let diagnostic = warnings.warn(Warning::Shadow, "`x` shadows").unwrap();
+        assert_eq!(diagnostic.severity(), Severity::Error);
+    }
+}
@@ -0,0 +1,223 @@
+//! Instruction level diffs between two versions of a function.
+//!
+//! `glouton-diff` uses them to compare the IR a program compiles to under two
+//! pass pipelines. Instructions are compared by their textual form and the
+//! edit script is built from the longest common subsequence of both versions,
+//! the table is quadratic in the function size which is fine for functions
+//! written by hand.
+use std::fmt;
+
+use crate::cfg::Graph;
+use crate::ir;
+
+/// A line of an edit script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    // Instruction present in both versions.
+    Keep(String),
+    // Instruction only present in the old version.
+    Delete(String),
+    // Instruction only present in the new version.
+    Insert(String),
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Keep(line) => write!(f, "  {line}"),
+            Self::Delete(line) => write!(f, "- {line}"),
+            Self::Insert(line) => write!(f, "+ {line}"),
+        }
+    }
+}
+
+/// Returns the shortest edit script turning `old` into `new`.
+#[must_use]
+pub fn edits(old: &[String], new: &[String]) -> Vec<Edit> {
+    // `common[i][j]` is the length of the longest common subsequence of
+    // `old[i..]` and `new[j..]`.
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut script = Vec::with_capacity(old.len().max(new.len()));
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            script.push(Edit::Keep(old[i].clone()));
+            i += 1;
+            j += 1;
+        } else if common[i + 1][j] >= common[i][j + 1] {
+            script.push(Edit::Delete(old[i].clone()));
+            i += 1;
+        } else {
+            script.push(Edit::Insert(new[j].clone()));
+            j += 1;
+        }
+    }
+    script.extend(old[i..].iter().cloned().map(Edit::Delete));
+    script.extend(new[j..].iter().cloned().map(Edit::Insert));
+    script
+}
+
+/// `FunctionDiff` compares two versions of the same function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionDiff {
+    // Function name.
+    name: String,
+    // Edit script from the old to the new version.
+    edits: Vec<Edit>,
+    // Code size of the old and new versions.
+    instructions: (usize, usize),
+    // Number of basic blocks of the old and new versions.
+    blocks: (usize, usize),
+}
+
+impl FunctionDiff {
+    /// Compare the `old` and `new` versions of a function.
+    #[must_use]
+    pub fn new(old: &ir::Function, new: &ir::Function) -> Self {
+        let lines = |function: &ir::Function| {
+            function
+                .instructions()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+        let blocks =
+            |function: &ir::Function| Graph::new(&vec![function.clone()]).len();
+        Self {
+            name: old.name().to_string(),
+            edits: edits(&lines(old), &lines(new)),
+            instructions: (old.code_size(), new.code_size()),
+            blocks: (blocks(old), blocks(new)),
+        }
+    }
+
+    /// Returns the function name.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the edit script from the old to the new version.
+    #[must_use]
+    pub fn edits(&self) -> &[Edit] {
+        &self.edits
+    }
+
+    /// Returns `true` if both versions are identical.
+    #[must_use]
+    pub fn is_unchanged(&self) -> bool {
+        self.edits.iter().all(|edit| matches!(edit, Edit::Keep(..)))
+    }
+
+    /// Returns the code size of the old and new versions.
+    #[must_use]
+    pub const fn instructions(&self) -> (usize, usize) {
+        self.instructions
+    }
+
+    /// Returns the number of basic blocks of the old and new versions.
+    #[must_use]
+    pub const fn blocks(&self) -> (usize, usize) {
+        self.blocks
+    }
+}
+
+/// Format a statistic as `old -> new (delta)`.
+#[must_use]
+pub fn delta((old, new): (usize, usize)) -> String {
+    let delta = new as i64 - old as i64;
+    format!("{old} -> {new} ({delta:+})")
+}
+
+impl fmt::Display for FunctionDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "@{}: instructions {}, blocks {}",
+            self.name,
+            delta(self.instructions),
+            delta(self.blocks)
+        )?;
+        if self.is_unchanged() {
+            return Ok(());
+        }
+        for edit in &self.edits {
+            writeln!(f, "{edit}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::{delta, edits, Edit, FunctionDiff};
+    use crate::ir::IRBuilder;
+    use crate::optim::{OptLevel, PassManager};
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::sema::analyze;
+
+    #[test]
+    fn can_compute_shortest_edit_script() {
+        let lines = |text: &str| {
+            text.split(' ').map(ToString::to_string).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            edits(&lines("a b c d"), &lines("a c e d")),
+            vec![
+                Edit::Keep("a".to_string()),
+                Edit::Delete("b".to_string()),
+                Edit::Keep("c".to_string()),
+                Edit::Insert("e".to_string()),
+                Edit::Keep("d".to_string()),
+            ]
+        );
+        assert_eq!(delta((3, 1)), "3 -> 1 (-2)");
+        assert_eq!(delta((1, 1)), "1 -> 1 (+0)");
+    }
+
+    #[test]
+    fn can_diff_optimization_levels() {
+        let source = r#"
+            int main() {
+                int a = 1 + 2;
+                int b = 4;
+                return a;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let old = irgen.functions()[0].clone();
+        let mut new = old.clone();
+        PassManager::with_level(OptLevel::Oz).run(&mut new);
+        let diff = FunctionDiff::new(&old, &new);
+        assert!(FunctionDiff::new(&old, &old).is_unchanged());
+        assert_eq!(diff.name(), "main");
+        assert_eq!(diff.blocks(), (1, 1));
+        let expected = r#"@main: instructions 7 -> 3 (-4), blocks 1 -> 1 (+0)
+- %v0: int = const 1
+- %v1: int = const 2
+- %v2: int = add %v0 %v1
++ %v2: int = const 3
+  a: int = id %v2
+- %v3: int = const 4
+- b: int = id %v3
+  ret a
+"#;
+        assert_eq!(diff.to_string(), expected);
+    }
+}
@@ -0,0 +1,232 @@
+//! Token level source formatter for glouton's C subset.
+//!
+//! The formatter works directly on the token stream (comments included) and
+//! re-emits it with consistent indentation, spacing and brace placement. It
+//! never builds an AST so it can format programs that don't type check.
+//!
+//! The style is close to K&R, blocks open on the same line as their owning
+//! statement, each statement sits on its own line indented by four spaces per
+//! nesting level and binary operators are surrounded by spaces.
+use crate::scanner::{ScanError, Scanner};
+use crate::token::Token;
+
+/// Number of spaces per indentation level.
+const INDENT_WIDTH: usize = 4;
+
+/// Format the given source code.
+///
+/// # Errors
+///
+/// Returns an error if the source code can't be scanned.
+pub fn format(source: &str) -> Result<String, ScanError> {
+    let tokens = Scanner::new(source).with_comments().scan()?;
+    let mut formatter = Formatter::new();
+    for (index, token) in tokens.iter().enumerate() {
+        formatter.push(token, tokens.get(index + 1));
+    }
+    Ok(formatter.output)
+}
+
+/// `Formatter` accumulates the formatted output token by token.
+struct Formatter {
+    // Formatted source code.
+    output: String,
+    // Current brace nesting depth.
+    depth: usize,
+    // Current parenthesis nesting depth, statements inside parenthesis
+    // (`for` loop headers) are kept on a single line.
+    parens: usize,
+    // Previously emitted token.
+    prev: Option<Token>,
+    // Whether the previously emitted token was a unary operator.
+    prev_unary: bool,
+    // Whether the next token starts a new line.
+    line_start: bool,
+}
+
+impl Formatter {
+    fn new() -> Self {
+        Self {
+            output: String::new(),
+            depth: 0,
+            parens: 0,
+            prev: None,
+            prev_unary: false,
+            line_start: true,
+        }
+    }
+
+    /// Emit a token, `next` is used to decide where the line ends.
+    fn push(&mut self, token: &Token, next: Option<&Token>) {
+        match token {
+            Token::Eof => return,
+            Token::RBrace => self.depth = self.depth.saturating_sub(1),
+            // Comments always sit on their own line.
+            Token::Comment(_) if !self.line_start => self.newline(),
+            _ => (),
+        }
+
+        if self.line_start {
+            self.output.push_str(&" ".repeat(self.depth * INDENT_WIDTH));
+        } else if self.space_before(token) {
+            self.output.push(' ');
+        }
+        self.output.push_str(&token.lexeme());
+        self.line_start = false;
+        self.prev_unary = self.is_unary(token);
+
+        match token {
+            Token::LParen => self.parens += 1,
+            Token::RParen => self.parens = self.parens.saturating_sub(1),
+            Token::LBrace => {
+                self.depth += 1;
+                self.newline();
+            }
+            Token::RBrace => match next {
+                Some(Token::Else | Token::SemiColon) => (),
+                // Top level declarations are separated by a blank line.
+                Some(Token::Eof) | None => self.newline(),
+                Some(_) if self.depth == 0 => {
+                    self.newline();
+                    self.output.push('\n');
+                }
+                Some(_) => self.newline(),
+            },
+            Token::SemiColon if self.parens == 0 => self.newline(),
+            // Labels.
+            Token::Colon | Token::Comment(_) => self.newline(),
+            _ => (),
+        }
+        self.prev = Some(token.clone());
+    }
+
+    /// Terminate the current line.
+    fn newline(&mut self) {
+        self.output.push('\n');
+        self.line_start = true;
+    }
+
+    /// Returns `true` if `token` is separated from the previous token by
+    /// a space.
+    fn space_before(&self, token: &Token) -> bool {
+        if self.prev_unary {
+            return false;
+        }
+        match (&self.prev, token) {
+            (
+                _,
+                Token::RParen
+                | Token::RBracket
+                | Token::SemiColon
+                | Token::Comma
+                | Token::Colon
+                | Token::LBracket,
+            ) => false,
+            (Some(Token::LParen | Token::LBracket), _) => false,
+            // Calls don't have a space between the callee and arguments.
+            (Some(Token::Identifier(_)), Token::LParen) => false,
+            _ => true,
+        }
+    }
+
+    /// Returns `true` if `token` is used as a unary operator given the
+    /// previously emitted token.
+    fn is_unary(&self, token: &Token) -> bool {
+        match token {
+            Token::Bang => true,
+            Token::Minus => !matches!(
+                self.prev,
+                Some(
+                    Token::Identifier(_)
+                        | Token::IntLiteral(_)
+                        | Token::CharLiteral(_)
+                        | Token::BoolLiteral(_)
+                        | Token::True
+                        | Token::False
+                        | Token::RParen
+                        | Token::RBracket
+                )
+            ),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formatter::format;
+
+    // Macro to generate test cases.
+    macro_rules! test_formatter {
+        ($name:ident, $source:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                let formatted = format($source)
+                    .expect("expected test case source to be valid");
+                assert_eq!(formatted, $expected);
+                // Formatting is idempotent.
+                assert_eq!(format(&formatted).unwrap(), formatted);
+            }
+        };
+    }
+
+    test_formatter!(
+        can_format_function_declaration,
+        "int f(int a,int b){return a+b*-1;}int main(){return f(1,2);}",
+        r#"int f(int a, int b) {
+    return a + b * -1;
+}
+
+int main() {
+    return f(1, 2);
+}
+"#
+    );
+
+    test_formatter!(
+        can_format_control_flow,
+        r#"int main() {
+        int i = 0;
+      for(i=0;i<10;i=i+1){ if(!(i==2)) {i=i - 1;} else { goto exit; } }
+  exit:
+            return i;
+}"#,
+        r#"int main() {
+    int i = 0;
+    for (i = 0; i < 10; i = i + 1) {
+        if (!(i == 2)) {
+            i = i - 1;
+        } else {
+            goto exit;
+        }
+    }
+    exit:
+    return i;
+}
+"#
+    );
+
+    test_formatter!(
+        can_preserve_comments,
+        r#"// Entry point.
+int main() { /* The answer. */ return 42; // Done.
+}"#,
+        r#"// Entry point.
+int main() {
+    /* The answer. */
+    return 42;
+    // Done.
+}
+"#
+    );
+
+    test_formatter!(
+        can_format_empty_for_loop_header,
+        "int main() { for(;;) { } }",
+        r#"int main() {
+    for (;;) {
+    }
+}
+"#
+    );
+}
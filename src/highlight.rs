@@ -0,0 +1,139 @@
+//! Token classification for syntax highlighting.
+//!
+//! `classify` maps every token in the source to a coarse `TokenClass`,
+//! editors (LSP semantic tokens) and the playground use the returned spans
+//! to colorize the source code without having to parse it.
+use crate::scanner::{ScanError, Scanner};
+use crate::token::{Span, Token};
+
+/// Classes of tokens distinguished by the highlighter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenClass {
+    Keyword,
+    Type,
+    Identifier,
+    Literal,
+    Operator,
+    Punctuation,
+    Comment,
+}
+
+impl TokenClass {
+    /// Returns the token class of a given token, `None` for `Token::Eof`.
+    #[must_use]
+    pub const fn of(token: &Token) -> Option<Self> {
+        let class = match token {
+            Token::LParen
+            | Token::RParen
+            | Token::LBrace
+            | Token::RBrace
+            | Token::LBracket
+            | Token::RBracket
+            | Token::SemiColon
+            | Token::Colon
+            | Token::Comma => Self::Punctuation,
+            Token::Equal
+            | Token::BangEqual
+            | Token::EqualEqual
+            | Token::GreaterEqual
+            | Token::LesserEqual
+            | Token::Greater
+            | Token::Lesser
+            | Token::Plus
+            | Token::Minus
+            | Token::Slash
+            | Token::Star
+            | Token::And
+            | Token::Or
+            | Token::Bang => Self::Operator,
+            Token::IntLiteral(_)
+            | Token::CharLiteral(_)
+            | Token::StringLiteral(_)
+            | Token::BoolLiteral(_)
+            | Token::True
+            | Token::False => Self::Literal,
+            Token::Identifier(_) => Self::Identifier,
+            Token::Comment(_) => Self::Comment,
+            Token::Const
+            | Token::Void
+            | Token::If
+            | Token::Else
+            | Token::While
+            | Token::For
+            | Token::Break
+            | Token::Switch
+            | Token::Case
+            | Token::Default
+            | Token::Goto
+            | Token::Asm
+            | Token::Extern
+            | Token::Static
+            | Token::Volatile
+            | Token::Return => Self::Keyword,
+            Token::Int | Token::Char | Token::Bool => Self::Type,
+            Token::Eof => return None,
+        };
+        Some(class)
+    }
+}
+
+/// Classify the tokens of `source`, spans are returned in source order.
+///
+/// # Errors
+///
+/// Returns an error if the source code can't be scanned.
+pub fn classify(source: &str) -> Result<Vec<(Span, TokenClass)>, ScanError> {
+    Ok(Scanner::new(source)
+        .with_comments()
+        .scan_with_spans()?
+        .iter()
+        .filter_map(|(token, span)| {
+            TokenClass::of(token).map(|class| (*span, class))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::highlight::{classify, TokenClass};
+    use crate::token::Span;
+
+    // Macro to generate test cases.
+    macro_rules! test_classify {
+        ($name:ident, $source:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                let classes = classify($source).unwrap();
+                assert_eq!(&classes, $expected);
+            }
+        };
+    }
+
+    test_classify!(
+        can_classify_declaration,
+        "int a = 42; // answer",
+        &vec![
+            (Span::new(0, 3), TokenClass::Type),
+            (Span::new(4, 5), TokenClass::Identifier),
+            (Span::new(6, 7), TokenClass::Operator),
+            (Span::new(8, 10), TokenClass::Literal),
+            (Span::new(10, 11), TokenClass::Punctuation),
+            (Span::new(12, 21), TokenClass::Comment),
+        ]
+    );
+
+    test_classify!(
+        can_classify_keywords_and_literals,
+        "if (true) return \"hi\";\n/* done */",
+        &vec![
+            (Span::new(0, 2), TokenClass::Keyword),
+            (Span::new(3, 4), TokenClass::Punctuation),
+            (Span::new(4, 8), TokenClass::Literal),
+            (Span::new(8, 9), TokenClass::Punctuation),
+            (Span::new(10, 16), TokenClass::Keyword),
+            (Span::new(17, 21), TokenClass::Literal),
+            (Span::new(21, 22), TokenClass::Punctuation),
+            (Span::new(23, 33), TokenClass::Comment),
+        ]
+    );
+}
@@ -0,0 +1,188 @@
+//! Instruction-level parallelism analysis.
+//!
+//! The analysis compares the number of instructions in every basic block
+//! with the length of its critical path, the longest chain of instructions
+//! depending on each other. Their ratio is a rough measure of how many
+//! instructions a wide enough core could issue every cycle, passes such as
+//! reassociation shorten dependence chains and should raise it.
+//!
+//! Every instruction is assumed to take a single cycle. An instruction
+//! depends on the instructions of its block defining the values it reads,
+//! instructions with side effects also stay in program order. Blocks are
+//! assumed to run one after the other so the critical path of a function is
+//! the sum of the critical paths of its blocks.
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::cfg::Graph;
+use crate::ir::{Function, Instruction, Value};
+
+/// Parallelism of a sequence of instructions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Parallelism {
+    /// Number of instructions, labels and `nop` excluded.
+    pub instructions: usize,
+    /// Length of the longest dependence chain.
+    pub critical_path: usize,
+}
+
+impl Parallelism {
+    /// Returns the average number of instructions available to issue every
+    /// cycle, 1.0 for empty sequences.
+    #[must_use]
+    pub fn ilp(&self) -> f64 {
+        if self.critical_path == 0 {
+            return 1.0;
+        }
+        self.instructions as f64 / self.critical_path as f64
+    }
+}
+
+impl fmt::Display for Parallelism {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} instructions, critical path {}, ilp {:.2}",
+            self.instructions,
+            self.critical_path,
+            self.ilp()
+        )
+    }
+}
+
+/// Parallelism of a function and of each of its basic blocks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionIlp {
+    /// Function name.
+    pub function: String,
+    /// Parallelism of the whole function.
+    pub total: Parallelism,
+    /// Label and parallelism of every basic block, in layout order. The
+    /// entry block is labeled `entry` when it has no label.
+    pub blocks: Vec<(String, Parallelism)>,
+}
+
+impl fmt::Display for FunctionIlp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}: {}", self.function, self.total)?;
+        for (label, block) in &self.blocks {
+            write!(f, "\n  {label}: {block}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the parallelism of a straight line sequence of instructions.
+#[must_use]
+pub fn block_parallelism(insts: &[Instruction]) -> Parallelism {
+    // Depth of the chain ending at the last definition of every name.
+    let mut depths = HashMap::new();
+    // Depth of the last instruction with side effects.
+    let mut effects = 0;
+    let mut result = Parallelism::default();
+    for inst in insts {
+        if matches!(inst, Instruction::Label(..) | Instruction::Nop) {
+            continue;
+        }
+        let mut depth = inst
+            .uses()
+            .filter_map(|value| match value {
+                Value::StorageLocation(symbol) => {
+                    depths.get(symbol.name()).copied()
+                }
+                Value::ConstantLiteral(..) => None,
+            })
+            .max()
+            .unwrap_or(0);
+        if inst.has_side_effects() || inst.barrier() {
+            depth = depth.max(effects);
+        }
+        depth += 1;
+        if inst.has_side_effects() || inst.barrier() {
+            effects = depth;
+        }
+        if let Some(dst) = inst.destination() {
+            depths.insert(dst.name(), depth);
+        }
+        result.instructions += 1;
+        result.critical_path = result.critical_path.max(depth);
+    }
+    result
+}
+
+/// Returns the parallelism of `function` and of each of its basic blocks.
+#[must_use]
+pub fn function_parallelism(function: &Function) -> FunctionIlp {
+    let mut total = Parallelism::default();
+    let blocks = Graph::form_basic_blocks(function)
+        .iter()
+        .map(|block| {
+            let label = match block.instructions().first() {
+                Some(label @ Instruction::Label(..)) => label.to_string(),
+                _ => "entry".to_string(),
+            };
+            let parallelism = block_parallelism(block.instructions());
+            total.instructions += parallelism.instructions;
+            total.critical_path += parallelism.critical_path;
+            (label, parallelism)
+        })
+        .collect();
+    FunctionIlp {
+        function: function.name().to_string(),
+        total,
+        blocks,
+    }
+}
+
+/// Returns the parallelism of every function in `functions`, in order.
+#[must_use]
+pub fn analyze(functions: &[Function]) -> Vec<FunctionIlp> {
+    functions.iter().map(function_parallelism).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ilp::{self, Parallelism};
+    use crate::ir::IRBuilder;
+    use crate::optim::PassRegistry;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::sema::analyze;
+
+    #[test]
+    fn can_measure_instruction_level_parallelism() {
+        let source = r"
+            int sum(int a, int b, int c, int d) { return a + b + c + d; }
+            int main() { return sum(1, 2, 3, 4); }
+        ";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let mut functions = irgen.functions().to_vec();
+        let before = ilp::analyze(&functions);
+        assert_eq!(before[0].blocks.len(), 1);
+        assert_eq!(before[0].blocks[0].0, "entry");
+        // `((a + b) + c) + d` then the return.
+        assert_eq!(before[0].total.critical_path, 4);
+
+        let reassociate =
+            PassRegistry::default().pipeline("reassociate").unwrap();
+        reassociate.run(&mut functions[0]);
+        // `(a + b) + (c + d)` then the return.
+        let after = ilp::function_parallelism(&functions[0]);
+        assert_eq!(after.total.critical_path, 3);
+        assert!(after.total.ilp() > before[0].total.ilp());
+        assert_eq!(
+            Parallelism {
+                instructions: 6,
+                critical_path: 4
+            }
+            .to_string(),
+            "6 instructions, critical path 4, ilp 1.50"
+        );
+    }
+}
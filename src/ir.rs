@@ -8,11 +8,24 @@
 //! `const` and `id` are core to the way the IR is structured as they allow us
 //! to easily translate into and out of SSA form; with potentially translating
 //! out of SSA can forgo the rename phase and just prune all the phi nodes.
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
 use std::fmt;
+use std::ops::{Deref, DerefMut};
 
-use crate::ast::{self, Visitor};
+use crate::ast::{self, Ref, Visitor};
+use crate::builtins::{
+    escape_char, parse_format, unescape, AtomicOp, Builtin, Directive,
+    MemoryOrder, EXIT, PRINTF,
+};
+use crate::cfg::Graph;
+use crate::consteval::{self, ArithmeticMode};
+use crate::conversions;
+use crate::coverage::{Coverage, COUNTERS_SYMBOL};
+use crate::limits::{LimitError, MAX_INSTRUCTIONS};
 use crate::sema::{self};
+use crate::simplify;
+use crate::trace;
 
 /// Types used in the IR.
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -51,7 +64,7 @@ impl fmt::Display for Type {
 }
 
 /// Literal values.
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Literal {
     /// Empty value.
     #[default]
@@ -70,7 +83,7 @@ impl fmt::Display for Literal {
             Self::Empty => write!(f, "NONE"),
             Self::Int(value) => write!(f, "{value}"),
             Self::Bool(value) => write!(f, "{value}"),
-            Self::Char(value) => write!(f, "{value}"),
+            Self::Char(value) => write!(f, "{}", escape_char(*value)),
         }
     }
 }
@@ -112,15 +125,129 @@ impl fmt::Display for Symbol {
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Label(usize);
 
+impl Label {
+    pub const fn new(index: usize) -> Self {
+        Self(index)
+    }
+
+    /// Returns the label index, the index of the `Label` pseudo instruction
+    /// marking the target.
+    pub const fn index(&self) -> usize {
+        self.0
+    }
+}
+
 impl fmt::Display for Label {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, ".LABEL_{}", self.0)
     }
 }
 
+/// Branch hints record whether the `then` target of a conditional branch is
+/// expected to be taken, hints come from `__builtin_expect` and are used by
+/// block layout and if-conversion heuristics.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BranchHint {
+    #[default]
+    Unknown,
+    Likely,
+    Unlikely,
+}
+
+impl BranchHint {
+    /// Returns the `(then, else)` branch weights, likely branches are given
+    /// the same 2000:1 ratio LLVM uses for `__builtin_expect`.
+    pub const fn weights(&self) -> (u32, u32) {
+        match self {
+            Self::Unknown => (1, 1),
+            Self::Likely => (2000, 1),
+            Self::Unlikely => (1, 2000),
+        }
+    }
+
+    /// Returns the hint of the branch with swapped targets.
+    #[must_use]
+    pub const fn invert(self) -> Self {
+        match self {
+            Self::Unknown => Self::Unknown,
+            Self::Likely => Self::Unlikely,
+            Self::Unlikely => Self::Likely,
+        }
+    }
+}
+
+impl fmt::Display for BranchHint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown => Ok(()),
+            Self::Likely => write!(f, " !likely"),
+            Self::Unlikely => write!(f, " !unlikely"),
+        }
+    }
+}
+
+/// Strategy used to dispatch a `switch` statement to its case labels.
+///
+/// The IR has no indirect branch, so `JumpTable` lowers to a bounds check on
+/// the case range followed by the comparison tree; the table itself is not
+/// implemented.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SwitchLowering {
+    // Pick a strategy from the number and density of the cases.
+    #[default]
+    Auto,
+    // Bounds check then dispatch, for dense case ranges.
+    JumpTable,
+    // Linear sequence of equality tests.
+    Chain,
+    // Balanced binary search tree of comparisons.
+    Tree,
+}
+
+impl SwitchLowering {
+    /// Switches with at most this many cases are lowered to a chain.
+    pub const CHAIN_THRESHOLD: usize = 3;
+    /// Minimum ratio of cases to values in the case range for a jump table.
+    pub const JUMP_TABLE_DENSITY: f64 = 0.4;
+
+    /// Returns the strategy used for a switch with the given sorted `cases`,
+    /// `Auto` picks a chain for small switches, a jump table for dense ones
+    /// and a tree for sparse ones.
+    #[must_use]
+    pub fn select(self, cases: &[i32]) -> Self {
+        if self != Self::Auto {
+            return self;
+        }
+        let (Some(min), Some(max)) = (cases.first(), cases.last()) else {
+            return Self::Chain;
+        };
+        if cases.len() <= Self::CHAIN_THRESHOLD {
+            return Self::Chain;
+        }
+        let range = (i64::from(*max) - i64::from(*min) + 1) as f64;
+        if cases.len() as f64 / range >= Self::JUMP_TABLE_DENSITY {
+            Self::JumpTable
+        } else {
+            Self::Tree
+        }
+    }
+
+    /// Returns the strategy named `name` as passed to `--switch-lowering`.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(Self::Auto),
+            "table" => Some(Self::JumpTable),
+            "chain" => Some(Self::Chain),
+            "tree" => Some(Self::Tree),
+            _ => None,
+        }
+    }
+}
+
 /// Every value in the intermediate representation is either a symbol reference
 /// to a storage location or a literal value.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Value {
     StorageLocation(Symbol),
     ConstantLiteral(Literal),
@@ -135,6 +262,120 @@ impl fmt::Display for Value {
     }
 }
 
+/// Argument list of a call or an intrinsic. Empty and single argument lists
+/// are stored inline and only longer ones are allocated on the heap, so
+/// building and cloning most calls to builtins and unary functions doesn't
+/// allocate. A single inline slot keeps `Instruction` from growing.
+#[derive(Debug, Clone)]
+pub struct Operands(OperandStorage);
+
+#[derive(Debug, Clone)]
+enum OperandStorage {
+    Empty,
+    Single(Value),
+    Spilled(Vec<Value>),
+}
+
+impl Operands {
+    /// Create an empty argument list.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(OperandStorage::Empty)
+    }
+
+    /// Append `value` to the arguments, spilling them to the heap once they
+    /// don't fit inline.
+    pub fn push(&mut self, value: Value) {
+        self.0 = match std::mem::replace(&mut self.0, OperandStorage::Empty) {
+            OperandStorage::Empty => OperandStorage::Single(value),
+            OperandStorage::Single(first) => {
+                OperandStorage::Spilled(vec![first, value])
+            }
+            OperandStorage::Spilled(mut values) => {
+                values.push(value);
+                OperandStorage::Spilled(values)
+            }
+        };
+    }
+
+    /// Returns the arguments as a slice.
+    #[must_use]
+    pub fn as_slice(&self) -> &[Value] {
+        self
+    }
+
+    /// Returns `true` if the arguments are allocated on the heap.
+    #[must_use]
+    pub const fn spilled(&self) -> bool {
+        matches!(self.0, OperandStorage::Spilled(_))
+    }
+}
+
+impl Default for Operands {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for Operands {
+    type Target = [Value];
+
+    fn deref(&self) -> &[Value] {
+        match &self.0 {
+            OperandStorage::Empty => &[],
+            OperandStorage::Single(value) => std::slice::from_ref(value),
+            OperandStorage::Spilled(values) => values,
+        }
+    }
+}
+
+impl DerefMut for Operands {
+    fn deref_mut(&mut self) -> &mut [Value] {
+        match &mut self.0 {
+            OperandStorage::Empty => &mut [],
+            OperandStorage::Single(value) => std::slice::from_mut(value),
+            OperandStorage::Spilled(values) => values,
+        }
+    }
+}
+
+impl PartialEq for Operands {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Operands {}
+
+impl FromIterator<Value> for Operands {
+    fn from_iter<I: IntoIterator<Item = Value>>(iter: I) -> Self {
+        let mut operands = Self::new();
+        for value in iter {
+            operands.push(value);
+        }
+        operands
+    }
+}
+
+impl From<Vec<Value>> for Operands {
+    fn from(values: Vec<Value>) -> Self {
+        if values.len() < 2 {
+            values.into_iter().collect()
+        } else {
+            Self(OperandStorage::Spilled(values))
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Operands {
+    type Item = &'a Value;
+    type IntoIter = std::slice::Iter<'a, Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
 /// OPCode is a type wrapper around all opcodes.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum OPCode {
@@ -153,6 +394,10 @@ pub enum OPCode {
     Sub,
     Mul,
     Div,
+    // Shift and mask operators.
+    Shl,
+    Shr,
+    BitAnd,
     // Comparison operators.
     Eq,
     Neq,
@@ -168,10 +413,24 @@ pub enum OPCode {
     Or,
     // Identity operator.
     Id,
+    // Type conversions.
+    Cast,
     // Label pseudo instruction.
     Label,
     // Nop instruction.
     Nop,
+    // Inline assembly.
+    Asm,
+    // Builtin function calls.
+    Intrinsic,
+    // Coverage counter increment.
+    Count,
+    // Program termination.
+    Exit,
+    // Runtime error.
+    Trap,
+    // Atomic memory operation.
+    Atomic,
 }
 
 /// Instructions in the intermediate representation are in three-address form.
@@ -185,6 +444,13 @@ pub enum Instruction {
     Sub(Symbol, Value, Value),
     Mul(Symbol, Value, Value),
     Div(Symbol, Value, Value),
+    // Shift operators, only emitted by the optimizer since the language has
+    // no bitwise operators. `shr` is an arithmetic shift, shift amounts are
+    // in `0..32`.
+    Shl(Symbol, Value, Value),
+    Shr(Symbol, Value, Value),
+    // Bitwise and of integers, `and` is the logical and of booleans.
+    BitAnd(Symbol, Value, Value),
     // Logical operations, similar to arithmetic operations but for boolean
     // values.
     And(Symbol, Value, Value),
@@ -201,6 +467,8 @@ pub enum Instruction {
     Gte(Symbol, Value, Value),
     // Return statements.
     Return(Value),
+    // Conversion of a value to the type of the destination.
+    Cast(Symbol, Value),
     // Function calls.
     Call(
         // Storage location for the function call result.
@@ -208,7 +476,7 @@ pub enum Instruction {
         // Called function name.
         Symbol,
         // Function arguments.
-        Vec<Value>,
+        Operands,
     ),
     // Direct jump to label.
     Jump(Label),
@@ -220,6 +488,8 @@ pub enum Instruction {
         Label,
         // Label for the `else` branch.
         Label,
+        // Hint on which branch is likely taken.
+        BranchHint,
     ),
     // Identity operator.
     Id(Symbol, Value),
@@ -227,6 +497,39 @@ pub enum Instruction {
     Label(usize),
     // Nop instruction.
     Nop,
+    // Inline assembly, emitted verbatim by the backends.
+    Asm(String),
+    // Calls to builtin functions.
+    Intrinsic(
+        // Storage location for the builtin result.
+        Symbol,
+        // Called builtin.
+        Builtin,
+        // Builtin arguments.
+        Operands,
+    ),
+    // Increment of a coverage counter, indexes the module level counter
+    // array.
+    Count(usize),
+    // Program termination with an exit code, unwinds every frame.
+    Exit(Value),
+    // Runtime error reported with a message, emitted by checks failing at
+    // runtime (e.g. overflowing arithmetic with `-ftrapv`), the program is
+    // aborted.
+    Trap(String),
+    // Atomic operation on a variable.
+    Atomic(
+        // Storage location for the operation result.
+        Symbol,
+        // Performed operation.
+        AtomicOp,
+        // Variable the operation applies to.
+        Value,
+        // Operand of stores and additions.
+        Option<Value>,
+        // Memory order of the operation.
+        MemoryOrder,
+    ),
 }
 
 impl fmt::Display for Instruction {
@@ -247,6 +550,15 @@ impl fmt::Display for Instruction {
             Instruction::Div(dst, lhs, rhs) => {
                 write!(f, "{}: {} = div {lhs} {rhs}", dst.0, dst.1)
             }
+            Instruction::Shl(dst, lhs, rhs) => {
+                write!(f, "{}: {} = shl {lhs} {rhs}", dst.0, dst.1)
+            }
+            Instruction::Shr(dst, lhs, rhs) => {
+                write!(f, "{}: {} = shr {lhs} {rhs}", dst.0, dst.1)
+            }
+            Instruction::BitAnd(dst, lhs, rhs) => {
+                write!(f, "{}: {} = bitand {lhs} {rhs}", dst.0, dst.1)
+            }
             Instruction::And(dst, lhs, rhs) => {
                 write!(f, "{}: {} = and {lhs} {rhs}", dst.0, dst.1)
             }
@@ -259,6 +571,9 @@ impl fmt::Display for Instruction {
             Instruction::Not(dst, operand) => {
                 write!(f, "{}: {} = not {operand}", dst.0, dst.1)
             }
+            Instruction::Cast(dst, operand) => {
+                write!(f, "{}: {} = cast {operand}", dst.0, dst.1)
+            }
             Instruction::Eq(dst, lhs, rhs) => {
                 write!(f, "{}: {} = eq {lhs} {rhs}", dst.0, dst.1)
             }
@@ -278,6 +593,15 @@ impl fmt::Display for Instruction {
                 write!(f, "{}: {} = gte {lhs} {rhs}", dst.0, dst.1)
             }
             Instruction::Return(value) => write!(f, "ret {value}"),
+            Instruction::Exit(code) => write!(f, "exit {code}"),
+            Instruction::Trap(message) => write!(f, "trap {message:?}"),
+            Instruction::Atomic(dst, op, variable, operand, order) => {
+                write!(f, "{}: {} = {op} {variable}", dst.0, dst.1)?;
+                if let Some(operand) = operand {
+                    write!(f, " {operand}")?;
+                }
+                write!(f, " {order}")
+            }
             Instruction::Call(dst, def, args) => {
                 write!(f, "{}: {} = call @{}", dst.0, dst.1, def.0)?;
                 for arg in args {
@@ -285,20 +609,36 @@ impl fmt::Display for Instruction {
                 }
                 write!(f, "")
             }
+            Instruction::Intrinsic(dst, builtin, args) => {
+                write!(f, "{}: {} = intrinsic @{builtin}", dst.0, dst.1)?;
+                for arg in args {
+                    write!(f, " {arg}")?;
+                }
+                Ok(())
+            }
             Instruction::Jump(target) => write!(f, "jmp {}", target),
-            Instruction::Branch(cond, then_target, else_target) => {
-                write!(f, "br {} {then_target} {else_target}", cond)
+            Instruction::Branch(cond, then_target, else_target, hint) => {
+                write!(f, "br {} {then_target} {else_target}{hint}", cond)
             }
             Instruction::Id(dst, value) => {
                 write!(f, "{}: {} = id {value}", dst.0, dst.1)
             }
             Instruction::Nop => write!(f, "nop"),
             Instruction::Label(addr) => write!(f, ".LABEL_{addr}"),
+            Instruction::Asm(code) => write!(f, "asm \"{code}\""),
+            Instruction::Count(counter) => {
+                write!(f, "count @{COUNTERS_SYMBOL} {counter}")
+            }
         }
     }
 }
 
 impl Instruction {
+    /// Returns a `Printer` to display the instruction in the given style.
+    pub fn display(&self, style: PrintStyle) -> Printer<'_, Self> {
+        Printer { inner: self, style }
+    }
+
     /// Returns `true` if the instruction is considered a terminator.
     pub fn terminator(&self) -> bool {
         match self {
@@ -306,10 +646,21 @@ impl Instruction {
             | Self::Jump(..)
             | Self::Branch(..)
             | Self::Return(..) => true,
-            _ => false,
+            _ => self.no_return(),
         }
     }
 
+    /// Returns `true` if control never continues past the instruction, the
+    /// instructions following it up to the next label are unreachable.
+    pub fn no_return(&self) -> bool {
+        matches!(
+            self,
+            Self::Exit(..)
+                | Self::Trap(..)
+                | Self::Intrinsic(_, Builtin::Trap, _)
+        )
+    }
+
     /// Returns `true` if the instruction is a label, which is a pseudo
     /// instruction used to mark offsets in the instructions slice.
     pub fn label(&self) -> bool {
@@ -319,6 +670,37 @@ impl Instruction {
         }
     }
 
+    /// Returns `true` if the instruction is an optimization barrier, inline
+    /// assembly is opaque to the compiler so transforms must not remove it
+    /// or move instructions across it. Atomics synchronizing with other
+    /// threads (any order but relaxed) are barriers as well.
+    pub fn barrier(&self) -> bool {
+        match self {
+            Self::Asm(..) => true,
+            Self::Atomic(.., order) => *order != MemoryOrder::Relaxed,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the instruction has side effects besides assigning
+    /// its destination, such instructions can't be removed when their
+    /// destination is unused.
+    pub fn has_side_effects(&self) -> bool {
+        match self {
+            Self::Call(..)
+            | Self::Asm(..)
+            | Self::Count(..)
+            | Self::Exit(..)
+            | Self::Trap(..) => true,
+            // Relaxed loads are the only atomics without an effect.
+            Self::Atomic(_, op, .., order) => {
+                *op != AtomicOp::Load || *order != MemoryOrder::Relaxed
+            }
+            Self::Intrinsic(_, builtin, _) => builtin.has_side_effects(),
+            _ => false,
+        }
+    }
+
     /// Returns the assignment destination of an IR instruction.
     pub fn destination(&self) -> Option<&Symbol> {
         match self {
@@ -328,17 +710,68 @@ impl Instruction {
             Self::Sub(dst, ..) => Some(dst),
             Self::Mul(dst, ..) => Some(dst),
             Self::Div(dst, ..) => Some(dst),
+            Self::Shl(dst, ..) => Some(dst),
+            Self::Shr(dst, ..) => Some(dst),
+            Self::BitAnd(dst, ..) => Some(dst),
+            Self::And(dst, ..) => Some(dst),
+            Self::Or(dst, ..) => Some(dst),
+            Self::Not(dst, ..) => Some(dst),
+            Self::Neg(dst, ..) => Some(dst),
+            Self::Cast(dst, ..) => Some(dst),
+            Self::Call(dst, ..) => Some(dst),
             Self::Eq(dst, ..) => Some(dst),
             Self::Neq(dst, ..) => Some(dst),
             Self::Lt(dst, ..) => Some(dst),
             Self::Lte(dst, ..) => Some(dst),
             Self::Gt(dst, ..) => Some(dst),
             Self::Gte(dst, ..) => Some(dst),
+            Self::Atomic(dst, ..) => Some(dst),
             Self::Branch(..) => None,
             Self::Jump(..) => None,
-            Self::Return(..) => None,
+            Self::Return(..) | Self::Exit(..) | Self::Trap(..) => None,
             Self::Label(..) => None,
-            _ => todo!("Todo {self}"),
+            Self::Asm(..) | Self::Count(..) => None,
+            Self::Intrinsic(dst, ..) => Some(dst),
+            Self::Nop => None,
+        }
+    }
+
+    /// Returns a mutable reference to the assignment destination of an IR
+    /// instruction.
+    pub fn destination_mut(&mut self) -> Option<&mut Symbol> {
+        match self {
+            Self::Id(dst, ..)
+            | Self::Const(dst, ..)
+            | Self::Add(dst, ..)
+            | Self::Sub(dst, ..)
+            | Self::Mul(dst, ..)
+            | Self::Div(dst, ..)
+            | Self::Shl(dst, ..)
+            | Self::Shr(dst, ..)
+            | Self::BitAnd(dst, ..)
+            | Self::And(dst, ..)
+            | Self::Or(dst, ..)
+            | Self::Not(dst, ..)
+            | Self::Neg(dst, ..)
+            | Self::Cast(dst, ..)
+            | Self::Call(dst, ..)
+            | Self::Eq(dst, ..)
+            | Self::Neq(dst, ..)
+            | Self::Lt(dst, ..)
+            | Self::Lte(dst, ..)
+            | Self::Gt(dst, ..)
+            | Self::Gte(dst, ..)
+            | Self::Intrinsic(dst, ..)
+            | Self::Atomic(dst, ..) => Some(dst),
+            Self::Branch(..)
+            | Self::Jump(..)
+            | Self::Return(..)
+            | Self::Exit(..)
+            | Self::Trap(..)
+            | Self::Label(..)
+            | Self::Asm(..)
+            | Self::Count(..)
+            | Self::Nop => None,
         }
     }
 
@@ -353,10 +786,14 @@ impl Instruction {
             Self::Sub(.., lhs, rhs) => (Some(lhs), Some(rhs)),
             Self::Mul(.., lhs, rhs) => (Some(lhs), Some(rhs)),
             Self::Div(.., lhs, rhs) => (Some(lhs), Some(rhs)),
+            Self::Shl(.., lhs, rhs) => (Some(lhs), Some(rhs)),
+            Self::Shr(.., lhs, rhs) => (Some(lhs), Some(rhs)),
+            Self::BitAnd(.., lhs, rhs) => (Some(lhs), Some(rhs)),
             Self::And(.., lhs, rhs) => (Some(lhs), Some(rhs)),
             Self::Or(.., lhs, rhs) => (Some(lhs), Some(rhs)),
             Self::Neg(.., operand) => (Some(operand), None),
             Self::Not(.., operand) => (Some(operand), None),
+            Self::Cast(.., operand) => (Some(operand), None),
             Self::Eq(.., lhs, rhs) => (Some(lhs), Some(rhs)),
             Self::Neq(.., lhs, rhs) => (Some(lhs), Some(rhs)),
             Self::Lt(.., lhs, rhs) => (Some(lhs), Some(rhs)),
@@ -366,11 +803,75 @@ impl Instruction {
             Self::Branch(operand, ..) => (Some(operand), None),
             Self::Jump(..) => (None, None),
             Self::Label(..) => (None, None),
-            Self::Return(operand) => (Some(operand), None),
+            Self::Asm(..) | Self::Count(..) | Self::Trap(..) => (None, None),
+            // Builtins take at most two arguments.
+            Self::Intrinsic(.., args) => (args.first(), args.get(1)),
+            Self::Return(operand) | Self::Exit(operand) => {
+                (Some(operand), None)
+            }
+            Self::Atomic(_, _, variable, operand, _) => {
+                (Some(variable), operand.as_ref())
+            }
             _ => todo!("{self}"),
         }
     }
 
+    /// Returns the values read by the instruction including call arguments,
+    /// left to right. Unlike `values_mut` it doesn't allocate, analyses
+    /// scanning every instruction of a function should prefer it.
+    pub fn uses(&self) -> impl Iterator<Item = &Value> + '_ {
+        let (args, (lhs, rhs)) = match self {
+            Self::Call(.., args) | Self::Intrinsic(.., args) => {
+                (&args[..], (None, None))
+            }
+            Self::Nop => (&[][..], (None, None)),
+            _ => (&[][..], self.operands()),
+        };
+        args.iter().chain(lhs).chain(rhs)
+    }
+
+    /// Returns mutable references to all the values read by the instruction
+    /// including call arguments.
+    pub fn values_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Self::Add(.., lhs, rhs)
+            | Self::Sub(.., lhs, rhs)
+            | Self::Mul(.., lhs, rhs)
+            | Self::Div(.., lhs, rhs)
+            | Self::Shl(.., lhs, rhs)
+            | Self::Shr(.., lhs, rhs)
+            | Self::BitAnd(.., lhs, rhs)
+            | Self::And(.., lhs, rhs)
+            | Self::Or(.., lhs, rhs)
+            | Self::Eq(.., lhs, rhs)
+            | Self::Neq(.., lhs, rhs)
+            | Self::Lt(.., lhs, rhs)
+            | Self::Lte(.., lhs, rhs)
+            | Self::Gt(.., lhs, rhs)
+            | Self::Gte(.., lhs, rhs) => vec![lhs, rhs],
+            Self::Const(.., operand)
+            | Self::Id(.., operand)
+            | Self::Neg(.., operand)
+            | Self::Not(.., operand)
+            | Self::Cast(.., operand)
+            | Self::Branch(operand, ..)
+            | Self::Return(operand)
+            | Self::Exit(operand) => vec![operand],
+            Self::Call(.., args) | Self::Intrinsic(.., args) => {
+                args.iter_mut().collect()
+            }
+            Self::Atomic(_, _, variable, operand, _) => {
+                std::iter::once(variable).chain(operand).collect()
+            }
+            Self::Jump(..)
+            | Self::Label(..)
+            | Self::Nop
+            | Self::Asm(..)
+            | Self::Count(..)
+            | Self::Trap(..) => vec![],
+        }
+    }
+
     /// Returns the instruction opcode as `OPCode`.
     pub fn opcode(&self) -> OPCode {
         match self {
@@ -379,10 +880,14 @@ impl Instruction {
             Instruction::Sub(..) => OPCode::Sub,
             Instruction::Mul(..) => OPCode::Mul,
             Instruction::Div(..) => OPCode::Div,
+            Instruction::Shl(..) => OPCode::Shl,
+            Instruction::Shr(..) => OPCode::Shr,
+            Instruction::BitAnd(..) => OPCode::BitAnd,
             Instruction::And(..) => OPCode::And,
             Instruction::Or(..) => OPCode::Or,
             Instruction::Neg(..) => OPCode::Neg,
             Instruction::Not(..) => OPCode::Not,
+            Instruction::Cast(..) => OPCode::Cast,
             Instruction::Eq(..) => OPCode::Eq,
             Instruction::Neq(..) => OPCode::Neq,
             Instruction::Lt(..) => OPCode::Lt,
@@ -390,12 +895,18 @@ impl Instruction {
             Instruction::Gt(..) => OPCode::Gt,
             Instruction::Gte(..) => OPCode::Gte,
             Instruction::Return(..) => OPCode::Return,
+            Instruction::Exit(..) => OPCode::Exit,
+            Instruction::Trap(..) => OPCode::Trap,
             Instruction::Call(..) => OPCode::Call,
             Instruction::Jump(..) => OPCode::Jump,
             Instruction::Branch(..) => OPCode::Branch,
             Instruction::Id(..) => OPCode::Id,
             Instruction::Nop => OPCode::Nop,
+            Instruction::Asm(..) => OPCode::Asm,
+            Instruction::Intrinsic(..) => OPCode::Intrinsic,
+            Instruction::Count(..) => OPCode::Count,
             Instruction::Label(..) => OPCode::Label,
+            Instruction::Atomic(..) => OPCode::Atomic,
         }
     }
 }
@@ -429,7 +940,7 @@ pub struct BasicBlock {
 ///
 /// The first handle or reference we expose is a `BlockRef` which is used
 /// to reference basic blocks (the nodes in the graph).
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct BlockRef(pub usize);
 
 impl Default for BasicBlock {
@@ -509,27 +1020,187 @@ enum Scope {
     Local,
 }
 
+/// `ConstantPool` records the temporaries holding each distinct literal of
+/// a function once its constants are pooled, pooled constants are defined
+/// once at the function entry and every use references the same temporary.
+///
+/// Backends that find reloading a constant cheaper than keeping it live
+/// across its uses can rematerialize pooled temporaries instead of spilling
+/// them.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct ConstantPool {
+    constants: Vec<(Symbol, Literal)>,
+}
+
+impl ConstantPool {
+    /// Returns the temporary holding `literal` with type `t` if it's pooled.
+    pub fn get(&self, t: Type, literal: Literal) -> Option<&Symbol> {
+        self.constants
+            .iter()
+            .find(|(symbol, lit)| symbol.t() == t && *lit == literal)
+            .map(|(symbol, _)| symbol)
+    }
+
+    /// Add a pooled constant.
+    pub fn insert(&mut self, symbol: Symbol, literal: Literal) {
+        self.constants.push((symbol, literal))
+    }
+
+    /// Returns the number of pooled constants.
+    pub fn len(&self) -> usize {
+        self.constants.len()
+    }
+
+    /// Returns `true` if no constants are pooled.
+    pub fn is_empty(&self) -> bool {
+        self.constants.is_empty()
+    }
+
+    /// Returns an iterator over the pooled temporaries and their literals.
+    pub fn iter(&self) -> impl Iterator<Item = &(Symbol, Literal)> {
+        self.constants.iter()
+    }
+
+    /// Rematerialization hook, returns a `const` instruction recomputing
+    /// `symbol` if it's a pooled temporary.
+    pub fn rematerialize(&self, symbol: &Symbol) -> Option<Instruction> {
+        self.constants
+            .iter()
+            .find(|(pooled, _)| pooled == symbol)
+            .map(|(pooled, literal)| {
+                Instruction::Const(
+                    pooled.clone(),
+                    Value::ConstantLiteral(*literal),
+                )
+            })
+    }
+}
+
+/// Mangle the name of a symbol with internal linkage defined in `module`.
+///
+/// The mangled name is `_GL` followed by the length prefixed module and
+/// symbol names (`_GL4util6helper` for `helper` in `util.c`) so helpers
+/// with the same name in different files don't collide at link time even
+/// when backends emit them as global symbols. Bytes that can't appear in
+/// an assembler symbol are escaped as `_` followed by their two hex digits
+/// and `_` itself as `__`, so distinct modules (`my-lib.c` and `my_lib.c`)
+/// never share a prefix.
+#[must_use]
+pub fn mangle(module: &str, name: &str) -> String {
+    let module = std::path::Path::new(module)
+        .file_stem()
+        .map_or_else(|| module.into(), |stem| stem.to_string_lossy())
+        .bytes()
+        .map(|byte| match byte {
+            b'_' => "__".to_string(),
+            byte if byte.is_ascii_alphanumeric() => {
+                char::from(byte).to_string()
+            }
+            byte => format!("_{byte:02x}"),
+        })
+        .collect::<String>();
+    format!("_GL{}{module}{}{name}", module.len(), name.len())
+}
+
 /// `Function` represents a function declaration in the AST, a `Function`
 /// is composed as a linear sequence of GIR instructions.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Function {
     // Function name.
     name: String,
+    // Function linkage, internal functions are local to their module.
+    linkage: ast::Linkage,
     // List of arguments the function accepts.
     args: Vec<Symbol>,
     // Body of the function.
     body: Vec<Instruction>,
     // Function's return type.
     return_type: Type,
+    // Constants pooled at the function entry.
+    constants: ConstantPool,
+    // Attributes of the function declaration.
+    attributes: Vec<ast::Attribute>,
+    // Globals backing the static local variables of the function.
+    statics: Vec<Symbol>,
+    // Variables qualified `volatile` the function can access, locals and
+    // globals.
+    volatiles: Vec<Symbol>,
+    // Section the function is placed in, the default text section if
+    // `None`.
+    section: Option<String>,
 }
 
 impl Function {
     fn new(name: &str, args: Vec<Symbol>, return_type: Type) -> Self {
         Self {
             name: name.to_string(),
+            linkage: ast::Linkage::External,
             args,
             body: vec![],
             return_type,
+            constants: ConstantPool::default(),
+            attributes: vec![],
+            statics: vec![],
+            volatiles: vec![],
+            section: None,
+        }
+    }
+
+    /// Returns the function name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the section the function is placed in, if it has a section
+    /// attribute.
+    pub fn section(&self) -> Option<&str> {
+        self.section.as_deref()
+    }
+
+    /// Returns the function's arguments.
+    pub fn args(&self) -> &[Symbol] {
+        &self.args
+    }
+
+    /// Returns the function's return type.
+    pub const fn return_type(&self) -> Type {
+        self.return_type
+    }
+
+    /// Returns the globals backing the static local variables of the
+    /// function, stores to them outlive the function.
+    pub fn statics(&self) -> &[Symbol] {
+        &self.statics
+    }
+
+    /// Returns the `volatile` variables accessed by the function, reads and
+    /// writes of these variables must not be removed, duplicated or
+    /// reordered with respect to each other.
+    pub fn volatiles(&self) -> &[Symbol] {
+        &self.volatiles
+    }
+
+    /// Returns the function linkage.
+    pub const fn linkage(&self) -> ast::Linkage {
+        self.linkage
+    }
+
+    /// Returns the attributes of the function declaration.
+    pub fn attributes(&self) -> &[ast::Attribute] {
+        &self.attributes
+    }
+
+    /// Returns `true` if the function was declared with `attribute`.
+    pub fn has_attribute(&self, attribute: ast::Attribute) -> bool {
+        self.attributes.contains(&attribute)
+    }
+
+    /// Returns the name of the function's symbol in `module`, the name of
+    /// functions with internal linkage is mangled.
+    pub fn symbol(&self, module: &str) -> String {
+        match self.linkage {
+            ast::Linkage::External => self.name.clone(),
+            ast::Linkage::Internal => mangle(module, &self.name),
         }
     }
 
@@ -543,6 +1214,17 @@ impl Function {
         self.body.len()
     }
 
+    /// Returns the size of the function in instructions, labels and `nop`
+    /// don't emit any code and aren't counted.
+    pub fn code_size(&self) -> usize {
+        self.body
+            .iter()
+            .filter(|inst| {
+                !matches!(inst, Instruction::Label(..) | Instruction::Nop)
+            })
+            .count()
+    }
+
     /// Returns a non-mutable slice of the function's body.
     pub fn instructions(&self) -> &[Instruction] {
         &self.body
@@ -553,6 +1235,69 @@ impl Function {
         self.body.as_mut()
     }
 
+    /// Returns a `Printer` to display the function in the given style.
+    pub fn display(&self, style: PrintStyle) -> Printer<'_, Self> {
+        Printer { inner: self, style }
+    }
+
+    /// Returns the function's constant pool.
+    pub const fn constants(&self) -> &ConstantPool {
+        &self.constants
+    }
+
+    /// Pool the function's constants, `const` instructions materializing the
+    /// same literal are merged into a single instruction at the function
+    /// entry and uses of the merged temporaries are rewritten to reference
+    /// the pooled one.
+    ///
+    /// Only temporaries are pooled, they're assigned exactly once so renaming
+    /// them is safe, and since the pooled definitions sit at the entry they
+    /// dominate every use. Named variables, such as the guards of static
+    /// locals, can be assigned more than once and keep their definitions.
+    pub fn pool_constants(&mut self) {
+        let mut renames = HashMap::new();
+        for inst in &mut self.body {
+            let Instruction::Const(dst, Value::ConstantLiteral(literal)) = inst
+            else {
+                continue;
+            };
+            if !dst.name().starts_with('%') {
+                continue;
+            }
+            match self.constants.get(dst.t(), *literal) {
+                Some(pooled) => {
+                    renames.insert(dst.clone(), pooled.clone());
+                }
+                None => self.constants.insert(dst.clone(), *literal),
+            }
+            *inst = Instruction::Nop;
+        }
+        for inst in &mut self.body {
+            for value in inst.values_mut() {
+                if let Value::StorageLocation(symbol) = value {
+                    if let Some(pooled) = renames.get(symbol) {
+                        *symbol = pooled.clone();
+                    }
+                }
+            }
+        }
+        self.remove_dead_instructions();
+        let entry = self
+            .constants
+            .iter()
+            .filter_map(|(symbol, _)| self.constants.rematerialize(symbol));
+        self.body.splice(0..0, entry.collect::<Vec<_>>());
+    }
+
+    /// Replace the instructions in `range` with `instructions`.
+    pub fn splice(
+        &mut self,
+        range: std::ops::Range<usize>,
+        instructions: impl IntoIterator<Item = Instruction>,
+    ) {
+        self.body.splice(range, instructions);
+    }
+
     /// Remove all dead instructions (`Nop`) in the function.
     pub fn remove_dead_instructions(&mut self) {
         self.body.retain(|inst| match inst {
@@ -560,11 +1305,145 @@ impl Function {
             _ => true,
         })
     }
+
+    /// Returns the first label index not used by the function.
+    pub fn next_label(&self) -> usize {
+        self.body
+            .iter()
+            .filter_map(|inst| match inst {
+                Instruction::Label(label) => Some(*label + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Returns the first temporary index not used by the function, passes
+    /// creating temporaries name them `%v{index}` from there on.
+    pub fn next_temp(&self) -> usize {
+        self.body
+            .iter()
+            .filter_map(Instruction::destination)
+            .filter_map(|dst| dst.name().strip_prefix("%v")?.parse().ok())
+            .map(|index: usize| index + 1)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Returns a copy of the instructions in `region` with their labels and
+    /// symbols renamed by `remapper`. Regions are ranges of the linear body
+    /// so a set of blocks is copied by passing the range spanning them, the
+    /// copy is left to the caller to splice back.
+    pub fn clone_region(
+        &self,
+        region: std::ops::Range<usize>,
+        remapper: &Remapper,
+    ) -> Vec<Instruction> {
+        self.body[region]
+            .iter()
+            .map(|inst| remapper.remap(inst.clone()))
+            .collect()
+    }
 }
 
-impl fmt::Display for Function {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "@{}", self.name)?;
+/// `Remapper` renames the labels and symbols of cloned instructions, labels
+/// and symbols it has no mapping for are left as is.
+///
+/// Inlining, loop unswitching, unrolling and specialization all copy code
+/// next to the original, `Remapper::fresh` builds the mapping giving the copy
+/// its own labels and temporaries so both versions can live in the same
+/// function.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Remapper {
+    labels: HashMap<usize, usize>,
+    symbols: HashMap<Symbol, Symbol>,
+}
+
+impl Remapper {
+    /// Create an empty remapper.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a remapper renaming the labels and the temporaries defined in
+    /// `region` of `function` to fresh ones. Named variables are shared by
+    /// both copies.
+    pub fn fresh(function: &Function, region: std::ops::Range<usize>) -> Self {
+        let mut next_label = function.next_label();
+        let mut next_temp = function.next_temp();
+        let mut remapper = Self::new();
+        for inst in &function.instructions()[region] {
+            if let Instruction::Label(label) = inst {
+                remapper.map_label(*label, next_label);
+                next_label += 1;
+            }
+            match inst.destination() {
+                Some(dst)
+                    if dst.name().starts_with('%')
+                        && !remapper.symbols.contains_key(dst) =>
+                {
+                    let renamed =
+                        Symbol::new(&format!("%v{next_temp}"), dst.t());
+                    remapper.map_symbol(dst.clone(), renamed);
+                    next_temp += 1;
+                }
+                _ => (),
+            }
+        }
+        remapper
+    }
+
+    /// Rename the label at index `from` to `to`.
+    pub fn map_label(&mut self, from: usize, to: usize) {
+        self.labels.insert(from, to);
+    }
+
+    /// Rename `from` to `to`.
+    pub fn map_symbol(&mut self, from: Symbol, to: Symbol) {
+        self.symbols.insert(from, to);
+    }
+
+    /// Returns the index `label` is renamed to.
+    pub fn label(&self, label: usize) -> usize {
+        self.labels.get(&label).copied().unwrap_or(label)
+    }
+
+    /// Returns the symbol `symbol` is renamed to.
+    pub fn symbol<'a>(&'a self, symbol: &'a Symbol) -> &'a Symbol {
+        self.symbols.get(symbol).unwrap_or(symbol)
+    }
+
+    /// Rename the labels and symbols of `inst`.
+    pub fn remap(&self, mut inst: Instruction) -> Instruction {
+        let relabel = |label: &mut Label| *label = Label(self.label(label.0));
+        match &mut inst {
+            Instruction::Label(label) => *label = self.label(*label),
+            Instruction::Jump(label) => relabel(label),
+            Instruction::Branch(_, then_label, else_label, _) => {
+                relabel(then_label);
+                relabel(else_label);
+            }
+            _ => (),
+        }
+        if let Some(dst) = inst.destination_mut() {
+            *dst = self.symbol(dst).clone();
+        }
+        for value in inst.values_mut() {
+            if let Value::StorageLocation(symbol) = value {
+                *symbol = self.symbol(symbol).clone();
+            }
+        }
+        inst
+    }
+}
+
+impl Function {
+    /// Write the function signature up to the opening brace.
+    fn fmt_header(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.linkage {
+            ast::Linkage::External => write!(f, "@{}", self.name)?,
+            ast::Linkage::Internal => write!(f, "@internal {}", self.name)?,
+        }
 
         if !self.args.is_empty() {
             write!(f, "(")?;
@@ -580,17 +1459,268 @@ impl fmt::Display for Function {
 
         write!(f, ": {}", self.return_type)?;
 
-        writeln!(f, " {{")?;
+        if !self.attributes.is_empty() {
+            let attributes = self
+                .attributes
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            write!(f, " [[{attributes}]]")?;
+        }
 
-        for inst in &self.body {
-            writeln!(f, "   {inst}")?;
+        if let Some(section) = &self.section {
+            write!(f, " section({section:?})")?;
+        }
+
+        writeln!(f, " {{")
+    }
+
+    /// Write the function one basic block at a time, each block starts with
+    /// a header naming the block and its predecessors.
+    fn fmt_blocks(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let graph = Graph::new(&vec![self.clone()]);
+        self.fmt_header(f)?;
+        for block_ref in graph.blocks() {
+            write!(f, "{}:", graph.label(block_ref))?;
+            let mut preds = vec![];
+            for pred in graph.predecessors(block_ref) {
+                let label = graph.label(pred);
+                if !preds.contains(&label) {
+                    preds.push(label);
+                }
+            }
+            if !preds.is_empty() {
+                write!(f, " ; preds = {}", preds.join(", "))?;
+            } else if block_ref != BlockRef(0) {
+                write!(f, " ; unreachable")?;
+            }
+            writeln!(f)?;
+            // The block label is printed in the header.
+            for inst in graph.block(block_ref).instructions() {
+                if !inst.label() {
+                    writeln!(f, "   {inst}")?;
+                }
+            }
         }
         writeln!(f, "}}")
     }
 }
 
-/// `IRBuilderTrackingRef` is a tuple of position within the function
-/// being currently lowered, a scope enum value to deal with nesting
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_header(f)?;
+        for inst in &self.body {
+            writeln!(f, "   {inst}")?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+/// Textual syntax used when printing the IR.
+///
+/// The native syntax is glouton's own flavor of Bril, the Bril syntax follows
+/// the `bril-txt` format so the output can be consumed by Bril tooling. Bril's
+/// core subset has no `neq` and `neg` operations, they are printed as is.
+///
+/// The blocks syntax prints native instructions grouped by basic block, each
+/// block starts with a header listing its predecessors.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrintStyle {
+    #[default]
+    Native,
+    Bril,
+    Blocks,
+}
+
+impl PrintStyle {
+    /// Returns the style named `name` (`native`, `bril` or `blocks`).
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "native" => Some(Self::Native),
+            "bril" => Some(Self::Bril),
+            "blocks" => Some(Self::Blocks),
+            _ => None,
+        }
+    }
+}
+
+/// `Printer` wraps an IR entity to display it using a given `PrintStyle`.
+pub struct Printer<'a, T> {
+    inner: &'a T,
+    style: PrintStyle,
+}
+
+impl fmt::Display for Printer<'_, Instruction> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.style != PrintStyle::Bril {
+            return write!(f, "{}", self.inner);
+        }
+        match self.inner {
+            Instruction::Const(
+                dst,
+                Value::ConstantLiteral(Literal::Char(c)),
+            ) => {
+                write!(f, "{}: {} = const {};", dst.0, dst.1, escape_char(*c))
+            }
+            Instruction::Const(dst, value) => {
+                write!(f, "{}: {} = const {value};", dst.0, dst.1)
+            }
+            Instruction::Add(dst, lhs, rhs)
+            | Instruction::Sub(dst, lhs, rhs)
+            | Instruction::Mul(dst, lhs, rhs)
+            | Instruction::Div(dst, lhs, rhs)
+            | Instruction::Shl(dst, lhs, rhs)
+            | Instruction::Shr(dst, lhs, rhs)
+            | Instruction::BitAnd(dst, lhs, rhs)
+            | Instruction::And(dst, lhs, rhs)
+            | Instruction::Or(dst, lhs, rhs)
+            | Instruction::Eq(dst, lhs, rhs)
+            | Instruction::Neq(dst, lhs, rhs)
+            | Instruction::Lt(dst, lhs, rhs)
+            | Instruction::Lte(dst, lhs, rhs)
+            | Instruction::Gt(dst, lhs, rhs)
+            | Instruction::Gte(dst, lhs, rhs) => {
+                let op = match self.inner.opcode() {
+                    OPCode::Add => "add",
+                    OPCode::Sub => "sub",
+                    OPCode::Mul => "mul",
+                    OPCode::Div => "div",
+                    OPCode::Shl => "shl",
+                    OPCode::Shr => "shr",
+                    OPCode::BitAnd => "bitand",
+                    OPCode::And => "and",
+                    OPCode::Or => "or",
+                    OPCode::Eq => "eq",
+                    OPCode::Neq => "neq",
+                    OPCode::Lt => "lt",
+                    OPCode::Lte => "le",
+                    OPCode::Gt => "gt",
+                    OPCode::Gte => "ge",
+                    _ => unreachable!("expected binary operation"),
+                };
+                write!(f, "{}: {} = {op} {lhs} {rhs};", dst.0, dst.1)
+            }
+            Instruction::Not(dst, operand) => {
+                write!(f, "{}: {} = not {operand};", dst.0, dst.1)
+            }
+            Instruction::Neg(dst, operand) => {
+                write!(f, "{}: {} = neg {operand};", dst.0, dst.1)
+            }
+            // Bril's char extension only converts between `char` and `int`.
+            Instruction::Cast(dst, operand) => {
+                let op = match (operand, dst.1) {
+                    (Value::StorageLocation(src), Type::Int)
+                        if src.1 == Type::Char =>
+                    {
+                        "char2int"
+                    }
+                    (Value::StorageLocation(src), Type::Char)
+                        if src.1 == Type::Int =>
+                    {
+                        "int2char"
+                    }
+                    _ => "cast",
+                };
+                write!(f, "{}: {} = {op} {operand};", dst.0, dst.1)
+            }
+            Instruction::Id(dst, value) => {
+                write!(f, "{}: {} = id {value};", dst.0, dst.1)
+            }
+            Instruction::Call(dst, def, args) => {
+                if dst.1 != Type::Unit {
+                    write!(f, "{}: {} = ", dst.0, dst.1)?;
+                }
+                write!(f, "call @{}", def.0)?;
+                for arg in args {
+                    write!(f, " {arg}")?;
+                }
+                write!(f, ";")
+            }
+            Instruction::Intrinsic(dst, builtin, args) => {
+                write!(f, "{}: {} = intrinsic @{builtin}", dst.0, dst.1)?;
+                for arg in args {
+                    write!(f, " {arg}")?;
+                }
+                write!(f, ";")
+            }
+            Instruction::Return(value) => write!(f, "ret {value};"),
+            // Bril has no exit instruction, the libc function is called.
+            Instruction::Exit(code) => write!(f, "call @exit {code};"),
+            // Nor atomics, they are printed as calls to their builtin and the
+            // memory order is dropped.
+            Instruction::Atomic(dst, op, variable, operand, _) => {
+                write!(
+                    f,
+                    "{}: {} = call @{} {variable}",
+                    dst.0,
+                    dst.1,
+                    op.name()
+                )?;
+                if let Some(operand) = operand {
+                    write!(f, " {operand}")?;
+                }
+                write!(f, ";")
+            }
+            // Nor a trap instruction, the message is kept as a comment.
+            Instruction::Trap(message) => {
+                write!(f, "call @abort; # {message}")
+            }
+            Instruction::Jump(target) => write!(f, "jmp {target};"),
+            // Bril has no branch metadata, hints are dropped.
+            Instruction::Branch(cond, then_target, else_target, _) => {
+                write!(f, "br {cond} {then_target} {else_target};")
+            }
+            Instruction::Label(addr) => write!(f, ".LABEL_{addr}:"),
+            Instruction::Nop => write!(f, "nop;"),
+            Instruction::Asm(code) => write!(f, "asm \"{code}\";"),
+            Instruction::Count(counter) => {
+                write!(f, "count @{COUNTERS_SYMBOL} {counter};")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Printer<'_, Function> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let function = self.inner;
+        match self.style {
+            PrintStyle::Native => return write!(f, "{function}"),
+            PrintStyle::Blocks => return function.fmt_blocks(f),
+            PrintStyle::Bril => (),
+        }
+        write!(f, "@{}", function.name)?;
+
+        if !function.args.is_empty() {
+            write!(f, "(")?;
+
+            for (i, arg) in function.args.iter().enumerate() {
+                if i != 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{arg}")?;
+            }
+            write!(f, ")")?;
+        }
+        // Functions that don't return a value have no type annotation.
+        if function.return_type != Type::Unit {
+            write!(f, ": {}", function.return_type)?;
+        }
+
+        writeln!(f, " {{")?;
+
+        for inst in &function.body {
+            // Labels are not indented.
+            let indent = if inst.label() { "" } else { "  " };
+            writeln!(f, "{indent}{}", inst.display(self.style))?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+/// `IRBuilderTrackingRef` is a tuple of position within the function
+/// being currently lowered, a scope enum value to deal with nesting
 /// and a pointer to the symbol table level we start symbol resolution
 /// from.
 struct IRBuilderContext {
@@ -670,21 +1800,102 @@ impl LocationLabelCounter {
     }
 }
 
-/// `GlobalValue` is a tuple of variable name and a compile time literal.
+/// `GlobalValue` is a tuple of variable name, a compile time literal and
+/// the section the variable is placed in, the default data section if `None`.
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
-pub struct GlobalValue(Symbol, Literal);
+pub struct GlobalValue(Symbol, Literal, Option<String>);
+
+impl GlobalValue {
+    /// Returns the global's symbol.
+    pub const fn symbol(&self) -> &Symbol {
+        &self.0
+    }
+
+    /// Returns the global's initial value.
+    pub const fn value(&self) -> Literal {
+        self.1
+    }
+
+    /// Returns the section the global is placed in, if it has a section
+    /// attribute.
+    pub fn section(&self) -> Option<&str> {
+        self.2.as_deref()
+    }
+}
+
+/// `Import` is an external function declaration, the function is defined
+/// outside the program and calls to it are resolved at link time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    // Function name.
+    name: String,
+    // List of arguments the function accepts.
+    args: Vec<Symbol>,
+    // Function's return type.
+    return_type: Type,
+}
+
+impl Import {
+    /// Returns the imported function's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Display for Import {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@extern {}(", self.name)?;
+        for (i, arg) in self.args.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{arg}")?;
+        }
+        write!(f, "): {}", self.return_type)
+    }
+}
 
 /// A program is a group of functions and globals.
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct Program {
+    imports: Vec<Import>,
     globals: Vec<GlobalValue>,
     functions: Vec<Function>,
 }
 
+impl Program {
+    /// Returns the external functions called by the program.
+    pub fn imports(&self) -> &[Import] {
+        &self.imports
+    }
+
+    /// Returns the program globals.
+    pub fn globals(&self) -> &[GlobalValue] {
+        &self.globals
+    }
+
+    /// Returns the program functions.
+    pub fn functions(&self) -> &[Function] {
+        &self.functions
+    }
+
+    /// Returns a mutable reference to the program functions.
+    pub fn functions_mut(&mut self) -> &mut [Function] {
+        &mut self.functions
+    }
+}
+
 impl fmt::Display for Program {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for import in &self.imports {
+            writeln!(f, "{import}")?
+        }
         for global in &self.globals {
-            writeln!(f, "{}: {}", global.0, global.1)?
+            write!(f, "{}: {}", global.0, global.1)?;
+            match &global.2 {
+                Some(section) => writeln!(f, " section({section:?})")?,
+                None => writeln!(f)?,
+            }
         }
 
         for func in &self.functions {
@@ -694,6 +1905,108 @@ impl fmt::Display for Program {
     }
 }
 
+/// Link error type is used to report symbols defined by more than one
+/// translation unit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkError {
+    details: String,
+}
+
+impl LinkError {
+    fn duplicate(name: &str, first: &str, second: &str) -> Self {
+        Self {
+            details: format!(
+                "duplicate definition of `{name}` in `{first}` and `{second}`"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for LinkError {}
+
+/// Link the programs of separate translation units, `units` pairs the
+/// module (source path) of every unit with its program.
+///
+/// Functions with internal linkage are renamed to their mangled symbol along
+/// with the calls to them, so helpers of different units don't collide.
+/// The globals backing static local variables have internal linkage too and
+/// are renamed along with their accesses.
+/// Imports defined by another unit are resolved and dropped, the remaining
+/// imports are deduplicated. Functions and globals with external linkage
+/// must be defined at most once. The linked program lists the functions and
+/// globals in the order of `units`.
+pub fn link(
+    units: impl IntoIterator<Item = (String, Program)>,
+) -> Result<Program, LinkError> {
+    let mut linked = Program::default();
+    let mut defined: HashMap<String, String> = HashMap::new();
+    for (module, mut program) in units {
+        let renames = program
+            .functions
+            .iter()
+            .filter(|function| function.linkage == ast::Linkage::Internal)
+            .map(|function| (function.name.clone(), function.symbol(&module)))
+            .collect::<HashMap<_, _>>();
+        let mut statics = Remapper::new();
+        for symbol in program.functions.iter().flat_map(Function::statics) {
+            let mangled =
+                Symbol::new(&mangle(&module, symbol.name()), symbol.t());
+            statics.map_symbol(symbol.clone(), mangled);
+        }
+        for global in &mut program.globals {
+            global.0 = statics.symbol(&global.0).clone();
+        }
+        for function in &mut program.functions {
+            if let Some(symbol) = renames.get(&function.name) {
+                function.name.clone_from(symbol);
+            }
+            for symbol in
+                function.statics.iter_mut().chain(&mut function.volatiles)
+            {
+                *symbol = statics.symbol(symbol).clone();
+            }
+            for inst in &mut function.body {
+                *inst =
+                    statics.remap(std::mem::replace(inst, Instruction::Nop));
+            }
+            for inst in &mut function.body {
+                if let Instruction::Call(_, def, _) = inst {
+                    if let Some(symbol) = renames.get(&def.0) {
+                        def.0.clone_from(symbol);
+                    }
+                }
+            }
+        }
+        let names = program
+            .functions
+            .iter()
+            .map(|function| function.name.as_str())
+            .chain(program.globals.iter().map(|global| global.0.name()));
+        for name in names {
+            if let Some(first) =
+                defined.insert(name.to_string(), module.clone())
+            {
+                return Err(LinkError::duplicate(name, &first, &module));
+            }
+        }
+        linked.imports.append(&mut program.imports);
+        linked.globals.append(&mut program.globals);
+        linked.functions.append(&mut program.functions);
+    }
+    let mut imported = HashSet::new();
+    linked.imports.retain(|import| {
+        !defined.contains_key(&import.name)
+            && imported.insert(import.name.clone())
+    });
+    Ok(linked)
+}
+
 /// `IRBuilder` is responsible for lowering the AST to the intermediate
 /// representation, the first lowering phase results in a program represented
 /// as a tuple of global values and functions. This first representation is
@@ -708,9 +2021,46 @@ pub struct IRBuilder<'a> {
     program: Vec<Function>,
     // Global scope declarations.
     globals: Vec<GlobalValue>,
+    // External functions declared by the program.
+    imports: Vec<Import>,
     // Counter used to keep track of temporaries, temporaries are storage
     // assignemnts for transient or non assigned values such as a literals.
     llc: LocationLabelCounter,
+    // Mapping from user-defined labels to IR labels in the function being
+    // lowered, reset for every function since labels are function scoped.
+    labels: HashMap<String, usize>,
+    // Stack of scopes mapping the local variables of the function being
+    // lowered to their unique IR names.
+    renames: Vec<HashMap<String, String>>,
+    // Number of declarations of every name in the function being lowered.
+    declarations: HashMap<String, usize>,
+    // Values of the `const` local variables with a constant initializer in
+    // the function being lowered, by unique IR name.
+    constants: HashMap<String, Literal>,
+    // Values of the `const` global variables with a constant initializer.
+    global_constants: HashMap<String, Literal>,
+    // Global variables qualified `volatile`.
+    global_volatiles: Vec<Symbol>,
+    // Functions declared `[[noreturn]]`.
+    noreturn: HashSet<String>,
+    // Exit labels of the enclosing loops and `switch` statements, targets of
+    // `break` statements.
+    breaks: Vec<usize>,
+    // Labels of the `case` values and of the `default` label of the enclosing
+    // `switch` statements.
+    switches: Vec<(HashMap<i32, usize>, usize)>,
+    // Strategy used to lower `switch` statements.
+    switch_lowering: SwitchLowering,
+    // Maximum number of instructions in the program.
+    max_instructions: usize,
+    // Coverage metadata, only present when coverage instrumentation is
+    // enabled.
+    coverage: Option<Coverage>,
+    // Counter of the basic block being lowered.
+    counter: usize,
+    // Whether the program runs without the C library, calls lowered to
+    // runtime library functions are rejected.
+    freestanding: bool,
     // TrackingRef for the `IRBuilder` acts as a composite pointer to keep track
     // of metadata that's useful during the lowering phase.
     context: IRBuilderContext,
@@ -721,7 +2071,22 @@ impl<'a> IRBuilder<'a> {
         Self {
             program: vec![],
             globals: vec![],
+            imports: vec![],
             llc: LocationLabelCounter::new(),
+            labels: HashMap::new(),
+            renames: vec![],
+            declarations: HashMap::new(),
+            constants: HashMap::new(),
+            global_constants: HashMap::new(),
+            global_volatiles: vec![],
+            noreturn: HashSet::new(),
+            breaks: vec![],
+            switches: vec![],
+            switch_lowering: SwitchLowering::default(),
+            max_instructions: MAX_INSTRUCTIONS,
+            coverage: None,
+            counter: 0,
+            freestanding: false,
             context: IRBuilderContext::new(),
             ast,
             symbol_table,
@@ -749,128 +2114,927 @@ impl<'a> IRBuilder<'a> {
         }
     }
 
-    /// Returns a non-mutable reference to the program functions.
-    pub const fn functions(&self) -> &Vec<Function> {
-        &self.program
+    /// Returns the IR label assigned to a user-defined label, labels can be
+    /// referenced before they are defined so the IR label is allocated on
+    /// first use.
+    fn user_label(&mut self, name: &str) -> usize {
+        if let Some(label) = self.labels.get(name) {
+            return *label;
+        }
+        let label = self.llc.next_label();
+        self.labels.insert(name.to_string(), label);
+        label
     }
 
-    /// Returns a mutable reference to the program functions.
-    pub fn functions_mut(&mut self) -> &mut [Function] {
-        &mut self.program
+    /// Bind a local variable or argument in the innermost scope and return
+    /// its IR name. Since the IR has no scopes, variables sharing the name of
+    /// a variable declared earlier in the function or of a global are renamed
+    /// `name.1`, `name.2`... so distinct variables never alias.
+    fn declare_local(&mut self, name: &str) -> String {
+        let shadows_global = self.symbol_table.global(name).is_some();
+        let count = self
+            .declarations
+            .entry(name.to_string())
+            .or_insert(usize::from(shadows_global));
+        let renamed = match *count {
+            0 => name.to_string(),
+            count => format!("{name}.{count}"),
+        };
+        *count += 1;
+        if let Some(scope) = self.renames.last_mut() {
+            scope.insert(name.to_string(), renamed.clone());
+        }
+        renamed
     }
 
-    /// Returns a non-mutable reference to the program globals.
-    pub const fn globals(&self) -> &Vec<GlobalValue> {
-        &self.globals
+    /// Returns the IR name of the variable `name` resolves to.
+    fn local_name(&self, name: &str) -> String {
+        self.renames
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .unwrap_or_else(|| name.to_string())
     }
 
-    /// Build the intermediate representation by invoking the IR-lowering
-    /// visitor.
-    pub fn build(&mut self) {
-        for decl in self.ast.declarations() {
-            let _ = self.visit_decl(decl);
+    /// Returns the value of the `const` variable `name` if its initializer
+    /// is a constant.
+    fn constant(&self, name: &str) -> Option<Literal> {
+        match self.renames.iter().rev().find_map(|scope| scope.get(name)) {
+            Some(local) => self.constants.get(local).copied(),
+            None => self.global_constants.get(name).copied(),
         }
     }
-}
-
-impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
-    fn visit_decl(
-        &mut self,
-        decl: &ast::Decl,
-    ) -> (Option<Value>, Vec<Instruction>) {
-        match decl {
-            // Function declarations are the only place where we need to switch
-            // scopes explicitely, since the scope only affects where variable
-            // declarations are positioned.
-            ast::Decl::Function {
-                name,
-                return_type,
-                args: parameters,
-                body,
-            } => {
-                // Build and push a new function frame.
-                let parameters = parameters
-                    .iter()
-                    .map(|arg| match self.ast.get_stmt(*arg) {
-                        Some(ast::Stmt::Argument { decl_type, name }) => {
-                            Symbol::new(name, Type::from(decl_type))
-                        }
-                        _ => unreachable!(
-                        "expected argument reference to be valid and to be `ast::Stmt::FuncArg`"
-                    ),
-                    })
-                    .collect::<Vec<_>>();
-                let return_type = Type::from(return_type);
-                let function = Function::new(name, parameters, return_type);
-                // Enter a new scope and push the new function frame.
-                self.program.push(function);
-                self.context.enter();
-
-                let mut code = vec![];
-                let mut span = None;
 
-                if let Some(func_body) = self.ast.get_stmt(*body) {
-                    match func_body {
-                        ast::Stmt::Block(body) => {
-                            for stmt_ref in body {
-                                if let Some(stmt) = self.ast.get_stmt(*stmt_ref)
-                                {
-                                    let (local_span, mut local_code): (
-                                        Option<Value>,
-                                        Vec<Instruction>,
-                                    ) = self.visit_stmt(stmt);
-                                    code.append(&mut local_code);
-                                    span = local_span;
-                                }
-                            }
-                        }
-                        _ => unreachable!(
-                            "function body must be a block statement"
-                        ),
-                    }
-                }
-                self.push(&code);
-                // Exit back to the global scope.
-                self.context.exit();
-                (span, code)
+    /// Evaluate the expression at `expr_ref` if it only involves literals
+    /// and `const` variables with a constant initializer.
+    fn fold(&self, expr_ref: ast::ExprRef) -> Option<Literal> {
+        match self.ast.get_expr(expr_ref)? {
+            ast::Expr::IntLiteral(value) => Some(Literal::Int(*value)),
+            ast::Expr::BoolLiteral(value) => Some(Literal::Bool(*value)),
+            ast::Expr::CharLiteral(value) => Some(Literal::Char(*value)),
+            ast::Expr::Named(name) => self.constant(name),
+            ast::Expr::Cast { to, operand } => {
+                Some(conversions::convert(self.fold(*operand)?, *to))
             }
-            ast::Decl::GlobalVariable {
-                decl_type,
-                name,
-                value,
-            } => {
-                let dst = Symbol::new(name, Type::from(decl_type));
-                let (arg, code) = if let Some(expr) = self.ast.get_expr(*value)
-                {
-                    self.visit_expr(expr)
-                } else {
-                    unreachable!(
-                        "Expected right handside to be a valid expression"
-                    )
+            ast::Expr::UnaryOp { operator, operand } => {
+                let opcode = match operator {
+                    ast::UnaryOperator::Neg => OPCode::Neg,
+                    ast::UnaryOperator::Not => OPCode::Not,
                 };
-                match arg {
-                    Some(Value::ConstantLiteral(literal)) => {
-                        self.globals.push(GlobalValue(dst, literal));
-                        (None, code)
-                    }
-                    _ => (None, vec![]),
-                }
+                let operand = self.fold(*operand)?;
+                consteval::unary(opcode, operand, ArithmeticMode::Wrapping)
+                    .ok()
+                    .flatten()
+            }
+            ast::Expr::BinOp {
+                left,
+                operator,
+                right,
+            } => {
+                let (lhs, rhs) = (self.fold(*left)?, self.fold(*right)?);
+                let opcode = simplify::opcode(*operator);
+                consteval::binary(opcode, lhs, rhs, ArithmeticMode::Wrapping)
+                    .ok()
+                    .flatten()
             }
+            ast::Expr::StringLiteral(_)
+            | ast::Expr::Assignment { .. }
+            | ast::Expr::Call { .. } => None,
         }
     }
 
-    fn visit_stmt(
+    /// Fold the declaration at `stmt_ref` if it's a `const` local variable
+    /// with a constant initializer, its uses are lowered to the constant and
+    /// no storage is defined for it. Returns `true` if the declaration was
+    /// folded.
+    fn fold_const_local(&mut self, stmt_ref: ast::StmtRef) -> bool {
+        let ast = self.ast;
+        let Some(ast::Stmt::LocalVariable {
+            decl_type,
+            name,
+            value,
+        }) = ast.get_stmt(stmt_ref)
+        else {
+            return false;
+        };
+        if !ast.is_const(ast::AnyRef::Stmt(stmt_ref))
+            || ast.is_volatile(ast::AnyRef::Stmt(stmt_ref))
+        {
+            return false;
+        }
+        let Some(literal) = self.fold(*value) else {
+            return false;
+        };
+        let renamed = self.declare_local(name);
+        self.constants
+            .insert(renamed, conversions::convert(literal, *decl_type));
+        true
+    }
+
+    /// Record the variable declared at `stmt_ref` as accessed by the current
+    /// function if it's `volatile` qualified, must be called once the
+    /// declaration is lowered.
+    fn volatile_local(&mut self, stmt_ref: ast::StmtRef) {
+        let Some(ast::Stmt::LocalVariable {
+            decl_type, name, ..
+        }) = self.ast.get_stmt(stmt_ref)
+        else {
+            return;
+        };
+        if !self.ast.is_volatile(ast::AnyRef::Stmt(stmt_ref)) {
+            return;
+        }
+        let symbol = Symbol::new(&self.local_name(name), Type::from(decl_type));
+        if let Some(function) = self.program.last_mut() {
+            function.volatiles.push(symbol);
+        }
+    }
+
+    /// Lower the declaration at `stmt_ref` if it's a `static` local variable,
+    /// returns the code initializing it.
+    ///
+    /// Static locals are module level globals named after their function
+    /// (`@f.x`) only visible in their scope. Constant initializers are the
+    /// initial value of the global, other initializers are evaluated once,
+    /// the first time the declaration is reached, behind a guard flag.
+    fn static_local(
         &mut self,
-        stmt: &ast::Stmt,
-    ) -> (Option<Value>, Vec<Instruction>) {
-        match stmt {
-            // Variable declaration are
-            ast::Stmt::LocalVariable {
+        stmt_ref: ast::StmtRef,
+    ) -> Option<Vec<Instruction>> {
+        let ast = self.ast;
+        let Some(ast::Stmt::LocalVariable {
+            decl_type,
+            name,
+            value,
+        }) = ast.get_stmt(stmt_ref)
+        else {
+            return None;
+        };
+        if !ast.is_static(stmt_ref) {
+            return None;
+        }
+        // The initializer is lowered before the variable is in scope.
+        let constant = self.fold(*value);
+        let init = match constant {
+            Some(_) => None,
+            None => ast.get_expr(*value).map(|expr| self.visit_expr(expr)),
+        };
+        let function = self
+            .program
+            .last()
+            .map(|function| function.name().to_string())
+            .unwrap_or_default();
+        let global = format!("{function}.{}", self.declare_local(name));
+        if let Some(scope) = self.renames.last_mut() {
+            scope.insert(name.to_string(), global.clone());
+        }
+        let dst = Symbol::new(&global, Type::from(decl_type));
+        if let Some(function) = self.program.last_mut() {
+            function.statics.push(dst.clone());
+        }
+        let Some((arg, mut init)) = init else {
+            let literal = constant.unwrap_or_default();
+            self.globals.push(GlobalValue(
+                dst,
+                conversions::convert(literal, *decl_type),
+                None,
+            ));
+            return Some(vec![]);
+        };
+        let guard = Symbol::new(&format!("{global}.guard"), Type::Bool);
+        self.globals.push(GlobalValue(
+            dst.clone(),
+            conversions::convert(Literal::Int(0), *decl_type),
+            None,
+        ));
+        self.globals.push(GlobalValue(
+            guard.clone(),
+            Literal::Bool(false),
+            None,
+        ));
+        if let Some(function) = self.program.last_mut() {
+            function.statics.push(guard.clone());
+        }
+
+        let init_label = self.llc.next_label();
+        let end_label = self.llc.next_label();
+        let mut code = vec![Instruction::Branch(
+            Value::StorageLocation(guard.clone()),
+            Label(end_label),
+            Label(init_label),
+            BranchHint::Likely,
+        )];
+        code.append(&mut self.block_label(init_label));
+        code.append(&mut init);
+        code.push(Instruction::Id(
+            dst,
+            arg.expect("Expected initializer to be a valid temporary"),
+        ));
+        code.push(Instruction::Const(
+            guard,
+            Value::ConstantLiteral(Literal::Bool(true)),
+        ));
+        code.push(Instruction::Jump(Label(end_label)));
+        code.append(&mut self.block_label(end_label));
+        Some(code)
+    }
+
+    /// Returns the symbols of a function's parameters.
+    fn parameters(&self, args: &[ast::StmtRef]) -> Vec<Symbol> {
+        args.iter()
+            .map(|arg| match self.ast.get_stmt(*arg) {
+                Some(ast::Stmt::Argument { decl_type, name }) => {
+                    Symbol::new(name, Type::from(decl_type))
+                }
+                _ => unreachable!(
+                    "expected argument reference to be valid and to be `ast::Stmt::FuncArg`"
+                ),
+            })
+            .collect()
+    }
+
+    /// Returns a non-mutable reference to the program imports.
+    pub const fn imports(&self) -> &Vec<Import> {
+        &self.imports
+    }
+
+    /// Returns the lowered program, the builder must have been built.
+    pub fn into_program(self) -> Program {
+        Program {
+            imports: self.imports,
+            globals: self.globals,
+            functions: self.program,
+        }
+    }
+
+    /// Enable coverage instrumentation, every basic block starts with a
+    /// `count` instruction.
+    #[must_use]
+    pub fn with_coverage(mut self) -> Self {
+        self.coverage = Some(Coverage::default());
+        self
+    }
+
+    /// Compile for a freestanding environment, calls to builtins needing
+    /// the C library (`printf`, `exit` and the print builtins) are rejected.
+    #[must_use]
+    pub const fn with_freestanding(mut self) -> Self {
+        self.freestanding = true;
+        self
+    }
+
+    /// Set the maximum number of instructions in the program.
+    #[must_use]
+    pub const fn with_max_instructions(
+        mut self,
+        max_instructions: usize,
+    ) -> Self {
+        self.max_instructions = max_instructions;
+        self
+    }
+
+    /// Lower `switch` statements with the given strategy.
+    #[must_use]
+    pub const fn with_switch_lowering(
+        mut self,
+        switch_lowering: SwitchLowering,
+    ) -> Self {
+        self.switch_lowering = switch_lowering;
+        self
+    }
+
+    /// Returns the coverage metadata if coverage instrumentation is enabled.
+    pub const fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Returns the instructions starting the basic block at `label`, the
+    /// label followed by its counter increment when coverage is enabled.
+    fn block_label(&mut self, label: usize) -> Vec<Instruction> {
+        match self.coverage.as_mut() {
+            Some(coverage) => {
+                self.counter = coverage.next_counter();
+                vec![
+                    Instruction::Label(label),
+                    Instruction::Count(self.counter),
+                ]
+            }
+            None => vec![Instruction::Label(label)],
+        }
+    }
+
+    /// Record the source location of a statement for coverage, blocks are
+    /// skipped since the statements they contain are recorded.
+    fn cover(&mut self, stmt_ref: ast::StmtRef) {
+        let Some(coverage) = self.coverage.as_mut() else {
+            return;
+        };
+        if matches!(self.ast.get_stmt(stmt_ref), Some(ast::Stmt::Block(_))) {
+            return;
+        }
+        if let Some(span) = self.ast.stmt_span(stmt_ref) {
+            coverage.record(span, self.counter);
+        }
+    }
+
+    /// Lower a branch condition, conditions of the form
+    /// `__builtin_expect(cond, true|false)` are lowered to `cond` and the
+    /// expectation is returned as a branch hint.
+    fn condition(
+        &mut self,
+        condition: ast::ExprRef,
+    ) -> (Option<Value>, Vec<Instruction>, BranchHint) {
+        let expr = self
+            .ast
+            .get_expr(condition)
+            .expect("Expected condition to reference a valid expression");
+        if let ast::Expr::Call { name, args } = expr {
+            let builtin = match self.ast.get_expr(*name) {
+                Some(ast::Expr::Named(name)) => Builtin::from_name(name),
+                _ => None,
+            };
+            let expected = args.get(1).and_then(|arg| self.ast.get_expr(*arg));
+            if let (
+                Some(Builtin::Expect),
+                Some(ast::Expr::BoolLiteral(likely)),
+            ) = (builtin, expected)
+            {
+                let hint = if *likely {
+                    BranchHint::Likely
+                } else {
+                    BranchHint::Unlikely
+                };
+                let cond = self.ast.get_expr(args[0]).expect(
+                    "Expected condition to reference a valid expression",
+                );
+                let (value, code) = self.visit_expr(cond);
+                return (value, code, hint);
+            }
+        }
+        let (value, code) = self.visit_expr(expr);
+        (value, code, BranchHint::Unknown)
+    }
+
+    /// Returns the instructions evaluating a loop `condition` then branching
+    /// to the loop body or to its exit.
+    fn loop_test(
+        &mut self,
+        condition: ast::ExprRef,
+        body_label: usize,
+        exit_label: usize,
+    ) -> Vec<Instruction> {
+        let (condition, mut code, hint) = self.condition(condition);
+        code.push(Instruction::Branch(
+            condition.expect("Expected condition variable to be valid"),
+            Label(body_label),
+            Label(exit_label),
+            hint,
+        ));
+        code
+    }
+
+    /// Returns a new temporary of type `t`.
+    fn temporary(&mut self, t: Type) -> Symbol {
+        Symbol::new(format!("%v{}", self.llc.next_location()).as_str(), t)
+    }
+
+    /// Returns the instructions comparing `scrutinee` against `value` and
+    /// branching to `then_label` if the comparison holds, the else target
+    /// starts a new block.
+    fn compare_and_branch(
+        &mut self,
+        scrutinee: &Value,
+        operator: OPCode,
+        value: i32,
+        then_label: usize,
+    ) -> Vec<Instruction> {
+        let constant = self.temporary(Type::Int);
+        let condition = self.temporary(Type::Bool);
+        let lhs = scrutinee.clone();
+        let rhs = Value::StorageLocation(constant.clone());
+        let else_label = self.llc.next_label();
+        let mut code = vec![
+            Instruction::Const(
+                constant,
+                Value::ConstantLiteral(Literal::Int(value)),
+            ),
+            match operator {
+                OPCode::Eq => Instruction::Eq(condition.clone(), lhs, rhs),
+                OPCode::Lt => Instruction::Lt(condition.clone(), lhs, rhs),
+                OPCode::Gt => Instruction::Gt(condition.clone(), lhs, rhs),
+                _ => unreachable!("Unexpected switch comparison {operator:?}"),
+            },
+            Instruction::Branch(
+                Value::StorageLocation(condition),
+                Label(then_label),
+                Label(else_label),
+                BranchHint::Unknown,
+            ),
+        ];
+        code.append(&mut self.block_label(else_label));
+        code
+    }
+
+    /// Lower the dispatch of a `switch` statement, `cases` are sorted by
+    /// value and values matching no case jump to `fallback`.
+    fn dispatch(
+        &mut self,
+        scrutinee: &Value,
+        cases: &[(i32, usize)],
+        fallback: usize,
+        lowering: SwitchLowering,
+    ) -> Vec<Instruction> {
+        let mut code = vec![];
+        match lowering {
+            SwitchLowering::JumpTable => {
+                let (min, max) = (cases[0].0, cases[cases.len() - 1].0);
+                code.append(&mut self.compare_and_branch(
+                    scrutinee,
+                    OPCode::Lt,
+                    min,
+                    fallback,
+                ));
+                code.append(&mut self.compare_and_branch(
+                    scrutinee,
+                    OPCode::Gt,
+                    max,
+                    fallback,
+                ));
+                code.append(&mut self.dispatch(
+                    scrutinee,
+                    cases,
+                    fallback,
+                    SwitchLowering::Tree,
+                ));
+            }
+            SwitchLowering::Tree
+                if cases.len() > SwitchLowering::CHAIN_THRESHOLD =>
+            {
+                // Split on the median so both halves have the same depth,
+                // values below the pivot branch to the low half and the
+                // others fall through to the high half.
+                let (low, high) = cases.split_at(cases.len() / 2);
+                let low_label = self.llc.next_label();
+                code.append(&mut self.compare_and_branch(
+                    scrutinee,
+                    OPCode::Lt,
+                    high[0].0,
+                    low_label,
+                ));
+                code.append(
+                    &mut self.dispatch(scrutinee, high, fallback, lowering),
+                );
+                code.append(&mut self.block_label(low_label));
+                code.append(
+                    &mut self.dispatch(scrutinee, low, fallback, lowering),
+                );
+            }
+            _ => {
+                for (value, label) in cases {
+                    code.append(&mut self.compare_and_branch(
+                        scrutinee,
+                        OPCode::Eq,
+                        *value,
+                        *label,
+                    ));
+                }
+                code.push(Instruction::Jump(Label(fallback)));
+            }
+        }
+        code
+    }
+
+    /// Reject calls to `name` in freestanding mode if it needs the C
+    /// library, `printf` and `exit` always do.
+    fn assert_hosted(&self, name: &str) {
+        let needs_runtime = Builtin::from_name(name)
+            .is_none_or(|builtin| builtin.needs_runtime());
+        assert!(
+            !(self.freestanding && needs_runtime),
+            "`{name}` needs the C library which isn't available with \
+             `--freestanding`."
+        );
+    }
+
+    /// Lower a call to a builtin function to an intrinsic instruction.
+    fn intrinsic(
+        &mut self,
+        builtin: Builtin,
+        args: &[ast::ExprRef],
+    ) -> (Option<Value>, Vec<Instruction>) {
+        self.assert_hosted(builtin.name());
+        let mut code = vec![];
+        let mut values = vec![];
+        for arg in args {
+            if let Some(expr) = self.ast.get_expr(*arg) {
+                let (value, mut arg_code) = self.visit_expr(expr);
+                code.append(&mut arg_code);
+                values.push(value.expect("Expected argument to be a value"));
+            } else {
+                unreachable!("Expected argument to be a valid expression")
+            }
+        }
+        let dst = Symbol::new(
+            format!("%v{}", self.llc.next_location()).as_str(),
+            Type::from(&builtin.return_type()),
+        );
+        code.push(Instruction::Intrinsic(dst.clone(), builtin, values.into()));
+        (Some(Value::StorageLocation(dst)), code)
+    }
+
+    /// Lower a call to the atomic operation `op` to an atomic instruction on
+    /// the variable named by the first argument.
+    fn atomic(
+        &mut self,
+        op: AtomicOp,
+        args: &[ast::ExprRef],
+    ) -> (Option<Value>, Vec<Instruction>) {
+        let exprs = args
+            .iter()
+            .map(|arg| {
+                self.ast.get_expr(*arg).unwrap_or_else(|| {
+                    unreachable!("Expected argument to be a valid expression")
+                })
+            })
+            .collect::<Vec<_>>();
+        let [ast::Expr::Named(name), operand @ .., ast::Expr::IntLiteral(order)] =
+            exprs.as_slice()
+        else {
+            unreachable!(
+                "Expected `{}` arguments to be type checked",
+                op.name()
+            )
+        };
+        let order = MemoryOrder::from_value(*order)
+            .expect("Expected memory order to be type checked");
+        let variable = Value::StorageLocation(Symbol::new(
+            &self.local_name(name),
+            Type::Int,
+        ));
+        let mut code = vec![];
+        let operand = operand.first().map(|operand| {
+            let (value, mut operand_code) = self.visit_expr(operand);
+            code.append(&mut operand_code);
+            value.expect("Expected atomic operand to be a value")
+        });
+        let dst = self.temporary(Type::Int);
+        code.push(Instruction::Atomic(
+            dst.clone(),
+            op,
+            variable,
+            operand,
+            order,
+        ));
+        (Some(Value::StorageLocation(dst)), code)
+    }
+
+    /// Lower an `exit` call to an `exit` instruction. The call never returns
+    /// but evaluates to an `int` like any call, the unreachable value is left
+    /// for dead code elimination.
+    fn exit(
+        &mut self,
+        args: &[ast::ExprRef],
+    ) -> (Option<Value>, Vec<Instruction>) {
+        self.assert_hosted(EXIT);
+        let (code, mut insts) =
+            match args.first().and_then(|arg| self.ast.get_expr(*arg)) {
+                Some(expr) => self.visit_expr(expr),
+                None => unreachable!("Expected `exit` to have a code argument"),
+            };
+        insts.push(Instruction::Exit(
+            code.expect("Expected exit code to be a valid value"),
+        ));
+        let dst = self.temporary(Type::Int);
+        insts.push(Instruction::Const(
+            dst.clone(),
+            Value::ConstantLiteral(Literal::Int(0)),
+        ));
+        (Some(Value::StorageLocation(dst)), insts)
+    }
+
+    /// Lower a `printf` call to print builtins, the call evaluates to the
+    /// number of characters printed.
+    fn printf(
+        &mut self,
+        args: &[ast::ExprRef],
+    ) -> (Option<Value>, Vec<Instruction>) {
+        self.assert_hosted(PRINTF);
+        let directives =
+            match args.first().and_then(|arg| self.ast.get_expr(*arg)) {
+                Some(ast::Expr::StringLiteral(format)) => parse_format(format)
+                    .expect("Expected `printf` format to be valid"),
+                _ => unreachable!(
+                    "Expected `printf` format to be a string literal"
+                ),
+            };
+        let mut args = args.iter().skip(1);
+        let mut code = vec![];
+        // Characters printed by the text and `%c` directives are counted
+        // statically, `%d` prints a variable number of characters.
+        let mut printed = 0;
+        let mut counts = vec![];
+        for directive in directives {
+            let text = match directive {
+                Directive::Text(text) => text,
+                Directive::Str => {
+                    match args.next().and_then(|arg| self.ast.get_expr(*arg)) {
+                        Some(ast::Expr::StringLiteral(literal)) => {
+                            unescape(literal)
+                                .expect("Expected string literal to be valid")
+                        }
+                        _ => unreachable!(
+                            "Expected `%s` argument to be a string literal"
+                        ),
+                    }
+                }
+                Directive::Int | Directive::Char => {
+                    let builtin = match directive {
+                        Directive::Int => Builtin::PrintInt,
+                        _ => Builtin::PrintChar,
+                    };
+                    let arg = *args.next().expect("Expected `printf` argument");
+                    let (count, mut arg_code) = self.intrinsic(builtin, &[arg]);
+                    code.append(&mut arg_code);
+                    match builtin {
+                        Builtin::PrintInt => counts.extend(count),
+                        _ => printed += 1,
+                    }
+                    continue;
+                }
+            };
+            for c in text.chars() {
+                let value = self.temporary(Type::Char);
+                code.push(Instruction::Const(
+                    value.clone(),
+                    Value::ConstantLiteral(Literal::Char(c)),
+                ));
+                let dst = self.temporary(Type::Int);
+                code.push(Instruction::Intrinsic(
+                    dst,
+                    Builtin::PrintChar,
+                    vec![Value::StorageLocation(value)].into(),
+                ));
+                printed += 1;
+            }
+        }
+        let mut total = self.temporary(Type::Int);
+        code.push(Instruction::Const(
+            total.clone(),
+            Value::ConstantLiteral(Literal::Int(printed)),
+        ));
+        for count in counts {
+            let sum = self.temporary(Type::Int);
+            code.push(Instruction::Add(
+                sum.clone(),
+                Value::StorageLocation(total),
+                count,
+            ));
+            total = sum;
+        }
+        (Some(Value::StorageLocation(total)), code)
+    }
+
+    /// Returns a non-mutable reference to the program functions.
+    pub const fn functions(&self) -> &Vec<Function> {
+        &self.program
+    }
+
+    /// Returns a mutable reference to the program functions.
+    pub fn functions_mut(&mut self) -> &mut [Function] {
+        &mut self.program
+    }
+
+    /// Returns a non-mutable reference to the program globals.
+    pub const fn globals(&self) -> &Vec<GlobalValue> {
+        &self.globals
+    }
+
+    /// Build the intermediate representation by invoking the IR-lowering
+    /// visitor.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a `LimitError` if the program has more instructions than
+    /// the maximum.
+    /// Place the function or global lowered from `decl` in `section`.
+    fn place(&mut self, decl: &ast::Decl, section: &str) {
+        match decl {
+            ast::Decl::Function { .. } => {
+                if let Some(function) = self.program.last_mut() {
+                    function.section = Some(section.to_string());
+                }
+            }
+            ast::Decl::GlobalVariable { name, .. } => {
+                if let Some(global) = self
+                    .globals
+                    .iter_mut()
+                    .rfind(|global| global.0.name() == name)
+                {
+                    global.2 = Some(section.to_string());
+                }
+            }
+            ast::Decl::Extern { .. } => {
+                unreachable!("Expected extern declarations to have no section")
+            }
+        }
+    }
+
+    pub fn build(&mut self) {
+        for (i, decl) in self.ast.declarations().iter().enumerate() {
+            if let ast::Decl::Function {
+                name, attributes, ..
+            } = decl
+            {
+                if attributes.contains(&ast::Attribute::NoReturn) {
+                    self.noreturn.insert(name.clone());
+                }
+            }
+            if let ast::Decl::GlobalVariable {
+                decl_type,
+                name,
+                value,
+            } = decl
+            {
+                let decl_ref = ast::DeclRef::new(i);
+                let volatile =
+                    self.ast.is_volatile(ast::AnyRef::Decl(decl_ref));
+                if volatile {
+                    self.global_volatiles
+                        .push(Symbol::new(name, Type::from(decl_type)));
+                }
+                if let Some(literal) =
+                    (self.ast.is_const(ast::AnyRef::Decl(decl_ref))
+                        && !volatile)
+                        .then(|| self.fold(*value))
+                        .flatten()
+                {
+                    self.global_constants.insert(
+                        name.clone(),
+                        conversions::convert(literal, *decl_type),
+                    );
+                }
+            }
+        }
+        for (i, decl) in self.ast.declarations().iter().enumerate() {
+            let _ = self.visit_decl(decl);
+            if let Some(section) = self.ast.section(ast::DeclRef::new(i)) {
+                self.place(decl, section);
+            }
+            let size = self.program.iter().map(Function::len).sum::<usize>();
+            if size > self.max_instructions {
+                LimitError::instructions(self.max_instructions).raise();
+            }
+        }
+    }
+}
+
+impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
+    fn visit_decl(
+        &mut self,
+        decl: &ast::Decl,
+    ) -> (Option<Value>, Vec<Instruction>) {
+        match decl {
+            // Function declarations are the only place where we need to switch
+            // scopes explicitely, since the scope only affects where variable
+            // declarations are positioned.
+            ast::Decl::Function {
+                name,
+                return_type,
+                args: parameters,
+                body,
+                linkage,
+                attributes,
+            } => {
+                let _span = trace::span(
+                    trace::Level::Debug,
+                    "irgen",
+                    &[("function", name)],
+                );
+                // Build and push a new function frame, arguments and the
+                // function body share a scope.
+                self.renames = vec![HashMap::new()];
+                self.declarations.clear();
+                self.constants.clear();
+                let parameters = self
+                    .parameters(parameters)
+                    .into_iter()
+                    .map(|Symbol(name, t)| Symbol(self.declare_local(&name), t))
+                    .collect();
+                let return_type = Type::from(return_type);
+                let mut function = Function::new(name, parameters, return_type);
+                function.linkage = *linkage;
+                function.attributes.clone_from(attributes);
+                function.volatiles.clone_from(&self.global_volatiles);
+                // Enter a new scope and push the new function frame.
+                self.program.push(function);
+                self.context.enter();
+                self.labels.clear();
+
+                let mut code = vec![];
+                if let Some(coverage) = self.coverage.as_mut() {
+                    let span = self.ast.stmt_span(*body).unwrap_or_default();
+                    self.counter = coverage.enter_function(name, span);
+                    code.push(Instruction::Count(self.counter));
+                }
+                let mut span = None;
+
+                if let Some(func_body) = self.ast.get_stmt(*body) {
+                    match func_body {
+                        ast::Stmt::Block(body) => {
+                            for stmt_ref in body {
+                                self.cover(*stmt_ref);
+                                if self.fold_const_local(*stmt_ref) {
+                                    continue;
+                                }
+                                if let Some(mut init) =
+                                    self.static_local(*stmt_ref)
+                                {
+                                    code.append(&mut init);
+                                    self.volatile_local(*stmt_ref);
+                                    continue;
+                                }
+                                if let Some(stmt) = self.ast.get_stmt(*stmt_ref)
+                                {
+                                    let (local_span, mut local_code): (
+                                        Option<Value>,
+                                        Vec<Instruction>,
+                                    ) = self.visit_stmt(stmt);
+                                    code.append(&mut local_code);
+                                    span = local_span;
+                                }
+                                self.volatile_local(*stmt_ref);
+                            }
+                        }
+                        _ => unreachable!(
+                            "function body must be a block statement"
+                        ),
+                    }
+                }
+                self.push(&code);
+                // Exit back to the global scope.
+                self.context.exit();
+                (span, code)
+            }
+            // External functions are only recorded as imports, calls to them
+            // are resolved at link time.
+            ast::Decl::Extern {
+                name,
+                return_type,
+                args,
+            } => {
+                let args = self.parameters(args);
+                self.imports.push(Import {
+                    name: name.clone(),
+                    args,
+                    return_type: Type::from(return_type),
+                });
+                (None, vec![])
+            }
+            ast::Decl::GlobalVariable {
                 decl_type,
                 name,
                 value,
             } => {
                 let dst = Symbol::new(name, Type::from(decl_type));
+                let (arg, code) = if let Some(expr) = self.ast.get_expr(*value)
+                {
+                    self.visit_expr(expr)
+                } else {
+                    unreachable!(
+                        "Expected right handside to be a valid expression"
+                    )
+                };
+                // Literal initializers are lowered to a `const` temporary,
+                // the global is initialized with the folded value instead.
+                let literal = match arg {
+                    Some(Value::ConstantLiteral(literal)) => Some(literal),
+                    _ => self.fold(*value).map(|literal| {
+                        conversions::convert(literal, *decl_type)
+                    }),
+                };
+                match literal {
+                    Some(literal) => {
+                        self.globals.push(GlobalValue(dst, literal, None));
+                        (None, code)
+                    }
+                    None => (None, vec![]),
+                }
+            }
+        }
+    }
+
+    fn visit_stmt(
+        &mut self,
+        stmt: &ast::Stmt,
+    ) -> (Option<Value>, Vec<Instruction>) {
+        match stmt {
+            // Variable declaration are
+            ast::Stmt::LocalVariable {
+                decl_type,
+                name,
+                value,
+            } => {
+                // The initializer is lowered before the variable is in scope.
                 let (arg, mut code) =
                     if let Some(expr) = self.ast.get_expr(*value) {
                         self.visit_expr(expr)
@@ -879,6 +3043,10 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                             "Expected right handside to be a valid expression"
                         )
                     };
+                let dst = Symbol::new(
+                    &self.declare_local(name),
+                    Type::from(decl_type),
+                );
                 // Get the destination of the right hand side.
                 code.push(Instruction::Id(
                     dst.clone(),
@@ -894,8 +3062,18 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                 // are invalidated, we save the old rename table and we reset it for
                 // the new scope.
                 self.context.scope_level_index += 1;
+                self.renames.push(HashMap::new());
                 let mut code = vec![];
                 for stmt_ref in stmts {
+                    self.cover(*stmt_ref);
+                    if self.fold_const_local(*stmt_ref) {
+                        continue;
+                    }
+                    if let Some(mut init) = self.static_local(*stmt_ref) {
+                        code.append(&mut init);
+                        self.volatile_local(*stmt_ref);
+                        continue;
+                    }
                     let (_, mut block) =
                         if let Some(stmt) = self.ast.get_stmt(*stmt_ref) {
                             self.visit_stmt(stmt)
@@ -904,11 +3082,13 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                             "Expected right handside to be a valid expression"
                         )
                         };
+                    self.volatile_local(*stmt_ref);
 
                     code.append(&mut block);
                 }
                 self.context.scope_level_index -= 1;
                 // Once the scope has been processed we need to reset the rename table.
+                self.renames.pop();
                 (None, code)
             }
             // Return statements.
@@ -946,14 +3126,7 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                 then_block,
                 else_block,
             } => {
-                let (condition, mut code) =
-                    if let Some(cond) = self.ast.get_expr(*condition) {
-                        self.visit_expr(cond)
-                    } else {
-                        unreachable!(
-                        "Expected condition to reference a valid expression"
-                    )
-                    };
+                let (condition, mut code, hint) = self.condition(*condition);
                 let then_label = self.llc.next_label();
                 let else_label = self.llc.next_label();
                 let end_label = self.llc.next_label();
@@ -963,11 +3136,11 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                     condition.expect("Expected condition variable to be valid"),
                     Label(then_label),
                     Label(else_label),
+                    hint,
                 );
                 code.push(inst);
                 // Push then label.
-                let inst = Instruction::Label(then_label);
-                code.push(inst);
+                code.append(&mut self.block_label(then_label));
                 // Generate instruction for the then block.
                 let (_, mut block) =
                     if let Some(block) = self.ast.get_stmt(*then_block) {
@@ -980,16 +3153,14 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                 code.append(&mut block);
                 // Push a jump instruction to the end label iif the last
                 // instruction was not a return..
-                if code.last().is_some_and(|inst| match inst.opcode() {
-                    OPCode::Return => false,
-                    _ => true,
+                if code.last().is_some_and(|inst| {
+                    inst.opcode() != OPCode::Return && !inst.no_return()
                 }) {
                     let inst = Instruction::Jump(Label(end_label));
                     code.push(inst);
                 }
                 // Push else label.
-                let inst = Instruction::Label(else_label);
-                code.push(inst);
+                code.append(&mut self.block_label(else_label));
                 // Generate instruction for the else block if one exists.
                 if else_block.is_some() {
                     let (_, mut block) = if let Some(block) = self.ast.get_stmt(
@@ -1003,16 +3174,14 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                 }
                 // Push a jump instruction to the end label iif the last
                 // instruction was not a return..
-                if code.last().is_some_and(|inst| match inst.opcode() {
-                    OPCode::Return => false,
-                    _ => true,
+                if code.last().is_some_and(|inst| {
+                    inst.opcode() != OPCode::Return && !inst.no_return()
                 }) {
                     let inst = Instruction::Jump(Label(end_label));
                     code.push(inst);
                 }
                 // Push end label.
-                let inst = Instruction::Label(end_label);
-                code.push(inst);
+                code.append(&mut self.block_label(end_label));
 
                 (None, code)
             }
@@ -1046,9 +3215,9 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                     code.append(&mut block);
                 }
                 // Generate the loop body label.
-                let inst = Instruction::Label(loop_body_label);
-                code.push(inst);
+                code.append(&mut self.block_label(loop_body_label));
                 // Generate the loop body block.
+                self.breaks.push(loop_exit_label);
                 let (_, mut block) =
                     if let Some(block) = self.ast.get_stmt(*body) {
                         self.visit_stmt(block)
@@ -1057,6 +3226,7 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                         "Expected reference to body to be a valid statement"
                     )
                     };
+                self.breaks.pop();
                 code.append(&mut block);
                 // Generate the iteration expression.
                 if iteration.is_some() {
@@ -1071,17 +3241,9 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                     code.append(&mut block);
                 }
                 // Generate the condition block if it exists.
-                if condition.is_some() {
-                    let (condition, mut block) = if let Some(block) =
-                        self.ast.get_expr(
-                            condition.expect(
-                                "Expected condition block to be `Some`",
-                            ),
-                        ) {
-                        self.visit_expr(block)
-                    } else {
-                        unreachable!("Expected reference to initializer to be a valid expression")
-                    };
+                if let Some(condition) = condition {
+                    let (condition, mut block, hint) =
+                        self.condition(*condition);
                     code.append(&mut block);
                     // Generate branch to exit code.
                     let inst = Instruction::Branch(
@@ -1089,13 +3251,13 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                             .expect("Expected condition variable to be valid"),
                         Label(loop_body_label),
                         Label(loop_exit_label),
+                        hint,
                     );
 
                     code.push(inst);
                 }
                 // Generate the loop exit code.
-                let inst = Instruction::Label(loop_exit_label);
-                code.push(inst);
+                code.append(&mut self.block_label(loop_exit_label));
                 (None, code)
             }
             ast::Stmt::While { condition, body } => {
@@ -1103,11 +3265,21 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                 let loop_body_label = self.llc.next_label();
                 let loop_exit_label = self.llc.next_label();
                 let mut code = Vec::new();
+                // The condition is tested before entering the loop and again
+                // at the end of every iteration, so loops whose condition is
+                // false from the start never run their body.
+                if let Some(condition) = condition {
+                    code.append(&mut self.loop_test(
+                        *condition,
+                        loop_body_label,
+                        loop_exit_label,
+                    ));
+                }
+                // Generate the loop body label.
+                code.append(&mut self.block_label(loop_body_label));
                 if body.is_some() {
-                    // Generate the loop body label.
-                    let inst = Instruction::Label(loop_body_label);
-                    code.push(inst);
                     // Generate the loop body block.
+                    self.breaks.push(loop_exit_label);
                     let (_, mut block) = if let Some(block) = self.ast.get_stmt(
                         body.expect("Expected loop body to be `Some`"),
                     ) {
@@ -1115,34 +3287,116 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                     } else {
                         unreachable!("Expected reference to body to be a valid statement")
                     };
+                    self.breaks.pop();
                     code.append(&mut block);
                 }
-                if condition.is_some() {
-                    let (condition, mut block) = if let Some(block) =
-                        self.ast.get_expr(
-                            condition.expect(
-                                "Expected condition block to be `Some`",
-                            ),
-                        ) {
-                        self.visit_expr(block)
-                    } else {
-                        unreachable!("Expected reference to initializer to be a valid expression")
-                    };
-                    code.append(&mut block);
-                    // Generate branch to exit code.
-                    let inst = Instruction::Branch(
-                        condition
-                            .expect("Expected condition variable to be valid"),
-                        Label(loop_body_label),
-                        Label(loop_exit_label),
-                    );
-                    code.push(inst);
+                match condition {
+                    Some(condition) => code.append(&mut self.loop_test(
+                        *condition,
+                        loop_body_label,
+                        loop_exit_label,
+                    )),
+                    None => {
+                        code.push(Instruction::Jump(Label(loop_body_label)))
+                    }
                 }
                 // Generate the loop exit code.
-                let inst = Instruction::Label(loop_exit_label);
-                code.push(inst);
+                code.append(&mut self.block_label(loop_exit_label));
                 (None, code)
             }
+            ast::Stmt::Switch { scrutinee, body } => {
+                let (scrutinee, mut code) = if let Some(expr) =
+                    self.ast.get_expr(*scrutinee)
+                {
+                    self.visit_expr(expr)
+                } else {
+                    unreachable!("Expected scrutinee to be a valid expression")
+                };
+                let scrutinee =
+                    scrutinee.expect("Expected scrutinee to be a valid value");
+                let exit_label = self.llc.next_label();
+                // Allocate the labels of the `case` and `default` labels of
+                // the body, without a `default` label unmatched values exit.
+                let mut cases = vec![];
+                let mut default_label = exit_label;
+                if let Some(ast::Stmt::Block(stmts)) = self.ast.get_stmt(*body)
+                {
+                    for stmt_ref in stmts {
+                        match self.ast.get_stmt(*stmt_ref) {
+                            Some(ast::Stmt::Case(value)) => {
+                                cases.push((*value, self.llc.next_label()))
+                            }
+                            Some(ast::Stmt::Default) => {
+                                default_label = self.llc.next_label()
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                cases.sort_unstable();
+                let values =
+                    cases.iter().map(|case| case.0).collect::<Vec<_>>();
+                let lowering = self.switch_lowering.select(&values);
+                code.append(&mut self.dispatch(
+                    &scrutinee,
+                    &cases,
+                    default_label,
+                    lowering,
+                ));
+                // Case labels are emitted inline in the body so control falls
+                // through from one case to the next.
+                self.switches
+                    .push((cases.into_iter().collect(), default_label));
+                self.breaks.push(exit_label);
+                let (_, mut block) =
+                    if let Some(block) = self.ast.get_stmt(*body) {
+                        self.visit_stmt(block)
+                    } else {
+                        unreachable!(
+                        "Expected reference to body to be a valid statement"
+                    )
+                    };
+                code.append(&mut block);
+                self.breaks.pop();
+                self.switches.pop();
+                code.append(&mut self.block_label(exit_label));
+                (None, code)
+            }
+            ast::Stmt::Case(value) => {
+                let (cases, _) = self
+                    .switches
+                    .last()
+                    .expect("Expected `case` label to be in a `switch` body");
+                let label = cases[value];
+                (None, self.block_label(label))
+            }
+            ast::Stmt::Default => {
+                let (_, label) = self.switches.last().expect(
+                    "Expected `default` label to be in a `switch` body",
+                );
+                let label = *label;
+                (None, self.block_label(label))
+            }
+            ast::Stmt::Break => {
+                let label = self
+                    .breaks
+                    .last()
+                    .expect("Expected `break` to be in a loop or `switch`");
+                (None, vec![Instruction::Jump(Label(*label))])
+            }
+            // Jumps to user-defined labels.
+            ast::Stmt::Goto(name) => {
+                let label = self.user_label(name);
+                (None, vec![Instruction::Jump(Label(label))])
+            }
+            // User-defined labels.
+            ast::Stmt::Label(name) => {
+                let label = self.user_label(name);
+                (None, self.block_label(label))
+            }
+            ast::Stmt::Asm(code) => {
+                (None, vec![Instruction::Asm(code.clone())])
+            }
             ast::Stmt::Empty => (None, vec![]),
         }
     }
@@ -1152,6 +3406,9 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
         expr: &ast::Expr,
     ) -> (Option<Value>, Vec<Instruction>) {
         match *expr {
+            ast::Expr::StringLiteral(_) => unreachable!(
+                "Expected string literals to only be `printf` arguments"
+            ),
             ast::Expr::IntLiteral(value) => {
                 let mut code = vec![];
                 let dst = Symbol::new(
@@ -1226,6 +3483,26 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                 code.push(inst);
                 (Some(Value::StorageLocation(_dst)), code)
             }
+            ast::Expr::Cast { to, operand } => {
+                let (operand, mut code) =
+                    if let Some(expr) = self.ast.get_expr(operand) {
+                        self.visit_expr(expr)
+                    } else {
+                        unreachable!(
+                            "Expected cast operand to be a valid expression"
+                        )
+                    };
+                let dst = Symbol::new(
+                    format!("%v{}", self.llc.next_location()).as_str(),
+                    Type::from(&to),
+                );
+                code.push(Instruction::Cast(
+                    dst.clone(),
+                    operand
+                        .expect("Expected cast operand to be in a temporary"),
+                ));
+                (Some(Value::StorageLocation(dst)), code)
+            }
             ast::Expr::BinOp {
                 left,
                 operator,
@@ -1256,7 +3533,10 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                     ast::BinaryOperator::Add
                     | ast::BinaryOperator::Sub
                     | ast::BinaryOperator::Mul
-                    | ast::BinaryOperator::Div => Type::Int,
+                    | ast::BinaryOperator::Div
+                    | ast::BinaryOperator::Shl
+                    | ast::BinaryOperator::Shr
+                    | ast::BinaryOperator::BitAnd => Type::Int,
                     _ => Type::Bool,
                 };
 
@@ -1280,6 +3560,11 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                     ast::BinaryOperator::Lte => Instruction::Lte(dst, lhs, rhs),
                     ast::BinaryOperator::And => Instruction::And(dst, lhs, rhs),
                     ast::BinaryOperator::Or => Instruction::Or(dst, lhs, rhs),
+                    ast::BinaryOperator::Shl => Instruction::Shl(dst, lhs, rhs),
+                    ast::BinaryOperator::Shr => Instruction::Shr(dst, lhs, rhs),
+                    ast::BinaryOperator::BitAnd => {
+                        Instruction::BitAnd(dst, lhs, rhs)
+                    }
                 };
 
                 code.push(inst);
@@ -1295,20 +3580,22 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                         "Expected a symbol for named expression : `{name}`"
                     ),
                 };
-                let name = Symbol::new(name, Type::from(&_t));
+                if let Some(literal) = self.constant(name) {
+                    let dst = Symbol::new(
+                        format!("%v{}", self.llc.next_location()).as_str(),
+                        Type::from(&_t),
+                    );
+                    return (
+                        Some(Value::StorageLocation(dst.clone())),
+                        vec![Instruction::Const(
+                            dst,
+                            Value::ConstantLiteral(literal),
+                        )],
+                    );
+                }
+                let name = Symbol::new(&self.local_name(name), Type::from(&_t));
                 (Some(Value::StorageLocation(name)), vec![])
             }
-            ast::Expr::Grouping(expr_ref) => {
-                let (name, code) =
-                    if let Some(expr) = self.ast.get_expr(expr_ref) {
-                        self.visit_expr(expr)
-                    } else {
-                        unreachable!(
-                            "Expected right handside to be a valid expression"
-                        )
-                    };
-                (name, code)
-            }
             ast::Expr::Assignment { name, value } => {
                 let mut code = vec![];
                 let (rhs, mut code_right) =
@@ -1331,23 +3618,13 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                     )
                 };
                 let location = match lhs {
-                    Some(Value::StorageLocation(ref sym)) => sym.0.clone(),
+                    Some(Value::StorageLocation(ref sym)) => sym.clone(),
                     _ => unreachable!(
                         "Expected assignment lvalue to be a storage location"
                     ),
                 };
-                let t = match self
-                    .symbol_table
-                    .find(&location, self.context.scope_level_index)
-                {
-                    Some(symbol) => symbol.t(),
-                    None => unreachable!(
-                        "Expected a symbol for named expression : `{}`",
-                        lhs.unwrap()
-                    ),
-                };
                 let inst = Instruction::Id(
-                    Symbol::new(&location, Type::from(&t)),
+                    location,
                     rhs.expect(
                         "Expected assignment rvalue to be a valid value",
                     ),
@@ -1360,6 +3637,20 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                     Some(ast::Expr::Named(name)) => name,
                     _ => unreachable!("Expected reference to be a named expression for a function"),
                 };
+                if name == EXIT && self.symbol_table.global(name).is_none() {
+                    return self.exit(args);
+                }
+                if let Some(op) = AtomicOp::from_name(name)
+                    .filter(|_| self.symbol_table.global(name).is_none())
+                {
+                    return self.atomic(op, args);
+                }
+                if name == PRINTF && self.symbol_table.global(name).is_none() {
+                    return self.printf(args);
+                }
+                if let Some(builtin) = Builtin::from_name(name) {
+                    return self.intrinsic(builtin, args);
+                }
                 let t = match self.symbol_table.global(name) {
                     Some(symbol) => symbol.t(),
                     None => unreachable!(
@@ -1389,9 +3680,14 @@ impl<'a> ast::Visitor<(Option<Value>, Vec<Instruction>)> for IRBuilder<'a> {
                 let inst = Instruction::Call(
                     dst.clone(),
                     Symbol::new(name, Type::from(&t)),
-                    vars,
+                    vars.into(),
                 );
                 code.push(inst);
+                // Calls to `[[noreturn]]` functions end their block, the
+                // trap is never reached unless the callee breaks its promise.
+                if self.noreturn.contains(name) {
+                    code.push(Instruction::Trap(format!("`{name}` returned")));
+                }
                 (Some(Value::StorageLocation(dst)), code)
             }
         }
@@ -1450,6 +3746,22 @@ mod tests {
 "#
     );
 
+    test_ir_gen!(
+        can_generate_char_constants,
+        r"int main() { char c = '\''; bool b = c < '\n'; return 0; }",
+        r#"
+@main: int {
+   %v0: char = const '\''
+   c: char = id %v0
+   %v1: char = const '\n'
+   %v2: bool = lt c %v1
+   b: bool = id %v2
+   %v3: int = const 0
+   ret %v3
+}
+"#
+    );
+
     test_ir_gen!(
         can_generate_unary_ops,
         "int main() { bool a = !true; return 0;}",
@@ -1731,16 +4043,19 @@ mod tests {
    i: int = id %v0
    %v1: int = const 0
    x: int = id %v1
+   %v2: int = const 100
+   %v3: bool = lte i %v2
+   br %v3 .LABEL_0 .LABEL_1
    .LABEL_0
-   %v2: int = const 1
-   %v3: int = add x %v2
-   x: int = id %v3
    %v4: int = const 1
-   %v5: int = add i %v4
-   i: int = id %v5
-   %v6: int = const 100
-   %v7: bool = lte i %v6
-   br %v7 .LABEL_0 .LABEL_1
+   %v5: int = add x %v4
+   x: int = id %v5
+   %v6: int = const 1
+   %v7: int = add i %v6
+   i: int = id %v7
+   %v8: int = const 100
+   %v9: bool = lte i %v8
+   br %v9 .LABEL_0 .LABEL_1
    .LABEL_1
    ret x
 }
@@ -1768,11 +4083,55 @@ mod tests {
    %v1: int = const 0
    x: int = id %v1
    %v2: int = const 1
-   x: int = id %v2
+   x.1: int = id %v2
    %v3: int = const 2
    y: int = id %v3
-   %v4: int = add x y
-   i: int = id %v4
+   %v4: int = add x.1 y
+   i.1: int = id %v4
+   ret x
+}
+"#
+    );
+
+    test_ir_gen!(
+        can_generate_sibling_scopes,
+        r#"
+            int main() {
+                int x = 1;
+                {
+                    int x = 2;
+                    int y = x + 1;
+                    {
+                        int y = 5;
+                        x = y;
+                    }
+                    x = x + y;
+                }
+                {
+                    int y = 10;
+                    x = x + y;
+                }
+                return x;
+            }
+        "#,
+        r#"
+@main: int {
+   %v0: int = const 1
+   x: int = id %v0
+   %v1: int = const 2
+   x.1: int = id %v1
+   %v2: int = const 1
+   %v3: int = add x.1 %v2
+   y: int = id %v3
+   %v4: int = const 5
+   y.1: int = id %v4
+   x.1: int = id y.1
+   %v5: int = add x.1 y
+   x.1: int = id %v5
+   %v6: int = const 10
+   y.2: int = id %v6
+   %v7: int = add x y.2
+   x: int = id %v7
    ret x
 }
 "#
@@ -1878,7 +4237,7 @@ int main() {
    br %v3 .LABEL_0 .LABEL_1
    .LABEL_0
    %v4: int = add a b
-   c: int = id %v4
+   c.1: int = id %v4
    jmp .LABEL_2
    .LABEL_1
    %v5: int = sub a b
@@ -1911,14 +4270,14 @@ int scopes(int x,int y,int z) {
    %v0: bool = const true
    br %v0 .LABEL_0 .LABEL_1
    .LABEL_0
-   x: int = id z
-   y: int = id x
-   z: int = id y
+   x.1: int = id z
+   y.1: int = id x.1
+   z.1: int = id y.1
    jmp .LABEL_2
    .LABEL_1
-   x: int = id x
-   y: int = id y
-   z: int = id z
+   x.2: int = id x
+   y.2: int = id y
+   z.2: int = id z
    jmp .LABEL_2
    .LABEL_2
    %v1: int = add x y
@@ -1927,4 +4286,926 @@ int scopes(int x,int y,int z) {
 }
 "#
     );
+
+    test_ir_gen!(
+        can_generate_goto_and_labels,
+        r#"
+int main() {
+    int i = 0;
+    loop:
+    i = i + 1;
+    if (i < 10) {
+        goto loop;
+    }
+    goto exit;
+    exit:
+    return i;
+}
+"#,
+        r#"
+@main: int {
+   %v0: int = const 0
+   i: int = id %v0
+   .LABEL_0
+   %v1: int = const 1
+   %v2: int = add i %v1
+   i: int = id %v2
+   %v3: int = const 10
+   %v4: bool = lt i %v3
+   br %v4 .LABEL_1 .LABEL_2
+   .LABEL_1
+   jmp .LABEL_0
+   jmp .LABEL_3
+   .LABEL_2
+   jmp .LABEL_3
+   .LABEL_3
+   jmp .LABEL_4
+   .LABEL_4
+   ret i
+}
+"#
+    );
+
+    test_ir_gen!(
+        can_generate_switch_as_comparison_chain,
+        r#"
+int main() {
+    int x = 2;
+    switch (x) {
+    case 1:
+        x = 3;
+        break;
+    case 2:
+        x = 4;
+    default:
+        x = x + 1;
+    }
+    return x;
+}
+"#,
+        r#"
+@main: int {
+   %v0: int = const 2
+   x: int = id %v0
+   %v1: int = const 1
+   %v2: bool = eq x %v1
+   br %v2 .LABEL_1 .LABEL_4
+   .LABEL_4
+   %v3: int = const 2
+   %v4: bool = eq x %v3
+   br %v4 .LABEL_2 .LABEL_5
+   .LABEL_5
+   jmp .LABEL_3
+   .LABEL_1
+   %v5: int = const 3
+   x: int = id %v5
+   jmp .LABEL_0
+   .LABEL_2
+   %v6: int = const 4
+   x: int = id %v6
+   .LABEL_3
+   %v7: int = const 1
+   %v8: int = add x %v7
+   x: int = id %v8
+   .LABEL_0
+   ret x
+}
+"#
+    );
+
+    test_ir_gen!(
+        can_generate_break_out_of_loops,
+        r#"
+int main() {
+    int i = 0;
+    for (i = 0; i < 10; i = i + 1) {
+        break;
+    }
+    return i;
+}
+"#,
+        r#"
+@main: int {
+   %v0: int = const 0
+   i: int = id %v0
+   %v1: int = const 0
+   i: int = id %v1
+   .LABEL_0
+   jmp .LABEL_1
+   %v2: int = const 1
+   %v3: int = add i %v2
+   i: int = id %v3
+   %v4: int = const 10
+   %v5: bool = lt i %v4
+   br %v5 .LABEL_0 .LABEL_1
+   .LABEL_1
+   ret i
+}
+"#
+    );
+
+    #[test]
+    fn can_select_switch_lowering() {
+        assert_eq!(
+            SwitchLowering::Auto.select(&[1, 5, 9]),
+            SwitchLowering::Chain
+        );
+        assert_eq!(
+            SwitchLowering::Auto.select(&[0, 1, 2, 4, 6]),
+            SwitchLowering::JumpTable
+        );
+        assert_eq!(
+            SwitchLowering::Auto.select(&[1, 10, 100, 1000]),
+            SwitchLowering::Tree
+        );
+        assert_eq!(
+            SwitchLowering::Chain.select(&[0, 1, 2, 3, 4]),
+            SwitchLowering::Chain
+        );
+    }
+
+    #[test]
+    fn can_generate_switch_as_balanced_tree() {
+        let source = "int main() { int x = 0; switch (x) { case 1: case 10: case 100: case 1000: x = 1; } return x; }";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        for (lowering, expected) in [
+            (SwitchLowering::Tree, "lt x %v1"),
+            (SwitchLowering::JumpTable, "gt x %v3"),
+        ] {
+            let mut irgen = IRBuilder::new(parser.ast(), &symbol_table)
+                .with_switch_lowering(lowering);
+            irgen.build();
+            let actual = irgen.functions()[0].to_string();
+            // Both halves of the tree are dispatched with equality chains.
+            assert_eq!(actual.matches("= eq x").count(), 4, "{actual}");
+            assert!(actual.contains(expected), "{actual}");
+        }
+    }
+
+    test_ir_gen!(
+        can_generate_inline_assembly,
+        r#"
+int main() {
+    int a = 1;
+    asm("pause");
+    return a;
+}
+"#,
+        r#"
+@main: int {
+   %v0: int = const 1
+   a: int = id %v0
+   asm "pause"
+   ret a
+}
+"#
+    );
+
+    test_ir_gen!(
+        can_generate_intrinsic_calls,
+        r#"
+int main() {
+    int a = __builtin_abs(-4);
+    if (__builtin_expect(a > 2, true)) {
+        __builtin_trap();
+    }
+    return __builtin_min(a, 2);
+}
+"#,
+        r#"
+@main: int {
+   %v0: int = const 4
+   %v1: int = neg %v0
+   %v2: int = intrinsic @__builtin_abs %v1
+   a: int = id %v2
+   %v3: int = const 2
+   %v4: bool = gt a %v3
+   br %v4 .LABEL_0 .LABEL_1 !likely
+   .LABEL_0
+   %v5: int = intrinsic @__builtin_trap
+   .LABEL_1
+   jmp .LABEL_2
+   .LABEL_2
+   %v6: int = const 2
+   %v7: int = intrinsic @__builtin_min a %v6
+   ret %v7
+}
+"#
+    );
+
+    test_ir_gen!(
+        can_generate_branch_hints,
+        r#"
+int main() {
+    int i = 0;
+    bool b = false;
+    for (i = 0; __builtin_expect(i < 10, false); i = i + 1) {
+        if (__builtin_expect(i == 5, b)) {
+            return i;
+        }
+    }
+    return 0;
+}
+"#,
+        r#"
+@main: int {
+   %v0: int = const 0
+   i: int = id %v0
+   %v1: bool = const false
+   b: bool = id %v1
+   %v2: int = const 0
+   i: int = id %v2
+   .LABEL_0
+   %v3: int = const 5
+   %v4: bool = eq i %v3
+   %v5: bool = intrinsic @__builtin_expect %v4 b
+   br %v5 .LABEL_2 .LABEL_3
+   .LABEL_2
+   ret i
+   .LABEL_3
+   jmp .LABEL_4
+   .LABEL_4
+   %v6: int = const 1
+   %v7: int = add i %v6
+   i: int = id %v7
+   %v8: int = const 10
+   %v9: bool = lt i %v8
+   br %v9 .LABEL_0 .LABEL_1 !unlikely
+   .LABEL_1
+   %v10: int = const 0
+   ret %v10
+}
+"#
+    );
+
+    // Macro to generate test cases for the printer styles.
+    macro_rules! test_ir_print {
+        ($name:ident, $source:expr, $style:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                let source = $source;
+                let mut scanner = Scanner::new(source);
+                let tokens = scanner
+                    .scan()
+                    .expect("expected test case source to be valid");
+                let mut parser = Parser::new(&tokens);
+                parser.parse();
+                let symbol_table = analyze(parser.ast());
+
+                let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+                irgen.build();
+
+                let mut actual = "\n".to_string();
+                for func in irgen.functions() {
+                    actual
+                        .push_str(format!("{}", func.display($style)).as_str());
+                }
+                assert_eq!(actual, $expected)
+            }
+        };
+    }
+
+    test_ir_print!(
+        can_print_native_style,
+        "int main() { return 0;}",
+        PrintStyle::Native,
+        r#"
+@main: int {
+   %v0: int = const 0
+   ret %v0
+}
+"#
+    );
+
+    test_ir_print!(
+        can_print_bril_style,
+        r#"
+        int f(int a, int b) {
+            if (a <= b) {
+                return a;
+            }
+            return a - b;
+        }
+        "#,
+        PrintStyle::Bril,
+        r#"
+@f(a: int, b: int): int {
+  %v0: bool = le a b;
+  br %v0 .LABEL_0 .LABEL_1;
+.LABEL_0:
+  ret a;
+.LABEL_1:
+  jmp .LABEL_2;
+.LABEL_2:
+  %v1: int = sub a b;
+  ret %v1;
+}
+"#
+    );
+
+    test_ir_print!(
+        can_print_blocks_style,
+        r#"
+        int f(int a, int b) {
+            if (a <= b) {
+                return a;
+            }
+            return a - b;
+        }
+        "#,
+        PrintStyle::Blocks,
+        r#"
+@f(a: int, b: int): int {
+.BLOCK_0:
+   %v0: bool = lte a b
+   br %v0 .LABEL_0 .LABEL_1
+.LABEL_0: ; preds = .BLOCK_0
+   ret a
+.LABEL_1: ; preds = .BLOCK_0
+   jmp .LABEL_2
+.LABEL_2: ; preds = .LABEL_1
+   %v1: int = sub a b
+   ret %v1
+}
+"#
+    );
+
+    #[test]
+    fn can_print_bril_char_literals() {
+        let inst = Instruction::Const(
+            Symbol::new("c", Type::Char),
+            Value::ConstantLiteral(Literal::Char('x')),
+        );
+        assert_eq!(
+            inst.display(PrintStyle::Bril).to_string(),
+            "c: char = const 'x';"
+        );
+        // Quotes and backslashes are escaped so the output can be read back.
+        for (c, literal) in [('\\', r"'\\'"), ('\'', r"'\''")] {
+            let inst = Instruction::Const(
+                Symbol::new("c", Type::Char),
+                Value::ConstantLiteral(Literal::Char(c)),
+            );
+            assert_eq!(
+                inst.display(PrintStyle::Bril).to_string(),
+                format!("c: char = const {literal};")
+            );
+        }
+    }
+
+    #[test]
+    fn can_drop_branch_hints_in_bril() {
+        let inst = Instruction::Branch(
+            Value::StorageLocation(Symbol::new("c", Type::Bool)),
+            Label(0),
+            Label(1),
+            BranchHint::Unlikely,
+        );
+        assert_eq!(inst.to_string(), "br c .LABEL_0 .LABEL_1 !unlikely");
+        assert_eq!(
+            inst.display(PrintStyle::Bril).to_string(),
+            "br c .LABEL_0 .LABEL_1;"
+        );
+    }
+
+    #[test]
+    fn can_instrument_basic_blocks_for_coverage() {
+        let source = r#"
+int main() {
+    int i = 0;
+    while (i < 3) {
+        i = i + 1;
+    }
+    return i;
+}
+"#;
+        let tokens = Scanner::new(source).scan_with_spans().unwrap();
+        let mut parser = Parser::with_spans(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen =
+            IRBuilder::new(parser.ast(), &symbol_table).with_coverage();
+        irgen.build();
+
+        let expected = r#"@main: int {
+   count @__glouton_coverage 0
+   %v0: int = const 0
+   i: int = id %v0
+   %v1: int = const 3
+   %v2: bool = lt i %v1
+   br %v2 .LABEL_0 .LABEL_1
+   .LABEL_0
+   count @__glouton_coverage 1
+   %v3: int = const 1
+   %v4: int = add i %v3
+   i: int = id %v4
+   %v5: int = const 3
+   %v6: bool = lt i %v5
+   br %v6 .LABEL_0 .LABEL_1
+   .LABEL_1
+   count @__glouton_coverage 2
+   ret i
+}
+"#;
+        assert_eq!(irgen.functions()[0].to_string(), expected);
+        assert_eq!(irgen.coverage().map(|c| c.len()), Some(3));
+    }
+
+    #[test]
+    fn can_generate_extern_imports() {
+        let source = r#"
+extern int putchar(int c);
+extern bool flush();
+int main() {
+    return putchar(65);
+}
+"#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let imports = irgen
+            .imports()
+            .iter()
+            .map(|import| import.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            imports,
+            vec!["@extern putchar(c: int): int", "@extern flush(): bool"]
+        );
+        let expected = r#"@main: int {
+   %v0: int = const 65
+   %v1: int = call @putchar %v0
+   ret %v1
+}
+"#;
+        assert_eq!(irgen.functions()[0].to_string(), expected);
+    }
+
+    #[test]
+    fn can_mangle_internal_functions() {
+        let source = r#"
+static int helper(int a) {
+    return a;
+}
+int main() {
+    return helper(1);
+}
+"#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let helper = &irgen.functions()[0];
+        assert_eq!(helper.linkage(), ast::Linkage::Internal);
+        assert_eq!(helper.symbol("src/util.c"), "_GL4util6helper");
+        assert!(helper.to_string().starts_with("@internal helper(a: int)"));
+        let main = &irgen.functions()[1];
+        assert_eq!(main.linkage(), ast::Linkage::External);
+        assert_eq!(main.symbol("src/util.c"), "main");
+        assert_eq!(mangle("my-lib.c", "f"), "_GL8my_2dlib1f");
+        assert_eq!(mangle("my_lib.c", "f"), "_GL7my__lib1f");
+    }
+
+    #[test]
+    fn can_optimize_functions_across_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ast::AST>();
+        assert_send_sync::<crate::sema::SymbolTable>();
+        assert_send_sync::<Function>();
+        assert_send_sync::<Program>();
+        assert_send_sync::<crate::cfg::Graph>();
+        assert_send_sync::<crate::optim::PassManager>();
+        assert_send_sync::<crate::optim::ModulePassManager>();
+
+        let source = "int main() { return 1 + 2; } int f() { return 2 * 3; }";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let mut functions = irgen.functions().clone();
+        let expected = functions
+            .iter()
+            .cloned()
+            .map(|mut function| {
+                crate::optim::PassManager::oz().run(&mut function);
+                function
+            })
+            .collect::<Vec<_>>();
+
+        let manager = crate::optim::PassManager::oz();
+        std::thread::scope(|scope| {
+            for function in &mut functions {
+                let manager = &manager;
+                scope.spawn(move || manager.run(function));
+            }
+        });
+        assert_eq!(functions, expected);
+    }
+
+    #[test]
+    fn can_fold_const_variables() {
+        let source = "const int n = 4; int main() { const int m = n * 2; int x = m + 1; return x; }";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        assert_eq!(
+            irgen.functions()[0].to_string(),
+            "@main: int {\n   %v1: int = const 8\n   %v2: int = const 1\n   \
+             %v3: int = add %v1 %v2\n   x: int = id %v3\n   ret x\n}\n"
+        );
+    }
+
+    #[test]
+    fn can_lower_static_locals() {
+        let source = "extern int next(); int main() { static int calls = 1; static int seed = next(); calls = calls + seed; return calls; }";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let int = |name| Symbol::new(name, Type::Int);
+        let guard = Symbol::new("main.seed.guard", Type::Bool);
+        assert_eq!(
+            irgen.globals(),
+            &vec![
+                GlobalValue(int("main.calls"), Literal::Int(1), None),
+                GlobalValue(int("main.seed"), Literal::Int(0), None),
+                GlobalValue(guard.clone(), Literal::Bool(false), None),
+            ]
+        );
+        assert_eq!(
+            irgen.functions()[0].to_string(),
+            "@main: int {\n   br main.seed.guard .LABEL_1 .LABEL_0 !likely\n   \
+             .LABEL_0\n   %v0: int = call @next\n   main.seed: int = id %v0\n   \
+             main.seed.guard: bool = const true\n   jmp .LABEL_1\n   .LABEL_1\n   \
+             %v1: int = add main.calls main.seed\n   main.calls: int = id %v1\n   \
+             ret main.calls\n}\n"
+        );
+
+        // Stores to static locals aren't dead.
+        let mut function = irgen.functions()[0].clone();
+        crate::optim::PassManager::oz().run(&mut function);
+        assert_eq!(
+            function.statics(),
+            &[int("main.calls"), int("main.seed"), guard]
+        );
+        assert!(function
+            .to_string()
+            .contains("main.seed.guard: bool = const true"));
+    }
+
+    #[test]
+    fn can_lower_printf_calls() {
+        let source =
+            r#"int main() { int x = 4; return printf("x=%d%s\n", x, "!"); }"#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let ir = irgen.functions()[0].to_string();
+        assert_eq!(ir.matches("@__builtin_print_char").count(), 4);
+        assert!(ir.contains("%v5: int = intrinsic @__builtin_print_int x"));
+        assert!(ir.contains(
+            "%v10: int = const 4\n   %v11: int = add %v10 %v5\n   ret %v11"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "`__builtin_print_int` needs the C library")]
+    fn can_reject_runtime_calls_when_freestanding() {
+        let source = "int main() { return __builtin_print_int(4); }";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen =
+            IRBuilder::new(parser.ast(), &symbol_table).with_freestanding();
+        irgen.build();
+    }
+
+    #[test]
+    fn can_lower_atomic_operations() {
+        let source = r"
+            int counter = 0;
+            int main() {
+                int old = __atomic_add(counter, 2, 5);
+                __atomic_store(counter, old, 3);
+                return __atomic_load(counter, 0);
+            }
+        ";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        assert!(irgen.functions()[0].to_string().ends_with(
+            "   %v2: int = atomic_add counter %v1 seq_cst\n   \
+             old: int = id %v2\n   \
+             %v3: int = atomic_store counter old release\n   \
+             %v4: int = atomic_load counter relaxed\n   ret %v4\n}\n"
+        ));
+    }
+
+    #[test]
+    fn can_store_short_argument_lists_inline() {
+        let one = Value::ConstantLiteral(Literal::Int(1));
+        let mut args = Operands::new();
+        assert!(args.is_empty() && !args.spilled());
+        args.push(one.clone());
+        assert_eq!(args.as_slice(), std::slice::from_ref(&one));
+        assert!(!args.spilled());
+        args.push(one.clone());
+        assert_eq!(args.len(), 2);
+        assert!(args.spilled());
+        assert_eq!(args, Operands::from(vec![one.clone(), one.clone()]));
+        assert!(!Operands::from(vec![one]).spilled());
+        // The inline slot doesn't make calls larger than three-address
+        // instructions.
+        assert_eq!(
+            std::mem::size_of::<Operands>(),
+            std::mem::size_of::<Value>()
+        );
+    }
+
+    #[test]
+    fn can_iterate_instruction_uses() {
+        let a = Symbol::new("a", Type::Int);
+        let t = Symbol::new("t", Type::Int);
+        let one = Value::ConstantLiteral(Literal::Int(1));
+        let call = Instruction::Call(
+            t.clone(),
+            Symbol::new("f", Type::Int),
+            vec![
+                Value::StorageLocation(a.clone()),
+                one.clone(),
+                Value::StorageLocation(t.clone()),
+            ]
+            .into(),
+        );
+        let add = Instruction::Add(
+            t.clone(),
+            Value::StorageLocation(a.clone()),
+            one.clone(),
+        );
+        for inst in [call, add, Instruction::Nop] {
+            let mut copy = inst.clone();
+            let expected = copy.values_mut().into_iter().map(|v| v.clone());
+            assert!(inst.uses().cloned().eq(expected));
+        }
+        let ret = Instruction::Return(one.clone());
+        assert_eq!(ret.uses().collect::<Vec<_>>(), vec![&one]);
+    }
+
+    #[test]
+    fn can_clone_regions() {
+        let source = r"
+            int main() {
+                int a = 0;
+                while (a < 10) { a = a + 1; }
+                return a;
+            }
+        ";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let main = &irgen.functions()[0];
+        let region = 0..main.len();
+        let remapper = Remapper::fresh(main, region.clone());
+        let copy = main.clone_region(region, &remapper);
+        assert_eq!(copy.len(), main.len());
+        let labels = |insts: &[Instruction]| {
+            insts
+                .iter()
+                .filter_map(|inst| match inst {
+                    Instruction::Label(label) => Some(*label),
+                    _ => None,
+                })
+                .collect::<HashSet<_>>()
+        };
+        let temps = |insts: &[Instruction]| {
+            insts
+                .iter()
+                .filter_map(Instruction::destination)
+                .filter(|dst| dst.name().starts_with('%'))
+                .cloned()
+                .collect::<HashSet<_>>()
+        };
+        // The copy only shares named variables with the original.
+        assert!(!labels(&copy).is_empty());
+        assert!(labels(&copy).is_disjoint(&labels(main.instructions())));
+        assert!(temps(&copy).is_disjoint(&temps(main.instructions())));
+        assert!(copy.iter().any(|inst| inst
+            .destination()
+            .is_some_and(|dst| dst.name() == "a")));
+        // Mapping the copy back yields the original.
+        let mut inverse = Remapper::new();
+        for (from, to) in remapper.labels.iter() {
+            inverse.map_label(*to, *from);
+        }
+        for (from, to) in remapper.symbols.iter() {
+            inverse.map_symbol(to.clone(), from.clone());
+        }
+        let restored = copy
+            .into_iter()
+            .map(|inst| inverse.remap(inst))
+            .collect::<Vec<_>>();
+        assert_eq!(restored, main.instructions());
+    }
+
+    #[test]
+    fn can_end_blocks_at_noreturn_calls() {
+        let source = r"
+            [[noreturn]] int fatal() { __builtin_trap(); return 0; }
+            int a = 1;
+            int main() {
+                if (a > 0) {
+                    fatal();
+                    a = a + 1;
+                }
+                return a;
+            }
+        ";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let main = &mut irgen.functions_mut()[1];
+        let graph = Graph::new(&vec![main.clone()]);
+        let trap = graph
+            .blocks()
+            .find(|block| {
+                graph.block(*block).instructions().iter().any(|inst| {
+                    matches!(inst, Instruction::Call(_, callee, _)
+                        if callee.name() == "fatal")
+                })
+            })
+            .unwrap();
+        assert!(graph.successors(trap).next().is_none());
+
+        crate::optim::PassRegistry::default()
+            .pipeline("dce")
+            .unwrap()
+            .run(main);
+        let expected = r#"@main: int {
+   %v3: int = const 0
+   %v4: bool = gt a %v3
+   br %v4 .LABEL_0 .LABEL_1
+   .LABEL_0
+   %v5: int = call @fatal
+   trap "`fatal` returned"
+   .LABEL_1
+   jmp .LABEL_2
+   .LABEL_2
+   ret a
+}
+"#;
+        assert_eq!(main.to_string(), expected);
+        assert!(irgen.functions()[0].has_attribute(ast::Attribute::NoReturn));
+    }
+
+    #[test]
+    fn can_place_declarations_in_sections() {
+        let source = r#"
+            __attribute__((section(".noinit"))) int boots = 0;
+            __attribute__((section(".text.boot")))
+            int main() { return boots; }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let program = irgen.into_program();
+        assert_eq!(program.globals()[0].section(), Some(".noinit"));
+        assert!(program.to_string().starts_with(
+            "boots: int: 0 section(\".noinit\")\n\
+             @main: int section(\".text.boot\") {"
+        ));
+    }
+
+    #[test]
+    fn can_link_translation_units() {
+        let lower = |source: &str| {
+            let tokens = Scanner::new(source).scan().unwrap();
+            let mut parser = Parser::new(&tokens);
+            parser.parse();
+            let symbol_table = analyze(parser.ast());
+            let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+            irgen.build();
+            irgen.into_program()
+        };
+        let main = lower(
+            "extern int twice(int x);
+             int main() { return twice(helper()); }
+             static int helper() { return 1; }",
+        );
+        let util = lower(
+            "int twice(int x) { return x + helper(); }
+             static int helper() { return 2; }",
+        );
+
+        let program = link([
+            ("src/main.c".to_string(), main.clone()),
+            ("src/util.c".to_string(), util.clone()),
+        ])
+        .unwrap();
+        assert!(program.imports().is_empty());
+        let names = program
+            .functions()
+            .iter()
+            .map(Function::name)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["main", "_GL4main6helper", "twice", "_GL4util6helper"]
+        );
+        let calls = program.functions()[0].to_string();
+        assert!(calls.contains("call @_GL4main6helper"), "{calls}");
+        let calls = program.functions()[2].to_string();
+        assert!(calls.contains("call @_GL4util6helper"), "{calls}");
+
+        let err = link([
+            ("main.c".to_string(), util.clone()),
+            ("util.c".to_string(), util),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "duplicate definition of `twice` in `main.c` and `util.c`"
+        );
+    }
+
+    #[test]
+    fn can_link_static_locals_of_translation_units() {
+        let lower = |source: &str| {
+            let tokens = Scanner::new(source).scan().unwrap();
+            let mut parser = Parser::new(&tokens);
+            parser.parse();
+            let symbol_table = analyze(parser.ast());
+            let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+            irgen.build();
+            irgen.into_program()
+        };
+        let unit = lower(
+            "static int helper(int s) { static int n = s; n = n + 1; return n; }
+             int main() { return helper(1); }",
+        );
+        let other =
+            lower("static int helper(int s) { static int n = s; return n; }");
+
+        let program =
+            link([("a.c".to_string(), unit), ("b.c".to_string(), other)])
+                .unwrap();
+        let globals = program
+            .globals()
+            .iter()
+            .map(|global| global.symbol().name())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            globals,
+            [
+                "_GL1a8helper.n",
+                "_GL1a14helper.n.guard",
+                "_GL1b8helper.n",
+                "_GL1b14helper.n.guard"
+            ]
+        );
+        let helper = &program.functions()[0];
+        assert_eq!(
+            helper.statics(),
+            [
+                Symbol::new("_GL1a8helper.n", Type::Int),
+                Symbol::new("_GL1a14helper.n.guard", Type::Bool)
+            ]
+        );
+        let body = helper.to_string();
+        assert!(body.contains("br _GL1a14helper.n.guard"), "{body}");
+        assert!(body.contains("_GL1a8helper.n: int = id s"), "{body}");
+        assert!(!body.contains(" helper.n"), "{body}");
+    }
 }
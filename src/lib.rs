@@ -1,8 +1,26 @@
 pub mod ast;
+pub mod builtins;
 pub mod cfg;
+pub mod consteval;
+pub mod conversions;
+pub mod cost;
+pub mod coverage;
+pub mod dataflow;
+pub mod decompile;
+pub mod diagnostics;
+pub mod diff;
+pub mod formatter;
+pub mod highlight;
+pub mod ilp;
 pub mod ir;
+pub mod limits;
 pub mod optim;
 pub mod parser;
+pub mod report;
 pub mod scanner;
 pub mod sema;
+pub mod simplify;
+pub mod stack;
+pub mod target;
 pub mod token;
+pub mod trace;
@@ -0,0 +1,134 @@
+//! Resource limits on the compiled program.
+//!
+//! Inputs from the fuzzer or the web playground can be arbitrarily large, the
+//! parser, IR generation and the optimizer check the size of what they build
+//! against `Limits` and fail with a `LimitError` instead of exhausting the
+//! memory of the process.
+//!
+//! Like other frontend errors, limits are enforced by panicking: the payload
+//! of the panic is the `LimitError` (see `std::panic::panic_any`) so drivers
+//! catching the panic can report a "program too large" diagnostic instead of
+//! a syntax or internal error.
+use std::error::Error;
+use std::fmt;
+
+/// Default maximum number of AST nodes.
+pub const MAX_AST_NODES: usize = 1 << 20;
+/// Default maximum number of IR instructions in a program.
+pub const MAX_INSTRUCTIONS: usize = 1 << 20;
+/// Default maximum growth factor of a function during optimization.
+pub const MAX_GROWTH: usize = 4;
+
+/// `Limits` bounds the size of the compiled program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    // Maximum number of declarations, statements and expressions.
+    ast_nodes: usize,
+    // Maximum number of IR instructions across the program.
+    instructions: usize,
+    // Maximum size of an optimized function, as a multiple of its size
+    // before optimization.
+    growth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            ast_nodes: MAX_AST_NODES,
+            instructions: MAX_INSTRUCTIONS,
+            growth: MAX_GROWTH,
+        }
+    }
+}
+
+impl Limits {
+    /// Set the maximum number of AST nodes.
+    #[must_use]
+    pub const fn with_ast_nodes(mut self, ast_nodes: usize) -> Self {
+        self.ast_nodes = ast_nodes;
+        self
+    }
+
+    /// Set the maximum number of IR instructions.
+    #[must_use]
+    pub const fn with_instructions(mut self, instructions: usize) -> Self {
+        self.instructions = instructions;
+        self
+    }
+
+    /// Set the maximum growth factor of optimized functions.
+    #[must_use]
+    pub const fn with_growth(mut self, growth: usize) -> Self {
+        self.growth = growth;
+        self
+    }
+
+    /// Returns the maximum number of AST nodes.
+    #[must_use]
+    pub const fn ast_nodes(&self) -> usize {
+        self.ast_nodes
+    }
+
+    /// Returns the maximum number of IR instructions.
+    #[must_use]
+    pub const fn instructions(&self) -> usize {
+        self.instructions
+    }
+
+    /// Returns the maximum growth factor of optimized functions.
+    #[must_use]
+    pub const fn growth(&self) -> usize {
+        self.growth
+    }
+}
+
+/// Limit error type is used to report programs exceeding a resource limit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitError {
+    details: String,
+}
+
+impl LimitError {
+    /// Create an error for an AST with more than `limit` nodes.
+    #[must_use]
+    pub fn ast_nodes(limit: usize) -> Self {
+        Self {
+            details: format!("AST exceeds the limit of {limit} nodes"),
+        }
+    }
+
+    /// Create an error for a program with more than `limit` instructions.
+    #[must_use]
+    pub fn instructions(limit: usize) -> Self {
+        Self {
+            details: format!("IR exceeds the limit of {limit} instructions"),
+        }
+    }
+
+    /// Create an error for a failed allocation.
+    #[must_use]
+    pub fn out_of_memory(what: &str) -> Self {
+        Self {
+            details: format!("out of memory allocating {what}"),
+        }
+    }
+
+    /// Returns the error details.
+    #[must_use]
+    pub fn details(&self) -> &str {
+        &self.details
+    }
+
+    /// Abort the current phase by panicking with the error as payload.
+    pub fn raise(self) -> ! {
+        std::panic::panic_any(self)
+    }
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "program too large: {}", self.details)
+    }
+}
+
+impl Error for LimitError {}
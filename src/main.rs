@@ -1,4 +1,15 @@
-use glouton::{ir, parser, scanner, sema};
+use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+
+use glouton::diagnostics::{self, Diagnostic, Severity, Warnings};
+use glouton::limits::{LimitError, Limits};
+use glouton::{
+    ast, consteval, conversions, decompile, ilp, ir, optim, parser, report,
+    scanner, sema, simplify, stack, target, trace,
+};
 
 const MAIN_PROGRAM: &str = r#"
 int main() {
@@ -6,18 +17,671 @@ int main() {
 }
 "#;
 
+const USAGE: &str = "usage: glouton [-O0|-O1|-O2|-Oz] [--lang=min|c89subset|gnu] [--trigraphs] [--unicode-identifiers] [--tab-width=N] [-ftrapv] [--freestanding] [--entry=SYMBOL] [--stats] [--analyze-stack] [--analyze-ilp] [--coverage] \
+[-Wall] [-Werror] [-W[no-]GROUP] [--target=TRIPLE] [--diagnostics-format=human|json] [--emit=ir|c|compile-info|ce-json] [--ir-style=native|bril|blocks] \
+[--switch-lowering=auto|table|chain|tree] [--passes=PIPELINE] [--opt-report=PATH] \
+[--log-level=error|warn|info|debug|trace] [--log-json] \
+[--max-ast-nodes=N] [--max-instructions=N] [--max-growth=N] [FILE...]";
+
+/// How diagnostics are printed.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+enum DiagnosticsFormat {
+    // Human readable messages.
+    #[default]
+    Human,
+    // One JSON object per line.
+    Json,
+}
+
+/// What the compiler writes to the standard output.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+enum Emit {
+    // The generated IR.
+    #[default]
+    Ir,
+    // A JSON description of the compilation (timings, pass statistics and
+    // artifacts) instead of the IR.
+    CompileInfo,
+    // The generated IR in the JSON format of Compiler Explorer.
+    CompilerExplorer,
+    // The optimized program decompiled to C.
+    C,
+}
+
+/// Command line options.
+#[derive(Default, Debug)]
+struct Options {
+    // Source files to compile, the builtin program is compiled if empty.
+    inputs: Vec<String>,
+    // Instrument every basic block with a coverage counter.
+    coverage: bool,
+    // Target the program is compiled for.
+    target: target::Target,
+    // Compile without the C library, the entry symbol is `entry` if set.
+    freestanding: bool,
+    entry: Option<String>,
+    // Optimization level selecting the pass pipeline.
+    opt_level: optim::OptLevel,
+    // Language level of the accepted syntax and builtins.
+    lang: parser::Lang,
+    // Translate trigraphs instead of reporting them.
+    trigraphs: bool,
+    // Accept Unicode letters and digits in identifiers.
+    unicode_identifiers: bool,
+    // Distance between tab stops when computing columns.
+    tab_width: usize,
+    // Evaluation of overflowing arithmetic folded by the optimizer.
+    arithmetic: consteval::ArithmeticMode,
+    // Print the code size of every function before and after optimization.
+    stats: bool,
+    // Print the stack usage of every function.
+    analyze_stack: bool,
+    // Print the instruction-level parallelism of every function.
+    analyze_ilp: bool,
+    // Format of the reported diagnostics.
+    diagnostics_format: DiagnosticsFormat,
+    // Output written to the standard output.
+    emit: Emit,
+    // Enabled warning groups.
+    warnings: Warnings,
+    // Strategy used to lower `switch` statements.
+    switch_lowering: ir::SwitchLowering,
+    // Pipeline description replacing the pipeline of the optimization
+    // level, e.g. `module(return-value-propagation,function(dce))`.
+    passes: Option<String>,
+    // Syntax of the IR emitted with `--emit=ir`.
+    ir_style: ir::PrintStyle,
+    // Path of the HTML optimization report.
+    opt_report: Option<String>,
+    // Most verbose trace spans reported, tracing is disabled if `None`.
+    log_level: Option<trace::Level>,
+    // Report trace spans as JSON objects.
+    log_json: bool,
+    // Limits on the size of the compiled program.
+    limits: Limits,
+}
+
+impl Options {
+    /// Parse the command line arguments (without the program name).
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self {
+            tab_width: diagnostics::DEFAULT_TAB_WIDTH,
+            ..Self::default()
+        };
+        for arg in args {
+            match arg.as_str() {
+                "--coverage" => options.coverage = true,
+                "--stats" => options.stats = true,
+                "--analyze-stack" => options.analyze_stack = true,
+                "--analyze-ilp" => options.analyze_ilp = true,
+                "-O0" => options.opt_level = optim::OptLevel::O0,
+                "-O1" => options.opt_level = optim::OptLevel::O1,
+                "-O2" => options.opt_level = optim::OptLevel::O2,
+                "-Oz" => options.opt_level = optim::OptLevel::Oz,
+                "--freestanding" => options.freestanding = true,
+                "--trigraphs" => options.trigraphs = true,
+                "--unicode-identifiers" => options.unicode_identifiers = true,
+                flag if flag.starts_with("--tab-width=") => {
+                    options.tab_width = flag["--tab-width=".len()..]
+                        .parse()
+                        .ok()
+                        .filter(|width| *width > 0)
+                        .ok_or_else(|| format!("invalid tab width `{flag}`"))?;
+                }
+                flag if flag.starts_with("--lang=") => {
+                    let name = &flag["--lang=".len()..];
+                    options.lang = parser::Lang::from_name(name)
+                        .ok_or_else(|| format!("unknown language {name}"))?;
+                }
+                flag if flag.starts_with("--entry=") => {
+                    options.entry = Some(flag["--entry=".len()..].to_string());
+                }
+                "-ftrapv" => {
+                    options.arithmetic = consteval::ArithmeticMode::Trapping;
+                }
+                flag if flag.starts_with("--target=") => {
+                    let triple = &flag["--target=".len()..];
+                    options.target = target::Target::from_triple(triple)
+                        .map_err(|err| err.to_string())?;
+                }
+                "--diagnostics-format=human" => {
+                    options.diagnostics_format = DiagnosticsFormat::Human;
+                }
+                "--diagnostics-format=json" => {
+                    options.diagnostics_format = DiagnosticsFormat::Json;
+                }
+                "--emit=ir" => options.emit = Emit::Ir,
+                "--emit=c" => options.emit = Emit::C,
+                "--emit=compile-info" => options.emit = Emit::CompileInfo,
+                "--emit=ce-json" => options.emit = Emit::CompilerExplorer,
+                flag if flag.starts_with("--switch-lowering=") => {
+                    let name = &flag["--switch-lowering=".len()..];
+                    options.switch_lowering = ir::SwitchLowering::from_name(
+                        name,
+                    )
+                    .ok_or_else(|| format!("unknown switch lowering {name}"))?;
+                }
+                flag if flag.starts_with("--ir-style=") => {
+                    let name = &flag["--ir-style=".len()..];
+                    options.ir_style = ir::PrintStyle::from_name(name)
+                        .ok_or_else(|| format!("unknown IR style {name}"))?;
+                }
+                flag if flag.starts_with("--log-level=") => {
+                    let name = &flag["--log-level=".len()..];
+                    options.log_level =
+                        Some(trace::Level::from_name(name).ok_or_else(
+                            || format!("unknown log level {name}"),
+                        )?);
+                }
+                "--log-json" => options.log_json = true,
+                flag if flag.starts_with("--max-ast-nodes=") => {
+                    options.limits =
+                        options.limits.with_ast_nodes(parse_limit(flag)?);
+                }
+                flag if flag.starts_with("--max-instructions=") => {
+                    options.limits =
+                        options.limits.with_instructions(parse_limit(flag)?);
+                }
+                flag if flag.starts_with("--max-growth=") => {
+                    options.limits =
+                        options.limits.with_growth(parse_limit(flag)?);
+                }
+                flag if flag.starts_with("--opt-report=") => {
+                    options.opt_report =
+                        Some(flag["--opt-report=".len()..].to_string());
+                }
+                flag if flag.starts_with("--passes=") => {
+                    let pipeline = &flag["--passes=".len()..];
+                    optim::PassRegistry::default()
+                        .parse_pipeline(pipeline)
+                        .map_err(|err| err.to_string())?;
+                    options.passes = Some(pipeline.to_string());
+                }
+                flag if options.warnings.apply_flag(flag) => (),
+                flag if flag.starts_with('-') => {
+                    return Err(format!("unknown option {flag}"))
+                }
+                _ => options.inputs.push(arg),
+            }
+        }
+        if options.inputs.len() > 1
+            && (options.opt_report.is_some()
+                || options.emit == Emit::CompilerExplorer)
+        {
+            return Err(
+                "--opt-report and --emit=ce-json expect a single input file"
+                    .to_string(),
+            );
+        }
+        if options.entry.is_some() && !options.freestanding {
+            return Err("--entry requires --freestanding".to_string());
+        }
+        Ok(options)
+    }
+}
+
+/// Parse the value of a `--max-*=N` flag.
+fn parse_limit(flag: &str) -> Result<usize, String> {
+    let (name, value) = flag.split_once('=').unwrap_or((flag, ""));
+    value
+        .parse()
+        .map_err(|_| format!("invalid value `{value}` for {name}"))
+}
+
+/// Statistics collected while compiling a program.
+#[derive(Default, Debug)]
+struct CompileInfo {
+    // Wall clock time spent in every phase, in microseconds.
+    timings: Vec<(&'static str, u128)>,
+    // Code size of every function before and after optimization.
+    functions: Vec<(String, usize, usize)>,
+}
+
+impl CompileInfo {
+    /// Run a compilation phase and record its duration, panics raised by the
+    /// phase are reported as diagnostics with the given `code`.
+    fn phase<T>(
+        &mut self,
+        name: &'static str,
+        code: &'static str,
+        f: impl FnOnce() -> T,
+    ) -> Result<T, Vec<Diagnostic>> {
+        let start = Instant::now();
+        let span = trace::span(trace::Level::Info, name, &[]);
+        let result = panic::catch_unwind(AssertUnwindSafe(f));
+        drop(span);
+        self.timings.push((name, start.elapsed().as_micros()));
+        result.map_err(|payload| {
+            if let Some(err) = payload.downcast_ref::<LimitError>() {
+                return vec![Diagnostic::from(err)];
+            }
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(ToString::to_string)
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "compilation failed".to_string());
+            vec![Diagnostic::error(code, &message)]
+        })
+    }
+
+    /// Add the timings of `other`, phases run by both are summed.
+    fn merge(&mut self, other: &Self) {
+        for (phase, micros) in &other.timings {
+            match self.timings.iter_mut().find(|(name, _)| name == phase) {
+                Some((_, total)) => *total += micros,
+                None => self.timings.push((phase, *micros)),
+            }
+        }
+    }
+
+    /// Returns the compilation description as a JSON object.
+    fn to_json(&self, options: &Options) -> String {
+        let input = options.inputs.first().map_or_else(
+            || "null".to_string(),
+            |path| diagnostics::escape(path),
+        );
+        let inputs = options
+            .inputs
+            .iter()
+            .map(|path| diagnostics::escape(path))
+            .collect::<Vec<_>>()
+            .join(",");
+        let timings = self
+            .timings
+            .iter()
+            .map(|(phase, micros)| {
+                format!(r#"{{"phase":"{phase}","micros":{micros}}}"#)
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let functions = self
+            .functions
+            .iter()
+            .map(|(name, before, after)| {
+                format!(
+                    r#"{{"name":{},"size_before":{before},"size_after":{after}}}"#,
+                    diagnostics::escape(name)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        // The IR is only written when it was requested.
+        let artifacts = match options.emit {
+            Emit::Ir => r#"{"kind":"ir","path":"-"}"#,
+            Emit::CompilerExplorer => r#"{"kind":"ce-json","path":"-"}"#,
+            Emit::C => r#"{"kind":"c","path":"-"}"#,
+            Emit::CompileInfo => "",
+        };
+        format!(
+            r#"{{"input":{input},"inputs":[{inputs}],"target":{},"opt_level":"{:?}","timings":[{timings}],"functions":[{functions}],"artifacts":[{artifacts}]}}"#,
+            diagnostics::escape(&options.target.to_string()),
+            options.opt_level,
+        )
+    }
+}
+
 fn main() {
-    compile()
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("glouton: {err}\n{USAGE}");
+            std::process::exit(1);
+        }
+    };
+    trace::init(options.log_level, options.log_json);
+    let sources = if options.inputs.is_empty() {
+        vec![(None, MAIN_PROGRAM.to_string())]
+    } else {
+        options
+            .inputs
+            .iter()
+            .map(|path| match std::fs::read_to_string(path) {
+                Ok(source) => (Some(path.clone()), source),
+                Err(err) => {
+                    eprintln!("glouton: failed to read {path}: {err}");
+                    std::process::exit(1);
+                }
+            })
+            .collect()
+    };
+    // Panics raised by the frontend are reported as diagnostics.
+    panic::set_hook(Box::new(|_| {}));
+    if let Err(diagnostics) = compile(&sources, &options) {
+        for diagnostic in &diagnostics {
+            report(diagnostic, &options);
+        }
+        std::process::exit(1);
+    }
 }
 
-fn compile() {
-    let tokens = scanner::Scanner::new(MAIN_PROGRAM).scan().unwrap();
-    let mut parser = parser::Parser::new(&tokens);
-    parser.parse();
-    let symbol_table = sema::analyze(parser.ast());
-    let mut irgen = ir::IRBuilder::new(parser.ast(), &symbol_table);
-    irgen.build();
-    for inst in irgen.functions() {
-        println!("{inst}");
+/// Print a diagnostic to the standard error.
+fn report(diagnostic: &Diagnostic, options: &Options) {
+    match options.diagnostics_format {
+        DiagnosticsFormat::Human => eprintln!("glouton: {diagnostic}"),
+        DiagnosticsFormat::Json => eprintln!("{}", diagnostic.to_json()),
+    }
+}
+
+/// A translation unit lowered to IR.
+struct Unit {
+    // Source file, `None` for the builtin program.
+    path: Option<String>,
+    // Source code of the unit.
+    source: String,
+    // AST of the unit, after simplification.
+    ast: ast::AST,
+    // IR of the unit.
+    program: ir::Program,
+    // Warnings reported by the linter.
+    warnings: Vec<Diagnostic>,
+    // Timings of the frontend phases.
+    info: CompileInfo,
+}
+
+/// Run the frontend on a translation unit, from scanning to IR generation.
+fn frontend(
+    path: Option<&str>,
+    source: &str,
+    options: &Options,
+) -> Result<Unit, Vec<Diagnostic>> {
+    let _span = trace::span(
+        trace::Level::Info,
+        "unit",
+        &[("file", &path.unwrap_or("-"))],
+    );
+    let mut info = CompileInfo::default();
+    let tokens = info
+        .phase("scan", diagnostics::SCAN_ERROR, || {
+            let mut scanner = scanner::Scanner::new(source);
+            if options.trigraphs {
+                scanner = scanner.with_trigraphs();
+            }
+            if options.unicode_identifiers {
+                scanner = scanner.with_unicode_identifiers();
+            }
+            scanner.scan_with_spans()
+        })?
+        .map_err(|err| vec![Diagnostic::from(&err)])?;
+    let mut parser = info.phase("parse", diagnostics::SYNTAX_ERROR, || {
+        let mut parser = parser::Parser::with_spans(&tokens)
+            .with_lang(options.lang)
+            .with_max_nodes(options.limits.ast_nodes());
+        parser.parse();
+        parser
+    })?;
+    let symbol_table =
+        info.phase("sema", diagnostics::SEMANTIC_ERROR, || {
+            conversions::insert_casts(parser.ast_mut());
+            sema::analyze_with_lang(parser.ast(), options.lang)
+        })?;
+    let source_map =
+        diagnostics::SourceMap::new(source).with_tab_width(options.tab_width);
+    let warnings = sema::lint(parser.ast(), &options.warnings)
+        .into_iter()
+        .map(|diagnostic| match diagnostic.span() {
+            Some(span) => diagnostic.with_source(&source_map, span),
+            None => diagnostic,
+        })
+        .collect::<Vec<_>>();
+    if warnings
+        .iter()
+        .any(|diagnostic| diagnostic.severity() == Severity::Error)
+    {
+        return Err(warnings);
+    }
+    if options.opt_level != optim::OptLevel::O0 {
+        info.phase("simplify", diagnostics::INTERNAL_ERROR, || {
+            simplify::simplify(parser.ast_mut())
+        })?;
+    }
+    let program = info.phase("irgen", diagnostics::INTERNAL_ERROR, || {
+        let mut irgen = ir::IRBuilder::new(parser.ast(), &symbol_table)
+            .with_switch_lowering(options.switch_lowering)
+            .with_max_instructions(options.limits.instructions());
+        if options.coverage {
+            irgen = irgen.with_coverage();
+        }
+        if options.freestanding {
+            irgen = irgen.with_freestanding();
+        }
+        irgen.build();
+        irgen.into_program()
+    })?;
+    Ok(Unit {
+        path: path.map(ToString::to_string),
+        source: source.to_string(),
+        ast: parser.into_ast(),
+        program,
+        warnings,
+        info,
+    })
+}
+
+/// Stack size of the worker threads. The parser and the IR builder recurse
+/// on nested expressions, so workers get more than the 8 MiB stack of the
+/// main thread rather than the 2 MiB default of spawned threads.
+const WORKER_STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Run `f` on every item from a pool of worker threads, the results are
+/// returned in the order of `items`.
+fn parallel<T: Sync, R: Send>(
+    items: &[T],
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let workers = thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(items.len());
+    let next = AtomicUsize::new(0);
+    let mut results = thread::scope(|scope| {
+        let handles = (0..workers)
+            .map(|_| {
+                thread::Builder::new()
+                    .stack_size(WORKER_STACK_SIZE)
+                    .spawn_scoped(scope, || {
+                        let mut results = vec![];
+                        loop {
+                            let i = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(item) = items.get(i) else {
+                                return results;
+                            };
+                            results.push((i, f(item)));
+                        }
+                    })
+                    .expect("failed to spawn a compilation worker")
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| {
+                handle.join().expect("compilation phases catch panics")
+            })
+            .collect::<Vec<_>>()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Returns the diagnostics of a translation unit attached to its file and
+/// sorted by position.
+fn unit_diagnostics(
+    path: Option<&str>,
+    mut diagnostics: Vec<Diagnostic>,
+) -> Vec<Diagnostic> {
+    diagnostics.sort_by_key(|diagnostic| {
+        (diagnostic.line(), diagnostic.span().map(|span| span.start))
+    });
+    match path {
+        Some(path) => diagnostics
+            .into_iter()
+            .map(|diagnostic| diagnostic.with_file(path))
+            .collect(),
+        None => diagnostics,
+    }
+}
+
+fn compile(
+    sources: &[(Option<String>, String)],
+    options: &Options,
+) -> Result<(), Vec<Diagnostic>> {
+    // Translation units are compiled in parallel, their diagnostics are
+    // reported in the order of the input files.
+    let results = parallel(sources, |(path, source)| {
+        frontend(path.as_deref(), source, options)
+    });
+    let mut units = vec![];
+    let mut reported = vec![];
+    let mut failed = false;
+    for ((path, _), result) in sources.iter().zip(results) {
+        match result {
+            Ok(mut unit) => {
+                let warnings = std::mem::take(&mut unit.warnings);
+                reported.extend(unit_diagnostics(path.as_deref(), warnings));
+                units.push(unit);
+            }
+            Err(errors) => {
+                failed = true;
+                reported.extend(unit_diagnostics(path.as_deref(), errors));
+            }
+        }
+    }
+    if failed {
+        return Err(reported);
+    }
+    for diagnostic in &reported {
+        report(diagnostic, options);
+    }
+
+    let mut info = CompileInfo::default();
+    for unit in &units {
+        info.merge(&unit.info);
+    }
+    let mut program = match units.as_mut_slice() {
+        [unit] => std::mem::take(&mut unit.program),
+        units => info
+            .phase("link", diagnostics::LINK_ERROR, || {
+                ir::link(units.iter_mut().map(|unit| {
+                    let module = unit.path.clone().unwrap_or_default();
+                    (module, std::mem::take(&mut unit.program))
+                }))
+            })?
+            .map_err(|err| {
+                vec![Diagnostic::error(
+                    diagnostics::LINK_ERROR,
+                    &err.to_string(),
+                )]
+            })?,
+    };
+    if options.freestanding {
+        let entry = options.entry.as_deref().unwrap_or("main");
+        if !program.functions().iter().any(|f| f.name() == entry) {
+            return Err(vec![Diagnostic::error(
+                diagnostics::LINK_ERROR,
+                &format!("entry symbol `{entry}` is not defined"),
+            )]);
+        }
+    }
+    let (functions, remarks) =
+        info.phase("optimize", diagnostics::INTERNAL_ERROR, || {
+            let pipeline = match &options.passes {
+                Some(pipeline) => optim::PassRegistry::default()
+                    .parse_pipeline(pipeline)
+                    .expect("pipelines are validated when parsing options"),
+                None => optim::ModulePassManager::new().with_function_pipeline(
+                    optim::PassManager::with_arithmetic(
+                        options.opt_level,
+                        options.arithmetic,
+                    ),
+                ),
+            }
+            .with_max_growth(options.limits.growth());
+            let sizes = program
+                .functions()
+                .iter()
+                .map(ir::Function::code_size)
+                .collect::<Vec<_>>();
+            let globals = program.globals().to_vec();
+            let mut remarks = vec![];
+            if options.opt_report.is_some() {
+                pipeline.run_with_remarks(
+                    program.functions_mut(),
+                    &globals,
+                    &mut remarks,
+                );
+            } else {
+                pipeline.run(program.functions_mut(), &globals);
+            }
+            let functions = program
+                .functions()
+                .iter()
+                .zip(sizes)
+                .map(|(function, size)| {
+                    (function.name().to_string(), size, function.code_size())
+                })
+                .collect::<Vec<_>>();
+            (functions, remarks)
+        })?;
+    let size = program
+        .functions()
+        .iter()
+        .map(ir::Function::len)
+        .sum::<usize>();
+    if size > options.limits.instructions() {
+        let err = LimitError::instructions(options.limits.instructions());
+        return Err(vec![Diagnostic::from(&err)]);
+    }
+    if let Some(path) = &options.opt_report {
+        // Reports are restricted to a single input when parsing options.
+        let unit = &units[0];
+        let html = report::render(&unit.source, &unit.ast, &remarks);
+        std::fs::write(path, html).map_err(|err| {
+            vec![Diagnostic::error(
+                diagnostics::INTERNAL_ERROR,
+                &format!("failed to write {path}: {err}"),
+            )]
+        })?;
+    }
+    if options.stats {
+        for (name, before, after) in &functions {
+            eprintln!("@{name}: code size {before} -> {after}");
+        }
+    }
+    if options.analyze_stack {
+        for stack in stack::analyze(
+            program.functions(),
+            program.globals(),
+            &options.target,
+        ) {
+            eprintln!("{stack}");
+        }
+    }
+    if options.analyze_ilp {
+        for function in ilp::analyze(program.functions()) {
+            eprintln!("{function}");
+        }
+    }
+    info.functions = functions;
+    match options.emit {
+        Emit::Ir => {
+            for import in program.imports() {
+                println!("{import}");
+            }
+            for function in program.functions() {
+                println!("{}", function.display(options.ir_style));
+            }
+        }
+        Emit::C => {
+            print!("{}", decompile::print(&decompile::decompile(&program)))
+        }
+        Emit::CompileInfo => println!("{}", info.to_json(options)),
+        Emit::CompilerExplorer => println!(
+            "{}",
+            report::compiler_explorer(
+                &units[0].source,
+                &units[0].ast,
+                program.imports(),
+                program.functions()
+            )
+        ),
     }
+    Ok(())
 }
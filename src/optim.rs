@@ -1,396 +1,5420 @@
 //! This module implements multiple transforms on the glouton IR
 //! mostly focused on scalar optimizations.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::ops::{Range, RangeInclusive};
 
 use crate::{
-    cfg::Graph,
-    ir::{self, Literal, OPCode, Symbol},
+    ast::{Attribute, DeclType},
+    builtins::Builtin,
+    cfg::{Dominators, Edge, EdgeKind, Graph},
+    consteval::{self, ArithmeticMode},
+    conversions,
+    cost::{CostModel, GenericCostModel},
+    dataflow::{self, Liveness},
+    ir::{self, BlockRef, Literal, OPCode, Symbol},
+    trace::{self, Level},
 };
 
 struct FunctionRewriter {}
 
 impl FunctionRewriter {
-    fn rewrite(f: &mut ir::Function, transform: &impl Transform) {
-        transform.run(f)
+    fn rewrite(
+        f: &mut ir::Function,
+        globals: &[ir::GlobalValue],
+        transform: &dyn Transform,
+    ) {
+        transform.run_in_module(f, globals);
+        // Catch transforms breaking the control flow structure early.
+        if cfg!(debug_assertions) {
+            let graph = Graph::new(&vec![f.clone()]);
+            if let Err(err) = graph.validate(f) {
+                panic!("{err}");
+            }
+        }
     }
 }
 
-/// `Transform` trait is used to encapsulate the behavior of independant
-/// optimizations executed on individual functions.
-pub trait Transform {
-    fn run(&self, function: &mut ir::Function) {}
+/// Optimization levels, each level selects a preset pipeline of transforms.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OptLevel {
+    // No optimizations.
+    #[default]
+    O0,
+    // Cheap local cleanups.
+    O1,
+    // Every transform, including the ones growing the code.
+    O2,
+    // Optimize for code size.
+    Oz,
 }
 
-/// Identity transform implements the identity transformation which is a noop.
-#[derive(Default, Debug)]
-struct Identity {}
+/// Maximum number of rounds of a fixed point group, groups of passes that
+/// keep undoing each other's work stop there.
+const MAX_FIXED_POINT_ROUNDS: usize = 16;
 
-impl Transform for Identity {
-    fn run(&self, function: &mut ir::Function) {}
+/// `FixedPoint` runs a group of transforms in order, over and over, until a
+/// round leaves the function unchanged. Cleanups enabling each other (e.g.
+/// folding then `DCE`) are grouped so none of them misses the work of the
+/// others.
+pub struct FixedPoint {
+    passes: Vec<Box<dyn Transform>>,
 }
 
-/// Instruction combination pass executes over basic blocks and tries to
-/// combine instructions that can be combined into one instruction.
-///
-/// Unlike local value numbering with `InstCombine` each pass is effectively
-/// a re-write of an existing instruction or multiple instructions.
-///
-/// This implementation is mainly inspired by the way LLVM does it and contains
-/// a strength reduction pass for some popular algebraic simplification.
-struct InstCombine {}
+impl FixedPoint {
+    /// Create a group running `passes` until they converge.
+    #[must_use]
+    pub fn new(passes: Vec<Box<dyn Transform>>) -> Self {
+        Self { passes }
+    }
+}
 
-impl Transform for InstCombine {}
+impl Transform for FixedPoint {
+    fn run(&self, function: &mut ir::Function) {
+        self.run_in_module(function, &[]);
+    }
 
-/// Local Value Numbering pass builds a value numbering table that is then
-/// re-used in several local optimizations such as dead code elimination
-/// copy propagation, constant folding and common subexpression elimination.
-struct LVN {}
+    fn run_in_module(
+        &self,
+        function: &mut ir::Function,
+        globals: &[ir::GlobalValue],
+    ) {
+        for _ in 0..MAX_FIXED_POINT_ROUNDS {
+            let before = function.instructions().to_vec();
+            for pass in &self.passes {
+                FunctionRewriter::rewrite(function, globals, pass.as_ref());
+            }
+            if function.instructions() == before {
+                break;
+            }
+        }
+    }
 
-impl LVN {
-    /// Run the local value numbering pass to build the value numbering table
-    /// then iteratively run peephole optimizations on using the table.
-    fn run(&self, function: &mut ir::Function) {
-        // First step when constructing the LVN is to form basic blocks
-        // for the input function.
-        let worklist = Graph::form_basic_blocks(function);
-
-        // The data structures used for LVN :
-        // 1. Hashmap from variable names to value numbers.
-        // 2. Hashmap from encoded instructions to their canonical variable names.
-        //
-        // Encoding instructions :
-        //
-        // match Inst(Args..) => Inst(VN#) where VN# are value numbers.
-        //
-        // ValueNumber act as row numbers in our value numbering table.
-        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
-        struct ValueNumber(usize);
-
-        // Value is how we encode the instruction to their tuples.
-        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
-        struct Value(OPCode, ValueNumber, ValueNumber);
-
-        #[derive(Debug, Clone)]
-        struct NumberingTable {
-            table: HashMap<ir::Symbol, ValueNumber>,
-            vn: ValueNumber,
-        }
-
-        impl Value {
-            fn from(inst: &ir::Instruction) {
-                match inst {
-                    _ => todo!(),
-                }
-            }
-        }
-
-        // Environment maps variable names to value numbers.
-        //
-        // TODO: Should potentially live nicely with declarations out of the
-        // current scope and variable arguments.
-        // var2num
-        let environment: HashMap<Symbol, ValueNumber> = HashMap::new();
-        // value2number
-        let value_table: HashMap<Value, ValueNumber> = HashMap::new();
-        // num2vars
-        let variables: HashMap<ValueNumber, Symbol> = HashMap::new();
-        // num2const
-        let constants: HashMap<ValueNumber, Literal> = HashMap::new();
-
-        let encode_instruction = |inst: &ir::Instruction| -> Value {
-            match inst {
-                _ => todo!(),
+    fn grows_code(&self) -> bool {
+        self.passes.iter().any(|pass| pass.grows_code())
+    }
+}
+
+/// `PassManager` runs an ordered pipeline of transforms over functions.
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Box<dyn Transform>>,
+    // Maximum size of an optimized function as a multiple of its size
+    // before the pipeline ran, unbounded if `None`.
+    max_growth: Option<usize>,
+}
+
+impl PassManager {
+    /// Create an empty pipeline.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create the preset pipeline for the optimization level `level`.
+    #[must_use]
+    pub fn with_level(level: OptLevel) -> Self {
+        Self::with_arithmetic(level, ArithmeticMode::Wrapping)
+    }
+
+    /// Create the preset pipeline for the optimization level `level`, with
+    /// constant arithmetic folded according to `mode`.
+    #[must_use]
+    pub fn with_arithmetic(level: OptLevel, mode: ArithmeticMode) -> Self {
+        let cleanup = || {
+            FixedPoint::new(vec![
+                Box::new(InstCombine { mode }),
+                Box::new(LVN {}),
+                Box::new(DCE {}),
+            ])
+        };
+        match level {
+            OptLevel::O0 => Self::new(),
+            OptLevel::O1 => Self::new()
+                .with_pass(cleanup())
+                .with_pass(CanonicalizeBranches {})
+                .with_pass(StripAssumptions {}),
+            OptLevel::O2 => Self::new()
+                .with_pass(Reassociate { mode })
+                .with_pass(cleanup())
+                .with_pass(SparseConditionalConstantPropagation { mode })
+                .with_pass(LoopInvariantCodeMotion {})
+                .with_pass(GlobalValueNumbering {})
+                .with_pass(CopyPropagation {})
+                .with_pass(LoopUnswitch::default())
+                .with_pass(cleanup())
+                .with_pass(StrengthReduce {})
+                .with_pass(CanonicalizeBranches {})
+                .with_pass(HotColdSplitting::default())
+                .with_pass(ConstantPooling {})
+                .with_pass(StripAssumptions {}),
+            OptLevel::Oz => Self::new()
+                .with_pass(Reassociate { mode })
+                .with_pass(InstCombine { mode })
+                .with_pass(DCE {})
+                .with_pass(CanonicalizeBranches {})
+                .with_pass(ConstantPooling {})
+                .with_pass(StripAssumptions {}),
+        }
+    }
+
+    /// Fast pipeline of local cleanups: folding, value numbering and `DCE`
+    /// until they converge, then branch canonicalization.
+    #[must_use]
+    pub fn o1() -> Self {
+        Self::with_level(OptLevel::O1)
+    }
+
+    /// Full pipeline, `o1` plus reassociation, constant propagation across
+    /// blocks, loop invariant code motion, global value numbering, copy
+    /// propagation and unswitching followed by another round of cleanups,
+    /// strength reduction, hot/cold splitting and constant pooling.
+    #[must_use]
+    pub fn o2() -> Self {
+        Self::with_level(OptLevel::O2)
+    }
+
+    /// Size optimizing pipeline, only transforms that never grow the code
+    /// are scheduled which rules out loop unswitching. Assumptions are
+    /// stripped last.
+    #[must_use]
+    pub fn oz() -> Self {
+        Self::with_level(OptLevel::Oz)
+    }
+
+    /// Append `pass` to the pipeline.
+    #[must_use]
+    pub fn with_pass(mut self, pass: impl Transform + 'static) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// Append a group of passes run until they converge, see `FixedPoint`.
+    #[must_use]
+    pub fn with_fixed_point(self, passes: Vec<Box<dyn Transform>>) -> Self {
+        self.with_pass(FixedPoint::new(passes))
+    }
+
+    /// Bound the growth of optimized functions, a pass growing a function
+    /// past `factor` times its size before the pipeline is reverted.
+    #[must_use]
+    pub const fn with_max_growth(mut self, factor: usize) -> Self {
+        self.max_growth = Some(factor);
+        self
+    }
+
+    /// Returns the number of passes in the pipeline.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.passes.len()
+    }
+
+    /// Returns `true` if the pipeline has no passes.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    /// Run the pipeline passes in order on `function`.
+    ///
+    /// Functions declared `[[optnone]]` are left untouched and functions
+    /// declared `[[optsize]]` skip the passes growing the code.
+    pub fn run(&self, function: &mut ir::Function) {
+        self.apply(function, &[], None);
+    }
+
+    /// Run the pipeline on `function` like `run`, every pass changing the
+    /// function pushes a remark to `remarks`.
+    pub fn run_with_remarks(
+        &self,
+        function: &mut ir::Function,
+        remarks: &mut Vec<Remark>,
+    ) {
+        self.apply(function, &[], Some(remarks));
+    }
+
+    /// Run the pipeline on `function` like `run`, passes are told which
+    /// variables are the program's `globals`.
+    pub fn run_in_module(
+        &self,
+        function: &mut ir::Function,
+        globals: &[ir::GlobalValue],
+    ) {
+        self.apply(function, globals, None);
+    }
+
+    fn apply(
+        &self,
+        function: &mut ir::Function,
+        globals: &[ir::GlobalValue],
+        mut remarks: Option<&mut Vec<Remark>>,
+    ) {
+        if function.has_attribute(Attribute::OptNone) {
+            return;
+        }
+        let optsize = function.has_attribute(Attribute::OptSize);
+        let budget = self
+            .max_growth
+            .map(|factor| function.code_size().max(1).saturating_mul(factor));
+        for pass in &self.passes {
+            if optsize && pass.grows_code() {
+                continue;
+            }
+            let _span = trace::span(
+                Level::Debug,
+                "pass",
+                &[("pass", &pass.name()), ("function", &function.name())],
+            );
+            let before = (remarks.is_some()
+                || (budget.is_some() && pass.grows_code()))
+            .then(|| function.clone());
+            FunctionRewriter::rewrite(function, globals, pass.as_ref());
+            if let (Some(budget), Some(before)) = (budget, before.as_ref()) {
+                if function.code_size() > budget {
+                    *function = before.clone();
+                    continue;
+                }
+            }
+            if let (Some(remarks), Some(before)) = (remarks.as_mut(), before) {
+                remarks.extend(Remark::new(pass.name(), &before, function));
             }
+        }
+    }
+}
+
+/// `Remark` records that a pass changed a function, optimization reports
+/// use remarks to show which passes fired where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remark {
+    /// Name of the pass.
+    pub pass: &'static str,
+    /// Name of the changed function.
+    pub function: String,
+    /// Code size of the function before the pass.
+    pub before: usize,
+    /// Code size of the function after the pass.
+    pub after: usize,
+}
+
+impl Remark {
+    /// Returns the remark of `pass` rewriting `before` into `after`, or
+    /// `None` if the pass didn't change the function.
+    fn new(
+        pass: &'static str,
+        before: &ir::Function,
+        after: &ir::Function,
+    ) -> Option<Self> {
+        (before.instructions() != after.instructions()).then(|| Self {
+            pass,
+            function: after.name().to_string(),
+            before: before.code_size(),
+            after: after.code_size(),
+        })
+    }
+}
+
+impl fmt::Display for Remark {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} changed @{}: code size {} -> {}",
+            self.pass, self.function, self.before, self.after
+        )
+    }
+}
+
+/// Returns the name of a pass type without its module path.
+fn pass_name<T: ?Sized>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// Pass error type is used to report pipelines naming unregistered passes
+/// and malformed pipeline descriptions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassError {
+    details: String,
+}
+
+impl PassError {
+    fn unknown(name: &str) -> Self {
+        Self {
+            details: format!("unknown pass `{name}`"),
+        }
+    }
+
+    fn syntax(details: &str, offset: usize) -> Self {
+        Self {
+            details: format!("{details} at offset {offset} of the pipeline"),
+        }
+    }
+}
+
+impl fmt::Display for PassError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for PassError {}
+
+/// Constructor of a registered pass.
+pub type PassConstructor = fn() -> Box<dyn Transform>;
+
+/// Constructor of a registered module pass.
+pub type ModulePassConstructor = fn() -> Box<dyn ModuleTransform>;
+
+/// `PassRegistry` maps pass names to their constructor so pipelines can be
+/// described by name, e.g. on the command line with `--passes=dce,mypass`.
+///
+/// The default registry contains the builtin transforms, downstream crates
+/// and tests register their own `Transform`s with `register` and their own
+/// `ModuleTransform`s with `register_module`.
+pub struct PassRegistry {
+    passes: BTreeMap<String, PassConstructor>,
+    module_passes: BTreeMap<String, ModulePassConstructor>,
+}
+
+impl Default for PassRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            passes: BTreeMap::new(),
+            module_passes: BTreeMap::new(),
         };
+        registry.register("instcombine", || Box::<InstCombine>::default());
+        registry.register("reassociate", || Box::<Reassociate>::default());
+        registry.register("dce", || Box::new(DCE {}));
+        registry.register("lvn", || Box::new(LVN {}));
+        registry.register("gvn", || Box::new(GlobalValueNumbering {}));
+        registry.register("copy-propagation", || Box::new(CopyPropagation {}));
+        registry.register("canonicalize-branches", || {
+            Box::new(CanonicalizeBranches {})
+        });
+        registry.register("constant-pooling", || Box::new(ConstantPooling {}));
+        registry
+            .register("strip-assumptions", || Box::new(StripAssumptions {}));
+        registry.register("licm", || Box::new(LoopInvariantCodeMotion {}));
+        registry.register("strength-reduce", || Box::new(StrengthReduce {}));
+        registry.register("sccp", || {
+            Box::new(SparseConditionalConstantPropagation::default())
+        });
+        registry.register("loop-unswitch", || Box::<LoopUnswitch>::default());
+        registry.register("hot-cold-splitting", || {
+            Box::<HotColdSplitting>::default()
+        });
+        registry.register_module("return-value-propagation", || {
+            Box::new(ReturnValuePropagation {})
+        });
+        registry
+    }
+}
+
+impl PassRegistry {
+    /// Register the pass `name`, replacing any pass registered under the
+    /// same name.
+    pub fn register(&mut self, name: &str, constructor: PassConstructor) {
+        self.module_passes.remove(name);
+        self.passes.insert(name.to_string(), constructor);
+    }
+
+    /// Register the module pass `name`, replacing any pass registered under
+    /// the same name.
+    pub fn register_module(
+        &mut self,
+        name: &str,
+        constructor: ModulePassConstructor,
+    ) {
+        self.passes.remove(name);
+        self.module_passes.insert(name.to_string(), constructor);
+    }
+
+    /// Returns `true` if a pass is registered under `name`.
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.passes.contains_key(name) || self.module_passes.contains_key(name)
+    }
+
+    /// Returns the names of the registered passes in alphabetical order,
+    /// function passes first.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.passes
+            .keys()
+            .chain(self.module_passes.keys())
+            .map(String::as_str)
+    }
+
+    /// Create a new instance of the pass `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no pass is registered under `name`.
+    pub fn create(&self, name: &str) -> Result<Box<dyn Transform>, PassError> {
+        self.passes
+            .get(name)
+            .map(|constructor| constructor())
+            .ok_or_else(|| PassError::unknown(name))
+    }
 
-        type SludgedValueNumber = usize;
+    /// Create a new instance of the module pass `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no module pass is registered under `name`.
+    pub fn create_module(
+        &self,
+        name: &str,
+    ) -> Result<Box<dyn ModuleTransform>, PassError> {
+        self.module_passes
+            .get(name)
+            .map(|constructor| constructor())
+            .ok_or_else(|| PassError::unknown(name))
     }
 
-    /// Common subexpression elimination pass replaces common subexpressions in
-    /// a basic block by their previously computed values. The pass will in most
-    /// cases introduce a new temporary storage location for the subexpression
-    /// before replacing its uses with the new variable.
-    fn cse(&self) {}
+    /// Create the pipeline running the comma separated list of passes
+    /// `names` in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the passes isn't registered.
+    pub fn pipeline(&self, names: &str) -> Result<PassManager, PassError> {
+        let mut pipeline = PassManager::new();
+        for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            pipeline.passes.push(self.create(name)?);
+        }
+        Ok(pipeline)
+    }
 
-    /// Constant folding and propagation pass targets expressions that can be
-    /// evaluated at compile time and replaces them with the evaluation, once
-    /// constants are folded a second sub-pass executes to propagate constants
-    /// to their usage locations.
-    fn fold(&self) {}
+    /// Create the module pipeline described by `text`.
+    ///
+    /// Pipelines nest function pipelines in module pipelines, for example
+    /// `module(return-value-propagation,function(instcombine,dce))`. The
+    /// outer `module(...)` can be omitted and function passes listed at the
+    /// module level are implicitly wrapped in a function pipeline, which
+    /// makes the plain lists accepted by `pipeline` valid descriptions.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the description is malformed, a pass isn't
+    /// registered or a module pass is listed in a function pipeline.
+    pub fn parse_pipeline(
+        &self,
+        text: &str,
+    ) -> Result<ModulePassManager, PassError> {
+        PipelineParser::new(self, text)?.parse()
+    }
 }
 
-/// Dead code elimination pass eliminates unused and unreachable instructions.
+/// Tokens of textual pipeline descriptions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PipelineToken<'a> {
+    Name(&'a str),
+    LeftParen,
+    RightParen,
+    Comma,
+}
+
+impl fmt::Display for PipelineToken<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Name(name) => write!(f, "`{name}`"),
+            Self::LeftParen => write!(f, "`(`"),
+            Self::RightParen => write!(f, "`)`"),
+            Self::Comma => write!(f, "`,`"),
+        }
+    }
+}
+
+/// Recursive descent parser of textual pipeline descriptions.
 ///
-/// Because most optimizations can cause dead instructions this pass is run
-/// after some optimizations multiple times until it converges i.e blocks
-/// remain unchanged after a pass.
-struct DCE {}
+/// ```text
+/// pipeline := "module" "(" elements ")" | elements
+/// elements := element ("," element)*
+/// element  := "function" "(" names ")" | pass
+/// names    := pass ("," pass)*
+/// pass     := "fixpoint" "(" names ")" | name
+/// ```
+struct PipelineParser<'a> {
+    registry: &'a PassRegistry,
+    // Tokens and their offset in the description.
+    tokens: Vec<(PipelineToken<'a>, usize)>,
+    // Offset of the end of the description.
+    end: usize,
+    position: usize,
+}
 
-impl DCE {
-    /// Trivial Global DCE pass on a function returns `true` if any instructions
-    /// are eliminated.
-    pub fn tdce(function: &mut ir::Function) -> bool {
-        let worklist = function.instructions_mut();
-        let candidates = worklist.len();
-        let mut use_defs = HashSet::new();
-
-        for inst in &mut *worklist {
-            // Check for instruction uses, if an instruction is uses defs
-            // we remove them from the `defs` set.
-            match inst.operands() {
-                (Some(lhs), Some(rhs)) => {
-                    match (lhs, rhs) {
-                        (
-                            ir::Value::StorageLocation(lhs),
-                            ir::Value::StorageLocation(rhs),
-                        ) => {
-                            use_defs.insert(lhs.clone());
-                            use_defs.insert(rhs.clone());
+impl<'a> PipelineParser<'a> {
+    fn new(
+        registry: &'a PassRegistry,
+        text: &'a str,
+    ) -> Result<Self, PassError> {
+        let mut tokens = vec![];
+        let mut chars = text.char_indices().peekable();
+        while let Some((offset, c)) = chars.next() {
+            let token = match c {
+                '(' => PipelineToken::LeftParen,
+                ')' => PipelineToken::RightParen,
+                ',' => PipelineToken::Comma,
+                c if c.is_whitespace() => continue,
+                c if Self::is_name_char(c) => {
+                    let mut end = offset + c.len_utf8();
+                    while let Some(&(next, c)) = chars.peek() {
+                        if !Self::is_name_char(c) {
+                            break;
                         }
-                        // The only instructions that receive a constant literal
-                        // as a value as a literal is `const` and it only has
-                        // one operand.
-                        _ => (),
+                        end = next + c.len_utf8();
+                        chars.next();
                     }
+                    PipelineToken::Name(&text[offset..end])
+                }
+                c => {
+                    return Err(PassError::syntax(
+                        &format!("unexpected character `{c}`"),
+                        offset,
+                    ))
                 }
-                (Some(operand), None) => match operand {
-                    ir::Value::StorageLocation(operand) => {
-                        use_defs.insert(operand.clone());
+            };
+            tokens.push((token, offset));
+        }
+        Ok(Self {
+            registry,
+            tokens,
+            end: text.len(),
+            position: 0,
+        })
+    }
+
+    const fn is_name_char(c: char) -> bool {
+        c.is_ascii_alphanumeric() || c == '-' || c == '_'
+    }
+
+    fn parse(mut self) -> Result<ModulePassManager, PassError> {
+        let mut pipeline = ModulePassManager::new();
+        if self.tokens.is_empty() {
+            return Ok(pipeline);
+        }
+        let wrapped = matches!(
+            self.tokens.as_slice(),
+            [
+                (PipelineToken::Name("module"), _),
+                (PipelineToken::LeftParen, _),
+                ..
+            ]
+        );
+        if wrapped {
+            self.position += 2;
+        }
+        self.elements(&mut pipeline)?;
+        if wrapped {
+            self.expect(PipelineToken::RightParen)?;
+        }
+        match self.peek() {
+            Some((token, offset)) => {
+                Err(PassError::syntax(&format!("unexpected {token}"), offset))
+            }
+            None => Ok(pipeline),
+        }
+    }
+
+    fn elements(
+        &mut self,
+        pipeline: &mut ModulePassManager,
+    ) -> Result<(), PassError> {
+        loop {
+            let (name, offset) = self.name()?;
+            if name == "function" {
+                self.expect(PipelineToken::LeftParen)?;
+                let mut function = PassManager::new();
+                self.names(&mut function)?;
+                self.expect(PipelineToken::RightParen)?;
+                pipeline.stages.push(Stage::Function(function));
+            } else if self.registry.module_passes.contains_key(name) {
+                pipeline
+                    .stages
+                    .push(Stage::Module(self.registry.create_module(name)?));
+            } else {
+                let pass = self.function_pass(name, offset)?;
+                // Consecutive function passes share a function pipeline.
+                match pipeline.stages.last_mut() {
+                    Some(Stage::Function(function)) => {
+                        function.passes.push(pass);
                     }
-                    _ => (),
-                },
-                _ => (),
+                    _ => {
+                        let mut function = PassManager::new();
+                        function.passes.push(pass);
+                        pipeline.stages.push(Stage::Function(function));
+                    }
+                }
+            }
+            if !self.eat(PipelineToken::Comma) {
+                return Ok(());
             }
         }
+    }
 
-        for inst in &mut *worklist {
-            if inst
-                .destination()
-                .is_some_and(|dst| !use_defs.contains(dst))
-            {
-                let _ = std::mem::replace(inst, ir::Instruction::Nop);
+    fn names(&mut self, function: &mut PassManager) -> Result<(), PassError> {
+        loop {
+            let (name, offset) = self.name()?;
+            function.passes.push(self.function_pass(name, offset)?);
+            if !self.eat(PipelineToken::Comma) {
+                return Ok(());
             }
         }
-        // Remove all instructions marked as dead i.e replaced with `Nop`.
-        function.remove_dead_instructions();
+    }
 
-        candidates != function.len()
+    fn function_pass(
+        &mut self,
+        name: &str,
+        offset: usize,
+    ) -> Result<Box<dyn Transform>, PassError> {
+        if name == "fixpoint" {
+            self.expect(PipelineToken::LeftParen)?;
+            let mut group = PassManager::new();
+            self.names(&mut group)?;
+            self.expect(PipelineToken::RightParen)?;
+            return Ok(Box::new(FixedPoint::new(group.passes)));
+        }
+        if self.registry.module_passes.contains_key(name) {
+            return Err(PassError::syntax(
+                &format!("module pass `{name}` in a function pipeline"),
+                offset,
+            ));
+        }
+        self.registry.create(name)
     }
-}
 
-impl Transform for DCE {
-    /// Run dead code elimination over a function repeatedly until all
-    /// convergence. The pass convergences when the number of candidates
-    /// for elimination reaches 0.
-    fn run(&self, function: &mut ir::Function) {
-        while Self::tdce(function) {}
+    fn name(&mut self) -> Result<(&'a str, usize), PassError> {
+        match self.peek() {
+            Some((PipelineToken::Name(name), offset)) => {
+                self.position += 1;
+                Ok((name, offset))
+            }
+            Some((token, offset)) => Err(PassError::syntax(
+                &format!("expected a pass name, found {token}"),
+                offset,
+            )),
+            None => Err(PassError::syntax("expected a pass name", self.end)),
+        }
+    }
+
+    fn expect(&mut self, expected: PipelineToken) -> Result<(), PassError> {
+        if self.eat(expected) {
+            return Ok(());
+        }
+        let (details, offset) = match self.peek() {
+            Some((token, offset)) => {
+                (format!("expected {expected}, found {token}"), offset)
+            }
+            None => (format!("expected {expected}"), self.end),
+        };
+        Err(PassError::syntax(&details, offset))
+    }
+
+    fn eat(&mut self, expected: PipelineToken) -> bool {
+        if self.peek().is_some_and(|(token, _)| token == expected) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn peek(&self) -> Option<(PipelineToken<'a>, usize)> {
+        self.tokens.get(self.position).copied()
     }
 }
 
-/// Strength reduction pass replaces some computations with cheaper and more
-/// efficient equivalent alternatives.
-struct StrengthReduce {}
+/// Stage of a module pipeline.
+enum Stage {
+    // Interprocedural pass run once over every function.
+    Module(Box<dyn ModuleTransform>),
+    // Function pipeline run on each function in turn.
+    Function(PassManager),
+}
 
-impl Transform for StrengthReduce {}
+/// `ModulePassManager` runs an ordered pipeline of module passes and
+/// function pipelines over the functions of a program.
+#[derive(Default)]
+pub struct ModulePassManager {
+    stages: Vec<Stage>,
+}
 
-/// Loop invariant code motion pass tries to remove as much code as possible
-/// from the loop body.
-struct LoopInvariantCodeMotion {}
+impl ModulePassManager {
+    /// Create an empty pipeline.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-impl Transform for LoopInvariantCodeMotion {}
+    /// Append the module pass `pass` to the pipeline.
+    #[must_use]
+    pub fn with_pass(mut self, pass: impl ModuleTransform + 'static) -> Self {
+        self.stages.push(Stage::Module(Box::new(pass)));
+        self
+    }
 
-#[cfg(test)]
-mod tests {
-    use crate::ir::IRBuilder;
-    use crate::optim::{Identity, Transform, DCE};
-    use crate::parser::Parser;
-    use crate::scanner::Scanner;
-    use crate::sema::analyze;
-    // Macro to generate test cases.
-    macro_rules! test_optimization_pass {
-        ($name:ident, $source:expr, $expected:expr) => {
-            #[test]
-            fn $name() {
-                let source = $source;
-                let mut scanner = Scanner::new(source);
-                let tokens = scanner
-                    .scan()
-                    .expect("expected test case source to be valid");
-                let mut parser = Parser::new(&tokens);
-                parser.parse();
-                let symbol_table = analyze(parser.ast());
+    /// Append the function pipeline `pipeline`, it runs on each function
+    /// before the next stage starts.
+    #[must_use]
+    pub fn with_function_pipeline(mut self, pipeline: PassManager) -> Self {
+        self.stages.push(Stage::Function(pipeline));
+        self
+    }
 
-                let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
-                irgen.build();
+    /// Bound the growth of optimized functions in the function pipelines
+    /// appended so far, see `PassManager::with_max_growth`.
+    #[must_use]
+    pub fn with_max_growth(mut self, factor: usize) -> Self {
+        for stage in &mut self.stages {
+            if let Stage::Function(pipeline) = stage {
+                pipeline.max_growth = Some(factor);
+            }
+        }
+        self
+    }
 
-                let ident = Identity {};
-                let dce = DCE {};
+    /// Returns the number of passes in the pipeline, counting the passes of
+    /// nested function pipelines.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.stages
+            .iter()
+            .map(|stage| match stage {
+                Stage::Module(_) => 1,
+                Stage::Function(function) => function.len(),
+            })
+            .sum()
+    }
 
-                for func in irgen.functions_mut() {
-                    ident.run(func);
-                    dce.run(func);
-                }
+    /// Returns `true` if the pipeline has no passes.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 
-                let mut actual = "".to_string();
-                for func in irgen.functions() {
-                    actual.push_str(format!("{func}").as_str());
+    /// Run the pipeline stages in order on `functions`.
+    pub fn run(
+        &self,
+        functions: &mut [ir::Function],
+        globals: &[ir::GlobalValue],
+    ) {
+        self.apply(functions, globals, None);
+    }
+
+    /// Run the pipeline on `functions` like `run`, every pass changing a
+    /// function pushes a remark to `remarks`.
+    pub fn run_with_remarks(
+        &self,
+        functions: &mut [ir::Function],
+        globals: &[ir::GlobalValue],
+        remarks: &mut Vec<Remark>,
+    ) {
+        self.apply(functions, globals, Some(remarks));
+    }
+
+    fn apply(
+        &self,
+        functions: &mut [ir::Function],
+        globals: &[ir::GlobalValue],
+        mut remarks: Option<&mut Vec<Remark>>,
+    ) {
+        for stage in &self.stages {
+            match stage {
+                Stage::Module(pass) => {
+                    let _span = trace::span(
+                        Level::Debug,
+                        "module-pass",
+                        &[("pass", &pass.name())],
+                    );
+                    // Module passes see every function but the ones declared
+                    // `[[optnone]]` are restored afterwards.
+                    let unoptimized = functions
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, f)| f.has_attribute(Attribute::OptNone))
+                        .map(|(i, f)| (i, f.clone()))
+                        .collect::<Vec<_>>();
+                    let before = remarks.is_some().then(|| functions.to_vec());
+                    pass.run(functions, globals);
+                    for (i, function) in unoptimized {
+                        functions[i] = function;
+                    }
+                    if let (Some(remarks), Some(before)) =
+                        (remarks.as_mut(), before)
+                    {
+                        remarks.extend(
+                            before.iter().zip(functions.iter()).filter_map(
+                                |(before, after)| {
+                                    Remark::new(pass.name(), before, after)
+                                },
+                            ),
+                        );
+                    }
                 }
-                // For readability trim the newlines at the start and end
-                // of our IR text fixture.
-                let expected = $expected
-                    .strip_suffix("\n")
-                    .and($expected.strip_prefix("\n"));
-                assert_eq!(actual, expected.unwrap())
+                Stage::Function(pipeline) => {
+                    for function in functions.iter_mut() {
+                        pipeline.apply(
+                            function,
+                            globals,
+                            remarks.as_deref_mut(),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// `Transform` trait is used to encapsulate the behavior of independant
+/// optimizations executed on individual functions. Transforms are shared
+/// between threads optimizing different functions.
+pub trait Transform: Send + Sync {
+    fn run(&self, function: &mut ir::Function) {}
+
+    /// Run the transform on `function` of a program defining `globals`,
+    /// transforms that need to tell globals from locals override it.
+    fn run_in_module(
+        &self,
+        function: &mut ir::Function,
+        globals: &[ir::GlobalValue],
+    ) {
+        let _ = globals;
+        self.run(function);
+    }
+
+    /// Returns the name of the transform used in remarks.
+    fn name(&self) -> &'static str {
+        pass_name::<Self>()
+    }
+
+    /// Returns `true` if the transform can grow the code, such transforms
+    /// skip functions declared `[[optsize]]`.
+    fn grows_code(&self) -> bool {
+        false
+    }
+}
+
+/// `ModuleTransform` trait is used to encapsulate the behavior of
+/// interprocedural optimizations which need to see every function of the
+/// program at once.
+pub trait ModuleTransform: Send + Sync {
+    fn run(&self, functions: &mut [ir::Function], globals: &[ir::GlobalValue]);
+
+    /// Returns the name of the transform used in remarks.
+    fn name(&self) -> &'static str {
+        pass_name::<Self>()
+    }
+}
+
+/// Identity transform implements the identity transformation which is a noop.
+#[derive(Default, Debug)]
+struct Identity {}
+
+impl Transform for Identity {
+    fn run(&self, function: &mut ir::Function) {}
+}
+
+/// Instruction combination pass executes over basic blocks and tries to
+/// combine instructions that can be combined into one instruction.
+///
+/// Unlike local value numbering with `InstCombine` each pass is effectively
+/// a re-write of an existing instruction or multiple instructions.
+///
+/// This implementation is mainly inspired by the way LLVM does it and contains
+/// a strength reduction pass for some popular algebraic simplification.
+///
+/// Arithmetic on constants is folded according to the pass `ArithmeticMode`,
+/// in checked mode signed overflow is reported as a compile time error and
+/// in trapping mode the overflowing instruction is replaced by a `trap`.
+///
+/// Algebraic identities such as `x + 0`, `x * 1`, `x * 0`, `x - x` and
+/// double negations are simplified, and in wrapping mode chains of additions
+/// (resp. multiplications) by constants in a basic block are folded into one.
+#[derive(Default, Debug)]
+struct InstCombine {
+    mode: ArithmeticMode,
+}
+
+impl InstCombine {
+    /// Returns the literals held by the temporaries of the function.
+    fn constants(function: &ir::Function) -> HashMap<Symbol, Literal> {
+        // Temporaries are assigned once so the literal they hold is valid
+        // for all their uses.
+        function
+            .instructions()
+            .iter()
+            .filter_map(|inst| match inst {
+                ir::Instruction::Const(
+                    dst,
+                    ir::Value::ConstantLiteral(literal),
+                ) if dst.name().starts_with('%') => {
+                    Some((dst.clone(), *literal))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Fold arithmetic, logic and comparison instructions whose operands are
+    /// all constants into a `const` instruction, returns `true` if any
+    /// instruction was folded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a folded operation overflows in checked mode.
+    fn fold_arithmetic(
+        function: &mut ir::Function,
+        mode: ArithmeticMode,
+    ) -> bool {
+        let constants = Self::constants(function);
+        let literal = |value: &ir::Value| match value {
+            ir::Value::StorageLocation(symbol) => {
+                constants.get(symbol).copied()
+            }
+            ir::Value::ConstantLiteral(literal) => Some(*literal),
+        };
+        let mut changed = false;
+        for inst in function.instructions_mut() {
+            let result = match inst {
+                ir::Instruction::Add(dst, lhs, rhs)
+                | ir::Instruction::Sub(dst, lhs, rhs)
+                | ir::Instruction::Mul(dst, lhs, rhs)
+                | ir::Instruction::Div(dst, lhs, rhs)
+                | ir::Instruction::Shl(dst, lhs, rhs)
+                | ir::Instruction::Shr(dst, lhs, rhs)
+                | ir::Instruction::BitAnd(dst, lhs, rhs)
+                | ir::Instruction::And(dst, lhs, rhs)
+                | ir::Instruction::Or(dst, lhs, rhs)
+                | ir::Instruction::Eq(dst, lhs, rhs)
+                | ir::Instruction::Neq(dst, lhs, rhs)
+                | ir::Instruction::Lt(dst, lhs, rhs)
+                | ir::Instruction::Lte(dst, lhs, rhs)
+                | ir::Instruction::Gt(dst, lhs, rhs)
+                | ir::Instruction::Gte(dst, lhs, rhs) => {
+                    match (literal(lhs), literal(rhs)) {
+                        (Some(lhs), Some(rhs)) => (
+                            dst.clone(),
+                            consteval::binary(inst.opcode(), lhs, rhs, mode),
+                        ),
+                        _ => continue,
+                    }
+                }
+                ir::Instruction::Neg(dst, operand)
+                | ir::Instruction::Not(dst, operand) => {
+                    match literal(operand) {
+                        Some(operand) => (
+                            dst.clone(),
+                            consteval::unary(inst.opcode(), operand, mode),
+                        ),
+                        None => continue,
+                    }
+                }
+                ir::Instruction::Cast(dst, operand) => {
+                    let to = match dst.t() {
+                        ir::Type::Int => DeclType::Int,
+                        ir::Type::Bool => DeclType::Bool,
+                        ir::Type::Char => DeclType::Char,
+                        ir::Type::Unit => continue,
+                    };
+                    match literal(operand) {
+                        Some(operand) => (
+                            dst.clone(),
+                            Ok(Some(conversions::convert(operand, to))),
+                        ),
+                        None => continue,
+                    }
+                }
+                _ => continue,
+            };
+            match result {
+                (dst, Ok(Some(literal))) => {
+                    *inst = ir::Instruction::Const(
+                        dst,
+                        ir::Value::ConstantLiteral(literal),
+                    );
+                    changed = true;
+                }
+                (_, Ok(None)) => (),
+                (_, Err(err)) if mode == ArithmeticMode::Trapping => {
+                    *inst = ir::Instruction::Trap(err.to_string());
+                    changed = true;
+                }
+                (_, Err(err)) => panic!("{err}"),
+            }
+        }
+        changed
+    }
+
+    /// Fold comparisons that `__builtin_assume` calls earlier in the same
+    /// basic block assert to hold (or not to hold) into a `const`, returns
+    /// `true` if any comparison was folded.
+    ///
+    /// Facts are comparisons of operands where temporaries are resolved to
+    /// the literal they hold, facts mentioning a variable are forgotten when
+    /// the variable is assigned.
+    fn fold_assumptions(function: &mut ir::Function) -> bool {
+        type Fact = (OPCode, ir::Value, ir::Value);
+        let constants = Self::constants(function);
+        let resolve = |value: &ir::Value| match value {
+            ir::Value::StorageLocation(symbol) => {
+                constants.get(symbol).map_or(value.clone(), |literal| {
+                    ir::Value::ConstantLiteral(*literal)
+                })
+            }
+            ir::Value::ConstantLiteral(..) => value.clone(),
+        };
+        // Returns the fact with its operands swapped and the negated fact.
+        let converse = |(opcode, lhs, rhs): &Fact| -> (Fact, Fact) {
+            let (swapped, negated) = match opcode {
+                OPCode::Eq => (OPCode::Eq, OPCode::Neq),
+                OPCode::Neq => (OPCode::Neq, OPCode::Eq),
+                OPCode::Lt => (OPCode::Gt, OPCode::Gte),
+                OPCode::Lte => (OPCode::Gte, OPCode::Gt),
+                OPCode::Gt => (OPCode::Lt, OPCode::Lte),
+                OPCode::Gte => (OPCode::Lte, OPCode::Lt),
+                _ => unreachable!("expected a comparison"),
+            };
+            (
+                (swapped, rhs.clone(), lhs.clone()),
+                (negated, lhs.clone(), rhs.clone()),
+            )
+        };
+        // Comparisons computed in the current block by their destination,
+        // and the facts holding at the current instruction.
+        let mut comparisons: HashMap<Symbol, Fact> = HashMap::new();
+        let mut facts: Vec<Fact> = vec![];
+        let mut changed = false;
+        for inst in function.instructions_mut() {
+            let opcode = inst.opcode();
+            match inst {
+                ir::Instruction::Label(..)
+                | ir::Instruction::Jump(..)
+                | ir::Instruction::Branch(..)
+                | ir::Instruction::Return(..)
+                | ir::Instruction::Exit(..)
+                | ir::Instruction::Trap(..)
+                // Atomics write their variable and may observe stores of
+                // other threads.
+                | ir::Instruction::Atomic(..) => {
+                    comparisons.clear();
+                    facts.clear();
+                }
+                ir::Instruction::Intrinsic(_, Builtin::Assume, args) => {
+                    if let [ir::Value::StorageLocation(cond)] = args.as_slice()
+                    {
+                        if let Some(fact) = comparisons.get(cond) {
+                            let (swapped, _) = converse(fact);
+                            facts.push(fact.clone());
+                            facts.push(swapped);
+                        }
+                    }
+                }
+                ir::Instruction::Eq(dst, lhs, rhs)
+                | ir::Instruction::Neq(dst, lhs, rhs)
+                | ir::Instruction::Lt(dst, lhs, rhs)
+                | ir::Instruction::Lte(dst, lhs, rhs)
+                | ir::Instruction::Gt(dst, lhs, rhs)
+                | ir::Instruction::Gte(dst, lhs, rhs) => {
+                    let fact = (opcode, resolve(lhs), resolve(rhs));
+                    let (_, negated) = converse(&fact);
+                    let known = if facts.contains(&fact) {
+                        Some(true)
+                    } else if facts.contains(&negated) {
+                        Some(false)
+                    } else {
+                        None
+                    };
+                    let dst = dst.clone();
+                    comparisons.insert(dst.clone(), fact);
+                    if let Some(known) = known {
+                        *inst = ir::Instruction::Const(
+                            dst,
+                            ir::Value::ConstantLiteral(Literal::Bool(known)),
+                        );
+                        changed = true;
+                    }
+                }
+                _ => (),
+            }
+            // Facts about a variable don't survive its assignment.
+            if let Some(dst) = inst.destination() {
+                let assigned = ir::Value::StorageLocation(dst.clone());
+                let mentions =
+                    |(_, lhs, rhs): &Fact| *lhs == assigned || *rhs == assigned;
+                comparisons.retain(|_, fact| !mentions(fact));
+                facts.retain(|fact| !mentions(fact));
+            }
+        }
+        changed
+    }
+
+    /// Returns the instruction defining the temporary `value` if it's in the
+    /// same basic block as the instruction at `index` and its `operand` can
+    /// be read at `index` instead of at the definition.
+    fn local_definition<'a>(
+        function: &'a ir::Function,
+        value: &ir::Value,
+        index: usize,
+        operand: impl Fn(&ir::Instruction) -> Option<ir::Value>,
+    ) -> Option<&'a ir::Instruction> {
+        let insts = function.instructions();
+        let ir::Value::StorageLocation(temp) = value else {
+            return None;
+        };
+        if !temp.name().starts_with('%') {
+            return None;
+        }
+        let def = insts[..index]
+            .iter()
+            .rposition(|inst| inst.destination() == Some(temp))?;
+        let operand = operand(&insts[def])?;
+        let local = insts[def + 1..index]
+            .iter()
+            .all(|inst| !inst.label() && !inst.terminator());
+        let volatile = matches!(&operand, ir::Value::StorageLocation(symbol)
+            if function.volatiles().contains(symbol));
+        (local
+            && !volatile
+            && !Reassociate::clobbered(insts, &operand, def, index))
+        .then_some(&insts[def])
+    }
+
+    /// Simplify algebraic identities into copies or constants, returns `true`
+    /// if any instruction was simplified.
+    ///
+    /// `x + 0`, `x - 0`, `x * 1` and `x / 1` are `x`, `x * 0` and `x - x`
+    /// are `0`, `-(-x)` and `!(!x)` are `x`. Reads of volatile variables are
+    /// kept.
+    fn simplify_identities(function: &mut ir::Function) -> bool {
+        let constants = Self::constants(function);
+        let int = |value: &ir::Value| match value {
+            ir::Value::StorageLocation(symbol) => match constants.get(symbol) {
+                Some(Literal::Int(value)) => Some(*value),
+                _ => None,
+            },
+            ir::Value::ConstantLiteral(Literal::Int(value)) => Some(*value),
+            ir::Value::ConstantLiteral(..) => None,
+        };
+        let volatile = |value: &ir::Value| {
+            matches!(value, ir::Value::StorageLocation(symbol)
+                if function.volatiles().contains(symbol))
+        };
+        let zero = ir::Value::ConstantLiteral(Literal::Int(0));
+        let mut rewrites = vec![];
+        for (index, inst) in function.instructions().iter().enumerate() {
+            let rewrite = match inst {
+                ir::Instruction::Add(dst, x, c)
+                | ir::Instruction::Add(dst, c, x)
+                    if int(c) == Some(0) =>
+                {
+                    ir::Instruction::Id(dst.clone(), x.clone())
+                }
+                ir::Instruction::Sub(dst, x, c) if int(c) == Some(0) => {
+                    ir::Instruction::Id(dst.clone(), x.clone())
+                }
+                ir::Instruction::Mul(dst, x, c)
+                | ir::Instruction::Mul(dst, c, x)
+                    if int(c) == Some(1) =>
+                {
+                    ir::Instruction::Id(dst.clone(), x.clone())
+                }
+                ir::Instruction::Div(dst, x, c) if int(c) == Some(1) => {
+                    ir::Instruction::Id(dst.clone(), x.clone())
+                }
+                ir::Instruction::Mul(dst, x, c)
+                | ir::Instruction::Mul(dst, c, x)
+                    if int(c) == Some(0) && !volatile(x) =>
+                {
+                    ir::Instruction::Const(dst.clone(), zero.clone())
+                }
+                ir::Instruction::Sub(dst, lhs, rhs)
+                    if lhs == rhs
+                        && matches!(lhs, ir::Value::StorageLocation(..))
+                        && !volatile(lhs) =>
+                {
+                    ir::Instruction::Const(dst.clone(), zero.clone())
+                }
+                ir::Instruction::Neg(dst, operand)
+                | ir::Instruction::Not(dst, operand) => {
+                    let opcode = inst.opcode();
+                    let inner = |def: &ir::Instruction| match def {
+                        ir::Instruction::Neg(_, x)
+                        | ir::Instruction::Not(_, x)
+                            if def.opcode() == opcode =>
+                        {
+                            Some(x.clone())
+                        }
+                        _ => None,
+                    };
+                    let Some(def) =
+                        Self::local_definition(function, operand, index, inner)
+                    else {
+                        continue;
+                    };
+                    let x = inner(def).expect("definition is a negation");
+                    ir::Instruction::Id(dst.clone(), x)
+                }
+                _ => continue,
+            };
+            // Copies of constants stay constants so folding goes on.
+            let folded = match &rewrite {
+                ir::Instruction::Id(
+                    dst,
+                    ir::Value::StorageLocation(symbol),
+                ) => constants.get(symbol).map(|literal| {
+                    ir::Instruction::Const(
+                        dst.clone(),
+                        ir::Value::ConstantLiteral(*literal),
+                    )
+                }),
+                _ => None,
+            };
+            rewrites.push((index, folded.unwrap_or(rewrite)));
+        }
+        let changed = !rewrites.is_empty();
+        for (index, rewrite) in rewrites {
+            function.instructions_mut()[index] = rewrite;
+        }
+        changed
+    }
+
+    /// Fold chains of additions and subtractions (resp. multiplications) of
+    /// constants, `(x + 1) - 3` becomes `x + -2`, returns `true` if any chain
+    /// was folded. The inner operation must be in the same basic block and
+    /// used only by the outer one so it's left dead. Only used in wrapping
+    /// mode, in the other modes the inner operation can overflow on its own.
+    fn fold_chains(function: &mut ir::Function) -> bool {
+        let constants = Self::constants(function);
+        let int = |value: &ir::Value| match value {
+            ir::Value::StorageLocation(symbol) => match constants.get(symbol) {
+                Some(Literal::Int(value)) => Some(*value),
+                _ => None,
+            },
+            ir::Value::ConstantLiteral(Literal::Int(value)) => Some(*value),
+            ir::Value::ConstantLiteral(..) => None,
+        };
+        // Returns the operation as `x + c` (resp. `x * c`).
+        let split = |inst: &ir::Instruction| match inst {
+            ir::Instruction::Add(_, x, c) | ir::Instruction::Add(_, c, x)
+                if int(c).is_some() && int(x).is_none() =>
+            {
+                Some((OPCode::Add, x.clone(), int(c)?))
+            }
+            ir::Instruction::Sub(_, x, c) if int(c).is_some() => {
+                Some((OPCode::Add, x.clone(), int(c)?.wrapping_neg()))
+            }
+            ir::Instruction::Mul(_, x, c) | ir::Instruction::Mul(_, c, x)
+                if int(c).is_some() && int(x).is_none() =>
+            {
+                Some((OPCode::Mul, x.clone(), int(c)?))
+            }
+            _ => None,
+        };
+        let mut uses: HashMap<Symbol, usize> = HashMap::new();
+        for inst in function.instructions() {
+            for value in inst.uses() {
+                if let ir::Value::StorageLocation(symbol) = value {
+                    *uses.entry(symbol.clone()).or_default() += 1;
+                }
+            }
+        }
+        let mut next_temp = function.next_temp();
+        let mut rewrites = vec![];
+        for (index, inst) in function.instructions().iter().enumerate() {
+            let Some((opcode, inner, outer)) = split(inst) else {
+                continue;
+            };
+            let single_use = matches!(&inner, ir::Value::StorageLocation(temp)
+                if uses.get(temp) == Some(&1));
+            if !single_use {
+                continue;
+            }
+            let Some(def) =
+                Self::local_definition(function, &inner, index, |def| {
+                    split(def)
+                        .filter(|(op, ..)| *op == opcode)
+                        .map(|(_, x, _)| x)
+                })
+            else {
+                continue;
+            };
+            let (_, x, constant) = split(def).expect("definition is a chain");
+            let dst = inst.destination().expect("binary operation").clone();
+            let Ok(Some(Literal::Int(folded))) = consteval::binary(
+                opcode,
+                Literal::Int(constant),
+                Literal::Int(outer),
+                ArithmeticMode::Wrapping,
+            ) else {
+                continue;
+            };
+            let temp = Symbol::new(&format!("%v{next_temp}"), dst.t());
+            next_temp += 1;
+            let literal = ir::Value::ConstantLiteral(Literal::Int(folded));
+            let code = vec![
+                ir::Instruction::Const(temp.clone(), literal),
+                Reassociate::binary(
+                    opcode,
+                    dst,
+                    x,
+                    ir::Value::StorageLocation(temp),
+                ),
+            ];
+            rewrites.push((index, code));
+        }
+        let changed = !rewrites.is_empty();
+        // Splice from the end so earlier indices stay valid.
+        for (index, code) in rewrites.into_iter().rev() {
+            function.splice(index..index + 1, code);
+        }
+        changed
+    }
+
+    /// Fold intrinsic calls whose arguments are all constants into a `const`
+    /// instruction, returns `true` if any intrinsic was folded.
+    fn fold_intrinsics(function: &mut ir::Function) -> bool {
+        let constants = Self::constants(function);
+        let mut changed = false;
+        for inst in function.instructions_mut() {
+            let ir::Instruction::Intrinsic(dst, builtin, args) = inst else {
+                continue;
+            };
+            let args = args
+                .iter()
+                .map(|arg| match arg {
+                    ir::Value::StorageLocation(symbol) => {
+                        constants.get(symbol).copied()
+                    }
+                    ir::Value::ConstantLiteral(literal) => Some(*literal),
+                })
+                .collect::<Option<Vec<_>>>();
+            if let Some(literal) = args.and_then(|args| builtin.fold(&args)) {
+                *inst = ir::Instruction::Const(
+                    dst.clone(),
+                    ir::Value::ConstantLiteral(literal),
+                );
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+impl Transform for InstCombine {
+    fn run(&self, function: &mut ir::Function) {
+        // Folding an instruction can make the operands of its users
+        // constant.
+        while Self::fold_intrinsics(function)
+            | Self::fold_arithmetic(function, self.mode)
+            | Self::fold_assumptions(function)
+            | Self::simplify_identities(function)
+            | (self.mode == ArithmeticMode::Wrapping
+                && Self::fold_chains(function))
+        {}
+    }
+}
+
+/// Reassociation pass rewrites chains of `add` (resp. `mul`) instructions
+/// within a basic block into a canonical form, exposing constants to folding
+/// and shortening dependence chains.
+///
+/// A chain is a tree of instructions of the same operation whose interior
+/// results are temporaries used once. In wrapping mode the leaves of a chain
+/// are sorted by rank (the position of their definition, arguments and
+/// variables assigned before the chain first), constants are folded into a
+/// single operand placed last and the tree is rebuilt balanced. Since
+/// rebalancing changes the intermediate results it could introduce a signed
+/// overflow, so in checked mode only constants trailing the chain are folded
+/// i.e `((x + y) + 1) + 2` becomes `(x + y) + 3` and folds overflowing at
+/// compile time are skipped.
+#[derive(Default, Debug)]
+struct Reassociate {
+    mode: ArithmeticMode,
+}
+
+impl Reassociate {
+    /// Returns the index of the instruction combining every chain interior
+    /// node with its unique user.
+    fn interior(function: &ir::Function) -> HashMap<Symbol, usize> {
+        let insts = function.instructions();
+        let mut block = 0;
+        let mut blocks = Vec::with_capacity(insts.len());
+        for inst in insts {
+            if inst.label() {
+                block += 1;
+            }
+            blocks.push(block);
+            if inst.terminator() {
+                block += 1;
+            }
+        }
+        let mut defs = HashMap::new();
+        let mut users: HashMap<Symbol, Vec<usize>> = HashMap::new();
+        for (index, inst) in insts.iter().enumerate() {
+            for value in inst.uses() {
+                if let ir::Value::StorageLocation(symbol) = value {
+                    users.entry(symbol.clone()).or_default().push(index);
+                }
+            }
+            if let Some(dst) = inst.destination() {
+                defs.insert(dst.clone(), index);
+            }
+        }
+        defs.into_iter()
+            .filter(|(dst, def)| {
+                let opcode = insts[*def].opcode();
+                dst.name().starts_with('%')
+                    && matches!(opcode, OPCode::Add | OPCode::Mul)
+                    && matches!(users.get(dst).map(Vec::as_slice), Some([user])
+                        if insts[*user].opcode() == opcode
+                            && blocks[*user] == blocks[*def])
+            })
+            .collect()
+    }
+
+    /// Append the leaves of the chain rooted at instruction `index` to
+    /// `leaves` in left to right order along with the index of the
+    /// instruction reading them, returns the depth of the chain.
+    fn flatten(
+        insts: &[ir::Instruction],
+        interior: &HashMap<Symbol, usize>,
+        index: usize,
+        leaves: &mut Vec<(ir::Value, usize)>,
+    ) -> usize {
+        let (Some(lhs), Some(rhs)) = insts[index].operands() else {
+            unreachable!("expected a binary operation")
+        };
+        let mut depth = 0;
+        for operand in [lhs, rhs] {
+            match operand {
+                ir::Value::StorageLocation(symbol)
+                    if interior.contains_key(symbol) =>
+                {
+                    depth = depth.max(Self::flatten(
+                        insts,
+                        interior,
+                        interior[symbol],
+                        leaves,
+                    ));
+                }
+                operand => leaves.push((operand.clone(), index)),
+            }
+        }
+        depth + 1
+    }
+
+    /// Returns `true` if the variable `value` read at instruction `read` may
+    /// hold a different value at instruction `root`, temporaries are
+    /// assigned once.
+    fn clobbered(
+        insts: &[ir::Instruction],
+        value: &ir::Value,
+        read: usize,
+        root: usize,
+    ) -> bool {
+        let ir::Value::StorageLocation(symbol) = value else {
+            return false;
+        };
+        !symbol.name().starts_with('%')
+            && insts.get(read + 1..root).is_some_and(|between| {
+                between.iter().any(|inst| {
+                    inst.destination() == Some(symbol)
+                        || matches!(
+                            inst.opcode(),
+                            OPCode::Call | OPCode::Asm | OPCode::Atomic
+                        )
+                })
+            })
+    }
+
+    /// Returns the instruction computing `opcode` on `lhs` and `rhs`.
+    fn binary(
+        opcode: OPCode,
+        dst: Symbol,
+        lhs: ir::Value,
+        rhs: ir::Value,
+    ) -> ir::Instruction {
+        match opcode {
+            OPCode::Add => ir::Instruction::Add(dst, lhs, rhs),
+            OPCode::Mul => ir::Instruction::Mul(dst, lhs, rhs),
+            _ => unreachable!("expected an associative operation"),
+        }
+    }
+
+    /// Returns the instructions replacing the chain rooted at `root`, or
+    /// `None` if the chain is left as is.
+    fn rewrite(
+        &self,
+        function: &ir::Function,
+        interior: &HashMap<Symbol, usize>,
+        root: usize,
+        next_temp: &mut usize,
+    ) -> Option<Vec<ir::Instruction>> {
+        let insts = function.instructions();
+        let constants = InstCombine::constants(function);
+        let literal = |value: &ir::Value| match value {
+            ir::Value::StorageLocation(symbol) => {
+                constants.get(symbol).copied()
+            }
+            ir::Value::ConstantLiteral(literal) => Some(*literal),
+        };
+        let opcode = insts[root].opcode();
+        let dst = insts[root].destination()?.clone();
+        let identity = match opcode {
+            OPCode::Add => Literal::Int(0),
+            _ => Literal::Int(1),
+        };
+        let mut temporary = || {
+            let temp = Symbol::new(&format!("%v{next_temp}"), dst.t());
+            *next_temp += 1;
+            temp
+        };
+
+        let (mut leaves, constant) = match self.mode {
+            ArithmeticMode::Wrapping => {
+                let mut leaves = vec![];
+                let depth = Self::flatten(insts, interior, root, &mut leaves);
+                // Reading every leaf at the root must not change its value,
+                // reads of volatile variables can't be moved at all.
+                if leaves.iter().any(|(leaf, read)| {
+                    Self::clobbered(insts, leaf, *read, root)
+                        || matches!(leaf, ir::Value::StorageLocation(symbol)
+                            if function.volatiles().contains(symbol))
+                }) {
+                    return None;
+                }
+                let (folded, mut leaves): (Vec<_>, Vec<_>) = leaves
+                    .into_iter()
+                    .map(|(leaf, _)| leaf)
+                    .partition(|leaf| literal(leaf).is_some());
+                // Leaves defined earlier come first, ties are broken by name
+                // to keep the order deterministic.
+                let rank = |leaf: &ir::Value| match leaf {
+                    ir::Value::StorageLocation(symbol) => (
+                        insts[..root]
+                            .iter()
+                            .rposition(|inst| {
+                                inst.destination() == Some(symbol)
+                            })
+                            .map_or(0, |index| index + 1),
+                        symbol.name().to_string(),
+                    ),
+                    ir::Value::ConstantLiteral(..) => (0, String::new()),
+                };
+                leaves.sort_by_cached_key(rank);
+                // Folded constants become a single extra operand.
+                let operands = leaves.len() + usize::from(!folded.is_empty());
+                let balanced = operands.next_power_of_two().ilog2();
+                if folded.len() < 2 && depth <= balanced as usize {
+                    return None;
+                }
+                let constant =
+                    folded.iter().try_fold(identity, |acc, leaf| {
+                        let leaf = literal(leaf)?;
+                        consteval::binary(opcode, acc, leaf, self.mode)
+                            .ok()
+                            .flatten()
+                    })?;
+                (leaves, constant)
+            }
+            ArithmeticMode::Checked | ArithmeticMode::Trapping => {
+                // Walk down the chain while the current node combines a
+                // constant with the rest of the chain.
+                let mut folded = vec![];
+                let mut node = root;
+                let base = loop {
+                    let (Some(lhs), Some(rhs)) = insts[node].operands() else {
+                        unreachable!("expected a binary operation")
+                    };
+                    let (constant, other) = match (literal(lhs), literal(rhs)) {
+                        (_, Some(constant)) => (constant, lhs),
+                        (Some(constant), None) => (constant, rhs),
+                        (None, None) => {
+                            break ir::Value::StorageLocation(
+                                insts[node].destination()?.clone(),
+                            );
+                        }
+                    };
+                    folded.push(constant);
+                    match other {
+                        ir::Value::StorageLocation(symbol)
+                            if interior.contains_key(symbol) =>
+                        {
+                            node = interior[symbol];
+                        }
+                        other => break other.clone(),
+                    }
+                };
+                if folded.len() < 2 || Self::clobbered(insts, &base, node, root)
+                {
+                    return None;
+                }
+                let constant = folded.into_iter().try_fold(
+                    identity,
+                    |acc, constant| {
+                        consteval::binary(opcode, acc, constant, self.mode)
+                            .ok()
+                            .flatten()
+                    },
+                )?;
+                (vec![base], constant)
+            }
+        };
+
+        if leaves.is_empty() {
+            return Some(vec![ir::Instruction::Const(
+                dst,
+                ir::Value::ConstantLiteral(constant),
+            )]);
+        }
+        let mut code = vec![];
+        if constant != identity {
+            let temp = temporary();
+            code.push(ir::Instruction::Const(
+                temp.clone(),
+                ir::Value::ConstantLiteral(constant),
+            ));
+            leaves.push(ir::Value::StorageLocation(temp));
+        }
+        // Combine leaves pairwise until two remain, the last combination
+        // assigns the root destination.
+        while leaves.len() > 2 {
+            let mut level = vec![];
+            for pair in leaves.chunks(2) {
+                match pair {
+                    [lhs, rhs] => {
+                        let temp = temporary();
+                        code.push(Self::binary(
+                            opcode,
+                            temp.clone(),
+                            lhs.clone(),
+                            rhs.clone(),
+                        ));
+                        level.push(ir::Value::StorageLocation(temp));
+                    }
+                    [leaf] => level.push(leaf.clone()),
+                    _ => unreachable!(),
+                }
+            }
+            leaves = level;
+        }
+        match leaves.as_slice() {
+            [lhs, rhs] => {
+                code.push(Self::binary(opcode, dst, lhs.clone(), rhs.clone()))
+            }
+            [leaf] => code.push(ir::Instruction::Id(dst, leaf.clone())),
+            _ => unreachable!("expected the chain to have leaves"),
+        }
+        Some(code)
+    }
+}
+
+impl Transform for Reassociate {
+    fn run(&self, function: &mut ir::Function) {
+        let interior = Self::interior(function);
+        let mut next_temp = function.next_temp();
+        let insts = function.instructions();
+        let mut rewrites = HashMap::new();
+        for (index, inst) in insts.iter().enumerate() {
+            // Roots of chains of at least two instructions.
+            let is_root = matches!(inst.opcode(), OPCode::Add | OPCode::Mul)
+                && inst
+                    .destination()
+                    .is_some_and(|dst| !interior.contains_key(dst))
+                && matches!(inst.operands(), (Some(lhs), Some(rhs))
+                    if [lhs, rhs].into_iter().any(|operand| matches!(operand,
+                        ir::Value::StorageLocation(symbol)
+                            if interior.contains_key(symbol))));
+            if !is_root {
+                continue;
+            }
+            if let Some(code) =
+                self.rewrite(function, &interior, index, &mut next_temp)
+            {
+                rewrites.insert(index, code);
+            }
+        }
+        if rewrites.is_empty() {
+            return;
+        }
+        // Interior nodes of rewritten chains are left for `DCE`.
+        let body = insts
+            .iter()
+            .enumerate()
+            .flat_map(|(index, inst)| {
+                rewrites
+                    .remove(&index)
+                    .unwrap_or_else(|| vec![inst.clone()])
+            })
+            .collect::<Vec<_>>();
+        let len = function.len();
+        function.splice(0..len, body);
+    }
+}
+
+/// Branch canonicalization pass normalizes conditional branches so their
+/// `else` target is the block laid out right after the branch, the backends
+/// then emit a single conditional jump to the `then` target.
+///
+/// Branches falling through to their `then` target are inverted when their
+/// condition is a comparison or a `not` only used by the branch : the
+/// comparison is replaced by its negation (`lt` becomes `gte`), a `not` is
+/// bypassed, and the targets and branch hint are swapped.
+struct CanonicalizeBranches {}
+
+impl CanonicalizeBranches {
+    /// Returns the negation of a comparison instruction.
+    fn negate(inst: &ir::Instruction) -> Option<ir::Instruction> {
+        let negated = match inst.clone() {
+            ir::Instruction::Eq(dst, lhs, rhs) => {
+                ir::Instruction::Neq(dst, lhs, rhs)
+            }
+            ir::Instruction::Neq(dst, lhs, rhs) => {
+                ir::Instruction::Eq(dst, lhs, rhs)
+            }
+            ir::Instruction::Lt(dst, lhs, rhs) => {
+                ir::Instruction::Gte(dst, lhs, rhs)
+            }
+            ir::Instruction::Gte(dst, lhs, rhs) => {
+                ir::Instruction::Lt(dst, lhs, rhs)
+            }
+            ir::Instruction::Gt(dst, lhs, rhs) => {
+                ir::Instruction::Lte(dst, lhs, rhs)
+            }
+            ir::Instruction::Lte(dst, lhs, rhs) => {
+                ir::Instruction::Gt(dst, lhs, rhs)
+            }
+            _ => return None,
+        };
+        Some(negated)
+    }
+}
+
+impl Transform for CanonicalizeBranches {
+    fn run(&self, function: &mut ir::Function) {
+        let mut uses: HashMap<Symbol, usize> = HashMap::new();
+        let mut defs = HashMap::new();
+        for (index, inst) in function.instructions().iter().enumerate() {
+            for value in inst.uses() {
+                if let ir::Value::StorageLocation(symbol) = value {
+                    *uses.entry(symbol.clone()).or_default() += 1;
+                }
+            }
+            if let Some(dst) = inst.destination() {
+                defs.insert(dst.clone(), index);
+            }
+        }
+        let insts = function.instructions_mut();
+        for index in 0..insts.len() {
+            let ir::Instruction::Branch(
+                ir::Value::StorageLocation(condition),
+                then_label,
+                _,
+                _,
+            ) = &insts[index]
+            else {
+                continue;
+            };
+            let falls_through = matches!(insts.get(index + 1),
+                Some(ir::Instruction::Label(label)) if *label == then_label.index());
+            if !falls_through || uses.get(condition) != Some(&1) {
+                continue;
+            }
+            let condition = condition.clone();
+            let Some(&def) = defs.get(&condition) else {
+                continue;
+            };
+            let inverted = match &insts[def] {
+                // The operand of a `not` is read at the branch instead, which
+                // is only valid for temporaries since they are assigned once.
+                ir::Instruction::Not(
+                    _,
+                    operand @ ir::Value::StorageLocation(symbol),
+                ) if symbol.name().starts_with('%') => Some(operand.clone()),
+                ir::Instruction::Not(..) => None,
+                comparison => Self::negate(comparison).map(|negated| {
+                    insts[def] = negated;
+                    ir::Value::StorageLocation(condition)
+                }),
+            };
+            let Some(inverted) = inverted else {
+                continue;
+            };
+            if let ir::Instruction::Branch(
+                condition,
+                then_label,
+                else_label,
+                hint,
+            ) = &mut insts[index]
+            {
+                *condition = inverted;
+                std::mem::swap(then_label, else_label);
+                *hint = hint.invert();
+            }
+        }
+    }
+}
+
+/// Local Value Numbering pass builds a value numbering table for every basic
+/// block and uses it for common subexpression elimination and copy
+/// propagation.
+///
+/// Every value computed in a block gets a number, instructions computing a
+/// number already held by a variable become copies of that variable and
+/// operands are rewritten to the variable first holding their number.
+/// Volatile variables are never numbered, and calls, atomics and inline
+/// assembly may write any variable so only temporaries survive them.
+#[derive(Default, Debug)]
+struct LVN {}
+
+/// Row of the value numbering table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct ValueNumber(usize);
+
+/// Instruction encoded as a tuple of value numbers, unary operations repeat
+/// their operand and casts record their destination type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum NumberedValue {
+    Constant(Literal),
+    Operation(OPCode, ir::Type, ValueNumber, ValueNumber),
+}
+
+/// Value numbering table of a basic block.
+#[derive(Debug, Default)]
+struct NumberingTable {
+    // Value number held by every variable (var2num).
+    environment: HashMap<Symbol, ValueNumber>,
+    // Value number of every encoded instruction (value2num).
+    values: HashMap<NumberedValue, ValueNumber>,
+    // Canonical variable of every value number (num2var).
+    variables: HashMap<ValueNumber, Symbol>,
+    // Next fresh value number.
+    next: usize,
+}
+
+impl NumberingTable {
+    /// Returns a fresh value number held by `symbol`.
+    fn fresh(&mut self, symbol: &Symbol) -> ValueNumber {
+        let vn = ValueNumber(self.next);
+        self.next += 1;
+        self.variables.insert(vn, symbol.clone());
+        vn
+    }
+
+    /// Returns the value number of `value`, variables read for the first
+    /// time get a fresh one.
+    fn number(&mut self, value: &ir::Value) -> ValueNumber {
+        match value {
+            ir::Value::StorageLocation(symbol) => {
+                match self.environment.get(symbol) {
+                    Some(vn) => *vn,
+                    None => {
+                        let vn = self.fresh(symbol);
+                        self.environment.insert(symbol.clone(), vn);
+                        vn
+                    }
+                }
+            }
+            ir::Value::ConstantLiteral(literal) => {
+                let constant = NumberedValue::Constant(*literal);
+                match self.values.get(&constant) {
+                    Some(vn) => *vn,
+                    None => {
+                        let vn = ValueNumber(self.next);
+                        self.next += 1;
+                        self.values.insert(constant, vn);
+                        vn
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the canonical variable holding `vn` if it still does.
+    fn canonical(&self, vn: ValueNumber) -> Option<&Symbol> {
+        self.variables
+            .get(&vn)
+            .filter(|symbol| self.environment.get(*symbol) == Some(&vn))
+    }
+
+    /// Record that `symbol` now holds `vn`, it becomes its canonical
+    /// variable if the number has none.
+    fn assign(&mut self, symbol: &Symbol, vn: ValueNumber) {
+        self.environment.insert(symbol.clone(), vn);
+        if self.canonical(vn).is_none() {
+            self.variables.insert(vn, symbol.clone());
+        }
+    }
+
+    /// Forget the values of every variable but temporaries, which are
+    /// assigned once.
+    fn forget_variables(&mut self) {
+        self.environment
+            .retain(|symbol, _| symbol.name().starts_with('%'));
+    }
+}
+
+impl LVN {
+    /// Returns the instruction encoded with the value numbers of its
+    /// operands, `None` for instructions with effects or reading memory
+    /// other threads write.
+    fn encode(
+        table: &mut NumberingTable,
+        inst: &ir::Instruction,
+    ) -> Option<NumberedValue> {
+        let opcode = inst.opcode();
+        let t = inst.destination()?.t();
+        match inst {
+            ir::Instruction::Const(_, ir::Value::ConstantLiteral(literal)) => {
+                Some(NumberedValue::Constant(*literal))
+            }
+            ir::Instruction::Add(_, lhs, rhs)
+            | ir::Instruction::Mul(_, lhs, rhs)
+            | ir::Instruction::BitAnd(_, lhs, rhs)
+            | ir::Instruction::Eq(_, lhs, rhs)
+            | ir::Instruction::Neq(_, lhs, rhs)
+            | ir::Instruction::And(_, lhs, rhs)
+            | ir::Instruction::Or(_, lhs, rhs) => {
+                // Commutative operations are numbered regardless of the
+                // order of their operands.
+                let (lhs, rhs) = (table.number(lhs), table.number(rhs));
+                Some(NumberedValue::Operation(
+                    opcode,
+                    t,
+                    lhs.min(rhs),
+                    lhs.max(rhs),
+                ))
+            }
+            ir::Instruction::Sub(_, lhs, rhs)
+            | ir::Instruction::Div(_, lhs, rhs)
+            | ir::Instruction::Shl(_, lhs, rhs)
+            | ir::Instruction::Shr(_, lhs, rhs)
+            | ir::Instruction::Lt(_, lhs, rhs)
+            | ir::Instruction::Lte(_, lhs, rhs)
+            | ir::Instruction::Gt(_, lhs, rhs)
+            | ir::Instruction::Gte(_, lhs, rhs) => {
+                let (lhs, rhs) = (table.number(lhs), table.number(rhs));
+                Some(NumberedValue::Operation(opcode, t, lhs, rhs))
+            }
+            ir::Instruction::Not(_, operand)
+            | ir::Instruction::Neg(_, operand)
+            | ir::Instruction::Cast(_, operand) => {
+                let operand = table.number(operand);
+                Some(NumberedValue::Operation(opcode, t, operand, operand))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Transform for LVN {
+    fn run(&self, function: &mut ir::Function) {
+        let volatiles = function.volatiles().to_vec();
+        let volatile = |value: &ir::Value| {
+            matches!(value, ir::Value::StorageLocation(symbol)
+                if volatiles.contains(symbol))
+        };
+        let mut table = NumberingTable::default();
+        for inst in function.instructions_mut() {
+            // Value numbers don't flow across basic blocks.
+            if inst.label() {
+                table = NumberingTable::default();
+            }
+            // Copy propagation, operands read the canonical variable holding
+            // their value.
+            let reads_volatile = inst.values_mut().into_iter().any(|value| {
+                if volatile(value) {
+                    return true;
+                }
+                let vn = table.number(value);
+                if let ir::Value::StorageLocation(symbol) = value {
+                    if let Some(canonical) = table.canonical(vn) {
+                        if canonical.t() == symbol.t() {
+                            *value =
+                                ir::Value::StorageLocation(canonical.clone());
+                        }
+                    }
+                }
+                false
+            });
+            if inst.has_side_effects()
+                || inst.barrier()
+                || matches!(inst, ir::Instruction::Atomic(..))
+            {
+                table.forget_variables();
+            }
+            let Some(dst) = inst.destination().cloned() else {
+                if inst.terminator() {
+                    table = NumberingTable::default();
+                }
+                continue;
+            };
+            if volatile(&ir::Value::StorageLocation(dst.clone())) {
+                continue;
+            }
+            let vn = match inst {
+                ir::Instruction::Id(_, operand) if !reads_volatile => {
+                    table.number(operand)
+                }
+                _ if reads_volatile => table.fresh(&dst),
+                _ => match Self::encode(&mut table, inst) {
+                    Some(value) => match table.values.get(&value).copied() {
+                        Some(vn) => {
+                            // Common subexpression, reuse the variable
+                            // holding the value.
+                            if let Some(canonical) = table.canonical(vn) {
+                                if !matches!(inst, ir::Instruction::Const(..)) {
+                                    *inst = ir::Instruction::Id(
+                                        dst.clone(),
+                                        ir::Value::StorageLocation(
+                                            canonical.clone(),
+                                        ),
+                                    );
+                                }
+                            }
+                            vn
+                        }
+                        None => {
+                            let vn = table.fresh(&dst);
+                            table.values.insert(value, vn);
+                            vn
+                        }
+                    },
+                    None => table.fresh(&dst),
+                },
+            };
+            table.assign(&dst, vn);
+        }
+    }
+}
+
+/// Global Value Numbering pass extends `LVN` across basic blocks, values
+/// computed in a block are reused by the blocks it dominates.
+///
+/// The IR isn't in SSA form so only temporaries assigned once and arguments
+/// never assigned are numbered, they hold the same value wherever they're
+/// read. Blocks are visited in a preorder walk of the dominator tree and an
+/// instruction recomputing a value available from a dominating block becomes
+/// a copy of the variable holding it, `DCE` then removes its operands.
+#[derive(Default, Debug)]
+struct GlobalValueNumbering {}
+
+impl GlobalValueNumbering {
+    /// Returns the variables holding a single value in `function`.
+    fn stable_variables(function: &ir::Function) -> HashSet<Symbol> {
+        let insts = function.instructions();
+        let mut definitions: HashMap<&Symbol, usize> = HashMap::new();
+        for inst in insts {
+            let assigned = match inst {
+                ir::Instruction::Atomic(
+                    _,
+                    _,
+                    ir::Value::StorageLocation(variable),
+                    ..,
+                ) => Some(variable),
+                _ => None,
+            };
+            for dst in inst.destination().into_iter().chain(assigned) {
+                *definitions.entry(dst).or_default() += 1;
+            }
+        }
+        // Inline assembly may assign any named variable.
+        let asm = insts
+            .iter()
+            .any(|inst| matches!(inst, ir::Instruction::Asm(..)));
+        let temporaries = definitions
+            .iter()
+            .filter(|(symbol, count)| {
+                symbol.name().starts_with('%') && **count == 1
+            })
+            .map(|(symbol, _)| (*symbol).clone())
+            .collect::<Vec<_>>();
+        let args = function
+            .args()
+            .iter()
+            .filter(|arg| !asm && !definitions.contains_key(arg))
+            .cloned();
+        temporaries
+            .into_iter()
+            .chain(args)
+            .filter(|symbol| !function.volatiles().contains(symbol))
+            .collect()
+    }
+
+    /// Number the instructions of `block` then of the blocks it dominates,
+    /// `available` maps the values computed in the dominators of `block` to
+    /// the variable holding them.
+    fn visit(
+        insts: &mut [ir::Instruction],
+        ranges: &[Range<usize>],
+        dominators: &Dominators,
+        block: BlockRef,
+        stable: &HashSet<Symbol>,
+        table: &mut NumberingTable,
+        available: &mut HashMap<NumberedValue, Symbol>,
+    ) {
+        let mut computed = vec![];
+        for inst in &mut insts[ranges[block.0].clone()] {
+            let Some(dst) = inst.destination().cloned() else {
+                continue;
+            };
+            let unstable = inst.uses().any(|value| {
+                matches!(value, ir::Value::StorageLocation(symbol)
+                    if !stable.contains(symbol))
+            });
+            if !stable.contains(&dst) || unstable {
+                continue;
+            }
+            if let ir::Instruction::Id(_, operand) = inst {
+                let vn = table.number(operand);
+                table.environment.insert(dst, vn);
+                continue;
+            }
+            let Some(value) = LVN::encode(table, inst) else {
+                continue;
+            };
+            let vn = match table.values.get(&value) {
+                Some(vn) => *vn,
+                None => {
+                    let vn = table.fresh(&dst);
+                    table.values.insert(value, vn);
+                    vn
+                }
+            };
+            table.environment.insert(dst.clone(), vn);
+            match available.get(&value) {
+                Some(holder) => {
+                    if !matches!(inst, ir::Instruction::Const(..)) {
+                        *inst = ir::Instruction::Id(
+                            dst,
+                            ir::Value::StorageLocation(holder.clone()),
+                        );
+                    }
+                }
+                None => {
+                    available.insert(value, dst);
+                    computed.push(value);
+                }
+            }
+        }
+        for child in dominators.children(block) {
+            Self::visit(
+                insts, ranges, dominators, *child, stable, table, available,
+            );
+        }
+        // Values computed in `block` aren't available in its siblings.
+        for value in computed {
+            available.remove(&value);
+        }
+    }
+}
+
+impl Transform for GlobalValueNumbering {
+    fn run(&self, function: &mut ir::Function) {
+        let graph = Graph::new(&vec![function.clone()]);
+        if graph.is_empty() {
+            return;
+        }
+        // Blocks are formed in layout order, find the instructions of each.
+        let mut ranges = vec![];
+        for block in graph.blocks() {
+            let start =
+                ranges.last().map_or(0, |range: &Range<usize>| range.end);
+            ranges.push(start..start + graph.block(block).len());
+        }
+        let stable = Self::stable_variables(function);
+        let dominators = graph.dominators();
+        Self::visit(
+            function.instructions_mut(),
+            &ranges,
+            &dominators,
+            BlockRef(0),
+            &stable,
+            &mut NumberingTable::default(),
+            &mut HashMap::new(),
+        );
+    }
+}
+
+/// Copy propagation pass rewrites the reads of `x` following a copy
+/// `x: T = id y` to read `y`, leaving the copy dead for `DCE`.
+///
+/// Copies flow across basic blocks with an available copies analysis : a
+/// copy reaches the entry of a block if it reaches the end of every
+/// predecessor, assigning either of its variables kills it. Calls, atomics
+/// and inline assembly may write any variable so only copies between
+/// temporaries survive them, and copies of volatile variables are never
+/// propagated.
+#[derive(Default, Debug)]
+struct CopyPropagation {}
+
+impl CopyPropagation {
+    /// Update the copies available after `inst`, `copies` maps the
+    /// destination of every copy to its source.
+    fn transfer(
+        copies: &mut HashMap<Symbol, Symbol>,
+        inst: &ir::Instruction,
+        volatiles: &[Symbol],
+    ) {
+        if inst.has_side_effects()
+            || inst.barrier()
+            || matches!(inst, ir::Instruction::Atomic(..))
+        {
+            copies.retain(|dst, src| {
+                dst.name().starts_with('%') && src.name().starts_with('%')
+            });
+        }
+        let Some(dst) = inst.destination() else {
+            return;
+        };
+        copies.retain(|copy, src| copy != dst && src != dst);
+        if let ir::Instruction::Id(_, ir::Value::StorageLocation(src)) = inst {
+            if src != dst
+                && src.t() == dst.t()
+                && !volatiles.contains(src)
+                && !volatiles.contains(dst)
+            {
+                copies.insert(dst.clone(), src.clone());
+            }
+        }
+    }
+}
+
+impl Transform for CopyPropagation {
+    fn run(&self, function: &mut ir::Function) {
+        let graph = Graph::new(&vec![function.clone()]);
+        if graph.is_empty() {
+            return;
+        }
+        // Blocks are formed in layout order, find the instructions of each.
+        let mut ranges = vec![];
+        for block in graph.blocks() {
+            let start =
+                ranges.last().map_or(0, |range: &Range<usize>| range.end);
+            ranges.push(start..start + graph.block(block).len());
+        }
+        let volatiles = function.volatiles().to_vec();
+        let insts = function.instructions_mut();
+
+        // Copies available at the entry of every block, `None` until a
+        // predecessor is visited.
+        let mut entries: Vec<Option<HashMap<Symbol, Symbol>>> =
+            vec![None; graph.len()];
+        entries[0] = Some(HashMap::new());
+        let mut exits: Vec<Option<HashMap<Symbol, Symbol>>> =
+            vec![None; graph.len()];
+        let order = graph.rpo().collect::<Vec<_>>();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in &order {
+                let mut copies = if block.0 == 0 {
+                    HashMap::new()
+                } else {
+                    // The intersection of the copies available at the end
+                    // of the visited predecessors.
+                    let mut predecessors =
+                        graph.predecessors(*block).filter_map(|predecessor| {
+                            exits[predecessor.0].as_ref()
+                        });
+                    let Some(first) = predecessors.next() else {
+                        continue;
+                    };
+                    let mut copies = first.clone();
+                    for exit in predecessors {
+                        copies.retain(|dst, src| exit.get(dst) == Some(src));
+                    }
+                    copies
+                };
+                entries[block.0] = Some(copies.clone());
+                for inst in &insts[ranges[block.0].clone()] {
+                    Self::transfer(&mut copies, inst, &volatiles);
+                }
+                if exits[block.0].as_ref() != Some(&copies) {
+                    exits[block.0] = Some(copies);
+                    changed = true;
+                }
+            }
+        }
+
+        for block in &order {
+            let Some(mut copies) = entries[block.0].take() else {
+                continue;
+            };
+            for inst in &mut insts[ranges[block.0].clone()] {
+                // Atomics read and write their variable in place.
+                if !matches!(inst, ir::Instruction::Atomic(..)) {
+                    for value in inst.values_mut() {
+                        if let ir::Value::StorageLocation(symbol) = value {
+                            if let Some(src) = copies.get(symbol) {
+                                *value =
+                                    ir::Value::StorageLocation(src.clone());
+                            }
+                        }
+                    }
+                }
+                Self::transfer(&mut copies, inst, &volatiles);
+            }
+        }
+    }
+}
+
+/// Dead code elimination pass eliminates unused and unreachable instructions.
+///
+/// Because most optimizations can cause dead instructions this pass is run
+/// after some optimizations multiple times until it converges i.e blocks
+/// remain unchanged after a pass.
+struct DCE {}
+
+impl DCE {
+    /// Trivial Global DCE pass on a function returns `true` if any instructions
+    /// are eliminated.
+    ///
+    /// Instructions assigning a variable that isn't live after them are
+    /// dead, see `dataflow::Liveness`. The program's `globals` escape, they
+    /// are live at the exits and across calls. The function doesn't know
+    /// which variables are globals so the named variables it reads escape
+    /// as well.
+    pub fn tdce(
+        function: &mut ir::Function,
+        globals: &[ir::GlobalValue],
+    ) -> bool {
+        // Stores to static locals are read by later calls, and accesses to
+        // volatile variables are observable.
+        let volatiles =
+            function.volatiles().iter().cloned().collect::<HashSet<_>>();
+        let candidates = function.len();
+
+        // Instructions following an `exit`, a `trap` or a call that never
+        // returns up to the next label are unreachable.
+        let mut reachable = true;
+        for inst in function.instructions_mut() {
+            if inst.label() {
+                reachable = true;
+            } else if !reachable {
+                *inst = ir::Instruction::Nop;
+            }
+            if inst.no_return() {
+                reachable = false;
+            }
+        }
+
+        let escaping = function
+            .instructions()
+            .iter()
+            .flat_map(ir::Instruction::uses)
+            .filter_map(|value| match value {
+                ir::Value::StorageLocation(symbol)
+                    if !symbol.name().starts_with('%')
+                        && !function.args().contains(symbol) =>
+                {
+                    Some(symbol)
+                }
+                _ => None,
+            })
+            .chain(globals.iter().map(ir::GlobalValue::symbol))
+            .chain(function.statics())
+            .chain(&volatiles)
+            .cloned()
+            .collect::<HashSet<_>>();
+        let graph = Graph::new(&vec![function.clone()]);
+        let liveness = Liveness::new(escaping);
+        let solution = dataflow::solve(&liveness, &graph);
+        let live = graph
+            .blocks()
+            .flat_map(|block| solution.after_each(&liveness, &graph, block))
+            .collect::<Vec<_>>();
+
+        for (inst, live) in function.instructions_mut().iter_mut().zip(live) {
+            let reads_volatile = inst.values_mut().into_iter().any(|value| {
+                matches!(value, ir::Value::StorageLocation(operand)
+                    if volatiles.contains(operand))
+            });
+            if !inst.has_side_effects()
+                && !reads_volatile
+                && inst.destination().is_some_and(|dst| {
+                    !live.contains(dst) && !volatiles.contains(dst)
+                })
+            {
+                let _ = std::mem::replace(inst, ir::Instruction::Nop);
+            }
+        }
+        // Remove all instructions marked as dead i.e replaced with `Nop`.
+        function.remove_dead_instructions();
+
+        candidates != function.len()
+    }
+}
+
+impl Transform for DCE {
+    /// Run dead code elimination over a function repeatedly until all
+    /// convergence. The pass convergences when the number of candidates
+    /// for elimination reaches 0.
+    fn run(&self, function: &mut ir::Function) {
+        self.run_in_module(function, &[]);
+    }
+
+    fn run_in_module(
+        &self,
+        function: &mut ir::Function,
+        globals: &[ir::GlobalValue],
+    ) {
+        while Self::tdce(function, globals) {}
+    }
+}
+
+/// Constant pooling pass merges `const` instructions materializing the same
+/// literal into a single definition at the function entry, see
+/// `ir::Function::pool_constants`.
+struct ConstantPooling {}
+
+impl Transform for ConstantPooling {
+    fn run(&self, function: &mut ir::Function) {
+        function.pool_constants()
+    }
+}
+
+/// Assumption stripping pass removes the `__builtin_assume` calls once the
+/// optimizations using the facts they carry ran, it must run before code
+/// generation since assumptions don't generate code.
+struct StripAssumptions {}
+
+impl Transform for StripAssumptions {
+    fn run(&self, function: &mut ir::Function) {
+        for inst in function.instructions_mut() {
+            if let ir::Instruction::Intrinsic(_, Builtin::Assume, _) = inst {
+                *inst = ir::Instruction::Nop;
+            }
+        }
+        function.remove_dead_instructions();
+    }
+}
+
+/// Value of a symbol in `SparseConditionalConstantPropagation`, from the
+/// most to the least precise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Lattice {
+    // No assignment reaches the symbol yet.
+    Undefined,
+    // Every assignment reaching the symbol assigns the same constant.
+    Constant(Literal),
+    // The symbol can hold different values.
+    Overdefined,
+}
+
+impl Lattice {
+    /// Returns the value of a symbol reached by assignments of `self` and
+    /// `other`.
+    fn meet(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Undefined, value) | (value, Self::Undefined) => value,
+            (Self::Constant(a), Self::Constant(b)) if a == b => self,
+            _ => Self::Overdefined,
+        }
+    }
+}
+
+/// Sparse conditional constant propagation pass propagates constants across
+/// basic blocks, following only the edges that can execute.
+///
+/// The IR isn't in SSA form so the analysis keeps the value of every symbol
+/// at the entry of each block and merges them where control flow joins
+/// (Wegman and Zadeck's conditional constant propagation on the CFG). A
+/// branch on a constant only marks the edge it takes as executable, blocks
+/// reached through untaken edges alone never contribute to the joins.
+///
+/// Named variables can be arguments or globals so they start overdefined,
+/// and calls or atomics may write globals so they become overdefined again
+/// after one. Volatile variables are always overdefined.
+///
+/// Temporaries holding constants are rewritten to `const`, named variables
+/// keep their `id` so other passes can still assume `const` only assigns
+/// temporaries. Branches on constants become jumps and the blocks no
+/// executable edge reaches are removed.
+#[derive(Default, Debug)]
+struct SparseConditionalConstantPropagation {
+    mode: ArithmeticMode,
+}
+
+impl SparseConditionalConstantPropagation {
+    /// Returns the value `inst` assigns to its destination in `env`.
+    fn evaluate(
+        &self,
+        inst: &ir::Instruction,
+        env: &HashMap<Symbol, Lattice>,
+    ) -> Lattice {
+        let foldable = matches!(
+            inst,
+            ir::Instruction::Const(..)
+                | ir::Instruction::Id(..)
+                | ir::Instruction::Cast(..)
+                | ir::Instruction::Neg(..)
+                | ir::Instruction::Not(..)
+                | ir::Instruction::Add(..)
+                | ir::Instruction::Sub(..)
+                | ir::Instruction::Mul(..)
+                | ir::Instruction::Div(..)
+                | ir::Instruction::Shl(..)
+                | ir::Instruction::Shr(..)
+                | ir::Instruction::BitAnd(..)
+                | ir::Instruction::And(..)
+                | ir::Instruction::Or(..)
+                | ir::Instruction::Eq(..)
+                | ir::Instruction::Neq(..)
+                | ir::Instruction::Lt(..)
+                | ir::Instruction::Lte(..)
+                | ir::Instruction::Gt(..)
+                | ir::Instruction::Gte(..)
+        );
+        if !foldable {
+            return Lattice::Overdefined;
+        }
+        let mut literals = vec![];
+        for value in inst.uses() {
+            match Self::value(value, env) {
+                Lattice::Constant(literal) => literals.push(literal),
+                value => return value,
+            }
+        }
+        let folded = match (inst, literals.as_slice()) {
+            (
+                ir::Instruction::Const(..) | ir::Instruction::Id(..),
+                [literal],
+            ) => Ok(Some(*literal)),
+            (ir::Instruction::Cast(dst, _), [literal]) => {
+                let to = match dst.t() {
+                    ir::Type::Int => DeclType::Int,
+                    ir::Type::Bool => DeclType::Bool,
+                    ir::Type::Char => DeclType::Char,
+                    ir::Type::Unit => return Lattice::Overdefined,
+                };
+                Ok(Some(conversions::convert(*literal, to)))
+            }
+            (_, [operand]) => {
+                consteval::unary(inst.opcode(), *operand, self.mode)
+            }
+            (_, [lhs, rhs]) => {
+                consteval::binary(inst.opcode(), *lhs, *rhs, self.mode)
+            }
+            _ => return Lattice::Overdefined,
+        };
+        // Overflows are left in place for `InstCombine` to report or trap.
+        match folded {
+            Ok(Some(literal)) => Lattice::Constant(literal),
+            _ => Lattice::Overdefined,
+        }
+    }
+
+    /// Returns the value of `value` in `env`.
+    fn value(value: &ir::Value, env: &HashMap<Symbol, Lattice>) -> Lattice {
+        match value {
+            ir::Value::StorageLocation(symbol) => {
+                env.get(symbol).copied().unwrap_or(Lattice::Undefined)
+            }
+            ir::Value::ConstantLiteral(literal) => Lattice::Constant(*literal),
+        }
+    }
+
+    /// Update `env` past `inst`, `names` are the named variables and
+    /// `volatiles` the volatile ones.
+    fn transfer(
+        &self,
+        inst: &ir::Instruction,
+        env: &mut HashMap<Symbol, Lattice>,
+        names: &HashSet<Symbol>,
+        volatiles: &HashSet<Symbol>,
+    ) {
+        let value = self.evaluate(inst, env);
+        if matches!(
+            inst,
+            ir::Instruction::Call(..)
+                | ir::Instruction::Intrinsic(..)
+                | ir::Instruction::Atomic(..)
+        ) {
+            for name in names {
+                env.insert(name.clone(), Lattice::Overdefined);
+            }
+        }
+        if let Some(dst) = inst.destination() {
+            if !volatiles.contains(dst) {
+                env.insert(dst.clone(), value);
+            }
+        }
+    }
+
+    /// Returns the blocks control can flow to from the end of `block` in
+    /// `env`, only the taken side of branches on constants is followed.
+    /// Branches on undefined values follow both sides so every block left
+    /// unreached is unreachable once branches on constants are rewritten.
+    fn successors(
+        graph: &Graph,
+        block: BlockRef,
+        env: &HashMap<Symbol, Lattice>,
+    ) -> Vec<BlockRef> {
+        match graph.block(block).terminator() {
+            Some(ir::Instruction::Branch(
+                condition,
+                then_label,
+                else_label,
+                _,
+            )) => {
+                let label = match Self::value(condition, env) {
+                    Lattice::Constant(Literal::Bool(true)) => then_label,
+                    Lattice::Constant(Literal::Bool(false)) => else_label,
+                    _ => return graph.successors(block).collect(),
+                };
+                graph
+                    .block_by_label(&label.to_string())
+                    .into_iter()
+                    .collect()
+            }
+            _ => graph.successors(block).collect(),
+        }
+    }
+}
+
+impl Transform for SparseConditionalConstantPropagation {
+    fn run(&self, function: &mut ir::Function) {
+        let graph = Graph::new(&vec![function.clone()]);
+        if graph.is_empty() {
+            return;
+        }
+        let volatiles =
+            function.volatiles().iter().cloned().collect::<HashSet<_>>();
+        let names = function
+            .instructions()
+            .iter()
+            .flat_map(|inst| {
+                inst.destination().into_iter().chain(inst.uses().filter_map(
+                    |value| match value {
+                        ir::Value::StorageLocation(symbol) => Some(symbol),
+                        ir::Value::ConstantLiteral(..) => None,
+                    },
+                ))
+            })
+            .chain(function.args())
+            .filter(|symbol| !symbol.name().starts_with('%'))
+            .cloned()
+            .collect::<HashSet<_>>();
+
+        // Values at the entry of every block reached so far.
+        let mut entries: Vec<Option<HashMap<Symbol, Lattice>>> =
+            vec![None; graph.len()];
+        entries[0] = Some(
+            names
+                .iter()
+                .map(|name| (name.clone(), Lattice::Overdefined))
+                .collect(),
+        );
+        let mut worklist = vec![BlockRef(0)];
+        while let Some(block) = worklist.pop() {
+            let mut env = entries[block.0].clone().unwrap_or_default();
+            for inst in graph.block(block).instructions() {
+                self.transfer(inst, &mut env, &names, &volatiles);
+            }
+            for successor in Self::successors(&graph, block, &env) {
+                let merged = match &entries[successor.0] {
+                    Some(entry) => {
+                        let mut merged = entry.clone();
+                        for (symbol, value) in &env {
+                            let old = merged
+                                .get(symbol)
+                                .copied()
+                                .unwrap_or(Lattice::Undefined);
+                            merged.insert(symbol.clone(), old.meet(*value));
+                        }
+                        merged
+                    }
+                    None => env.clone(),
+                };
+                if entries[successor.0].as_ref() != Some(&merged) {
+                    entries[successor.0] = Some(merged);
+                    worklist.push(successor);
+                }
+            }
+        }
+
+        // Blocks are formed in layout order, rewrite each executable one.
+        let mut start = 0;
+        let insts = function.instructions_mut();
+        for block in graph.blocks() {
+            let range = start..start + graph.block(block).len();
+            start = range.end;
+            let Some(mut env) = entries[block.0].clone() else {
+                insts[range].fill(ir::Instruction::Nop);
+                continue;
+            };
+            for inst in &mut insts[range] {
+                let value = self.evaluate(inst, &env);
+                self.transfer(inst, &mut env, &names, &volatiles);
+                match (&*inst, value) {
+                    (ir::Instruction::Const(..), _) => (),
+                    (
+                        ir::Instruction::Branch(
+                            condition,
+                            then_label,
+                            else_label,
+                            _,
+                        ),
+                        _,
+                    ) => match Self::value(condition, &env) {
+                        Lattice::Constant(Literal::Bool(true)) => {
+                            *inst = ir::Instruction::Jump(*then_label);
+                        }
+                        Lattice::Constant(Literal::Bool(false)) => {
+                            *inst = ir::Instruction::Jump(*else_label);
+                        }
+                        _ => (),
+                    },
+                    (_, Lattice::Constant(literal)) => {
+                        if let Some(dst) = inst.destination() {
+                            if dst.name().starts_with('%') {
+                                *inst = ir::Instruction::Const(
+                                    dst.clone(),
+                                    ir::Value::ConstantLiteral(literal),
+                                );
+                            }
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        function.remove_dead_instructions();
+    }
+}
+
+/// Strength reduction pass replaces multiplications and divisions by powers
+/// of two with shifts.
+///
+/// `x * 2^k` is `x << k` in two's complement, overflows included. Signed
+/// divisions round toward zero while arithmetic shifts round toward minus
+/// infinity, so negative dividends are biased by `2^k - 1` first :
+///
+/// ```text
+/// sign = shr x 31
+/// bias = bitand sign 2^k-1
+/// sum = add x bias
+/// q = shr sum k
+/// ```
+///
+/// Not implemented: remainders by powers of two (a mask corrected for
+/// negative dividends), the language has no `%` operator. Divisions by other
+/// constants need the high multiplication of `consteval::Magic`.
+struct StrengthReduce {}
+
+impl StrengthReduce {
+    /// Returns `k` if `value` is the constant `2^k` with `k >= 1`.
+    fn log2(
+        value: &ir::Value,
+        constants: &HashMap<Symbol, Literal>,
+    ) -> Option<i32> {
+        let constant = match value {
+            ir::Value::StorageLocation(symbol) => constants.get(symbol)?,
+            ir::Value::ConstantLiteral(literal) => literal,
+        };
+        match constant {
+            Literal::Int(c) if *c > 1 && c.count_ones() == 1 => {
+                Some(c.trailing_zeros() as i32)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Transform for StrengthReduce {
+    fn grows_code(&self) -> bool {
+        true
+    }
+
+    fn run(&self, function: &mut ir::Function) {
+        let constants = InstCombine::constants(function);
+        let volatile = |value: &ir::Value| {
+            matches!(value, ir::Value::StorageLocation(symbol)
+                if function.volatiles().contains(symbol))
+        };
+        let int = |value| ir::Value::ConstantLiteral(Literal::Int(value));
+        let mut next_temp = function.next_temp();
+        let mut temp = || {
+            let temp = Symbol::new(&format!("%v{next_temp}"), ir::Type::Int);
+            next_temp += 1;
+            temp
+        };
+        let mut body = Vec::with_capacity(function.len());
+        for inst in function.instructions() {
+            match inst {
+                ir::Instruction::Mul(dst, x, c)
+                | ir::Instruction::Mul(dst, c, x)
+                    if dst.t() == ir::Type::Int
+                        && Self::log2(c, &constants).is_some() =>
+                {
+                    let k = Self::log2(c, &constants).unwrap_or_default();
+                    body.push(ir::Instruction::Shl(
+                        dst.clone(),
+                        x.clone(),
+                        int(k),
+                    ));
+                }
+                // The dividend is read twice, volatile ones are left alone.
+                ir::Instruction::Div(dst, x, c)
+                    if dst.t() == ir::Type::Int
+                        && !volatile(x)
+                        && Self::log2(c, &constants).is_some() =>
+                {
+                    let k = Self::log2(c, &constants).unwrap_or_default();
+                    let (sign, bias, sum) = (temp(), temp(), temp());
+                    body.extend([
+                        ir::Instruction::Shr(sign.clone(), x.clone(), int(31)),
+                        ir::Instruction::BitAnd(
+                            bias.clone(),
+                            ir::Value::StorageLocation(sign),
+                            int((1 << k) - 1),
+                        ),
+                        ir::Instruction::Add(
+                            sum.clone(),
+                            x.clone(),
+                            ir::Value::StorageLocation(bias),
+                        ),
+                        ir::Instruction::Shr(
+                            dst.clone(),
+                            ir::Value::StorageLocation(sum),
+                            int(k),
+                        ),
+                    ]);
+                }
+                _ => body.push(inst.clone()),
+            }
+        }
+        function.splice(0..function.len(), body);
+    }
+}
+
+/// Loop invariant code motion pass hoists the computations whose operands
+/// don't change while a loop runs to a preheader block, executed once before
+/// the loop is entered.
+///
+/// Natural loops are found on the CFG : an edge whose target dominates its
+/// source is a back edge, the loop is its target (the header) and the blocks
+/// reaching the source without going through the header. Only pure
+/// instructions assigning a temporary defined once are hoisted, their
+/// operands must be constants, hoisted temporaries or variables the loop
+/// doesn't assign. Calls and atomics may write globals so loops containing
+/// them only hoist instructions reading constants and temporaries.
+///
+/// Hoisted instructions run even when the loop body wouldn't have reached
+/// them, divisions can trap so they stay in place unless their divisor is a
+/// constant other than 0 and -1.
+struct LoopInvariantCodeMotion {}
+
+impl LoopInvariantCodeMotion {
+    /// Returns the natural loops of `graph` as their header and blocks, loops
+    /// sharing a header are merged and innermost loops come first.
+    fn loops(graph: &Graph) -> Vec<(BlockRef, HashSet<BlockRef>)> {
+        let dominators = graph.dominators();
+        let mut loops: HashMap<BlockRef, HashSet<BlockRef>> = HashMap::new();
+        for latch in graph.rpo() {
+            for header in graph.successors(latch) {
+                if !dominators.dominates(header, latch) {
+                    continue;
+                }
+                let body = loops
+                    .entry(header)
+                    .or_insert_with(|| HashSet::from([header]));
+                let mut worklist = vec![latch];
+                while let Some(block) = worklist.pop() {
+                    if body.insert(block) {
+                        worklist.extend(
+                            graph.predecessors(block).filter(|pred| {
+                                graph.rpo_number(*pred).is_some()
+                            }),
+                        );
+                    }
+                }
+            }
+        }
+        let mut loops = loops.into_iter().collect::<Vec<_>>();
+        loops.sort_by_key(|(header, body)| (body.len(), *header));
+        loops
+    }
+
+    /// Returns `true` if `inst` can be executed before the loop without
+    /// changing the behavior of the program, ignoring its operands.
+    fn hoistable(inst: &ir::Instruction, definitions: usize) -> bool {
+        let Some(dst) = inst.destination() else {
+            return false;
+        };
+        let traps = match inst {
+            ir::Instruction::Div(.., divisor) => !matches!(
+                divisor,
+                ir::Value::ConstantLiteral(Literal::Int(divisor))
+                    if *divisor != 0 && *divisor != -1
+            ),
+            _ => false,
+        };
+        dst.name().starts_with('%')
+            && definitions == 1
+            && !traps
+            && !inst.has_side_effects()
+            && !inst.barrier()
+            && !matches!(inst, ir::Instruction::Atomic(..))
+    }
+
+    /// Hoist the invariant instructions of the loop made of the blocks in
+    /// `body` to a preheader laid out before `header`, returns `true` if any
+    /// instruction moved.
+    fn hoist(
+        function: &mut ir::Function,
+        graph: &Graph,
+        header: BlockRef,
+        body: &HashSet<BlockRef>,
+    ) -> bool {
+        // Blocks are formed in layout order, find the instructions of each.
+        let mut ranges = vec![];
+        for block in graph.blocks() {
+            let start =
+                ranges.last().map_or(0, |range: &Range<usize>| range.end);
+            ranges.push(start..start + graph.block(block).len());
+        }
+        let insts = function.instructions();
+        let ir::Instruction::Label(header_label) =
+            insts[ranges[header.0].start]
+        else {
+            return false;
+        };
+        // The preheader falls through to the header, the block laid out
+        // before it can only do the same if it's outside the loop.
+        if let Some(previous) = header.0.checked_sub(1).map(BlockRef) {
+            let last = &insts[ranges[previous.0].end - 1];
+            let falls_through = !last.no_return()
+                && !matches!(
+                    last.opcode(),
+                    OPCode::Jump | OPCode::Branch | OPCode::Return
+                );
+            if body.contains(&previous) && falls_through {
+                return false;
+            }
+        }
+        let mut indices = body
+            .iter()
+            .flat_map(|block| ranges[block.0].clone())
+            .collect::<Vec<_>>();
+        indices.sort_unstable();
+
+        let mut definitions: HashMap<&Symbol, usize> = HashMap::new();
+        for dst in insts.iter().filter_map(ir::Instruction::destination) {
+            *definitions.entry(dst).or_default() += 1;
+        }
+        let mut assigned = HashSet::new();
+        let mut clobbers = false;
+        for inst in indices.iter().map(|index| &insts[*index]) {
+            assigned.extend(inst.destination());
+            if let ir::Instruction::Atomic(
+                _,
+                _,
+                ir::Value::StorageLocation(variable),
+                ..,
+            ) = inst
+            {
+                assigned.insert(variable);
+            }
+            clobbers |= inst.barrier()
+                || matches!(
+                    inst,
+                    ir::Instruction::Call(..) | ir::Instruction::Atomic(..)
+                );
+        }
+        let volatiles = function.volatiles();
+        let mut hoisted = vec![];
+        let mut invariants = HashSet::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for index in &indices {
+                let inst = &insts[*index];
+                let Some(dst) = inst.destination() else {
+                    continue;
+                };
+                if invariants.contains(dst)
+                    || !Self::hoistable(inst, definitions[dst])
+                {
+                    continue;
+                }
+                let invariant = inst.uses().all(|value| match value {
+                    ir::Value::ConstantLiteral(..) => true,
+                    ir::Value::StorageLocation(symbol) => {
+                        invariants.contains(symbol)
+                            || (!assigned.contains(symbol)
+                                && !volatiles.contains(symbol)
+                                && (symbol.name().starts_with('%')
+                                    || !clobbers))
+                    }
+                });
+                if invariant {
+                    invariants.insert(dst);
+                    hoisted.push(*index);
+                    changed = true;
+                }
+            }
+        }
+        if hoisted.is_empty() {
+            return false;
+        }
+
+        // Entries of the loop branch to the preheader instead of the header.
+        let preheader = function.next_label();
+        let mut entries = ir::Remapper::new();
+        entries.map_label(header_label, preheader);
+        let header_start = ranges[header.0].start;
+        let moved = hoisted.iter().copied().collect::<HashSet<_>>();
+        let mut rewritten = Vec::with_capacity(insts.len() + 1);
+        for (index, inst) in insts.iter().enumerate() {
+            if index == header_start {
+                rewritten.push(ir::Instruction::Label(preheader));
+                rewritten.extend(hoisted.iter().map(|i| insts[*i].clone()));
+            }
+            if moved.contains(&index) {
+                continue;
+            }
+            match indices.binary_search(&index) {
+                Ok(_) => rewritten.push(inst.clone()),
+                Err(_) => rewritten.push(entries.remap(inst.clone())),
+            }
+        }
+        function.splice(0..function.len(), rewritten);
+        true
+    }
+}
+
+impl Transform for LoopInvariantCodeMotion {
+    fn run(&self, function: &mut ir::Function) {
+        // Hoisting changes the layout so loops are found again after every
+        // hoist, invariants of nested loops move out one level at a time.
+        loop {
+            let graph = Graph::new(&vec![function.clone()]);
+            if !graph.is_reducible() {
+                return;
+            }
+            let hoisted = Self::loops(&graph).iter().any(|(header, body)| {
+                Self::hoist(function, &graph, *header, body)
+            });
+            if !hoisted {
+                return;
+            }
+        }
+    }
+}
+
+/// Loop unswitching pass hoists branches on loop invariant conditions out of
+/// loops, the loop is cloned into two versions specialized for either outcome
+/// of the condition and a single branch before the loop selects which one
+/// runs.
+///
+/// Loops are recognized in the linear IR as the instructions between a label
+/// and a backward jump or branch to it, only loops entered through their
+/// header are unswitched. Every clone grows the function by the cost of the
+/// loop so the pass stops once code costing `budget` was added.
+struct LoopUnswitch {
+    // Cost of the code the pass is allowed to add to a function.
+    budget: u32,
+    // Cost model pricing the cloned instructions.
+    cost_model: Box<dyn CostModel>,
+}
+
+impl Default for LoopUnswitch {
+    fn default() -> Self {
+        Self {
+            budget: 256,
+            cost_model: Box::new(GenericCostModel {}),
+        }
+    }
+}
+
+impl LoopUnswitch {
+    /// Returns the labels an instruction transfers control to.
+    fn targets(inst: &ir::Instruction) -> Vec<usize> {
+        match inst {
+            ir::Instruction::Jump(label) => vec![label.index()],
+            ir::Instruction::Branch(_, then_label, else_label, _) => {
+                vec![then_label.index(), else_label.index()]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Returns the ranges of instructions spanning each loop, from the header
+    /// label to the backward jump, innermost loops first.
+    fn loops(function: &ir::Function) -> Vec<RangeInclusive<usize>> {
+        let insts = function.instructions();
+        let headers = insts
+            .iter()
+            .enumerate()
+            .filter_map(|(index, inst)| match inst {
+                ir::Instruction::Label(label) => Some((*label, index)),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        let mut loops = insts
+            .iter()
+            .enumerate()
+            .flat_map(|(index, inst)| {
+                Self::targets(inst)
+                    .into_iter()
+                    .filter_map(|label| headers.get(&label).copied())
+                    .filter(move |header| *header <= index)
+                    .map(move |header| header..=index)
+            })
+            .collect::<Vec<_>>();
+        loops.sort_by_key(|region| region.end() - region.start());
+        loops
+    }
+
+    /// Returns the index of a branch on a loop invariant condition in the
+    /// loop spanning `region` or `None` if the loop can't be unswitched.
+    fn invariant_branch(
+        function: &ir::Function,
+        region: &RangeInclusive<usize>,
+    ) -> Option<usize> {
+        let insts = function.instructions();
+        let body = &insts[region.clone()];
+        // Control can only enter the loop through its header.
+        let labels = body
+            .iter()
+            .skip(1)
+            .filter_map(|inst| match inst {
+                ir::Instruction::Label(label) => Some(*label),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        let entered = insts
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !region.contains(index))
+            .flat_map(|(_, inst)| Self::targets(inst))
+            .any(|label| labels.contains(&label));
+        // Calls and atomics can write the variables the condition reads and
+        // inline assembly is opaque so such loops are left as is.
+        if entered
+            || body.iter().any(|inst| {
+                inst.barrier()
+                    || matches!(
+                        inst,
+                        ir::Instruction::Call(..) | ir::Instruction::Atomic(..)
+                    )
+            })
+        {
+            return None;
+        }
+        let defined = body
+            .iter()
+            .filter_map(ir::Instruction::destination)
+            .collect::<HashSet<_>>();
+        // Temporaries defined in the loop are renamed in the clone, a use
+        // after the loop would only see the definition of one version.
+        let escapes = insts
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !region.contains(index))
+            .flat_map(|(_, inst)| inst.uses())
+            .any(|value| match value {
+                ir::Value::StorageLocation(symbol) => {
+                    symbol.name().starts_with('%') && defined.contains(symbol)
+                }
+                ir::Value::ConstantLiteral(..) => false,
+            });
+        if escapes {
+            return None;
+        }
+        body.iter()
+            .position(|inst| match inst {
+                ir::Instruction::Branch(
+                    ir::Value::StorageLocation(cond),
+                    then_label,
+                    else_label,
+                    _,
+                ) => then_label != else_label && !defined.contains(cond),
+                _ => false,
+            })
+            .map(|index| region.start() + index)
+    }
+
+    /// Clone the loop spanning `region` and specialize both versions for the
+    /// outcome of the branch at `branch`, labels and temporaries defined in
+    /// the loop are renamed in the clone.
+    fn unswitch(
+        function: &mut ir::Function,
+        region: RangeInclusive<usize>,
+        branch: usize,
+    ) {
+        let insts = function.instructions();
+        let ir::Instruction::Branch(cond, then_label, else_label, hint) =
+            insts[branch].clone()
+        else {
+            unreachable!("expected a conditional branch");
+        };
+        let (start, end) = region.clone().into_inner();
+        let remapper = ir::Remapper::fresh(function, start..end + 1);
+        let header = match insts[*region.start()] {
+            ir::Instruction::Label(label) => label,
+            _ => unreachable!("expected loop to start with its header label"),
+        };
+        let specialized = insts[region.clone()].iter().zip(region.clone()).map(
+            |(inst, index)| match index == branch {
+                true => ir::Instruction::Jump(then_label),
+                false => inst.clone(),
+            },
+        );
+        // The branch selecting the specialized loop is placed in a new block
+        // which takes over the entries of the loop header.
+        // Its label follows the ones given to the clone.
+        let preheader = function.next_label()
+            + insts[region.clone()]
+                .iter()
+                .filter(|inst| matches!(inst, ir::Instruction::Label(..)))
+                .count();
+        let guard = ir::Instruction::Branch(
+            cond,
+            ir::Label::new(header),
+            ir::Label::new(remapper.label(header)),
+            hint,
+        );
+        let mut clone = function.clone_region(start..end + 1, &remapper);
+        clone[branch - start] =
+            remapper.remap(ir::Instruction::Jump(else_label));
+        let unswitched = [ir::Instruction::Label(preheader), guard]
+            .into_iter()
+            .chain(specialized)
+            .chain(clone)
+            .collect::<Vec<_>>();
+        let mut entries = ir::Remapper::new();
+        entries.map_label(header, preheader);
+        for (index, inst) in function.instructions_mut().iter_mut().enumerate()
+        {
+            if !region.contains(&index) {
+                *inst = entries.remap(inst.clone());
+            }
+        }
+        function.splice(start..end + 1, unswitched);
+    }
+
+    /// Remove the blocks that became unreachable once the specialized loops
+    /// replaced the invariant branches with jumps.
+    fn remove_unreachable_blocks(function: &mut ir::Function) {
+        let graph = Graph::new(&vec![function.clone()]);
+        let body = graph
+            .blocks()
+            .filter(|block_ref| graph.rpo_number(*block_ref).is_some())
+            .flat_map(|block_ref| graph.block(block_ref).instructions())
+            .cloned()
+            .collect::<Vec<_>>();
+        function.splice(0..function.len(), body);
+    }
+}
+
+impl Transform for LoopUnswitch {
+    fn grows_code(&self) -> bool {
+        true
+    }
+
+    fn run(&self, function: &mut ir::Function) {
+        let mut budget = self.budget;
+        // The clone and the guard branch of the preheader.
+        let cost = |function: &ir::Function, region: &RangeInclusive<usize>| {
+            let insts = &function.instructions()[region.clone()];
+            let guard =
+                insts.iter().find(|inst| inst.opcode() == OPCode::Branch);
+            self.cost_model.cost_of(insts)
+                + guard.map_or(0, |guard| self.cost_model.cost(guard))
+        };
+        // Unswitching renumbers the instructions so loops are recomputed
+        // after every clone, the specialized loops are unswitched in turn
+        // on their remaining invariant branches until the budget runs out.
+        while let Some((region, branch, cost)) =
+            Self::loops(function).into_iter().find_map(|region| {
+                let cost = cost(function, &region);
+                match cost <= budget {
+                    true => Self::invariant_branch(function, &region)
+                        .map(|branch| (region, branch, cost)),
+                    false => None,
+                }
+            })
+        {
+            budget -= cost;
+            Self::unswitch(function, region, branch);
+            Self::remove_unreachable_blocks(function);
+        }
+    }
+}
+
+/// Hot/cold splitting pass moves the blocks that rarely execute (error paths
+/// and traps) to the end of the function so the hot path is laid out
+/// contiguously, blocks are cold when their estimated frequency is below
+/// `threshold` times the entry frequency (see `Graph::frequencies`).
+///
+/// Blocks keep their relative order within the hot and cold parts, blocks
+/// that fell through into a block that moved end with an explicit `jmp`.
+struct HotColdSplitting {
+    // Frequency relative to the entry under which blocks are cold.
+    threshold: f64,
+}
+
+impl Default for HotColdSplitting {
+    fn default() -> Self {
+        Self { threshold: 0.001 }
+    }
+}
+
+impl Transform for HotColdSplitting {
+    // Blocks falling through into moved blocks get a new jump.
+    fn grows_code(&self) -> bool {
+        true
+    }
+
+    fn run(&self, function: &mut ir::Function) {
+        let graph = Graph::new(&vec![function.clone()]);
+        let frequencies = graph.frequencies();
+        // The entry block stays first and unreachable blocks stay in place.
+        let (hot, cold): (Vec<_>, Vec<_>) =
+            graph.blocks().partition(|block_ref| {
+                block_ref.0 == 0
+                    || graph.rpo_number(*block_ref).is_none()
+                    || frequencies[block_ref.0] >= self.threshold
+            });
+        if cold.is_empty() {
+            return;
+        }
+        let layout = hot.into_iter().chain(cold).collect::<Vec<_>>();
+        let mut body = Vec::with_capacity(function.len());
+        for (position, block_ref) in layout.iter().enumerate() {
+            body.extend_from_slice(graph.block(*block_ref).instructions());
+            let fallthrough = graph
+                .succ_edges(*block_ref)
+                .iter()
+                .map(|edge_ref| graph.edge(*edge_ref))
+                .find(|edge| edge.kind() == EdgeKind::Fallthrough)
+                .map(Edge::target);
+            match (fallthrough, layout.get(position + 1)) {
+                (Some(target), Some(next)) if target == *next => (),
+                (Some(target), _) => match graph.block(target).leader() {
+                    Some(ir::Instruction::Label(label)) => {
+                        body.push(ir::Instruction::Jump(ir::Label::new(
+                            *label,
+                        )));
+                    }
+                    _ => unreachable!("expected fallthrough target label"),
+                },
+                (None, _) => (),
+            }
+        }
+        function.splice(0..function.len(), body);
+    }
+}
+
+/// Value a function returns on every path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReturnValue {
+    // The function always returns the same constant.
+    Constant(Literal),
+    // The function always returns its argument at this position unchanged.
+    Argument(usize),
+}
+
+/// Return value propagation pass replaces the result of calls to functions
+/// that provably always return a constant or one of their arguments with
+/// that value.
+///
+/// Calls to pure functions (no side effects and no writes to globals) are
+/// removed, other calls are kept for their side effects but their result
+/// is no longer used. Uses of the result are left for `InstCombine` and
+/// `DCE` to clean up.
+struct ReturnValuePropagation {}
+
+impl ReturnValuePropagation {
+    /// Returns the value `function` returns on every path if it's the same
+    /// constant or argument on all of them.
+    fn summarize(function: &ir::Function) -> Option<ReturnValue> {
+        // Temporaries are assigned once so their definition holds for all
+        // their uses.
+        let definitions = function
+            .instructions()
+            .iter()
+            .filter_map(|inst| match inst.destination() {
+                Some(dst) if dst.name().starts_with('%') => Some((dst, inst)),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        let assigned = function
+            .instructions()
+            .iter()
+            .filter_map(ir::Instruction::destination)
+            .collect::<HashSet<_>>();
+        // Resolve the returned values through the temporaries holding them.
+        let mut returns = function
+            .instructions()
+            .iter()
+            .filter_map(|inst| match inst {
+                ir::Instruction::Return(value) => Some(value),
+                _ => None,
+            })
+            .map(|mut value| loop {
+                match value {
+                    ir::Value::ConstantLiteral(Literal::Empty) => return None,
+                    ir::Value::ConstantLiteral(literal) => {
+                        return Some(ReturnValue::Constant(*literal))
+                    }
+                    ir::Value::StorageLocation(symbol) => {
+                        match definitions.get(symbol) {
+                            Some(
+                                ir::Instruction::Const(_, operand)
+                                | ir::Instruction::Id(_, operand),
+                            ) => value = operand,
+                            Some(_) => return None,
+                            None if assigned.contains(symbol) => return None,
+                            None => {
+                                return function
+                                    .args()
+                                    .iter()
+                                    .position(|arg| arg == symbol)
+                                    .map(ReturnValue::Argument)
+                            }
+                        }
+                    }
+                }
+            });
+        let first = returns.next()??;
+        returns.all(|value| value == Some(first)).then_some(first)
+    }
+
+    /// Returns the names of the functions without side effects, functions
+    /// only calling pure functions are pure. Accesses to `volatile`
+    /// variables are observable, so functions reading them aren't pure.
+    fn pure_functions<'a>(
+        functions: &'a [ir::Function],
+        globals: &[ir::GlobalValue],
+    ) -> HashSet<&'a str> {
+        let globals = globals
+            .iter()
+            .map(|global| global.symbol().name())
+            .collect::<HashSet<_>>();
+        let mut pure = functions
+            .iter()
+            .filter(|function| {
+                let volatile =
+                    |symbol: &Symbol| function.volatiles().contains(symbol);
+                function.instructions().iter().all(|inst| {
+                    (matches!(inst, ir::Instruction::Call(..))
+                        || !inst.has_side_effects())
+                        && inst.destination().is_none_or(|dst| {
+                            !globals.contains(dst.name()) && !volatile(dst)
+                        })
+                        && !inst.uses().any(|value| {
+                            matches!(value, ir::Value::StorageLocation(symbol)
+                                if volatile(symbol))
+                        })
+                })
+            })
+            .map(ir::Function::name)
+            .collect::<HashSet<_>>();
+        // Remove the functions calling impure functions until none is left.
+        loop {
+            let impure = functions
+                .iter()
+                .filter(|function| pure.contains(function.name()))
+                .filter(|function| {
+                    function.instructions().iter().any(|inst| match inst {
+                        ir::Instruction::Call(_, callee, _) => {
+                            !pure.contains(callee.name())
+                        }
+                        _ => false,
+                    })
+                })
+                .map(ir::Function::name)
+                .collect::<Vec<_>>();
+            if impure.is_empty() {
+                return pure;
+            }
+            for name in impure {
+                pure.remove(name);
+            }
+        }
+    }
+}
+
+impl ModuleTransform for ReturnValuePropagation {
+    fn run(&self, functions: &mut [ir::Function], globals: &[ir::GlobalValue]) {
+        let summaries = functions
+            .iter()
+            .filter_map(|function| {
+                Self::summarize(function)
+                    .map(|value| (function.name().to_string(), value))
+            })
+            .collect::<HashMap<_, _>>();
+        let pure = Self::pure_functions(functions, globals)
+            .into_iter()
+            .map(str::to_string)
+            .collect::<HashSet<_>>();
+        for function in functions.iter_mut() {
+            let mut next_temp = function.next_temp();
+            let mut body = Vec::with_capacity(function.len());
+            for inst in function.instructions() {
+                let (dst, callee, args) = match inst {
+                    ir::Instruction::Call(dst, callee, args)
+                        if dst.t() != ir::Type::Unit
+                            && summaries.contains_key(callee.name()) =>
+                    {
+                        (dst, callee, args)
+                    }
+                    _ => {
+                        body.push(inst.clone());
+                        continue;
+                    }
+                };
+                let value = match summaries[callee.name()] {
+                    ReturnValue::Constant(literal) => {
+                        ir::Value::ConstantLiteral(literal)
+                    }
+                    ReturnValue::Argument(index) => args[index].clone(),
+                };
+                // Impure calls are kept for their side effects, their
+                // result is assigned to a fresh temporary left unused.
+                if !pure.contains(callee.name()) {
+                    let unused =
+                        Symbol::new(&format!("%v{next_temp}"), dst.t());
+                    next_temp += 1;
+                    body.push(ir::Instruction::Call(
+                        unused,
+                        callee.clone(),
+                        args.clone(),
+                    ));
+                }
+                body.push(match value {
+                    ir::Value::ConstantLiteral(..) => {
+                        ir::Instruction::Const(dst.clone(), value)
+                    }
+                    ir::Value::StorageLocation(..) => {
+                        ir::Instruction::Id(dst.clone(), value)
+                    }
+                });
+            }
+            function.splice(0..function.len(), body);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Attribute;
+    use crate::builtins::Builtin;
+    use crate::consteval::ArithmeticMode;
+    use crate::conversions;
+    use crate::ir::IRBuilder;
+    use crate::ir::{Instruction, Literal, Symbol, Type, Value};
+    use crate::optim::{
+        CanonicalizeBranches, ConstantPooling, CopyPropagation,
+        GlobalValueNumbering, HotColdSplitting, Identity, InstCombine,
+        LoopInvariantCodeMotion, LoopUnswitch, ModulePassManager,
+        ModuleTransform, OptLevel, PassManager, PassRegistry, Reassociate,
+        ReturnValuePropagation, SparseConditionalConstantPropagation,
+        StrengthReduce, StripAssumptions, Transform, DCE, LVN,
+    };
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::sema::analyze;
+    // Macro to generate test cases.
+    macro_rules! test_optimization_pass {
+        ($name:ident, $source:expr, $expected:expr) => {
+            test_optimization_pass!($name, Identity {}, $source, $expected);
+        };
+        ($name:ident, $pass:expr, $source:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                let source = $source;
+                let mut scanner = Scanner::new(source);
+                let tokens = scanner
+                    .scan()
+                    .expect("expected test case source to be valid");
+                let mut parser = Parser::new(&tokens);
+                parser.parse();
+                let symbol_table = analyze(parser.ast());
+
+                let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+                irgen.build();
+
+                let pass = $pass;
+                let dce = DCE {};
+
+                for func in irgen.functions_mut() {
+                    pass.run(func);
+                    dce.run(func);
+                }
+
+                let mut actual = "".to_string();
+                for func in irgen.functions() {
+                    actual.push_str(format!("{func}").as_str());
+                }
+                // For readability trim the newlines at the start and end
+                // of our IR text fixture.
+                let expected = $expected
+                    .strip_suffix("\n")
+                    .and($expected.strip_prefix("\n"));
+                assert_eq!(actual, expected.unwrap())
+            }
+        };
+    }
+
+    test_optimization_pass!(
+        can_do_nothing_on_input_program,
+        r#"
+            int main() {
+                return 42;
+            }
+        "#,
+        r#"
+@main: int {
+   %v0: int = const 42
+   ret %v0
+}
+"#
+    );
+
+    test_optimization_pass!(
+        can_trivially_dce_single_dead_store,
+        r#"
+            int main() {
+                int a = 4;
+                int b = 2;
+                int c = 1;
+                int d = a + b;
+                return d;
+            }
+        "#,
+        r#"
+@main: int {
+   %v0: int = const 4
+   a: int = id %v0
+   %v1: int = const 2
+   b: int = id %v1
+   %v3: int = add a b
+   d: int = id %v3
+   ret d
+}
+"#
+    );
+
+    test_optimization_pass!(
+        can_trivially_dce_multiple_dead_stores,
+        r#"
+            int main() {
+                int a = 42;
+                int b = 313;
+                int c = 212;
+                int d = 111;
+                int e = 414;
+                int f = 515;
+                int g = 616;
+                return a;
+            }
+        "#,
+        r#"
+@main: int {
+   %v0: int = const 42
+   a: int = id %v0
+   ret a
+}
+"#
+    );
+
+    test_optimization_pass!(
+        can_trivially_dce_add_unused_result,
+        r#"
+            int main() {
+                int a = 1;
+                int b = 2;
+                int c = a + b;
+                int d = a + b;
+                return d;
+            }
+        "#,
+        r#"
+@main: int {
+   %v0: int = const 1
+   a: int = id %v0
+   %v1: int = const 2
+   b: int = id %v1
+   %v3: int = add a b
+   d: int = id %v3
+   ret d
+}
+"#
+    );
+
+    test_optimization_pass!(
+        can_trivially_dce_dead_store_and_unused_add_result,
+        r#"
+            int main() {
+                int a = 4;
+                int b = 2;
+                int c = 1;
+                int d = a + b;
+                int e = c + d;
+                return d;
+            }
+        "#,
+        r#"
+@main: int {
+   %v0: int = const 4
+   a: int = id %v0
+   %v1: int = const 2
+   b: int = id %v1
+   %v3: int = add a b
+   d: int = id %v3
+   ret d
+}
+"#
+    );
+
+    test_optimization_pass!(
+        can_trivially_dce_dead_store_in_different_blocks,
+        r#"
+            int main() {
+                int a = 4;
+                int b = 2;
+                int c = 0;
+                if (a < b) {
+                    int c = a + b;
+                } else {
+                    int d = a - b;
+                }
+                return c;
+            }
+        "#,
+        r#"
+@main: int {
+   %v0: int = const 4
+   a: int = id %v0
+   %v1: int = const 2
+   b: int = id %v1
+   %v2: int = const 0
+   c: int = id %v2
+   %v3: bool = lt a b
+   br %v3 .LABEL_0 .LABEL_1
+   .LABEL_0
+   jmp .LABEL_2
+   .LABEL_1
+   jmp .LABEL_2
+   .LABEL_2
+   ret c
+}
+"#
+    );
+
+    test_optimization_pass!(
+        can_preserve_inline_assembly,
+        r#"
+            int main() {
+                int a = 1;
+                asm("nop");
+                return 0;
+            }
+        "#,
+        r#"
+@main: int {
+   asm "nop"
+   %v1: int = const 0
+   ret %v1
+}
+"#
+    );
+
+    test_optimization_pass!(
+        can_simplify_algebraic_identities,
+        InstCombine::default(),
+        r#"
+            int f(int x) {
+                int a = x + 0;
+                int b = 1 * a;
+                int c = b * 0;
+                int d = x - x;
+                return -(-a) + c + d;
+            }
+        "#,
+        r#"
+@f(x: int): int {
+   %v1: int = id x
+   a: int = id %v1
+   %v5: int = const 0
+   c: int = id %v5
+   %v6: int = const 0
+   d: int = id %v6
+   %v8: int = id a
+   %v9: int = add %v8 c
+   %v10: int = add %v9 d
+   ret %v10
+}
+"#
+    );
+
+    test_optimization_pass!(
+        can_number_local_values,
+        LVN {},
+        r#"
+            int f(int x, int y) {
+                int a = x + y;
+                int b = y + x;
+                int c = a;
+                int d = c * b;
+                x = 1;
+                int e = x + y;
+                return d + e;
+            }
+        "#,
+        r#"
+@f(x: int, y: int): int {
+   %v0: int = add x y
+   %v2: int = mul %v0 %v0
+   %v3: int = const 1
+   %v4: int = add %v3 y
+   %v5: int = add %v2 %v4
+   ret %v5
+}
+"#
+    );
+
+    test_optimization_pass!(
+        can_number_values_across_blocks,
+        GlobalValueNumbering {},
+        r#"
+            int f(int a, int b, bool c) {
+                int x = a * b;
+                if (c) {
+                    x = x + a * b;
+                } else {
+                    x = x - a * b;
+                }
+                return x + a * b;
+            }
+        "#,
+        r#"
+@f(a: int, b: int, c: bool): int {
+   %v0: int = mul a b
+   x: int = id %v0
+   br c .LABEL_0 .LABEL_1
+   .LABEL_0
+   %v1: int = id %v0
+   %v2: int = add x %v1
+   x: int = id %v2
+   jmp .LABEL_2
+   .LABEL_1
+   %v3: int = id %v0
+   %v4: int = sub x %v3
+   x: int = id %v4
+   jmp .LABEL_2
+   .LABEL_2
+   %v5: int = id %v0
+   %v6: int = add x %v5
+   ret %v6
+}
+"#
+    );
+
+    test_optimization_pass!(
+        can_only_reuse_values_of_dominating_blocks,
+        GlobalValueNumbering {},
+        r#"
+            int g(int a, bool c) {
+                int y = 0;
+                if (c) {
+                    y = a + 1;
+                } else {
+                    y = a + 1;
+                }
+                a = y;
+                return y + (a + 1);
+            }
+        "#,
+        r#"
+@g(a: int, c: bool): int {
+   br c .LABEL_0 .LABEL_1
+   .LABEL_0
+   %v1: int = const 1
+   %v2: int = add a %v1
+   y: int = id %v2
+   jmp .LABEL_2
+   .LABEL_1
+   %v3: int = const 1
+   %v4: int = add a %v3
+   y: int = id %v4
+   jmp .LABEL_2
+   .LABEL_2
+   a: int = id y
+   %v5: int = const 1
+   %v6: int = add a %v5
+   %v7: int = add y %v6
+   ret %v7
+}
+"#
+    );
+
+    test_optimization_pass!(
+        can_propagate_copies_across_blocks,
+        CopyPropagation {},
+        r#"
+            int f(int a, bool c) {
+                int x = a;
+                int y = 0;
+                if (c) {
+                    y = x + 1;
+                } else {
+                    y = x + 2;
+                    a = 3;
+                }
+                return x + y + a;
+            }
+        "#,
+        r#"
+@f(a: int, c: bool): int {
+   x: int = id a
+   br c .LABEL_0 .LABEL_1
+   .LABEL_0
+   %v1: int = const 1
+   %v2: int = add a %v1
+   y: int = id %v2
+   jmp .LABEL_2
+   .LABEL_1
+   %v3: int = const 2
+   %v4: int = add a %v3
+   y: int = id %v4
+   %v5: int = const 3
+   a: int = id %v5
+   jmp .LABEL_2
+   .LABEL_2
+   %v6: int = add x y
+   %v7: int = add %v6 a
+   ret %v7
+}
+"#
+    );
+
+    test_optimization_pass!(
+        can_fold_constant_chains,
+        InstCombine::default(),
+        r#"
+            int f(int x) {
+                return ((x + 1) - 3) * 2 * 4;
+            }
+        "#,
+        r#"
+@f(x: int): int {
+   %v8: int = const -2
+   %v3: int = add x %v8
+   %v9: int = const 8
+   %v7: int = mul %v3 %v9
+   ret %v7
+}
+"#
+    );
+
+    #[test]
+    fn can_pool_constants() {
+        let source = r#"
+            int main() {
+                int a = 1;
+                int b = 0;
+                if (a > 0) {
+                    b = a + 1;
+                }
+                return b + 0;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let pooling = ConstantPooling {};
+        let main = &mut irgen.functions_mut()[0];
+        pooling.run(main);
+
+        let expected = r#"@main: int {
+   %v0: int = const 1
+   %v1: int = const 0
+   a: int = id %v0
+   b: int = id %v1
+   %v3: bool = gt a %v1
+   br %v3 .LABEL_0 .LABEL_1
+   .LABEL_0
+   %v5: int = add a %v0
+   b: int = id %v5
+   jmp .LABEL_2
+   .LABEL_1
+   jmp .LABEL_2
+   .LABEL_2
+   %v7: int = add b %v1
+   ret %v7
+}
+"#;
+        assert_eq!(main.to_string(), expected);
+        assert_eq!(main.constants().len(), 2);
+
+        let zero = Symbol::new("%v1", Type::Int);
+        assert_eq!(
+            main.constants().rematerialize(&zero),
+            Some(Instruction::Const(
+                zero.clone(),
+                Value::ConstantLiteral(Literal::Int(0))
+            ))
+        );
+        assert_eq!(
+            main.constants().rematerialize(&Symbol::new("a", Type::Int)),
+            None
+        );
+        // Pooling is idempotent.
+        let pooled = main.clone();
+        pooling.run(main);
+        assert_eq!(*main, pooled);
+    }
+
+    #[test]
+    fn can_keep_static_local_guards_at_o2() {
+        let source = r#"
+            int counter(int s) {
+                static int n = s;
+                n = n + 1;
+                return n;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let counter = &mut irgen.functions_mut()[0];
+        PassManager::o2().run(counter);
+        // The guard is only set once the initializer ran.
+        let insts = counter.instructions();
+        let guard = |inst: &Instruction| matches!(inst, Instruction::Const(dst, _) if dst.name() == "counter.n.guard");
+        let branch =
+            |inst: &Instruction| matches!(inst, Instruction::Branch(..));
+        let set = insts.iter().position(guard).expect("guard is set");
+        let check = insts.iter().position(branch).expect("guard is checked");
+        assert!(check < set);
+        assert!(counter
+            .constants()
+            .iter()
+            .all(|(symbol, _)| { symbol.name().starts_with('%') }));
+    }
+
+    #[test]
+    fn can_fold_constant_intrinsics() {
+        let source = r#"
+            int main() {
+                int a = __builtin_min(__builtin_abs(3), 2);
+                int b = __builtin_abs(a);
+                return b;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let main = &mut irgen.functions_mut()[0];
+        InstCombine::default().run(main);
+        DCE {}.run(main);
+
+        // `__builtin_abs(a)` reads a variable so it's left as is.
+        let expected = r#"@main: int {
+   %v3: int = const 2
+   a: int = id %v3
+   %v4: int = intrinsic @__builtin_abs a
+   b: int = id %v4
+   ret b
+}
+"#;
+        assert_eq!(main.to_string(), expected);
+    }
+
+    #[test]
+    fn can_fold_constant_arithmetic() {
+        let source = r#"
+            int main() {
+                int a = 2 * 3 + -1;
+                bool b = 2147483647 + 1 < 0;
+                int c = 1 / 0;
+                return a;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let main = &mut irgen.functions_mut()[0];
+        InstCombine::default().run(main);
+        let folded = main
+            .instructions()
+            .iter()
+            .filter(|inst| matches!(inst, Instruction::Const(..)))
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert!(folded.contains(&"%v5: int = const 5".to_string()));
+        // Overflow wraps around by default.
+        assert!(folded.contains(&"%v10: bool = const true".to_string()));
+        // Division by zero is left to the program.
+        assert!(main
+            .instructions()
+            .iter()
+            .any(|inst| matches!(inst, Instruction::Div(..))));
+    }
+
+    #[test]
+    #[should_panic(expected = "signed overflow evaluating `add 2147483647 1`")]
+    fn can_report_overflow_in_checked_mode() {
+        let source = r#"
+            int main() {
+                int a = 2147483647 + 1;
+                return a;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let main = &mut irgen.functions_mut()[0];
+        InstCombine {
+            mode: ArithmeticMode::Checked,
+        }
+        .run(main);
+    }
+
+    #[test]
+    fn can_keep_stores_to_globals() {
+        let source = r#"
+            int g = 0;
+            int set(int v) {
+                g = v;
+                g = v + 1;
+                return 0;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let globals = irgen.globals().clone();
+        ModulePassManager::new()
+            .with_function_pipeline(PassManager::new().with_pass(DCE {}))
+            .run(irgen.functions_mut(), &globals);
+        // The first store is overwritten, the last one is read by callers.
+        let expected = r#"@set(v: int): int {
+   %v1: int = const 1
+   %v2: int = add v %v1
+   g: int = id %v2
+   %v3: int = const 0
+   ret %v3
+}
+"#;
+        assert_eq!(irgen.functions()[0].to_string(), expected);
+    }
+
+    #[test]
+    fn can_prune_code_after_exit() {
+        let source = "int main() { int x = 3; if (x > 1) { exit(2); } exit(x); return x; }";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let mut function = irgen.functions()[0].clone();
+        DCE {}.run(&mut function);
+        assert_eq!(
+            function.to_string(),
+            "@main: int {\n   %v0: int = const 3\n   x: int = id %v0\n   \
+             %v1: int = const 1\n   %v2: bool = gt x %v1\n   \
+             br %v2 .LABEL_0 .LABEL_1\n   .LABEL_0\n   %v3: int = const 2\n   \
+             exit %v3\n   .LABEL_1\n   jmp .LABEL_2\n   .LABEL_2\n   exit x\n}\n"
+        );
+        let graph = crate::cfg::Graph::new(&vec![function]);
+        let exit = graph.block_by_label(".LABEL_0").unwrap();
+        assert_eq!(graph.successors(exit).count(), 0);
+    }
+
+    #[test]
+    fn can_keep_volatile_accesses() {
+        let source = r"
+            volatile int status = 0;
+            int main() {
+                volatile int x = 1;
+                int y = x + x;
+                y = status;
+                status = 2;
+                return 0;
+            }
+        ";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let mut function = irgen.functions()[0].clone();
+        PassManager::oz().run(&mut function);
+        // Dead reads and writes of volatile variables are kept.
+        assert_eq!(
+            function.to_string(),
+            "@main: int {\n   %v1: int = const 1\n   %v3: int = const 2\n   \
+             %v4: int = const 0\n   x: int = id %v1\n   \
+             %v2: int = add x x\n   y: int = id status\n   \
+             status: int = id %v3\n   ret %v4\n}\n"
+        );
+    }
+
+    #[test]
+    fn can_trap_on_overflowing_arithmetic() {
+        let source = "int main() { int x = 2147483647 + 1; return x; }";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let mut function = irgen.functions()[0].clone();
+        PassManager::with_arithmetic(OptLevel::Oz, ArithmeticMode::Trapping)
+            .run(&mut function);
+        // The return following the trap is unreachable.
+        assert_eq!(
+            function.to_string(),
+            "@main: int {\n   \
+             trap \"signed overflow evaluating `add 2147483647 1`\"\n}\n"
+        );
+    }
+
+    /// Deterministic xorshift generator driving the random program tests.
+    struct Rng(u32);
+
+    impl Rng {
+        fn next(&mut self, bound: u32) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0 % bound
+        }
+    }
+
+    /// Returns a random arithmetic expression over literals and `vars` along
+    /// with its value, evaluated with wrapping arithmetic.
+    fn random_expr(
+        rng: &mut Rng,
+        vars: &[(String, i32)],
+        depth: u32,
+    ) -> (String, i32) {
+        if depth == 0 || rng.next(4) == 0 {
+            if !vars.is_empty() && rng.next(2) == 0 {
+                return vars[rng.next(vars.len() as u32) as usize].clone();
+            }
+            let value = rng.next(1000) as i32;
+            return (value.to_string(), value);
+        }
+        let (lhs, a) = random_expr(rng, vars, depth - 1);
+        let (rhs, b) = random_expr(rng, vars, depth - 1);
+        match rng.next(4) {
+            0 => (format!("({lhs} + {rhs})"), a.wrapping_add(b)),
+            1 => (format!("({lhs} - {rhs})"), a.wrapping_sub(b)),
+            2 => (format!("({lhs} * {rhs})"), a.wrapping_mul(b)),
+            _ => (format!("(-{lhs})"), a.wrapping_neg()),
+        }
+    }
+
+    #[test]
+    fn can_preserve_random_programs() {
+        let registry = PassRegistry::default();
+        let mut rng = Rng(0x2545_f491);
+        for _ in 0..64 {
+            // Every function pass keeps the CFG of straight line code and
+            // branches valid.
+            let mut body = String::new();
+            let mut vars = vec![];
+            for i in 0..rng.next(4) + 1 {
+                let (expr, value) = random_expr(&mut rng, &vars, 3);
+                body.push_str(&format!("int x{i} = {expr};\n"));
+                vars.push((format!("x{i}"), value));
+                if rng.next(2) == 0 {
+                    let (cond, _) = random_expr(&mut rng, &vars, 2);
+                    body.push_str(&format!(
+                        "if ({cond} < x{i}) {{ x{i} = {cond}; }}\n"
+                    ));
+                }
+            }
+            let source = format!("int main() {{\n{body}return x0;\n}}");
+            let tokens = Scanner::new(&source).scan().unwrap();
+            let mut parser = Parser::new(&tokens);
+            parser.parse();
+            let symbol_table = analyze(parser.ast());
+            let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+            irgen.build();
+            for name in registry
+                .names()
+                .filter(|name| registry.create(name).is_ok())
+            {
+                let mut function = irgen.functions()[0].clone();
+                registry.create(name).unwrap().run(&mut function);
+                let graph = crate::cfg::Graph::new(&vec![function.clone()]);
+                assert!(graph.validate(&function).is_ok(), "{name}: {source}");
+            }
+
+            // Constant expressions fold to their wrapped value.
+            let (expr, value) = random_expr(&mut rng, &[], 4);
+            let source = format!("int main() {{ return {expr}; }}");
+            let tokens = Scanner::new(&source).scan().unwrap();
+            let mut parser = Parser::new(&tokens);
+            parser.parse();
+            let symbol_table = analyze(parser.ast());
+            let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+            irgen.build();
+            let mut function = irgen.functions()[0].clone();
+            PassManager::oz().run(&mut function);
+            assert!(
+                function.instructions().iter().any(|inst| matches!(
+                    inst,
+                    Instruction::Const(_, Value::ConstantLiteral(Literal::Int(folded)))
+                        if *folded == value
+                )),
+                "{source}: {function}"
+            );
+        }
+    }
+
+    #[test]
+    fn can_compile_deterministically() {
+        // Hash maps are seeded differently on every instantiation, compiling
+        // the same program twice must still emit the same code.
+        let corpus = [
+            r"
+            int sum(int a, int b, int c, int d) { return a + b + c + d; }
+            int main() { return sum(1, 2, 3, 4) * 2; }
+            ",
+            r"
+            int main() {
+                int x = 3;
+                int y = 0;
+                int i = 0;
+                for (i = 0; i < 10; i = i + 1) {
+                    if (i < x) { y = y + i * 4; } else { y = y - 1; }
+                }
+                return y + 2 * 3;
+            }
+            ",
+            r#"
+            int main() {
+                static int counter = 0;
+                int a = 1 + 2;
+                switch (a) {
+                    case 1: a = 2;
+                    case 3: a = 4; break;
+                    default: a = 0;
+                }
+                printf("%d", a);
+                return a * 8 + counter;
             }
+            "#,
+        ];
+        let registry = PassRegistry::default();
+        let compile = |source: &str| {
+            let tokens = Scanner::new(source).scan().unwrap();
+            let mut parser = Parser::new(&tokens);
+            parser.parse();
+            let symbol_table = analyze(parser.ast());
+            let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+            irgen.build();
+            let mut program = irgen.into_program();
+            let globals = program.globals().to_vec();
+            registry
+                .parse_pipeline("return-value-propagation")
+                .unwrap()
+                .run(program.functions_mut(), &globals);
+            let mut output = program.to_string();
+            for function in program.functions_mut() {
+                PassManager::oz().run(function);
+                for name in registry
+                    .names()
+                    .filter(|name| registry.create(name).is_ok())
+                {
+                    registry.create(name).unwrap().run(function);
+                }
+            }
+            output.push_str(&program.to_string());
+            output.push_str(&crate::decompile::print(
+                &crate::decompile::decompile(&program),
+            ));
+            output
         };
+        for source in corpus {
+            assert_eq!(compile(source), compile(source), "{source}");
+        }
     }
 
-    test_optimization_pass!(
-        can_do_nothing_on_input_program,
-        r#"
+    #[test]
+    fn can_bound_code_growth() {
+        let source = r#"
             int main() {
-                return 42;
+                int n = 10;
+                bool flag = true;
+                int s = 0;
+                int i = 0;
+                for (i = 0; i < n; i = i + 1) {
+                    if (flag) {
+                        s = s + i;
+                    } else {
+                        s = s - i;
+                    }
+                }
+                return s;
             }
-        "#,
-        r#"
-@main: int {
-   %v0: int = const 42
-   ret %v0
-}
-"#
-    );
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let main = &mut irgen.functions_mut()[0];
+        let original = main.clone();
+        // Unswitching duplicates the loop, more than doubling the function.
+        PassManager::new()
+            .with_pass(LoopUnswitch::default())
+            .with_max_growth(1)
+            .run(main);
+        assert_eq!(*main, original);
+
+        PassManager::new()
+            .with_pass(LoopUnswitch::default())
+            .with_max_growth(4)
+            .run(main);
+        assert!(main.code_size() > original.code_size());
+    }
 
     test_optimization_pass!(
-        can_trivially_dce_single_dead_store,
+        can_hoist_loop_invariant_code,
+        LoopInvariantCodeMotion {},
         r#"
             int main() {
-                int a = 4;
-                int b = 2;
-                int c = 1;
-                int d = a + b;
-                return d;
+                int i = 0;
+                int x = 0;
+                int n = 10;
+                while (i < n) {
+                    x = x + n * 2;
+                    i = i + 1;
+                }
+                return x;
             }
         "#,
         r#"
 @main: int {
-   %v0: int = const 4
-   a: int = id %v0
-   %v1: int = const 2
-   b: int = id %v1
-   %v3: int = add a b
-   d: int = id %v3
-   ret d
+   %v0: int = const 0
+   i: int = id %v0
+   %v1: int = const 0
+   x: int = id %v1
+   %v2: int = const 10
+   n: int = id %v2
+   %v3: bool = lt i n
+   br %v3 .LABEL_2 .LABEL_1
+   .LABEL_2
+   %v4: int = const 2
+   %v5: int = mul n %v4
+   %v7: int = const 1
+   .LABEL_0
+   %v6: int = add x %v5
+   x: int = id %v6
+   %v8: int = add i %v7
+   i: int = id %v8
+   %v9: bool = lt i n
+   br %v9 .LABEL_0 .LABEL_1
+   .LABEL_1
+   ret x
 }
 "#
     );
 
+    #[test]
+    fn can_fold_char_conversions() {
+        let source =
+            "int main() { int n = 'a' + 1; bool b = 'a' < 'b'; if (b) { return n; } return 0; }";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        conversions::insert_casts(parser.ast_mut());
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let function = &mut irgen.functions_mut()[0];
+        InstCombine::default().run(function);
+        DCE {}.run(function);
+        // `'a' + 1` and `'a' < 'b'` fold through the inserted casts.
+        let folded = function.to_string();
+        assert!(folded.contains("%v3: int = const 98\n"), "{folded}");
+        assert!(folded.contains("%v6: bool = const true\n"), "{folded}");
+    }
+
     test_optimization_pass!(
-        can_trivially_dce_multiple_dead_stores,
+        can_propagate_constants_across_blocks,
+        SparseConditionalConstantPropagation::default(),
         r#"
             int main() {
-                int a = 42;
-                int b = 313;
-                int c = 212;
-                int d = 111;
-                int e = 414;
-                int f = 515;
-                int g = 616;
-                return a;
+                int a = 4;
+                int b = 0;
+                int i = 0;
+                if (a > 2) {
+                    b = a * 2;
+                } else {
+                    b = 1;
+                }
+                while (i < b) {
+                    a = 4;
+                    i = i + 1;
+                }
+                return a + b;
             }
         "#,
         r#"
 @main: int {
-   %v0: int = const 42
-   a: int = id %v0
-   ret a
+   %v2: int = const 0
+   i: int = id %v2
+   jmp .LABEL_0
+   .LABEL_0
+   %v6: int = const 8
+   b: int = id %v6
+   jmp .LABEL_2
+   .LABEL_2
+   jmp .LABEL_3
+   .LABEL_3
+   %v10: int = const 1
+   %v11: int = add i %v10
+   i: int = id %v11
+   %v12: bool = lt i b
+   br %v12 .LABEL_3 .LABEL_4
+   .LABEL_4
+   %v13: int = const 12
+   ret %v13
 }
 "#
     );
 
     test_optimization_pass!(
-        can_trivially_dce_add_unused_result,
+        can_strength_reduce_powers_of_two,
+        StrengthReduce {},
         r#"
             int main() {
-                int a = 1;
-                int b = 2;
-                int c = a + b;
-                int d = a + b;
+                int a = 7;
+                int b = 8 * a;
+                int c = b / 4;
+                int d = c / 3;
                 return d;
             }
         "#,
         r#"
 @main: int {
-   %v0: int = const 1
+   %v0: int = const 7
    a: int = id %v0
-   %v1: int = const 2
-   b: int = id %v1
-   %v3: int = add a b
-   d: int = id %v3
+   %v2: int = shl a 3
+   b: int = id %v2
+   %v7: int = shr b 31
+   %v8: int = bitand %v7 3
+   %v9: int = add b %v8
+   %v4: int = shr %v9 2
+   c: int = id %v4
+   %v5: int = const 3
+   %v6: int = div c %v5
+   d: int = id %v6
    ret d
 }
 "#
     );
 
     test_optimization_pass!(
-        can_trivially_dce_dead_store_and_unused_add_result,
+        can_hoist_loop_invariant_code_from_nested_loops,
+        LoopInvariantCodeMotion {},
         r#"
             int main() {
-                int a = 4;
-                int b = 2;
-                int c = 1;
-                int d = a + b;
-                int e = c + d;
-                return d;
+                int i = 0;
+                int j = 0;
+                int total = 0;
+                while (i < 10) {
+                    j = 0;
+                    while (j < i) {
+                        total = total + i * 3;
+                        j = j + 1;
+                    }
+                    i = i + 1;
+                }
+                return total;
             }
         "#,
         r#"
 @main: int {
-   %v0: int = const 4
-   a: int = id %v0
-   %v1: int = const 2
-   b: int = id %v1
-   %v3: int = add a b
-   d: int = id %v3
-   ret d
+   %v0: int = const 0
+   i: int = id %v0
+   %v1: int = const 0
+   j: int = id %v1
+   %v2: int = const 0
+   total: int = id %v2
+   %v3: int = const 10
+   %v4: bool = lt i %v3
+   br %v4 .LABEL_5 .LABEL_1
+   .LABEL_5
+   %v5: int = const 0
+   %v7: int = const 3
+   %v10: int = const 1
+   %v13: int = const 1
+   %v15: int = const 10
+   .LABEL_0
+   j: int = id %v5
+   %v6: bool = lt j i
+   br %v6 .LABEL_4 .LABEL_3
+   .LABEL_4
+   %v8: int = mul i %v7
+   .LABEL_2
+   %v9: int = add total %v8
+   total: int = id %v9
+   %v11: int = add j %v10
+   j: int = id %v11
+   %v12: bool = lt j i
+   br %v12 .LABEL_2 .LABEL_3
+   .LABEL_3
+   %v14: int = add i %v13
+   i: int = id %v14
+   %v16: bool = lt i %v15
+   br %v16 .LABEL_0 .LABEL_1
+   .LABEL_1
+   ret total
 }
 "#
     );
 
-    test_optimization_pass!(
-        can_trivially_dce_dead_store_in_different_blocks,
-        r#"
+    #[test]
+    fn can_unswitch_loop_invariant_branch() {
+        let source = r#"
             int main() {
-                int a = 4;
-                int b = 2;
-                int c = 0;
-                if (a < b) {
-                    int c = a + b;
-                } else {
-                    int d = a - b;
+                int n = 10;
+                bool flag = true;
+                int s = 0;
+                int i = 0;
+                for (i = 0; i < n; i = i + 1) {
+                    if (flag) {
+                        s = s + i;
+                    } else {
+                        s = s - i;
+                    }
                 }
-                return c;
+                return s;
             }
-        "#,
-        r#"
-@main: int {
-   %v0: int = const 4
-   a: int = id %v0
-   %v1: int = const 2
-   b: int = id %v1
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let main = &mut irgen.functions_mut()[0];
+        let original = main.clone();
+        LoopUnswitch {
+            budget: 8,
+            ..Default::default()
+        }
+        .run(main);
+        // The loop doesn't fit in the budget.
+        assert_eq!(*main, original);
+
+        LoopUnswitch::default().run(main);
+        let expected = r#"@main: int {
+   %v0: int = const 10
+   n: int = id %v0
+   %v1: bool = const true
+   flag: bool = id %v1
    %v2: int = const 0
+   s: int = id %v2
+   %v3: int = const 0
+   i: int = id %v3
+   %v4: int = const 0
+   i: int = id %v4
+   .LABEL_9
+   br flag .LABEL_0 .LABEL_5
+   .LABEL_0
+   jmp .LABEL_2
+   .LABEL_2
+   %v5: int = add s i
+   s: int = id %v5
+   jmp .LABEL_4
+   .LABEL_4
+   %v7: int = const 1
+   %v8: int = add i %v7
+   i: int = id %v8
+   %v9: bool = lt i n
+   br %v9 .LABEL_0 .LABEL_1
+   .LABEL_5
+   jmp .LABEL_7
+   .LABEL_7
+   %v11: int = sub s i
+   s: int = id %v11
+   jmp .LABEL_8
+   .LABEL_8
+   %v12: int = const 1
+   %v13: int = add i %v12
+   i: int = id %v13
+   %v14: bool = lt i n
+   br %v14 .LABEL_5 .LABEL_1
+   .LABEL_1
+   ret s
+}
+"#;
+        assert_eq!(main.to_string(), expected);
+    }
+
+    #[test]
+    fn can_not_unswitch_loop_variant_branch() {
+        let source = r#"
+            int main() {
+                bool flag = true;
+                int i = 0;
+                for (i = 0; i < 10; i = i + 1) {
+                    if (flag) {
+                        flag = false;
+                    }
+                }
+                return i;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let main = &mut irgen.functions_mut()[0];
+        let original = main.clone();
+        LoopUnswitch::default().run(main);
+        assert_eq!(*main, original);
+    }
+
+    #[test]
+    fn can_not_unswitch_loop_with_temporaries_used_after_it() {
+        let source = r#"
+            int f(int a, int b) {
+                int i = 0;
+                int x = 0;
+                int y = 0;
+                while (i < 10) {
+                    bool k = a > 3;
+                    y = b + i;
+                    if (k) {
+                        x = x + 1;
+                    } else {
+                        x = x + 2;
+                    }
+                    i = i + 1;
+                }
+                return x + y;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let f = &mut irgen.functions_mut()[0];
+        PassRegistry::default()
+            .pipeline("licm,sccp,copy-propagation")
+            .unwrap()
+            .run(f);
+        // The return reads the temporary holding `b + i` which the clone
+        // would rename.
+        let original = f.clone();
+        LoopUnswitch::default().run(f);
+        assert_eq!(*f, original);
+    }
+
+    #[test]
+    fn can_run_size_pipeline() {
+        let source = r#"
+            extern int f(int a, int b, int c);
+            int main() {
+                int a = 1 + 2;
+                int b = 4;
+                return f(1, 2, a);
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let main = &mut irgen.functions_mut()[0];
+        assert!(PassManager::with_level(OptLevel::O0).is_empty());
+        let size = main.code_size();
+        PassManager::with_level(OptLevel::Oz).run(main);
+        // Call arguments past the second one are uses too.
+        let expected = r#"@main: int {
+   %v2: int = const 3
+   %v4: int = const 1
+   %v5: int = const 2
+   a: int = id %v2
+   %v6: int = call @f %v4 %v5 a
+   ret %v6
+}
+"#;
+        assert_eq!(main.to_string(), expected);
+        assert!(main.code_size() < size);
+    }
+
+    #[test]
+    fn can_run_preset_pipelines() {
+        let source = r#"
+            int main() {
+                int a = 2 * 3;
+                int b = a + 0;
+                int c = b * 1 + a - a;
+                if (c > 5) {
+                    c = c + 1;
+                }
+                return c;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        let main = &irgen.functions()[0];
+
+        // A single round of folding leaves work for the next one.
+        let mut once = main.clone();
+        PassManager::new()
+            .with_pass(InstCombine::default())
+            .with_pass(DCE {})
+            .run(&mut once);
+        let mut o1 = main.clone();
+        PassManager::o1().run(&mut o1);
+        assert!(o1.code_size() < once.code_size());
+        let expected = r#"@main: int {
+   %v2: int = const 6
    c: int = id %v2
-   %v3: bool = lt a b
-   br %v3 .LABEL_0 .LABEL_1
+   %v10: bool = const true
+   br %v10 .LABEL_0 .LABEL_1
    .LABEL_0
-   %v4: int = add a b
-   c: int = id %v4
+   %v11: int = const 1
+   %v12: int = add c %v11
+   c: int = id %v12
    jmp .LABEL_2
    .LABEL_1
    jmp .LABEL_2
    .LABEL_2
    ret c
 }
-"#
-    );
+"#;
+        assert_eq!(o1.to_string(), expected);
+
+        let mut o2 = main.clone();
+        PassManager::o2().run(&mut o2);
+        assert!(o2.code_size() <= o1.code_size());
+        assert_eq!(PassManager::o2().len(), 13);
+    }
+
+    #[test]
+    fn can_move_cold_blocks_to_the_end() {
+        let source = r#"
+            int main() {
+                int a = 1;
+                if (a > 10) {
+                    __builtin_trap();
+                }
+                a = a + 1;
+                return a;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let main = &mut irgen.functions_mut()[0];
+        HotColdSplitting::default().run(main);
+        let expected = r#"@main: int {
+   %v0: int = const 1
+   a: int = id %v0
+   %v1: int = const 10
+   %v2: bool = gt a %v1
+   br %v2 .LABEL_0 .LABEL_1
+   .LABEL_1
+   jmp .LABEL_2
+   .LABEL_2
+   %v4: int = const 1
+   %v5: int = add a %v4
+   a: int = id %v5
+   ret a
+   .LABEL_0
+   %v3: int = intrinsic @__builtin_trap
+}
+"#;
+        assert_eq!(main.to_string(), expected);
+    }
+
+    #[test]
+    fn can_propagate_return_values() {
+        let source = r#"
+            extern int f(int a);
+            int first(int x, int y) {
+                f(y);
+                return x;
+            }
+            int seven() {
+                return 7;
+            }
+            int main() {
+                return first(seven(), 2) + seven();
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let globals = irgen.globals().clone();
+        ReturnValuePropagation {}.run(irgen.functions_mut(), &globals);
+        // `first` calls an external function so the call to it is kept.
+        let expected = r#"@main: int {
+   %v2: int = const 7
+   %v3: int = const 2
+   %v7: int = call @first %v2 %v3
+   %v4: int = id %v2
+   %v5: int = const 7
+   %v6: int = add %v4 %v5
+   ret %v6
+}
+"#;
+        assert_eq!(irgen.functions()[2].to_string(), expected);
+    }
+
+    #[test]
+    fn can_keep_calls_reading_volatile_globals() {
+        let source = r#"
+            volatile int port = 0;
+            int poll() {
+                int status = port;
+                return 7;
+            }
+            int main() {
+                return poll() + poll();
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let globals = irgen.globals().clone();
+        ReturnValuePropagation {}.run(irgen.functions_mut(), &globals);
+        // Both reads of `port` are observable, the calls are kept even
+        // though their return value is propagated.
+        let main = irgen.functions()[1].to_string();
+        assert_eq!(main.matches("call @poll").count(), 2, "{main}");
+    }
+
+    #[test]
+    fn can_fold_assumed_comparisons() {
+        let source = r#"
+            int main() {
+                int x = 5;
+                __builtin_assume(x > 0);
+                bool a = x > 0;
+                bool b = 0 < x;
+                bool c = x <= 0;
+                x = 1;
+                bool d = x > 0;
+                return x;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let main = &mut irgen.functions_mut()[0];
+        InstCombine::default().run(main);
+        let folded = main
+            .instructions()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert!(folded.contains(&"%v5: bool = const true".to_string()));
+        assert!(folded.contains(&"%v7: bool = const true".to_string()));
+        assert!(folded.contains(&"%v9: bool = const false".to_string()));
+        // `x` was assigned since the assumption.
+        assert!(folded.contains(&"%v12: bool = gt x %v11".to_string()));
+
+        StripAssumptions {}.run(main);
+        assert!(!main.instructions().iter().any(|inst| matches!(
+            inst,
+            Instruction::Intrinsic(_, Builtin::Assume, _)
+        )));
+    }
+
+    #[test]
+    fn can_reassociate_chains_in_wrapping_mode() {
+        let source = r#"
+            int main() {
+                int x = 1;
+                int y = 2;
+                int a = x + 1 + y + 2;
+                int b = 4 * x * 5 * y;
+                return a + b;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let main = &mut irgen.functions_mut()[0];
+        Reassociate::default().run(main);
+        DCE {}.run(main);
+
+        let expected = r#"@main: int {
+   %v0: int = const 1
+   x: int = id %v0
+   %v1: int = const 2
+   y: int = id %v1
+   %v13: int = const 3
+   %v14: int = add x y
+   %v6: int = add %v14 %v13
+   a: int = id %v6
+   %v15: int = const 20
+   %v16: int = mul x y
+   %v11: int = mul %v16 %v15
+   b: int = id %v11
+   %v12: int = add a b
+   ret %v12
+}
+"#;
+        assert_eq!(main.to_string(), expected);
+    }
+
+    #[test]
+    fn can_only_fold_trailing_constants_in_checked_mode() {
+        let source = r#"
+            int main() {
+                int x = 1;
+                int y = 2;
+                int a = x + y + 1 + 2;
+                int b = x + 1 + y + 2;
+                int c = x + 2147483647 + 1;
+                return a;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let main = &mut irgen.functions_mut()[0];
+        let before = main.to_string();
+        Reassociate {
+            mode: ArithmeticMode::Checked,
+        }
+        .run(main);
+
+        let after = main.to_string();
+        assert!(after.contains("= const 3\n"), "{after}");
+        assert!(after.contains("%v6: int = add %v2 %v"), "{after}");
+        // Reordering `b` or folding the constants of `c` could overflow.
+        for line in before.lines().skip_while(|line| !line.starts_with("   a:"))
+        {
+            assert!(after.contains(line), "{line} missing in {after}");
+        }
+    }
+
+    #[test]
+    fn can_canonicalize_branch_polarity() {
+        let source = r#"
+            int main() {
+                int x = 3;
+                if (__builtin_expect(x < 10, true)) {
+                    x = 1;
+                } else {
+                    x = 2;
+                }
+                if (!(x > 5)) {
+                    x = 4;
+                }
+                return x;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let main = &mut irgen.functions_mut()[0];
+        CanonicalizeBranches {}.run(main);
+        DCE {}.run(main);
+
+        let expected = r#"@main: int {
+   %v0: int = const 3
+   x: int = id %v0
+   %v1: int = const 10
+   %v2: bool = gte x %v1
+   br %v2 .LABEL_1 .LABEL_0 !unlikely
+   .LABEL_0
+   %v3: int = const 1
+   x: int = id %v3
+   jmp .LABEL_2
+   .LABEL_1
+   %v4: int = const 2
+   x: int = id %v4
+   jmp .LABEL_2
+   .LABEL_2
+   %v5: int = const 5
+   %v6: bool = gt x %v5
+   br %v6 .LABEL_4 .LABEL_3
+   .LABEL_3
+   %v8: int = const 4
+   x: int = id %v8
+   jmp .LABEL_5
+   .LABEL_4
+   jmp .LABEL_5
+   .LABEL_5
+   ret x
+}
+"#;
+        assert_eq!(main.to_string(), expected);
+    }
+
+    #[test]
+    fn can_register_custom_passes() {
+        // Replaces every addition by a multiplication.
+        struct AddToMul {}
+
+        impl Transform for AddToMul {
+            fn run(&self, function: &mut crate::ir::Function) {
+                for inst in function.instructions_mut() {
+                    if let Instruction::Add(dst, lhs, rhs) = inst {
+                        *inst = Instruction::Mul(
+                            dst.clone(),
+                            lhs.clone(),
+                            rhs.clone(),
+                        );
+                    }
+                }
+            }
+        }
+
+        let mut registry = PassRegistry::default();
+        assert!(!registry.contains("add-to-mul"));
+        registry.register("add-to-mul", || Box::new(AddToMul {}));
+        assert!(registry.names().any(|name| name == "add-to-mul"));
+        assert_eq!(
+            registry
+                .pipeline("add-to-mul, sroa")
+                .err()
+                .map(|err| err.to_string()),
+            Some("unknown pass `sroa`".to_string())
+        );
+
+        let source = "int main() { int x = 2; return x + 3; }";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let main = &mut irgen.functions_mut()[0];
+        let pipeline = registry.pipeline("add-to-mul,instcombine,dce").unwrap();
+        assert_eq!(pipeline.len(), 3);
+        pipeline.run(main);
+        let expected = r#"@main: int {
+   %v0: int = const 2
+   x: int = id %v0
+   %v1: int = const 3
+   %v2: int = mul x %v1
+   ret %v2
+}
+"#;
+        assert_eq!(main.to_string(), expected);
+    }
+
+    #[test]
+    fn can_parse_pipeline_descriptions() {
+        let registry = PassRegistry::default();
+        let pipeline = registry
+            .parse_pipeline(
+                "module(return-value-propagation, function(instcombine,dce))",
+            )
+            .unwrap();
+        assert_eq!(pipeline.len(), 3);
+        assert_eq!(
+            registry.parse_pipeline("instcombine,dce").unwrap().len(),
+            2
+        );
+        assert!(registry.parse_pipeline("").unwrap().is_empty());
+        // Fixed point groups count as a single pass.
+        assert_eq!(
+            registry
+                .parse_pipeline("fixpoint(instcombine, lvn, dce), dce")
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let error = |text| {
+            registry
+                .parse_pipeline(text)
+                .err()
+                .map(|err| err.to_string())
+        };
+        assert_eq!(error("function(sroa)").unwrap(), "unknown pass `sroa`");
+        assert_eq!(
+            error("module(function(dce)").unwrap(),
+            "expected `)` at offset 20 of the pipeline"
+        );
+        assert_eq!(
+            error("function(dce,)").unwrap(),
+            "expected a pass name, found `)` at offset 13 of the pipeline"
+        );
+        assert_eq!(
+            error("function(return-value-propagation)").unwrap(),
+            "module pass `return-value-propagation` in a function pipeline \
+             at offset 9 of the pipeline"
+        );
+        assert_eq!(
+            error("dce) instcombine").unwrap(),
+            "unexpected `)` at offset 3 of the pipeline"
+        );
+
+        let source = r#"
+            int main() {
+                return seven() + 1;
+            }
+            int seven() {
+                return 7;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let globals = irgen.globals().clone();
+        registry
+            .parse_pipeline("return-value-propagation,instcombine,dce")
+            .unwrap()
+            .run(irgen.functions_mut(), &globals);
+        let expected = r#"@main: int {
+   %v2: int = const 8
+   ret %v2
+}
+"#;
+        assert_eq!(irgen.functions()[0].to_string(), expected);
+    }
+
+    #[test]
+    fn can_honor_optimization_attributes() {
+        // Replaces every addition by a multiplication, pretending it grows
+        // the code.
+        struct Grow {}
+
+        impl Transform for Grow {
+            fn grows_code(&self) -> bool {
+                true
+            }
+
+            fn run(&self, function: &mut crate::ir::Function) {
+                for inst in function.instructions_mut() {
+                    if let Instruction::Add(dst, lhs, rhs) = inst {
+                        *inst = Instruction::Mul(
+                            dst.clone(),
+                            lhs.clone(),
+                            rhs.clone(),
+                        );
+                    }
+                }
+            }
+        }
+
+        let source = r#"
+            int main() {
+                return 2 + 3;
+            }
+            [[optnone]] int keep() {
+                return 2 + 3;
+            }
+            [[optsize]] int small() {
+                return 4 + 5;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let pipeline = PassManager::new()
+            .with_pass(Grow {})
+            .with_pass(InstCombine::default())
+            .with_pass(DCE {});
+        for function in irgen.functions_mut() {
+            pipeline.run(function);
+        }
+        let functions = irgen.functions();
+        assert_eq!(
+            functions[0].to_string(),
+            "@main: int {\n   %v2: int = const 6\n   ret %v2\n}\n"
+        );
+        assert!(functions[1].has_attribute(Attribute::OptNone));
+        assert_eq!(
+            functions[1].to_string(),
+            "@keep: int [[optnone]] {\n   %v3: int = const 2\n   %v4: int = const 3\n   %v5: int = add %v3 %v4\n   ret %v5\n}\n"
+        );
+        assert_eq!(
+            functions[2].to_string(),
+            "@small: int [[optsize]] {\n   %v8: int = const 9\n   ret %v8\n}\n"
+        );
+    }
 }
@@ -1,10 +1,63 @@
 //! Parser for a subset of C0 language that uses Pratt's approach to parsing
 //! expressions and a flat representation for the AST.
+use std::fmt;
+
 use crate::ast::{
-    BinaryOperator, Decl, DeclType, Expr, ExprRef, Stmt, StmtRef,
-    UnaryOperator, AST,
+    AnyRef, Attribute, BinaryOperator, Decl, DeclType, Expr, ExprRef, Linkage,
+    Stmt, StmtRef, UnaryOperator, AST,
 };
-use crate::token::Token;
+use crate::limits::{LimitError, MAX_AST_NODES};
+use crate::scanner::MAX_NESTING_DEPTH;
+use crate::token::{Span, Token};
+
+/// Language levels selected with `--lang`, every level accepts the syntax
+/// and builtins of the levels before it. The minimal level used for teaching
+/// stays stable while larger C features land in the other levels.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Lang {
+    // Variables, functions, structured control flow, `printf` and the print
+    // builtins.
+    Min,
+    // Adds `switch`, `goto` and labels, storage classes (`static`,
+    // `extern`), qualifiers (`const`, `volatile`) and the arithmetic
+    // builtins.
+    C89Subset,
+    // Adds inline assembly, attributes and the optimizer builtins
+    // (`__builtin_expect`, `__builtin_assume`, `__builtin_trap`, atomics).
+    #[default]
+    Gnu,
+}
+
+impl Lang {
+    /// Returns the language level named `name`.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Min, Self::C89Subset, Self::Gnu]
+            .into_iter()
+            .find(|lang| lang.name() == name)
+    }
+
+    /// Returns the name of the level as passed to `--lang`.
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Min => "min",
+            Self::C89Subset => "c89subset",
+            Self::Gnu => "gnu",
+        }
+    }
+
+    /// Panic unless the level accepts `feature`, which needs `lang`.
+    pub fn require(self, lang: Self, feature: &str) {
+        assert!(self >= lang, "{feature} requires --lang={lang}.");
+    }
+}
+
+impl fmt::Display for Lang {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
 
 /// Operator precedence tablet.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -59,12 +112,73 @@ impl From<Precedence> for u8 {
     }
 }
 
+/// Associativity of infix operators, decides how a sequence of operators
+/// with the same precedence groups (`a - b - c` is `(a - b) - c` while
+/// `a = b = c` is `a = (b = c)`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Associativity {
+    Left,
+    Right,
+}
+
+/// AST node built by an infix operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Infix {
+    Binary(BinaryOperator),
+    Assignment,
+    Call,
+}
+
+/// Entry of the infix operator table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct InfixRule {
+    precedence: Precedence,
+    associativity: Associativity,
+    operator: Infix,
+}
+
+impl InfixRule {
+    /// Returns the precedence the right operand is parsed with, operators
+    /// of the same precedence are only folded into the right operand of
+    /// right associative operators.
+    fn right_precedence(&self) -> Precedence {
+        match self.associativity {
+            Associativity::Left => self.precedence,
+            Associativity::Right => (u8::from(self.precedence) - 1).into(),
+        }
+    }
+}
+
 /// Parser implements a recursive descent Pratt style parser.
 pub struct Parser {
     // Input tokens to process.
     tokens: Vec<Token>,
+    // Source locations of the input tokens, empty if the parser was built
+    // without them.
+    spans: Vec<Span>,
     // Cursor in the tokens list.
     cursor: usize,
+    // Current nesting depth of expressions and statements.
+    depth: usize,
+    // Maximum nesting depth of expressions and statements.
+    max_depth: usize,
+    // Maximum number of nodes in the constructed AST.
+    max_nodes: usize,
+    // Whether the last parsed local variable declaration has no initializer.
+    default_initialized: bool,
+    // Whether the last parsed variable declaration is `const` qualified.
+    const_qualified: bool,
+    // Whether the last parsed local variable declaration is `static`.
+    static_local: bool,
+    // Whether the last parsed variable declaration is `volatile` qualified.
+    volatile_qualified: bool,
+    // Type of the local declaration being parsed when its last declarator
+    // was followed by a comma.
+    declarator: Option<DeclType>,
+    // Section the last parsed declaration is placed in.
+    section: Option<String>,
+    // Language level of the accepted syntax.
+    lang: Lang,
     /// Constructed AST.
     ast: AST,
 }
@@ -75,40 +189,177 @@ impl Parser {
     pub fn new(tokens: &[Token]) -> Self {
         Self {
             tokens: tokens.to_owned(),
+            spans: vec![],
             cursor: 0usize,
+            depth: 0,
+            max_depth: MAX_NESTING_DEPTH,
+            max_nodes: MAX_AST_NODES,
+            default_initialized: false,
+            const_qualified: false,
+            static_local: false,
+            volatile_qualified: false,
+            declarator: None,
+            section: None,
+            lang: Lang::default(),
             ast: AST::new(),
         }
     }
 
+    /// Returns a new `Parser` instance from tokens and their source locations
+    /// (see `Scanner::scan_with_spans`), the AST nodes built by the parser
+    /// record their source location.
+    #[must_use]
+    pub fn with_spans(tokens: &[(Token, Span)]) -> Self {
+        let (tokens, spans) = tokens.iter().cloned().unzip();
+        Self {
+            tokens,
+            spans,
+            cursor: 0usize,
+            depth: 0,
+            max_depth: MAX_NESTING_DEPTH,
+            max_nodes: MAX_AST_NODES,
+            default_initialized: false,
+            const_qualified: false,
+            static_local: false,
+            volatile_qualified: false,
+            declarator: None,
+            section: None,
+            lang: Lang::default(),
+            ast: AST::new(),
+        }
+    }
+
+    /// Set the maximum nesting depth of expressions and statements.
+    #[must_use]
+    pub const fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Set the maximum number of nodes in the constructed AST.
+    #[must_use]
+    pub const fn with_max_nodes(mut self, max_nodes: usize) -> Self {
+        self.max_nodes = max_nodes;
+        self
+    }
+
+    /// Set the language level of the accepted syntax.
+    #[must_use]
+    pub const fn with_lang(mut self, lang: Lang) -> Self {
+        self.lang = lang;
+        self
+    }
+
+    /// Hash-cons the expressions of the constructed AST, structurally
+    /// identical pure subexpressions share a single node.
+    #[must_use]
+    pub fn with_interning(mut self) -> Self {
+        self.ast = std::mem::take(&mut self.ast).with_interning();
+        self
+    }
+
     /// Return a reference to the constructed AST.
     #[must_use]
     pub const fn ast(&self) -> &AST {
         &self.ast
     }
 
+    /// Return a mutable reference to the constructed AST.
+    #[must_use]
+    pub fn ast_mut(&mut self) -> &mut AST {
+        &mut self.ast
+    }
+
+    /// Consume the parser and return the constructed AST.
+    #[must_use]
+    pub fn into_ast(self) -> AST {
+        self.ast
+    }
+
     /// Parse the input program and construct an AST.
     pub fn parse(&mut self) {
         while !self.eof() {
+            let start = self.cursor;
             let decl = self.declaration();
-            self.ast.push_decl(decl);
+            let decl_ref = self.ast.push_decl(decl);
+            self.ast.set_decl_span(decl_ref, self.span_from(start));
+            if std::mem::take(&mut self.const_qualified) {
+                self.ast.set_const(AnyRef::Decl(decl_ref));
+            }
+            if std::mem::take(&mut self.volatile_qualified) {
+                self.ast.set_volatile(AnyRef::Decl(decl_ref));
+            }
+            if let Some(section) = self.section.take() {
+                self.ast.set_section(decl_ref, section);
+            }
+            self.check_size();
         }
     }
 
     /// Parse a statement.
     fn statement(&mut self) -> Stmt {
-        match *self.peek() {
-            Token::Int | Token::Char | Token::Bool => self.local_var_decl(),
+        self.nest();
+        let stmt = match *self.peek() {
+            Token::Int
+            | Token::Char
+            | Token::Bool
+            | Token::Const
+            | Token::Volatile
+            | Token::Static => self.local_var_decl(),
             Token::Return => self.return_stmt(),
             Token::LBrace => self.block(),
+            Token::SemiColon => {
+                self.eat(&Token::SemiColon);
+                Stmt::Empty
+            }
             Token::For => self.loop_stmt(),
             Token::While => self.while_loop_stmt(),
             Token::If => self.if_stmt(),
+            Token::Switch => {
+                self.lang.require(Lang::C89Subset, "`switch`");
+                self.switch_stmt()
+            }
+            Token::Case => {
+                self.lang.require(Lang::C89Subset, "`case`");
+                self.case_stmt()
+            }
+            Token::Default => {
+                self.lang.require(Lang::C89Subset, "`default`");
+                self.eat(&Token::Default);
+                self.eat(&Token::Colon);
+                Stmt::Default
+            }
+            Token::Break => {
+                self.eat(&Token::Break);
+                self.eat(&Token::SemiColon);
+                Stmt::Break
+            }
+            Token::Goto => {
+                self.lang.require(Lang::C89Subset, "`goto`");
+                self.goto_stmt()
+            }
+            Token::Asm => {
+                self.lang.require(Lang::Gnu, "`asm`");
+                self.asm_stmt()
+            }
+            Token::Identifier(_) if self.peek_next() == &Token::Colon => {
+                self.lang.require(Lang::C89Subset, "Label statement");
+                self.label_stmt()
+            }
             _ => self.expr_stmt(),
-        }
+        };
+        self.depth -= 1;
+        stmt
     }
 
     /// Parse a local variable declaration.
     fn local_var_decl(&mut self) -> Stmt {
+        self.static_local = self.at(&Token::Static);
+        if self.static_local {
+            self.lang.require(Lang::C89Subset, "`static`");
+            self.eat(&Token::Static);
+        }
+        self.qualifiers();
         let decl_type = match *self.advance() {
             Token::Int => DeclType::Int,
             Token::Char => DeclType::Char,
@@ -117,41 +368,74 @@ impl Parser {
                 "Expected declaration type to be one of (int, char, bool)."
             ),
         };
+        self.declarator(decl_type)
+    }
+
+    /// Parse the declarator of a local variable of type `decl_type`, its
+    /// name and initializer. Declarators separated by commas declare one
+    /// variable each, in order, so initializers can read the variables
+    /// declared before them.
+    fn declarator(&mut self, decl_type: DeclType) -> Stmt {
         let identifier = match self.advance() {
             Token::Identifier(ident) => ident.clone(),
             _ => unreachable!("Expected identifier, found {}", self.peek()),
         };
 
-        match *self.peek() {
+        let value = match *self.peek() {
             // Variable declaration without right value assignment.
-            Token::SemiColon => {
-                self.eat(&Token::SemiColon);
+            Token::SemiColon | Token::Comma => {
+                assert!(
+                    !self.const_qualified,
+                    "Const variable `{identifier}` must be initialized."
+                );
+                self.default_initialized = true;
                 let assigned = decl_type.default_value();
-                let assigned_ref = self.ast.push_expr(assigned);
-                // Declaration without an assignment.
-                Stmt::LocalVariable {
-                    decl_type,
-                    name: identifier,
-                    value: assigned_ref,
-                }
+                self.ast.push_expr(assigned)
             }
             // Variable declaration with right value assignment.
             Token::Equal => {
                 self.eat(&Token::Equal);
-                let assigned = self.expression();
-                self.eat(&Token::SemiColon);
-                Stmt::LocalVariable {
-                    decl_type,
-                    name: identifier,
-                    value: assigned,
-                }
+                self.expression()
             }
             _ => unreachable!("Unsupported token in position {}", self.peek()),
+        };
+        if self.at(&Token::Comma) {
+            self.eat(&Token::Comma);
+            self.declarator = Some(decl_type);
+        } else {
+            self.eat(&Token::SemiColon);
+        }
+        Stmt::LocalVariable {
+            decl_type,
+            name: identifier,
+            value,
         }
     }
 
     /// Parse a declaration.
     fn declaration(&mut self) -> Decl {
+        let attributes = self.attributes();
+        self.section = self.section_attribute();
+        if self.at(&Token::Extern) {
+            self.lang.require(Lang::C89Subset, "`extern`");
+            assert!(
+                attributes.is_empty(),
+                "Attributes only apply to function definitions."
+            );
+            assert!(
+                self.section.is_none(),
+                "Sections only apply to definitions."
+            );
+            return self.extern_decl();
+        }
+        let linkage = if self.at(&Token::Static) {
+            self.lang.require(Lang::C89Subset, "`static`");
+            self.eat(&Token::Static);
+            Linkage::Internal
+        } else {
+            Linkage::External
+        };
+        self.qualifiers();
         let decl_type = match *self.advance() {
             Token::Int => DeclType::Int,
             Token::Char => DeclType::Char,
@@ -165,6 +449,26 @@ impl Parser {
             Token::Identifier(ident) => ident.clone(),
             _ => unreachable!("Expected identifier, found {}", self.peek()),
         };
+        assert!(
+            linkage == Linkage::External || self.at(&Token::LParen),
+            "Only functions can be declared static, found `{identifier}`."
+        );
+        assert!(
+            attributes.is_empty() || self.at(&Token::LParen),
+            "Attributes only apply to function definitions, found `{identifier}`."
+        );
+        assert!(
+            !self.const_qualified || !self.at(&Token::LParen),
+            "Only variables can be declared const, found `{identifier}`."
+        );
+        assert!(
+            !self.volatile_qualified || !self.at(&Token::LParen),
+            "Only variables can be declared volatile, found `{identifier}`."
+        );
+        assert!(
+            !self.const_qualified || self.at(&Token::Equal),
+            "Const variable `{identifier}` must be initialized."
+        );
 
         match *self.peek() {
             // Variable declaration without right value assignment.
@@ -198,9 +502,10 @@ impl Parser {
                 let args = self.args();
                 self.eat(&Token::RParen);
                 // Body
+                let start = self.cursor;
                 self.eat(&Token::LBrace);
                 let body = self.block();
-                let body_ref = self.ast.push_stmt(body);
+                let body_ref = self.push_stmt(body, start);
                 // End of body
                 self.eat(&Token::RBrace);
                 Decl::Function {
@@ -208,6 +513,8 @@ impl Parser {
                     return_type: decl_type,
                     args,
                     body: body_ref,
+                    linkage,
+                    attributes,
                 }
             }
             _ => unreachable!(
@@ -217,6 +524,111 @@ impl Parser {
         }
     }
 
+    /// Parse the `const` and `volatile` qualifiers of a variable
+    /// declaration, in any order.
+    fn qualifiers(&mut self) {
+        loop {
+            match self.peek() {
+                Token::Const => {
+                    self.lang.require(Lang::C89Subset, "`const`");
+                    self.const_qualified = true;
+                }
+                Token::Volatile => {
+                    self.lang.require(Lang::C89Subset, "`volatile`");
+                    self.volatile_qualified = true;
+                }
+                _ => break,
+            }
+            self.advance();
+        }
+    }
+
+    /// Parse the GNU section attribute of a declaration such as
+    /// `__attribute__((section(".boot")))`, returns the section name.
+    fn section_attribute(&mut self) -> Option<String> {
+        if !matches!(self.peek(), Token::Identifier(name) if name == "__attribute__")
+        {
+            return None;
+        }
+        self.lang.require(Lang::Gnu, "`__attribute__`");
+        self.advance();
+        self.eat(&Token::LParen);
+        self.eat(&Token::LParen);
+        match self.advance() {
+            Token::Identifier(name) if name == "section" => (),
+            token => panic!("Unknown attribute `{token}`."),
+        }
+        self.eat(&Token::LParen);
+        let section = match self.advance() {
+            Token::StringLiteral(section) => section.clone(),
+            _ => unreachable!(
+                "Expected section name to be a string literal, found {}",
+                self.prev()
+            ),
+        };
+        self.eat(&Token::RParen);
+        self.eat(&Token::RParen);
+        self.eat(&Token::RParen);
+        Some(section)
+    }
+
+    /// Parse the attributes of a declaration such as `[[optnone, optsize]]`,
+    /// declarations without attributes return an empty list.
+    fn attributes(&mut self) -> Vec<Attribute> {
+        let mut attributes = vec![];
+        if !self.at(&Token::LBracket) {
+            return attributes;
+        }
+        self.lang.require(Lang::Gnu, "Attribute list");
+        self.eat(&Token::LBracket);
+        self.eat(&Token::LBracket);
+        loop {
+            let attribute = match self.advance() {
+                Token::Identifier(name) => Attribute::from_name(name)
+                    .unwrap_or_else(|| panic!("Unknown attribute `{name}`.")),
+                _ => unreachable!("Expected attribute, found {}", self.prev()),
+            };
+            if !attributes.contains(&attribute) {
+                attributes.push(attribute);
+            }
+            if !self.at(&Token::Comma) {
+                break;
+            }
+            self.eat(&Token::Comma);
+        }
+        self.eat(&Token::RBracket);
+        self.eat(&Token::RBracket);
+        attributes
+    }
+
+    /// Parse an external function declaration such as
+    /// `extern int putchar(int c);`.
+    fn extern_decl(&mut self) -> Decl {
+        self.eat(&Token::Extern);
+        let return_type = match *self.advance() {
+            Token::Int => DeclType::Int,
+            Token::Char => DeclType::Char,
+            Token::Bool => DeclType::Bool,
+            _ => unreachable!(
+                "Expected declaration type to be one of (int, char, bool) got {}.",
+                self.prev()
+            ),
+        };
+        let name = match self.advance() {
+            Token::Identifier(ident) => ident.clone(),
+            _ => unreachable!("Expected identifier, found {}", self.prev()),
+        };
+        self.eat(&Token::LParen);
+        let args = self.args();
+        self.eat(&Token::RParen);
+        self.eat(&Token::SemiColon);
+        Decl::Extern {
+            name,
+            return_type,
+            args,
+        }
+    }
+
     /// Parse function arguments.
     fn args(&mut self) -> Vec<StmtRef> {
         let mut args = vec![];
@@ -243,7 +655,7 @@ impl Parser {
                     decl_type: arg_type,
                     name: arg_name,
                 };
-                let arg_ref = self.ast.push_stmt(arg);
+                let arg_ref = self.push_stmt(arg, self.cursor - 2);
                 // Push the function argument statement to the AST.
                 args.push(arg_ref);
                 // We've reached the last argument, break.
@@ -264,10 +676,28 @@ impl Parser {
         // Parse and build the block.
         self.eat(&Token::LBrace);
         while !self.at(&Token::RBrace) && !self.eof() {
+            let start = self.cursor;
             let stmt = self.statement();
-            let stmt_ref = self.ast.push_stmt(stmt);
+            let qualifiers = (
+                self.const_qualified,
+                self.static_local,
+                self.volatile_qualified,
+            );
+            let stmt_ref = self.push_stmt(stmt, start);
 
             stmts.push(stmt_ref);
+            // Declarators after a comma share the type and qualifiers of
+            // the first one.
+            while let Some(decl_type) = self.declarator.take() {
+                (
+                    self.const_qualified,
+                    self.static_local,
+                    self.volatile_qualified,
+                ) = qualifiers;
+                let start = self.cursor;
+                let stmt = self.declarator(decl_type);
+                stmts.push(self.push_stmt(stmt, start));
+            }
         }
         self.eat(&Token::RBrace);
 
@@ -284,19 +714,17 @@ impl Parser {
         // Closing parenthesis.
         self.eat(&Token::RParen);
         // Body of the conditional branch, maybe some day we will support next
-        // line statements. For now, expect a brace.
-        self.eat(&Token::LBrace);
-        // Conditional block.
-        let then_block = self.block();
-        let then_block_ref = self.ast.push_stmt(then_block);
+        // line statements. For now, expect a brace or an empty statement.
+        let start = self.cursor;
+        let then_block = self.branch_body();
+        let then_block_ref = self.push_stmt(then_block, start);
         match self.peek() {
             &Token::Else => {
                 // Consume else.
                 self.eat(&Token::Else);
-                // Consume opening brace for the block.
-                let else_body = self.block();
-                let else_body_ref = self.ast.push_stmt(else_body);
-                self.eat(&Token::LBrace);
+                let start = self.cursor;
+                let else_body = self.branch_body();
+                let else_body_ref = self.push_stmt(else_body, start);
                 Stmt::If {
                     condition,
                     then_block: then_block_ref,
@@ -311,6 +739,15 @@ impl Parser {
         }
     }
 
+    /// Parse the body of an `if` or `else` branch, a block or an empty
+    /// statement (`if (c);`).
+    fn branch_body(&mut self) -> Stmt {
+        if self.at(&Token::SemiColon) {
+            return self.statement();
+        }
+        self.block()
+    }
+
     /// Parse a loop statement.
     fn loop_stmt(&mut self) -> Stmt {
         self.eat(&Token::For);
@@ -336,8 +773,9 @@ impl Parser {
         };
         self.eat(&Token::RParen);
         // Loop body.
+        let start = self.cursor;
         let body = self.statement();
-        let body_ref = self.ast.push_stmt(body);
+        let body_ref = self.push_stmt(body, start);
         Stmt::For {
             init,
             condition,
@@ -359,14 +797,52 @@ impl Parser {
         };
         self.eat(&Token::RParen);
         // Loop body.
+        let start = self.cursor;
         let body = self.statement();
-        let body_ref = self.ast.push_stmt(body);
+        let body_ref = self.push_stmt(body, start);
         Stmt::While {
             condition,
             body: Some(body_ref),
         }
     }
 
+    /// Parse a switch statement.
+    fn switch_stmt(&mut self) -> Stmt {
+        self.eat(&Token::Switch);
+        self.eat(&Token::LParen);
+        let scrutinee = self.expression();
+        self.eat(&Token::RParen);
+        let start = self.cursor;
+        let body = match self.peek() {
+            Token::LBrace => self.block(),
+            token => unreachable!(
+                "Expected `switch` body to be a block, found {token}"
+            ),
+        };
+        let body = self.push_stmt(body, start);
+        Stmt::Switch { scrutinee, body }
+    }
+
+    /// Parse a case label such as "case -1:", case values are integer
+    /// literals.
+    fn case_stmt(&mut self) -> Stmt {
+        self.eat(&Token::Case);
+        let negative = self.expect(&Token::Minus);
+        if negative {
+            self.eat(&Token::Minus);
+        }
+        let value = match self.advance() {
+            Token::IntLiteral(value) if negative => value.wrapping_neg(),
+            Token::IntLiteral(value) => *value,
+            _ => unreachable!(
+                "Expected integer literal after case, found {}",
+                self.prev()
+            ),
+        };
+        self.eat(&Token::Colon);
+        Stmt::Case(value)
+    }
+
     /// Parse a return statement.
     fn return_stmt(&mut self) -> Stmt {
         self.eat(&Token::Return);
@@ -375,13 +851,55 @@ impl Parser {
         Stmt::Return(expr_ref)
     }
 
+    /// Parse a goto statement.
+    fn goto_stmt(&mut self) -> Stmt {
+        self.eat(&Token::Goto);
+        let label = match self.advance() {
+            Token::Identifier(label) => label.clone(),
+            _ => {
+                unreachable!("Expected label after goto, found {}", self.prev())
+            }
+        };
+        self.eat(&Token::SemiColon);
+        Stmt::Goto(label)
+    }
+
+    /// Parse an inline assembly statement such as `asm("nop");`.
+    fn asm_stmt(&mut self) -> Stmt {
+        self.eat(&Token::Asm);
+        self.eat(&Token::LParen);
+        let code = match self.advance() {
+            Token::StringLiteral(code) => code.clone(),
+            _ => unreachable!(
+                "Expected string literal in asm statement, found {}",
+                self.prev()
+            ),
+        };
+        self.eat(&Token::RParen);
+        self.eat(&Token::SemiColon);
+        Stmt::Asm(code)
+    }
+
+    /// Parse a label statement such as "exit:".
+    fn label_stmt(&mut self) -> Stmt {
+        let label = match self.advance() {
+            Token::Identifier(label) => label.clone(),
+            _ => unreachable!("Expected label, found {}", self.prev()),
+        };
+        self.eat(&Token::Colon);
+        Stmt::Label(label)
+    }
+
     /// Parse an expression.
     fn expression(&mut self) -> ExprRef {
         self.by_precedence(Precedence::None)
     }
 
-    /// Parse an expression by its precedence level.
+    /// Parse an expression by its precedence level, infix operators binding
+    /// tighter than `prec` are folded into the expression.
     fn by_precedence(&mut self, prec: Precedence) -> ExprRef {
+        self.nest();
+        let start = self.cursor;
         // Prefix part.
         let mut prefix_ref = match self.advance() {
             &Token::LParen => self.grouping(),
@@ -390,99 +908,140 @@ impl Parser {
                 let literal_expr = Expr::IntLiteral(value);
                 self.ast.push_expr(literal_expr)
             }
+            Token::StringLiteral(value) => {
+                let literal_expr = Expr::StringLiteral(value.clone());
+                self.ast.push_expr(literal_expr)
+            }
+            &Token::CharLiteral(value) => {
+                self.ast.push_expr(Expr::CharLiteral(value))
+            }
             &Token::True => self.ast.push_expr(Expr::BoolLiteral(true)),
             &Token::False => self.ast.push_expr(Expr::BoolLiteral(false)),
             Token::Identifier(_) => self.named(),
             _ => unreachable!("Unexpected prefix token {}", self.prev()),
         };
+        prefix_ref = self.set_expr_span(prefix_ref, start);
 
-        while prec < Self::get_token_precedence(self.peek()) {
-            let infix_ref = match self.advance() {
-                // Arithmetic expressions.
-                &Token::Plus
-                | &Token::Minus
-                | &Token::Star
-                | &Token::Slash
-                | &Token::And
-                | &Token::Or => self.binary(prefix_ref),
-                // Comparison expressions.
-                &Token::EqualEqual
-                | &Token::BangEqual
-                | &Token::GreaterEqual
-                | &Token::Greater
-                | &Token::LesserEqual
-                | &Token::Lesser => self.comparison(prefix_ref),
-                // Call expressions.
-                &Token::LParen => self.call(prefix_ref),
-                // Assignment.
-                &Token::Equal => self.assignment(prefix_ref),
-                _ => todo!("Unexpected infix token {}", self.peek()),
+        // Infix part.
+        while let Some(rule) = Self::infix_rule(self.peek()) {
+            if rule.precedence <= prec {
+                break;
+            }
+            self.advance();
+            prefix_ref = match rule.operator {
+                Infix::Binary(operator) => {
+                    let right = self.by_precedence(rule.right_precedence());
+                    self.ast.push_expr(Expr::BinOp {
+                        left: prefix_ref,
+                        operator,
+                        right,
+                    })
+                }
+                Infix::Assignment => {
+                    let value = self.by_precedence(rule.right_precedence());
+                    self.ast.push_expr(Expr::Assignment {
+                        name: prefix_ref,
+                        value,
+                    })
+                }
+                Infix::Call => self.call(prefix_ref),
             };
-
-            prefix_ref = infix_ref;
+            prefix_ref = self.set_expr_span(prefix_ref, start);
         }
 
+        self.depth -= 1;
         prefix_ref
     }
 
-    /// Parse a comparison expression.
-    fn comparison(&mut self, left: ExprRef) -> ExprRef {
-        let operator = match *self.prev() {
-            Token::Lesser => BinaryOperator::Lt,
-            Token::LesserEqual => BinaryOperator::Lte,
-            Token::Greater => BinaryOperator::Gt,
-            Token::GreaterEqual => BinaryOperator::Gte,
-            Token::EqualEqual => BinaryOperator::Eq,
-            Token::BangEqual => BinaryOperator::Neq,
-            // There is no infix operator.
-            _ => unreachable!(
-                "Unknown token in binary expression {}",
-                self.peek()
-            ),
-        };
+    /// Enter a nested expression or statement.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the input is nested deeper than the maximum depth, which
+    /// would otherwise overflow the stack of the recursive passes, and with
+    /// a `LimitError` if the AST has more nodes than the maximum.
+    fn nest(&mut self) {
+        self.depth += 1;
+        assert!(
+            self.depth <= self.max_depth,
+            "Nesting depth exceeds the limit of {} at {}",
+            self.max_depth,
+            self.peek()
+        );
+        self.check_size();
+    }
 
-        let precedence =
-            (Self::get_token_precedence(self.prev()) as u8 + 1).into();
-        let right = self.by_precedence(precedence);
-        self.ast.push_expr(Expr::BinOp {
-            left,
-            operator,
-            right,
-        })
+    /// Panics with a `LimitError` if the AST has more nodes than the
+    /// maximum, checked as nodes are built so oversized inputs fail before
+    /// exhausting the memory.
+    fn check_size(&self) {
+        if self.ast.node_count() > self.max_nodes {
+            LimitError::ast_nodes(self.max_nodes).raise();
+        }
     }
 
-    /// Parse a binary expression.
-    fn binary(&mut self, left: ExprRef) -> ExprRef {
-        let operator = match *self.prev() {
-            Token::Plus => BinaryOperator::Add,
-            Token::Minus => BinaryOperator::Sub,
-            Token::Star => BinaryOperator::Mul,
-            Token::Slash => BinaryOperator::Div,
-            Token::And => BinaryOperator::And,
-            Token::Or => BinaryOperator::Or,
-            // There is no infix operator.
-            _ => unreachable!(
-                "Unknown token in binary expression {}",
-                self.peek()
-            ),
+    /// Returns the infix operator table entry of `token`, `None` if the
+    /// token isn't an infix operator.
+    ///
+    /// Adding an infix operator only requires an entry here, precedence
+    /// climbing in `by_precedence` takes care of the rest.
+    const fn infix_rule(token: &Token) -> Option<InfixRule> {
+        use Associativity::{Left, Right};
+        use BinaryOperator as Op;
+        use Infix::{Assignment, Binary, Call};
+        use Precedence as P;
+        let (precedence, associativity, operator) = match *token {
+            // Arithmetic expressions.
+            Token::Plus => (P::Term, Left, Binary(Op::Add)),
+            Token::Minus => (P::Term, Left, Binary(Op::Sub)),
+            Token::Star => (P::Factor, Left, Binary(Op::Mul)),
+            Token::Slash => (P::Factor, Left, Binary(Op::Div)),
+            // Logical expressions.
+            Token::Or => (P::Or, Left, Binary(Op::Or)),
+            Token::And => (P::And, Left, Binary(Op::And)),
+            // Comparison expressions.
+            Token::EqualEqual => (P::Equal, Left, Binary(Op::Eq)),
+            Token::BangEqual => (P::Equal, Left, Binary(Op::Neq)),
+            Token::Greater => (P::Comparison, Left, Binary(Op::Gt)),
+            Token::GreaterEqual => (P::Comparison, Left, Binary(Op::Gte)),
+            Token::Lesser => (P::Comparison, Left, Binary(Op::Lt)),
+            Token::LesserEqual => (P::Comparison, Left, Binary(Op::Lte)),
+            // Assignment.
+            Token::Equal => (P::Assignment, Right, Assignment),
+            // Call expressions.
+            Token::LParen => (P::Call, Left, Call),
+            _ => return None,
         };
-        let precedence = Self::get_token_precedence(self.prev());
-        let right = self.by_precedence(precedence);
-        self.ast.push_expr(Expr::BinOp {
-            left,
+        Some(InfixRule {
+            precedence,
+            associativity,
             operator,
-            right,
         })
     }
 
     /// Parse a grouping expression.
     fn grouping(&mut self) -> ExprRef {
+        // Parenthesised types start cast expressions, `(int) c`.
+        let to = match self.peek() {
+            Token::Int => Some(DeclType::Int),
+            Token::Char => Some(DeclType::Char),
+            Token::Bool => Some(DeclType::Bool),
+            _ => None,
+        };
+        if let Some(to) = to {
+            self.advance();
+            self.eat(&Token::RParen);
+            let operand = self.by_precedence(Precedence::Unary);
+            return self.ast.push_expr(Expr::Cast { to, operand });
+        }
         // Parse the grouped expression (inside the parenthesis).
         let expr_ref = self.expression();
         // Consume the closing parenthesis.
         self.eat(&Token::RParen);
-        // Push the expression to pool and return a ref to it.
-        self.ast.push_expr(Expr::Grouping(expr_ref))
+        // The parentheses only drive precedence, record them for display.
+        let expr_ref = self.ast.unshare(expr_ref);
+        self.ast.set_grouped(expr_ref);
+        expr_ref
     }
 
     /// Parse a unary expression.
@@ -500,16 +1059,6 @@ impl Parser {
         self.ast.push_expr(Expr::UnaryOp { operator, operand })
     }
 
-    /// Parse an assignment expression.
-    fn assignment(&mut self, left: ExprRef) -> ExprRef {
-        // Parse the right hand side expression.
-        let expr_ref = self.by_precedence(Precedence::None);
-        self.ast.push_expr(Expr::Assignment {
-            name: left,
-            value: expr_ref,
-        })
-    }
-
     /// Parse a named expression such as "x".
     fn named(&mut self) -> ExprRef {
         // Consume the token and build a named expr.
@@ -553,23 +1102,60 @@ impl Parser {
         Stmt::Expr(expr_ref)
     }
 
-    /// Return a token's precedence.
-    const fn get_token_precedence(token: &Token) -> Precedence {
-        match *token {
-            Token::Plus | Token::Minus => Precedence::Term,
-            Token::Star | Token::Slash => Precedence::Factor,
-            Token::Or => Precedence::Or,
-            Token::And => Precedence::And,
-            Token::EqualEqual | Token::BangEqual => Precedence::Equal,
-            Token::Greater
-            | Token::GreaterEqual
-            | Token::Lesser
-            | Token::LesserEqual => Precedence::Comparison,
-            // Assignment.
-            Token::Equal => Precedence::Assignment,
-            // Call.
-            Token::LParen => Precedence::Call,
-            _ => Precedence::None,
+    /// Push a statement that starts at token index `start` and ends at the
+    /// previously consumed token to the AST.
+    fn push_stmt(&mut self, stmt: Stmt, start: usize) -> StmtRef {
+        let default_initialized = std::mem::take(&mut self.default_initialized)
+            && matches!(stmt, Stmt::LocalVariable { .. });
+        let const_qualified = std::mem::take(&mut self.const_qualified)
+            && matches!(stmt, Stmt::LocalVariable { .. });
+        let static_local = std::mem::take(&mut self.static_local)
+            && matches!(stmt, Stmt::LocalVariable { .. });
+        let volatile_qualified = std::mem::take(&mut self.volatile_qualified)
+            && matches!(stmt, Stmt::LocalVariable { .. });
+        let stmt_ref = self.ast.push_stmt(stmt);
+        self.ast.set_stmt_span(stmt_ref, self.span_from(start));
+        if default_initialized {
+            self.ast.set_default_initialized(stmt_ref);
+        }
+        if const_qualified {
+            self.ast.set_const(AnyRef::Stmt(stmt_ref));
+        }
+        if static_local {
+            self.ast.set_static(stmt_ref);
+        }
+        if volatile_qualified {
+            self.ast.set_volatile(AnyRef::Stmt(stmt_ref));
+        }
+        stmt_ref
+    }
+
+    /// Record the source location of an expression that starts at token
+    /// index `start`, returns the reference of this occurrence since an
+    /// interned expression shared with others gets its own node.
+    fn set_expr_span(&mut self, expr_ref: ExprRef, start: usize) -> ExprRef {
+        let span = self.span_from(start);
+        if !span.is_empty()
+            && self
+                .ast
+                .expr_span(expr_ref)
+                .is_some_and(|span| span.is_empty())
+        {
+            let expr_ref = self.ast.unshare(expr_ref);
+            self.ast.set_expr_span(expr_ref, span);
+            return expr_ref;
+        }
+        expr_ref
+    }
+
+    /// Returns the source location covering the tokens from index `start`
+    /// up to the previously consumed token.
+    fn span_from(&self, start: usize) -> Span {
+        match (self.spans.get(start), self.spans.get(self.cursor - 1)) {
+            (Some(first), Some(last)) if start < self.cursor => {
+                Span::new(first.start, last.end)
+            }
+            _ => Span::default(),
         }
     }
 
@@ -614,6 +1200,12 @@ impl Parser {
         &self.tokens[self.cursor]
     }
 
+    /// Peek and return a reference to the token following the next one without
+    /// moving the cursor position.
+    fn peek_next(&self) -> &Token {
+        self.tokens.get(self.cursor + 1).unwrap_or(&Token::Eof)
+    }
+
     /// Return the previously consumed token.
     fn prev(&self) -> &Token {
         &self.tokens[self.cursor - 1]
@@ -627,8 +1219,13 @@ impl Parser {
 
 #[cfg(test)]
 mod tests {
-    use crate::parser::Parser;
+    use crate::ast::{
+        AnyRef, BinaryOperator, Decl, DeclRef, Expr, Reclaimed, Ref, Stmt,
+    };
+    use crate::limits::LimitError;
+    use crate::parser::{Lang, Parser};
     use crate::scanner::Scanner;
+    use crate::token::Span;
 
     // Macro to generate test cases.
     macro_rules! test_parser {
@@ -913,4 +1510,380 @@ Stmt(Return(0)),
 Stmt(Return(1)),
 }"
     );
+
+    test_parser!(
+        can_parse_declarations_with_several_declarators,
+        "int main() { int a = 1, b = a + 1, c; return c; }",
+        "Stmt(VAR(INT_TYPE, a, 1)),
+Stmt(VAR(INT_TYPE, b, Add(Named(a), 1))),
+Stmt(VAR(INT_TYPE, c, 0)),
+Stmt(Return(Named(c))),"
+    );
+
+    test_parser!(
+        can_parse_empty_statements,
+        "int main() { int a = 1; ; if (a > 0); else { a = 2; } return a; }",
+        "Stmt(VAR(INT_TYPE, a, 1)),
+Stmt(EMPTY),
+Stmt(IF(Greater(Named(a), 0), EMPTY, Block {
+Stmt(Expr(Assign(Named(a), 2))),
+})),
+Stmt(Return(Named(a))),"
+    );
+
+    test_parser!(
+        can_parse_goto_and_label_statements,
+        r#"int main() {
+            int i = 0;
+            loop:
+            i = i + 1;
+            if (i < 10) {
+                goto loop;
+            }
+            return i;
+        }"#,
+        "Block {
+Stmt(VAR(INT_TYPE, i, 0)),
+Stmt(LABEL(loop)),
+Stmt(Expr(Assign(Named(i), Add(Named(i), 1)))),
+Stmt(IF(Lesser(Named(i), 10), Block {
+Stmt(GOTO(loop)),
+})),
+Stmt(Return(Named(i))),
+}"
+    );
+
+    test_parser!(
+        can_parse_switch_statements,
+        r#"int main() {
+            int x = 2;
+            switch (x) {
+            case -1:
+                x = 0;
+                break;
+            case 2:
+            default:
+                x = 1;
+            }
+            return x;
+        }"#,
+        "Block {
+Stmt(VAR(INT_TYPE, x, 2)),
+Stmt(SWITCH(Named(x), Block {
+Stmt(CASE(-1)),
+Stmt(Expr(Assign(Named(x), 0))),
+Stmt(BREAK),
+Stmt(CASE(2)),
+Stmt(DEFAULT),
+Stmt(Expr(Assign(Named(x), 1))),
+})),
+Stmt(Return(Named(x))),
+}"
+    );
+
+    test_parser!(
+        can_parse_inline_assembly,
+        r#"int main() {
+            asm("nop");
+            return 0;
+        }"#,
+        "Block {
+Stmt(ASM(nop)),
+Stmt(Return(0)),
+}"
+    );
+
+    #[test]
+    fn can_parse_with_interning() {
+        let source = "int main() { int a = 2; return a * 3 + a * 3; }";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens).with_interning();
+        parser.parse();
+        let ast = parser.ast();
+        let sum = ast
+            .expressions()
+            .iter()
+            .find_map(|expr| match expr {
+                Expr::BinOp {
+                    left,
+                    operator: BinaryOperator::Add,
+                    right,
+                } => Some((*left, *right)),
+                _ => None,
+            })
+            .expect("expected an addition");
+        assert_eq!(sum.0, sum.1);
+        assert!(ast
+            .to_string()
+            .contains("Add(Mul(Named(a), 3), Mul(Named(a), 3))"));
+    }
+
+    #[test]
+    fn can_keep_spans_and_groupings_per_occurrence() {
+        let source = "int main() { int a = 2; return a * 3 + (a * 3); }";
+        let tokens = Scanner::new(source).scan_with_spans().unwrap();
+        let mut parser = Parser::with_spans(&tokens).with_interning();
+        parser.parse();
+        let ast = parser.ast();
+        let (left, right) = ast
+            .expressions()
+            .iter()
+            .find_map(|expr| match expr {
+                Expr::BinOp {
+                    left,
+                    operator: BinaryOperator::Add,
+                    right,
+                } => Some((*left, *right)),
+                _ => None,
+            })
+            .expect("expected an addition");
+        assert_eq!(ast.canonical(left), ast.canonical(right));
+        assert!(!ast.is_grouped(left));
+        assert!(ast.is_grouped(right));
+        assert!(ast
+            .to_string()
+            .contains("Add(Mul(Named(a), 3), Grouping(Mul(Named(a), 3)))"));
+        let second = source.rfind("a * 3").unwrap();
+        assert_eq!(ast.node_at(source, second + 2), Some(AnyRef::Expr(right)));
+
+        // Without spans the grouped occurrence still gets its own node.
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens).with_interning();
+        parser.parse();
+        assert!(parser
+            .ast()
+            .to_string()
+            .contains("Add(Mul(Named(a), 3), Grouping(Mul(Named(a), 3)))"));
+    }
+
+    #[test]
+    fn can_record_parentheses_without_nodes() {
+        let source = "int main() { return (1 + 2) * 3; }";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let ast = parser.ast();
+        let (left, right) = ast
+            .expressions()
+            .iter()
+            .find_map(|expr| match expr {
+                Expr::BinOp {
+                    left,
+                    operator: BinaryOperator::Mul,
+                    right,
+                } => Some((*left, *right)),
+                _ => None,
+            })
+            .expect("expected a multiplication");
+        assert!(matches!(
+            ast.get_expr(left),
+            Some(Expr::BinOp {
+                operator: BinaryOperator::Add,
+                ..
+            })
+        ));
+        assert!(ast.is_grouped(left));
+        assert!(!ast.is_grouped(right));
+        assert!(ast
+            .to_string()
+            .contains("Return(Mul(Grouping(Add(1, 2)), 3))"));
+    }
+
+    #[test]
+    fn can_compact_orphaned_nodes() {
+        let source = r#"int main() {
+            int a = 2;
+            if (a > 1) {
+                a = a - 1;
+            }
+            return a * 3;
+        }"#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let mut ast = parser.ast().clone();
+        let expected = ast.to_string();
+        let (stmts, exprs) = (ast.statements().len(), ast.expressions().len());
+
+        // Orphan nodes as a rewrite replacing them would.
+        let orphan = ast.push_expr(Expr::IntLiteral(42));
+        ast.push_stmt(Stmt::Return(orphan));
+        ast.push_expr(Expr::Named("a".to_string()));
+
+        let reclaimed = ast.compact();
+        assert_eq!(reclaimed.statements, 1);
+        assert_eq!(reclaimed.expressions, 2);
+        assert_eq!(ast.statements().len(), stmts);
+        assert_eq!(ast.expressions().len(), exprs);
+        assert_eq!(ast.to_string(), expected);
+        assert_eq!(ast.compact(), Reclaimed::default());
+    }
+
+    test_parser!(
+        can_parse_left_associative_operators,
+        "int x = a - b - c / d / e;",
+        "VAR(INT_TYPE, x, Sub(Sub(Named(a), Named(b)), Div(Div(Named(c), Named(d)), Named(e))))"
+    );
+
+    test_parser!(
+        can_parse_right_associative_assignment,
+        "int main() { a = b = c + 1; }",
+        "Assign(Named(a), Assign(Named(b), Add(Named(c), 1)))"
+    );
+
+    test_parser!(
+        can_parse_operators_by_precedence,
+        "bool x = a + b * c < d == e || f && !g;",
+        "VAR(BOOL_TYPE, x, Or(Equal(Lesser(Add(Named(a), Mul(Named(b), Named(c))), Named(d)), Named(e)), And(Named(f), Not(Named(g)))))"
+    );
+
+    #[test]
+    fn can_parse_nested_expressions_within_depth_limit() {
+        let source = format!("int x = {}1;", "-".repeat(200));
+        let tokens = Scanner::new(&source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        assert_eq!(parser.ast().expressions().len(), 201);
+    }
+
+    #[test]
+    #[should_panic(expected = "Nesting depth exceeds the limit of 256")]
+    fn can_reject_deeply_nested_expressions() {
+        let source = format!("int x = {}1;", "-".repeat(1000));
+        let tokens = Scanner::new(&source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+    }
+
+    #[test]
+    #[should_panic(expected = "Nesting depth exceeds the limit of 4")]
+    fn can_reject_deeply_nested_statements() {
+        let source = "int main() { { { { { return 0; } } } } }";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens).with_max_depth(4);
+        parser.parse();
+    }
+
+    #[test]
+    #[should_panic(expected = "`switch` requires --lang=c89subset.")]
+    fn can_gate_syntax_by_language_level() {
+        let source = "int main() { switch (1) { default: break; } return 0; }";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens).with_lang(Lang::C89Subset);
+        parser.parse();
+        let mut parser = Parser::new(&tokens).with_lang(Lang::Min);
+        parser.parse();
+    }
+
+    test_parser!(
+        can_parse_static_function_declarations,
+        "static int helper(int a) { return a; }",
+        "STATIC FUNCTION(helper, INT_TYPE, ARGS(ARG(INT_TYPE, a))"
+    );
+
+    test_parser!(
+        can_parse_function_attributes,
+        "[[optnone, optsize]] static int helper(int a) { return a; }",
+        "[[optnone, optsize]] STATIC FUNCTION(helper, INT_TYPE"
+    );
+
+    #[test]
+    fn can_limit_ast_size() {
+        let tokens = Scanner::new("int main() { return 1 + 2 * 3; }")
+            .scan()
+            .unwrap();
+        let mut parser = Parser::new(&tokens).with_max_nodes(16);
+        parser.parse();
+        assert_eq!(parser.ast().node_count(), 8);
+
+        let payload = std::panic::catch_unwind(|| {
+            Parser::new(&tokens).with_max_nodes(4).parse();
+        })
+        .unwrap_err();
+        assert_eq!(
+            payload.downcast_ref::<LimitError>(),
+            Some(&LimitError::ast_nodes(4))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Attributes only apply to function definitions")]
+    fn can_reject_attributes_on_variables() {
+        let tokens = Scanner::new("[[optnone]] int x = 1;").scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+    }
+
+    #[test]
+    fn can_parse_const_qualifiers() {
+        let source =
+            "const int n = 4; int main() { const int m = n; int x = m; }";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let ast = parser.ast();
+        assert!(ast.is_const(AnyRef::Decl(DeclRef::new(0))));
+        let Some(Decl::Function { body, .. }) = ast.declarations().get(1)
+        else {
+            unreachable!("Expected a function declaration");
+        };
+        let Some(Stmt::Block(stmts)) = ast.get_stmt(*body) else {
+            unreachable!("Expected a block statement");
+        };
+        assert!(ast.is_const(AnyRef::Stmt(stmts[0])));
+        assert!(!ast.is_const(AnyRef::Stmt(stmts[1])));
+    }
+
+    #[test]
+    #[should_panic(expected = "Const variable `m` must be initialized.")]
+    fn can_reject_uninitialized_const_variables() {
+        let tokens = Scanner::new("int main() { const int m; return 0; }")
+            .scan()
+            .unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+    }
+
+    test_parser!(
+        can_parse_cast_expressions,
+        "int main() { bool b = (bool) 2; return (int) b + 1; }",
+        "Return(Add(Cast(INT_TYPE, Named(b)), 1))"
+    );
+
+    test_parser!(
+        can_parse_extern_declarations,
+        "extern int putchar(int c); int main() { return putchar(65); }",
+        "EXTERN(putchar, INT_TYPE, ARGS(ARG(INT_TYPE, c)))"
+    );
+
+    #[test]
+    fn can_record_statement_spans() {
+        let source = "int main() {\n    int a = 1;\n    return a;\n}";
+        let tokens = Scanner::new(source).scan_with_spans().unwrap();
+        let mut parser = Parser::with_spans(&tokens);
+        parser.parse();
+        let ast = parser.ast();
+        let spans = match ast.declarations().first() {
+            Some(Decl::Function { body, .. }) => match ast.get_stmt(*body) {
+                Some(Stmt::Block(stmts)) => stmts
+                    .iter()
+                    .map(|stmt_ref| ast.stmt_span(*stmt_ref).unwrap())
+                    .collect::<Vec<_>>(),
+                _ => unreachable!("expected function body to be a block"),
+            },
+            _ => unreachable!("expected a function declaration"),
+        };
+        let text = spans
+            .iter()
+            .map(|span| {
+                source
+                    .chars()
+                    .skip(span.start)
+                    .take(span.len())
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(text, vec!["int a = 1;", "return a;"]);
+        assert_eq!(ast.decl_span(DeclRef::new(0)), Some(Span::new(0, 43)));
+    }
 }
@@ -0,0 +1,212 @@
+//! Reports rendering the compiler output for external viewers.
+//!
+//! The HTML optimization report renders the compiled source with the remarks
+//! of the passes that fired next to it, in the spirit of Compiler Explorer's
+//! optimization pipeline view. The Compiler Explorer output lists the IR in
+//! the JSON format Compiler Explorer expects from custom compilers.
+//!
+//! The IR doesn't carry source locations so remarks and IR lines are mapped
+//! to the line declaring their function.
+use std::collections::HashMap;
+
+use crate::ast::{Decl, DeclRef, Ref, AST};
+use crate::diagnostics::escape as json;
+use crate::ir::{Function, Import};
+use crate::optim::Remark;
+
+const STYLE: &str = "body { font-family: monospace; }
+table { border-collapse: collapse; }
+td { padding: 0 8px; vertical-align: top; }
+td.line { color: #888; text-align: right; }
+td.remarks { color: #0a6e31; }
+pre { margin: 0; }";
+
+/// Render the HTML report of `source`, `ast` is the AST parsed with spans
+/// from `source` and `remarks` the remarks of the optimization pipeline.
+///
+/// Remarks on functions without a declaration in `source` are listed before
+/// the source.
+#[must_use]
+pub fn render(source: &str, ast: &AST, remarks: &[Remark]) -> String {
+    let lines = function_lines(source, ast);
+    let mut annotations: HashMap<usize, Vec<&Remark>> = HashMap::new();
+    let mut unattached = vec![];
+    for remark in remarks {
+        match lines.get(remark.function.as_str()) {
+            Some(line) => annotations.entry(*line).or_default().push(remark),
+            None => unattached.push(remark),
+        }
+    }
+
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>glouton optimization report</title>\n<style>\n{STYLE}\n</style>\n\
+         </head>\n<body>\n"
+    );
+    if !unattached.is_empty() {
+        html.push_str("<ul>\n");
+        for remark in unattached {
+            html.push_str(&format!(
+                "<li>{}</li>\n",
+                escape(&remark.to_string())
+            ));
+        }
+        html.push_str("</ul>\n");
+    }
+    html.push_str("<table>\n");
+    for (i, code) in source.lines().enumerate() {
+        let remarks = annotations
+            .get(&(i + 1))
+            .map(|remarks| {
+                remarks
+                    .iter()
+                    .map(|remark| escape(&remark.to_string()))
+                    .collect::<Vec<_>>()
+                    .join("<br>")
+            })
+            .unwrap_or_default();
+        html.push_str(&format!(
+            "<tr><td class=\"line\">{}</td><td><pre>{}</pre></td>\
+             <td class=\"remarks\">{remarks}</td></tr>\n",
+            i + 1,
+            escape(code)
+        ));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+/// Returns the IR of the program as the JSON object Compiler Explorer reads
+/// from custom compilers, an `asm` array with one object per line of IR.
+///
+/// Import and function headers map to the line declaring them in `source`,
+/// other lines have a `null` source.
+#[must_use]
+pub fn compiler_explorer(
+    source: &str,
+    ast: &AST,
+    imports: &[Import],
+    functions: &[Function],
+) -> String {
+    let lines = function_lines(source, ast);
+    let mut asm = vec![];
+    let mut push = |text: &str, line: Option<&usize>| {
+        let source = line.map_or_else(
+            || "null".to_string(),
+            |line| format!(r#"{{"file":null,"line":{line}}}"#),
+        );
+        asm.push(format!(r#"{{"text":{},"source":{source}}}"#, json(text)));
+    };
+    for import in imports {
+        push(&import.to_string(), lines.get(import.name()));
+    }
+    for function in functions {
+        let text = function.to_string();
+        let mut text = text.lines();
+        if let Some(header) = text.next() {
+            push(header, lines.get(function.name()));
+        }
+        text.for_each(|line| push(line, None));
+    }
+    format!(r#"{{"asm":[{}]}}"#, asm.join(","))
+}
+
+/// Returns the line, starting at 1, on which each function is declared.
+fn function_lines<'a>(source: &str, ast: &'a AST) -> HashMap<&'a str, usize> {
+    ast.declarations()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, decl)| {
+            let (Decl::Function { name, .. } | Decl::Extern { name, .. }) =
+                decl
+            else {
+                return None;
+            };
+            let span = ast.decl_span(DeclRef::new(i))?;
+            let line = source
+                .chars()
+                .take(span.start)
+                .filter(|c| *c == '\n')
+                .count();
+            Some((name.as_str(), line + 1))
+        })
+        .collect()
+}
+
+/// Returns `text` with the HTML special characters escaped.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::IRBuilder;
+    use crate::optim::PassManager;
+    use crate::parser::Parser;
+    use crate::report::{compiler_explorer, render};
+    use crate::scanner::Scanner;
+    use crate::sema::analyze;
+
+    #[test]
+    fn can_render_optimization_reports() {
+        let source =
+            "int main() {\n    bool b = 1 < 2;\n    return 2 + 3;\n}\n";
+        let tokens = Scanner::new(source).scan_with_spans().unwrap();
+        let mut parser = Parser::with_spans(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let mut remarks = vec![];
+        for function in irgen.functions_mut() {
+            PassManager::oz().run_with_remarks(function, &mut remarks);
+        }
+        assert!(remarks.iter().any(|remark| remark.pass == "InstCombine"));
+        let html = render(source, parser.ast(), &remarks);
+        assert!(html.contains(
+            "<tr><td class=\"line\">1</td><td><pre>int main() {</pre></td>\
+             <td class=\"remarks\">InstCombine changed @main: code size"
+        ));
+        assert!(html.contains("<pre>    bool b = 1 &lt; 2;</pre>"));
+    }
+
+    #[test]
+    fn can_emit_compiler_explorer_json() {
+        let source = "extern int putchar(int c);\n\nint main() {\n    return putchar(65);\n}\n";
+        let tokens = Scanner::new(source).scan_with_spans().unwrap();
+        let mut parser = Parser::with_spans(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        assert_eq!(
+            compiler_explorer(
+                source,
+                parser.ast(),
+                irgen.imports(),
+                irgen.functions()
+            ),
+            concat!(
+                r#"{"asm":["#,
+                r#"{"text":"@extern putchar(c: int): int","source":{"file":null,"line":1}},"#,
+                r#"{"text":"@main: int {","source":{"file":null,"line":3}},"#,
+                r#"{"text":"   %v0: int = const 65","source":null},"#,
+                r#"{"text":"   %v1: int = call @putchar %v0","source":null},"#,
+                r#"{"text":"   ret %v1","source":null},"#,
+                r#"{"text":"}","source":null}]}"#
+            )
+        );
+    }
+}
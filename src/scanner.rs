@@ -1,4 +1,5 @@
-use crate::token::{Token, KEYWORDS};
+use crate::builtins::unescape;
+use crate::token::{self, Span, Token, KEYWORDS};
 use std::error::Error;
 use std::fmt;
 
@@ -12,13 +13,46 @@ pub struct Scanner {
     start: usize,
     // Walking cursor used to walk and process tokens.
     cursor: usize,
+    // Position of the last character returned by `next`, line splices
+    // before it are skipped.
+    previous: usize,
     // Line in the input we're currently processing, incremented
     // on newlines.
     line: usize,
     // Vec of individual chars of the input.
     source: Vec<char>,
+    // Whether comments are kept as `Token::Comment` or discarded.
+    keep_comments: bool,
+    // Current nesting depth of brackets (parenthesis, braces and square
+    // brackets).
+    depth: usize,
+    // Maximum nesting depth of brackets.
+    max_depth: usize,
+    // Whether trigraphs are translated or reported.
+    trigraphs: bool,
+    // Whether identifiers can contain non-ASCII letters and digits.
+    unicode_identifiers: bool,
 }
 
+/// Trigraph sequences without their `??` prefix and the character they
+/// stand for.
+const TRIGRAPHS: &[(char, char)] = &[
+    ('=', '#'),
+    ('(', '['),
+    ('/', '\\'),
+    (')', ']'),
+    ('\'', '^'),
+    ('<', '{'),
+    ('!', '|'),
+    ('>', '}'),
+    ('-', '~'),
+];
+
+/// Default maximum nesting depth of brackets in the scanner and of
+/// expressions and statements in the parser, deeper inputs are rejected
+/// rather than overflowing the stack of the recursive passes.
+pub const MAX_NESTING_DEPTH: usize = 256;
+
 /// Scanner error type is used to report scanning errors to the user.
 #[derive(Debug, Clone)]
 pub struct ScanError {
@@ -30,6 +64,18 @@ impl ScanError {
     const fn new(line: usize, details: String) -> Self {
         Self { details, line }
     }
+
+    /// Returns the error message without the location.
+    #[must_use]
+    pub fn details(&self) -> &str {
+        &self.details
+    }
+
+    /// Returns the line the error was found at.
+    #[must_use]
+    pub const fn line(&self) -> usize {
+        self.line
+    }
 }
 
 impl fmt::Display for ScanError {
@@ -51,20 +97,69 @@ impl Scanner {
         Scanner {
             start: 0,
             cursor: 0,
+            previous: 0,
             line: 1,
             source: source.chars().collect(),
+            keep_comments: false,
+            depth: 0,
+            max_depth: MAX_NESTING_DEPTH,
+            trigraphs: false,
+            unicode_identifiers: false,
         }
     }
 
+    /// Set the maximum nesting depth of brackets.
+    #[must_use]
+    pub fn with_max_depth(mut self, max_depth: usize) -> Scanner {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Keep comments in the token stream, used by tools that need to
+    /// reproduce the source code such as the formatter.
+    #[must_use]
+    pub fn with_comments(mut self) -> Scanner {
+        self.keep_comments = true;
+        self
+    }
+
+    /// Translate trigraphs (`??=` for `#`, `??<` for `{`...) before
+    /// scanning, they're reported as errors otherwise.
+    #[must_use]
+    pub fn with_trigraphs(mut self) -> Scanner {
+        self.trigraphs = true;
+        self
+    }
+
+    /// Accept Unicode letters and digits in identifiers, such as `résumé`.
+    #[must_use]
+    pub fn with_unicode_identifiers(mut self) -> Scanner {
+        self.unicode_identifiers = true;
+        self
+    }
+
     /// Lex the passed source code and returns a list of tokens.
     /// # Errors
     /// Returns an error when it encounters an unknown token.
     pub fn scan(&mut self) -> Result<Vec<Token>, ScanError> {
+        Ok(self
+            .scan_with_spans()?
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect())
+    }
+
+    /// Lex the passed source code and returns a list of tokens alongside
+    /// their location in the source code.
+    /// # Errors
+    /// Returns an error when it encounters an unknown token.
+    pub fn scan_with_spans(&mut self) -> Result<Vec<(Token, Span)>, ScanError> {
         let mut tokens = Vec::new();
+        let mut spans = Vec::new();
         while let Some(ch) = self.next() {
             // Grab the first lexeme as we will need to used it to scan
             // multi-character tokens such as identifiers, numbers and strings
-            self.start = self.cursor - 1;
+            self.start = self.previous;
             match ch {
                 '&' if self.consume('&') => tokens.push(Token::And),
                 '|' if self.consume('|') => tokens.push(Token::Or),
@@ -72,13 +167,61 @@ impl Scanner {
                 '=' if self.consume('=') => tokens.push(Token::EqualEqual),
                 '>' if self.consume('=') => tokens.push(Token::GreaterEqual),
                 '<' if self.consume('=') => tokens.push(Token::LesserEqual),
-                '/' if self.consume('/') || self.consume('*') => self.comment(),
-                '(' => tokens.push(Token::LParen),
-                ')' => tokens.push(Token::RParen),
-                '{' => tokens.push(Token::LBrace),
-                '}' => tokens.push(Token::RBrace),
-                '[' => tokens.push(Token::LBracket),
-                ']' => tokens.push(Token::RBracket),
+                // Digraphs are alternative spellings of brackets.
+                '<' if self.consume(':') => {
+                    tokens.push(self.open(Token::LBracket)?);
+                }
+                ':' if self.consume('>') => {
+                    tokens.push(self.close(Token::RBracket));
+                }
+                '<' if self.consume('%') => {
+                    tokens.push(self.open(Token::LBrace)?);
+                }
+                '%' if self.consume('>') => {
+                    tokens.push(self.close(Token::RBrace));
+                }
+                '%' if self.consume(':') => {
+                    return Err(ScanError::new(
+                        self.line,
+                        "Digraph `%:` stands for `#`, preprocessing directives \
+                         aren't supported"
+                            .to_string(),
+                    ));
+                }
+                '?' if self.peek() == '?' => {
+                    let trigraph =
+                        self.source.get(self.cursor + 1).and_then(|third| {
+                            TRIGRAPHS.iter().find(|(ch, _)| ch == third)
+                        });
+                    return Err(ScanError::new(
+                        self.line,
+                        match trigraph {
+                            Some((third, ch)) => format!(
+                                "Trigraph `??{third}` is only translated to \
+                                 `{ch}` with --trigraphs"
+                            ),
+                            None => "Unrecognized token ?".to_string(),
+                        },
+                    ));
+                }
+                '/' if self.consume('/') => {
+                    self.comment();
+                    if self.keep_comments {
+                        tokens.push(self.lexeme_comment());
+                    }
+                }
+                '/' if self.consume('*') => {
+                    self.block_comment()?;
+                    if self.keep_comments {
+                        tokens.push(self.lexeme_comment());
+                    }
+                }
+                '(' => tokens.push(self.open(Token::LParen)?),
+                ')' => tokens.push(self.close(Token::RParen)),
+                '{' => tokens.push(self.open(Token::LBrace)?),
+                '}' => tokens.push(self.close(Token::RBrace)),
+                '[' => tokens.push(self.open(Token::LBracket)?),
+                ']' => tokens.push(self.close(Token::RBracket)),
                 ';' => tokens.push(Token::SemiColon),
                 ':' => tokens.push(Token::Colon),
                 ',' => tokens.push(Token::Comma),
@@ -91,9 +234,12 @@ impl Scanner {
                 '+' => tokens.push(Token::Plus),
                 '-' => tokens.push(Token::Minus),
                 '"' => tokens.push(self.string()),
-                '\'' => tokens.push(self.char()),
+                '\'' => tokens.push(self.char()?),
                 '0'..='9' => tokens.push(self.integer()),
                 '_' | 'a'..='z' | 'A'..='Z' => tokens.push(self.identifier()),
+                ch if self.unicode_identifiers && ch.is_alphabetic() => {
+                    tokens.push(self.identifier());
+                }
                 // Do nothing on whitespace.
                 ' ' | '\r' | '\t' => (),
                 // Increment line number on newlines.
@@ -105,30 +251,110 @@ impl Scanner {
                     ))
                 }
             }
+            // Whitespace and discarded comments don't produce tokens.
+            if spans.len() < tokens.len() {
+                spans.push(Span::new(self.start, self.cursor));
+            }
         }
         tokens.push(Token::Eof);
-        Ok(tokens)
+        spans.push(Span::new(self.source.len(), self.source.len()));
+        Ok(tokens.into_iter().zip(spans).collect())
+    }
+
+    // Track an opening bracket, fails if it's nested too deep.
+    fn open(&mut self, bracket: Token) -> Result<Token, ScanError> {
+        self.depth += 1;
+        if self.depth > self.max_depth {
+            return Err(ScanError::new(
+                self.line,
+                format!(
+                    "Brackets nested deeper than the limit of {}",
+                    self.max_depth
+                ),
+            ));
+        }
+        Ok(bracket)
+    }
+
+    // Track a closing bracket.
+    fn close(&mut self, bracket: Token) -> Token {
+        self.depth = self.depth.saturating_sub(1);
+        bracket
+    }
+
+    // Returns the character at `index` after trigraph translation and the
+    // number of source characters spelling it.
+    fn raw(&self, index: usize) -> Option<(char, usize)> {
+        let ch = *self.source.get(index)?;
+        if self.trigraphs
+            && ch == '?'
+            && self.source.get(index + 1) == Some(&'?')
+        {
+            let third = self.source.get(index + 2);
+            if let Some((_, ch)) =
+                TRIGRAPHS.iter().find(|(ch, _)| Some(ch) == third)
+            {
+                return Some((*ch, 3));
+            }
+        }
+        Some((ch, 1))
+    }
+
+    // Returns the position of the character at `index`, the character and
+    // the position following it. Line splices (a backslash followed by a
+    // newline) are skipped as if they weren't in the source.
+    fn decode(&self, mut index: usize) -> Option<(usize, char, usize)> {
+        loop {
+            let (ch, len) = self.raw(index)?;
+            if ch == '\\' {
+                let mut newline = index + len;
+                if self.source.get(newline) == Some(&'\r') {
+                    newline += 1;
+                }
+                if self.source.get(newline) == Some(&'\n') {
+                    index = newline + 1;
+                    continue;
+                }
+            }
+            return Some((index, ch, index + len));
+        }
     }
 
     // Return next char and increment cursor position.
     fn next(&mut self) -> Option<char> {
-        // Refactor to iterator style
-        if !self.eof() {
-            self.cursor += 1;
-            return Some(self.source[self.cursor - 1]);
-        }
-        None
+        let (start, ch, end) = self.decode(self.cursor)?;
+        // Newlines of skipped line splices still count.
+        self.line += self.source[self.cursor..start]
+            .iter()
+            .filter(|ch| **ch == '\n')
+            .count();
+        self.previous = start;
+        self.cursor = end;
+        Some(ch)
     }
 
     // Match current character, advancing the cursor if we match `expected`.
     fn consume(&mut self, expected: char) -> bool {
-        if self.eof() || self.source[self.cursor] != expected {
+        if self.eof() || self.peek() != expected {
             return false;
         }
-        self.cursor += 1;
+        self.next();
         true
     }
 
+    // Returns the source between `start` and `end` without line splices and
+    // with trigraphs translated.
+    fn lexeme(&self, mut start: usize, end: usize) -> String {
+        let mut lexeme = String::new();
+        while let Some((_, ch, next)) =
+            self.decode(start).filter(|(_, _, next)| *next <= end)
+        {
+            lexeme.push(ch);
+            start = next;
+        }
+        lexeme
+    }
+
     // Consume comments.
     fn comment(&mut self) {
         while self.peek() != '\n' && !self.eof() {
@@ -137,12 +363,30 @@ impl Scanner {
         }
     }
 
+    // Consume block comments, block comments can span multiple lines.
+    fn block_comment(&mut self) -> Result<(), ScanError> {
+        let line = self.line;
+        while !self.eof() {
+            match self.next() {
+                Some('*') if self.consume('/') => return Ok(()),
+                Some('\n') => self.line += 1,
+                _ => (),
+            }
+        }
+        Err(ScanError::new(
+            line,
+            "Unterminated block comment".to_string(),
+        ))
+    }
+
+    // Build a comment token from the current lexeme.
+    fn lexeme_comment(&self) -> Token {
+        Token::Comment(self.lexeme(self.start, self.cursor))
+    }
+
     // Peek next character without advancing the cursor
     fn peek(&self) -> char {
-        if self.eof() {
-            return '\0';
-        }
-        self.source[self.cursor]
+        self.decode(self.cursor).map_or('\0', |(_, ch, _)| ch)
     }
 
     // Scan integer literal.
@@ -151,9 +395,7 @@ impl Scanner {
             self.next();
         }
 
-        let int_literal = self.source[self.start..self.cursor]
-            .iter()
-            .collect::<String>();
+        let int_literal = self.lexeme(self.start, self.cursor);
         let value = int_literal
             .as_str()
             .parse::<i32>()
@@ -173,32 +415,56 @@ impl Scanner {
         // Consume closing quote
         self.next();
         // Trim surrounding quotes and build the string literal.
-        let str_literal = self.source[self.start + 1..self.cursor - 1]
-            .iter()
-            .collect::<String>();
+        let lexeme = self
+            .lexeme(self.start, self.cursor)
+            .chars()
+            .collect::<Vec<_>>();
+        let str_literal =
+            lexeme[1..lexeme.len() - 1].iter().collect::<String>();
         Token::StringLiteral(str_literal)
     }
-    // Scan literal characters enclosed in single quotes.
-    fn char(&mut self) -> Token {
+    // Scan literal characters enclosed in single quotes, escape sequences
+    // are resolved as in string literals.
+    fn char(&mut self) -> Result<Token, ScanError> {
         while self.peek() != '\'' && !self.eof() {
+            // Skip escaped characters so `'\''` is a quote.
+            if self.peek() == '\\' {
+                self.next();
+            }
             self.next();
         }
         // Consume closing quote.
         self.next();
         // Trim surrounding quotes and build the char literal.
-        let char_literal = self.source[self.start + 1];
-        Token::CharLiteral(char_literal)
+        let lexeme = self.lexeme(self.start, self.cursor);
+        let inner = lexeme
+            .strip_prefix('\'')
+            .and_then(|lexeme| lexeme.strip_suffix('\''))
+            .unwrap_or_default();
+        let error = |details| Err(ScanError::new(self.line, details));
+        let unescaped = match unescape(inner) {
+            Ok(unescaped) => unescaped,
+            Err(err) => return error(err.details().to_string()),
+        };
+        let mut chars = unescaped.chars();
+        match (chars.next(), chars.next()) {
+            (Some(ch), None) => Ok(Token::CharLiteral(ch)),
+            _ => error(format!(
+                "Character literal {lexeme} must hold a single character"
+            )),
+        }
     }
 
     // Scan identifiers.
     fn identifier(&mut self) -> Token {
-        while self.peek().is_ascii_alphanumeric() {
+        while self.peek().is_ascii_alphanumeric()
+            || self.peek() == '_'
+            || (self.unicode_identifiers && self.peek().is_alphanumeric())
+        {
             self.next();
         }
 
-        let identifier = self.source[self.start..self.cursor]
-            .iter()
-            .collect::<String>();
+        let identifier = self.lexeme(self.start, self.cursor);
 
         if KEYWORDS.contains(&identifier.as_str()) {
             return self.keyword(&identifier);
@@ -222,6 +488,14 @@ impl Scanner {
             "while" => Token::While,
             "for" => Token::For,
             "break" => Token::Break,
+            "switch" => Token::Switch,
+            "case" => Token::Case,
+            "default" => Token::Default,
+            "goto" => Token::Goto,
+            "asm" => Token::Asm,
+            "extern" => Token::Extern,
+            "static" => Token::Static,
+            "volatile" => Token::Volatile,
             "true" => Token::True,
             "false" => Token::False,
             _ => todo!("Unsupported identifier or token: {identifier}"),
@@ -230,14 +504,30 @@ impl Scanner {
 
     // Check if we reached the end of the source.
     fn eof(&self) -> bool {
-        self.cursor >= self.source.len()
+        self.decode(self.cursor).is_none()
     }
 }
 
+/// Returns the index of the token covering the byte offset `position` of
+/// `source` in `tokens` as returned by `Scanner::scan_with_spans`, `None` if
+/// the position falls between tokens.
+#[must_use]
+pub fn token_at(
+    source: &str,
+    tokens: &[(Token, Span)],
+    position: usize,
+) -> Option<usize> {
+    let offsets = token::byte_offsets(source);
+    let index = tokens
+        .partition_point(|(_, span)| span.to_bytes(&offsets).start <= position)
+        .checked_sub(1)?;
+    (position < tokens[index].1.to_bytes(&offsets).end).then_some(index)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::scanner::Scanner;
-    use crate::token::Token;
+    use crate::scanner::{token_at, Scanner};
+    use crate::token::{Span, Token};
 
     // Macro to generate test cases.
     macro_rules! test_scanner {
@@ -353,4 +643,161 @@ mod tests {
             Token::Eof,
         ]
     );
+
+    #[test]
+    fn can_scan_comments() {
+        let source = r#"
+            // Line comment.
+            int a; /* Block comment
+            spanning lines. */
+            int b;
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Int,
+                Token::Identifier("a".to_string()),
+                Token::SemiColon,
+                Token::Int,
+                Token::Identifier("b".to_string()),
+                Token::SemiColon,
+                Token::Eof,
+            ]
+        );
+        let tokens = Scanner::new(source).with_comments().scan().unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Comment("// Line comment.".to_string()),
+                Token::Int,
+                Token::Identifier("a".to_string()),
+                Token::SemiColon,
+                Token::Comment(
+                    "/* Block comment\n            spanning lines. */"
+                        .to_string()
+                ),
+                Token::Int,
+                Token::Identifier("b".to_string()),
+                Token::SemiColon,
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn can_report_unterminated_block_comment() {
+        assert!(Scanner::new("int a; /* no end").scan().is_err());
+    }
+
+    #[test]
+    fn can_report_deeply_nested_brackets() {
+        let source = format!("{}1{}", "(".repeat(300), ")".repeat(300));
+        let err = Scanner::new(&source).scan().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Brackets nested deeper than the limit of 256 at line 1."
+        );
+        // Sequential brackets don't nest.
+        let source = "(1)".repeat(300);
+        assert!(Scanner::new(&source).with_max_depth(1).scan().is_ok());
+    }
+
+    #[test]
+    fn can_scan_line_splices_digraphs_and_trigraphs() {
+        let source = "ret\\\nurn a<:0:> <%%>;\\\r\n";
+        let tokens = Scanner::new(source).scan().unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Return,
+                Token::Identifier("a".to_string()),
+                Token::LBracket,
+                Token::IntLiteral(0),
+                Token::RBracket,
+                Token::LBrace,
+                Token::RBrace,
+                Token::SemiColon,
+                Token::Eof,
+            ]
+        );
+        let mut scanner = Scanner::new("x\\\n\n??<");
+        assert_eq!(
+            scanner.scan().unwrap_err().to_string(),
+            "Trigraph `??<` is only translated to `{` with --trigraphs at line 3."
+        );
+        let tokens = Scanner::new("??<??>").with_trigraphs().scan().unwrap();
+        assert_eq!(tokens, vec![Token::LBrace, Token::RBrace, Token::Eof]);
+    }
+
+    #[test]
+    fn can_scan_escaped_char_literals() {
+        let tokens = Scanner::new(r"'\n' '\'' '\\' '\0'").scan().unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::CharLiteral('\n'),
+                Token::CharLiteral('\''),
+                Token::CharLiteral('\\'),
+                Token::CharLiteral('\0'),
+                Token::Eof,
+            ]
+        );
+        assert_eq!(Token::CharLiteral('\'').lexeme(), r"'\''");
+        let err = Scanner::new("'ab'").scan().unwrap_err();
+        assert_eq!(
+            err.details(),
+            "Character literal 'ab' must hold a single character"
+        );
+    }
+
+    #[test]
+    fn can_scan_token_spans() {
+        let tokens = Scanner::new("a  >= 'c';").scan_with_spans().unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                (Token::Identifier("a".to_string()), Span::new(0, 1)),
+                (Token::GreaterEqual, Span::new(3, 5)),
+                (Token::CharLiteral('c'), Span::new(6, 9)),
+                (Token::SemiColon, Span::new(9, 10)),
+                (Token::Eof, Span::new(10, 10)),
+            ]
+        );
+    }
+
+    #[test]
+    fn can_scan_unicode_identifiers() {
+        assert!(Scanner::new("int café;").scan().is_err());
+        let tokens = Scanner::new("int café = 1;")
+            .with_unicode_identifiers()
+            .scan_with_spans()
+            .unwrap();
+        // Spans count characters, not bytes.
+        assert_eq!(
+            tokens[1],
+            (Token::Identifier("café".to_string()), Span::new(4, 8))
+        );
+        assert_eq!(tokens[2].1, Span::new(9, 10));
+    }
+
+    #[test]
+    fn can_find_tokens_at_positions() {
+        let source = "a  >= 'c';";
+        let tokens = Scanner::new(source).scan_with_spans().unwrap();
+        assert_eq!(token_at(source, &tokens, 0), Some(0));
+        assert_eq!(token_at(source, &tokens, 2), None);
+        assert_eq!(token_at(source, &tokens, 4), Some(1));
+        assert_eq!(token_at(source, &tokens, 9), Some(3));
+        assert_eq!(token_at(source, &tokens, 10), None);
+
+        // Positions are byte offsets, `é` takes two bytes.
+        let source = "/* é */ x = 1;";
+        let tokens = Scanner::new(source).scan_with_spans().unwrap();
+        let position = source.find('x').unwrap();
+        assert_eq!(position, 9);
+        assert_eq!(token_at(source, &tokens, position), Some(0));
+        assert_eq!(token_at(source, &tokens, position + 2), Some(1));
+        assert_eq!(token_at(source, &tokens, source.len() - 1), Some(3));
+    }
 }
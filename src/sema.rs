@@ -7,9 +7,23 @@
 //!
 //! This implementation of semantic analysis mostly focuses on type correctness
 //! and general soundness. Reachability analysis is currently not implemented.
-use std::{borrow::BorrowMut, collections::HashMap, fmt};
-
-use crate::ast::{self, Decl, DeclType, Expr, Ref, Stmt};
+use std::{
+    borrow::BorrowMut,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+};
+
+use crate::ast::{
+    self, Decl, DeclRef, DeclType, Expr, ExprRef, Ref, Stmt, StmtRef,
+};
+use crate::builtins::{
+    parse_format, unescape, AtomicOp, Builtin, MemoryOrder, BUILTIN_PREFIX,
+    EXIT, PRINTF,
+};
+use crate::diagnostics::{Diagnostic, Warning, Warnings};
+use crate::parser::Lang;
+use crate::token::Span;
+use crate::trace;
 
 /// Scope is used to localize the symbol table scope.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -151,12 +165,17 @@ pub struct SymbolTable {
 
 impl fmt::Display for SymbolTable {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for (name, symbol) in &self.global.table {
+        // Names are sorted, hash maps iterate in a different order on every
+        // run.
+        for (name, symbol) in
+            self.global.table.iter().collect::<BTreeMap<_, _>>()
+        {
             writeln!(f, "GLOBAL: {name} @ {symbol}")?
         }
 
         for (level, table) in self.tables.iter().enumerate() {
-            for (name, symbol) in &table.table {
+            for (name, symbol) in table.table.iter().collect::<BTreeMap<_, _>>()
+            {
                 writeln!(f, "LOCAL {level}: {name} @ {symbol}")?
             }
         }
@@ -201,6 +220,31 @@ impl SymbolTable {
         self.global.lookup(name)
     }
 
+    // Rename the local variable or argument `name` visible from the scope at
+    // `start`, returns `false` if no local binding of `name` is visible.
+    //
+    // # Panics
+    //
+    // When `new_name` is already bound in the scope of the renamed binding.
+    pub fn rename(&mut self, name: &str, new_name: &str, start: usize) -> bool {
+        let mut idx = start.min(self.tables.len() - 1);
+        while idx > 0 {
+            if let Some(mut symbol) = self.tables[idx].table.remove(name) {
+                match &mut symbol {
+                    Symbol::LocalVariable { name, .. }
+                    | Symbol::FunctionArgument { name, .. } => {
+                        *name = new_name.to_string();
+                    }
+                    _ => unreachable!("local scopes only bind variables"),
+                }
+                self.tables[idx].bind(new_name, symbol);
+                return true;
+            }
+            idx -= 1;
+        }
+        false
+    }
+
     // Find a symbol in the global scope table.
     pub fn global(&self, name: &str) -> Option<&Symbol> {
         self.global.lookup(name)
@@ -316,6 +360,11 @@ impl<'a> DeclAnalyzer<'a> {
                 return_type,
                 args,
                 ..
+            }
+            | Decl::Extern {
+                name,
+                return_type,
+                args,
             } => {
                 let args = args
                             .iter()
@@ -395,6 +444,10 @@ impl<'a> ast::Visitor<()> for DeclAnalyzer<'a> {
             global_var @ Decl::GlobalVariable { .. } => {
                 self.define_global_binding(global_var)
             }
+            // External functions have no body, only their signature is bound.
+            extern_decl @ Decl::Extern { .. } => {
+                self.define_global_binding(extern_decl)
+            }
         }
     }
     fn visit_expr(&mut self, _: &Expr) {}
@@ -433,7 +486,8 @@ impl<'a> ast::Visitor<()> for DeclAnalyzer<'a> {
             Stmt::While {
                 body: Some(stmt_ref),
                 ..
-            } => {
+            }
+            | Stmt::Switch { body: stmt_ref, .. } => {
                 if let Some(stmt) = self.ast.get_stmt(*stmt_ref) {
                     self.visit_stmt(stmt)
                 }
@@ -456,6 +510,11 @@ struct SemanticAnalyzer<'a> {
     ast: &'a ast::AST,
     symbol_table: &'a SymbolTable,
     current_scope: usize,
+    // Number of enclosing loops and `switch` statements, `break` is only
+    // valid when non zero.
+    breakable: usize,
+    // Language level deciding which builtins exist.
+    lang: Lang,
 }
 
 impl<'a> SemanticAnalyzer<'a> {
@@ -464,6 +523,8 @@ impl<'a> SemanticAnalyzer<'a> {
             ast,
             symbol_table,
             current_scope: 0,
+            breakable: 0,
+            lang: Lang::default(),
         }
     }
 
@@ -475,6 +536,103 @@ impl<'a> SemanticAnalyzer<'a> {
             Scope::Local => self.symbol_table.find(name, self.current_scope),
         }
     }
+    /// Type check a `printf` call, the format must be a string literal and
+    /// the arguments following it match the type of their conversion.
+    fn printf(&self, args: &[ExprRef]) -> DeclType {
+        let format = match args.first().and_then(|arg| self.ast.get_expr(*arg))
+        {
+            Some(ast::Expr::StringLiteral(format)) => format,
+            _ => panic!("Expected `printf` format to be a string literal."),
+        };
+        let directives =
+            parse_format(format).unwrap_or_else(|err| panic!("{err}"));
+        let params = directives
+            .iter()
+            .filter(|directive| directive.takes_arg())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            args.len() - 1,
+            params.len(),
+            "Expected `printf` to have {} arguments after the format got {} in call.",
+            params.len(),
+            args.len() - 1
+        );
+        for (arg_ref, directive) in args[1..].iter().zip(params) {
+            let Some(arg) = self.ast.get_expr(*arg_ref) else {
+                unreachable!(
+                    "Expression at ref {} was not found",
+                    arg_ref.get()
+                )
+            };
+            match (directive.arg_type(), arg) {
+                (Some(t), arg) => assert_eq!(
+                    t,
+                    self.resolve(arg),
+                    "Expected `printf` argument to be of type {t}."
+                ),
+                (None, ast::Expr::StringLiteral(literal)) => {
+                    if let Err(err) = unescape(literal) {
+                        panic!("{err}");
+                    }
+                }
+                (None, _) => panic!(
+                    "Expected `printf` `%s` argument to be a string literal."
+                ),
+            }
+        }
+        DeclType::Int
+    }
+
+    /// Type check a call to the atomic operation `op`, the first argument
+    /// must name an `int` variable and the last one be a memory order the
+    /// operation accepts.
+    fn atomic(&self, op: AtomicOp, args: &[ExprRef]) -> DeclType {
+        let name = op.name();
+        assert_eq!(
+            args.len(),
+            op.arity(),
+            "Expected `{name}` to have {} arguments got {} in call.",
+            op.arity(),
+            args.len()
+        );
+        let args = args
+            .iter()
+            .map(|arg_ref| {
+                self.ast.get_expr(*arg_ref).unwrap_or_else(|| {
+                    unreachable!(
+                        "Expression at ref {} was not found",
+                        arg_ref.get()
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        assert!(
+            matches!(args[0], ast::Expr::Named(..)),
+            "Expected `{name}` first argument to be a variable."
+        );
+        for arg in &args[..args.len() - 1] {
+            assert_eq!(
+                self.resolve(arg),
+                DeclType::Int,
+                "Expected `{name}` argument to be of type INT_TYPE."
+            );
+        }
+        let order = match args[args.len() - 1] {
+            ast::Expr::IntLiteral(value) => MemoryOrder::from_value(*value),
+            _ => None,
+        };
+        match order {
+            Some(order) => assert!(
+                op.accepts(order),
+                "Memory order `{order}` is invalid for `{name}`."
+            ),
+            None => panic!(
+                "Expected `{name}` memory order to be an integer literal between 0 and 5."
+            ),
+        }
+        DeclType::Int
+    }
+
     /// Enter a new scope by increment the current scope pointer.
     fn enter_scope(&mut self) {
         self.current_scope += 1
@@ -485,6 +643,196 @@ impl<'a> SemanticAnalyzer<'a> {
         self.current_scope -= 1
     }
 
+    /// Validate that the initializers of the local variables declared in a
+    /// block only read the variables declared before them, or visible from an
+    /// enclosing scope. Must be called before entering the block scope.
+    ///
+    /// # Panics
+    ///
+    /// Scopes bind every declaration of a block upfront, an initializer
+    /// reading a variable only declared by the same or a later statement of
+    /// the block (`int a = b, b = 1;`) panics.
+    fn check_declaration_order(&self, stmts: &[StmtRef]) {
+        let positions = stmts
+            .iter()
+            .enumerate()
+            .filter_map(|(position, stmt_ref)| {
+                match self.ast.get_stmt(*stmt_ref) {
+                    Some(Stmt::LocalVariable { name, .. }) => {
+                        Some((name.as_str(), position))
+                    }
+                    _ => None,
+                }
+            })
+            .collect::<HashMap<_, _>>();
+        for (position, stmt_ref) in stmts.iter().enumerate() {
+            let Some(Stmt::LocalVariable { name, value, .. }) =
+                self.ast.get_stmt(*stmt_ref)
+            else {
+                continue;
+            };
+            let mut worklist = vec![*value];
+            while let Some(expr_ref) = worklist.pop() {
+                match self.ast.get_expr(expr_ref) {
+                    Some(Expr::Named(read)) => assert!(
+                        self.lookup(read, Scope::Local).is_some()
+                            || !matches!(
+                                positions.get(read.as_str()),
+                                Some(declared) if *declared >= position
+                            ),
+                        "Identifier {read} is read by the initializer of {name} before being declared."
+                    ),
+                    Some(
+                        Expr::UnaryOp { operand: expr, .. }
+                        | Expr::Cast { operand: expr, .. },
+                    ) => worklist.push(*expr),
+                    Some(
+                        Expr::BinOp { left, right, .. }
+                        | Expr::Assignment {
+                            name: left,
+                            value: right,
+                        },
+                    ) => worklist.extend([*left, *right]),
+                    Some(Expr::Call { args, .. }) => {
+                        worklist.extend(args.iter().copied());
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    /// Validate the labels and `goto` statements in a function body.
+    ///
+    /// # Panics
+    ///
+    /// Labels must be defined exactly once per function, each `goto` must
+    /// target a defined label and can't jump into the scope of a variable
+    /// i.e every variable in scope at the label must be in scope at the jump.
+    fn check_labels(&self, body: StmtRef) {
+        let mut labels = HashMap::new();
+        let mut gotos = vec![];
+        self.collect_labels(body, &mut vec![], &mut labels, &mut gotos);
+
+        for (label, in_scope) in gotos {
+            match labels.get(&label) {
+                Some(visible) => assert!(
+                    visible.iter().all(|decl| in_scope.contains(decl)),
+                    "`goto {label}` jumps into the scope of a variable."
+                ),
+                None => panic!("Label {label} is used but never defined."),
+            }
+        }
+    }
+
+    /// Walk a statement recording the variables in scope at every label
+    /// definition and at every `goto` statement.
+    fn collect_labels(
+        &self,
+        stmt_ref: StmtRef,
+        scopes: &mut Vec<Vec<StmtRef>>,
+        labels: &mut HashMap<String, Vec<StmtRef>>,
+        gotos: &mut Vec<(String, Vec<StmtRef>)>,
+    ) {
+        match self.ast.get_stmt(stmt_ref) {
+            Some(Stmt::Block(stmts)) => {
+                scopes.push(vec![]);
+                for stmt_ref in stmts {
+                    self.collect_labels(*stmt_ref, scopes, labels, gotos);
+                }
+                scopes.pop();
+            }
+            Some(Stmt::LocalVariable { .. }) => {
+                if let Some(scope) = scopes.last_mut() {
+                    scope.push(stmt_ref);
+                }
+            }
+            Some(Stmt::Label(label)) => {
+                if labels.insert(label.clone(), scopes.concat()).is_some() {
+                    panic!("Label {label} is already defined in this function.")
+                }
+            }
+            Some(Stmt::Goto(label)) => {
+                gotos.push((label.clone(), scopes.concat()))
+            }
+            Some(Stmt::If {
+                then_block,
+                else_block,
+                ..
+            }) => {
+                self.collect_labels(*then_block, scopes, labels, gotos);
+                if let Some(else_block) = else_block {
+                    self.collect_labels(*else_block, scopes, labels, gotos);
+                }
+            }
+            Some(Stmt::For { body, .. }) => {
+                self.collect_labels(*body, scopes, labels, gotos)
+            }
+            Some(Stmt::While {
+                body: Some(body), ..
+            }) => self.collect_labels(*body, scopes, labels, gotos),
+            Some(Stmt::Switch { body, .. }) => {
+                self.collect_labels(*body, scopes, labels, gotos)
+            }
+            Some(_) => (),
+            None => unreachable!(
+                "Statement at ref {} was not found.",
+                stmt_ref.get()
+            ),
+        }
+    }
+
+    /// Validate the body of a `switch` statement, `case` and `default` labels
+    /// are only valid as statements of the body block.
+    ///
+    /// # Panics
+    ///
+    /// Case values must be unique, there is at most one `default` label and
+    /// labels can't jump into the scope of a variable declared in the body.
+    fn check_switch_body(&mut self, body: StmtRef) {
+        let Some(Stmt::Block(stmts)) = self.ast.get_stmt(body) else {
+            unreachable!("Expected `switch` body to be `Block` statement.")
+        };
+        let mut cases = HashSet::new();
+        let mut has_default = false;
+        let mut declared = None;
+        self.enter_scope();
+        for stmt_ref in stmts {
+            match self.ast.get_stmt(*stmt_ref) {
+                Some(Stmt::Case(value)) => {
+                    assert!(
+                        cases.insert(*value),
+                        "Duplicate `case {value}` label in `switch` statement."
+                    );
+                }
+                Some(Stmt::Default) => {
+                    assert!(
+                        !has_default,
+                        "Multiple `default` labels in `switch` statement."
+                    );
+                    has_default = true;
+                }
+                Some(stmt) => {
+                    if let Stmt::LocalVariable { name, .. } = stmt {
+                        declared.get_or_insert(name.clone());
+                    }
+                    ast::Visitor::visit_stmt(self, stmt);
+                    continue;
+                }
+                None => unreachable!(
+                    "Statement at ref {} was not found.",
+                    stmt_ref.get()
+                ),
+            }
+            if let Some(name) = &declared {
+                panic!(
+                    "`switch` label jumps into the scope of variable {name}."
+                )
+            }
+        }
+        self.exit_scope();
+    }
+
     /// Resolve an expression's type.
     ///
     /// # Panicw
@@ -516,6 +864,59 @@ impl<'a> SemanticAnalyzer<'a> {
                         "Expected call expression to reference `NamedExpr`"
                     ),
                 };
+                // `printf` is implicitly declared unless the program
+                // declares its own.
+                if name == PRINTF && self.lookup(name, Scope::Global).is_none()
+                {
+                    return self.printf(args);
+                }
+                if let Some(op) = AtomicOp::from_name(name)
+                    .filter(|_| self.lookup(name, Scope::Global).is_none())
+                {
+                    self.lang.require(Lang::Gnu, &format!("`{name}`"));
+                    return self.atomic(op, args);
+                }
+                if name == EXIT && self.lookup(name, Scope::Global).is_none() {
+                    assert_eq!(
+                        args.len(),
+                        1,
+                        "Expected `exit` to have 1 argument got {} in call.",
+                        args.len()
+                    );
+                    if let Some(code) = self.ast.get_expr(args[0]) {
+                        let code_t = self.resolve(code);
+                        assert_eq!(
+                            code_t,
+                            DeclType::Int,
+                            "Expected `exit` code to be of type INT_TYPE."
+                        );
+                    }
+                    return DeclType::Int;
+                }
+                // Builtins are implicitly declared.
+                if let Some(builtin) = Builtin::from_name(name) {
+                    self.lang.require(builtin.lang(), &format!("`{name}`"));
+                    let params = builtin.params();
+                    assert_eq!(
+                        args.len(),
+                        params.len(),
+                        "Expected builtin `{name}` to have {} arguments got {} in call.",
+                        params.len(),
+                        args.len()
+                    );
+                    for (arg_ref, param_t) in args.iter().zip(params) {
+                        if let Some(expr) = self.ast.get_expr(*arg_ref) {
+                            let expr_t = self.resolve(expr);
+                            assert_eq!(*param_t, expr_t, "Expected builtin `{name}` call argument to be of the same type")
+                        } else {
+                            unreachable!(
+                                "Expression at ref {} was not found",
+                                arg_ref.get()
+                            )
+                        }
+                    }
+                    return builtin.return_type();
+                }
                 // Functions are defined in the global scope.
                 match self.lookup(name, Scope::Global) {
                     Some(Symbol::FunctionDefinition {
@@ -553,16 +954,6 @@ impl<'a> SemanticAnalyzer<'a> {
                     ),
                 }
             }
-            ast::Expr::Grouping(group_ref) => {
-                if let Some(group) = self.ast.get_expr(*group_ref) {
-                    self.resolve(group)
-                } else {
-                    unreachable!(
-                        "Expression at ref {} was not found.",
-                        group_ref.get()
-                    )
-                }
-            }
             ast::Expr::Assignment { name, value } => {
                 // The type of an assignment expression is ambigious since
                 // the left hand side might resolve to a type different
@@ -646,7 +1037,10 @@ impl<'a> SemanticAnalyzer<'a> {
                     &ast::BinaryOperator::Add
                     | &ast::BinaryOperator::Div
                     | &ast::BinaryOperator::Mul
-                    | &ast::BinaryOperator::Sub => {
+                    | &ast::BinaryOperator::Sub
+                    | &ast::BinaryOperator::Shl
+                    | &ast::BinaryOperator::Shr
+                    | &ast::BinaryOperator::BitAnd => {
                         assert!(
                             lhs == DeclType::Int,
                             "expected left handside to {operator} to be of type `int` got {}",
@@ -701,6 +1095,19 @@ impl<'a> SemanticAnalyzer<'a> {
             ast::Expr::BoolLiteral(_) => DeclType::Bool,
             ast::Expr::IntLiteral(_) => DeclType::Int,
             ast::Expr::CharLiteral(_) => DeclType::Char,
+            ast::Expr::StringLiteral(_) => panic!(
+                "String literals are only supported as `printf` arguments."
+            ),
+            // Conversions are only inserted between the integer types
+            // (`conversions::is_implicit`), every operand is convertible.
+            ast::Expr::Cast { to, operand } => {
+                if let Some(expr) = self.ast.get_expr(*operand) {
+                    self.resolve(expr);
+                    *to
+                } else {
+                    unreachable!("Expected cast to have a valid operand.")
+                }
+            }
         }
     }
 }
@@ -750,6 +1157,7 @@ impl<'a> ast::Visitor<()> for SemanticAnalyzer<'a> {
                 }
             }
             ast::Stmt::Block(stmts) => {
+                self.check_declaration_order(stmts);
                 self.enter_scope();
                 for stmt_ref in stmts.iter() {
                     if let Some(stmt) = self.ast.get_stmt(*stmt_ref) {
@@ -815,6 +1223,7 @@ impl<'a> ast::Visitor<()> for SemanticAnalyzer<'a> {
                     }
                 }
                 // Recurisvely validate the statements in the block.
+                self.breakable += 1;
                 if let Some(block) = self.ast.get_stmt(*body) {
                     self.visit_stmt(block)
                 } else {
@@ -822,6 +1231,7 @@ impl<'a> ast::Visitor<()> for SemanticAnalyzer<'a> {
                         "Expected `for` loop body to be `Block` statement."
                     )
                 }
+                self.breakable -= 1;
             }
             ast::Stmt::If {
                 condition,
@@ -871,6 +1281,7 @@ impl<'a> ast::Visitor<()> for SemanticAnalyzer<'a> {
                     }
                 }
                 // Recurisvely validate the statements in the block.
+                self.breakable += 1;
                 if let Some(block) = self.ast.get_stmt(
                     body.expect("Expected body to be a valid statement"),
                 ) {
@@ -880,7 +1291,40 @@ impl<'a> ast::Visitor<()> for SemanticAnalyzer<'a> {
                         "Expected `for` loop body to be `Block` statement."
                     )
                 }
+                self.breakable -= 1;
+            }
+            ast::Stmt::Switch { scrutinee, body } => {
+                // Validate `scrutinee` resolves to an integer expression.
+                if let Some(scrutinee) = self.ast.get_expr(*scrutinee) {
+                    let t = self.resolve(scrutinee);
+                    assert_eq!(
+                        t,
+                        DeclType::Int,
+                        "invalid expression type in `switch` statement, must be of type int found {t}"
+                    );
+                } else {
+                    unreachable!(
+                        "Expression at ref {} was not found.",
+                        scrutinee.get()
+                    )
+                }
+                self.breakable += 1;
+                self.check_switch_body(*body);
+                self.breakable -= 1;
             }
+            // Case labels nested in a `switch` body are handled by
+            // `check_switch_body`.
+            ast::Stmt::Case(_) | ast::Stmt::Default => {
+                panic!("`case` and `default` labels must appear directly in a `switch` body.")
+            }
+            ast::Stmt::Break => assert!(
+                self.breakable > 0,
+                "`break` statement is not within a loop or `switch`."
+            ),
+            // Labels are validated per function in `check_labels`.
+            ast::Stmt::Goto(_) | ast::Stmt::Label(_) => (),
+            // Inline assembly is opaque.
+            ast::Stmt::Asm(_) | ast::Stmt::Empty => (),
             _ => todo!("Unimplemented visitor for stmt of kind {:?}", stmt),
         }
     }
@@ -901,10 +1345,30 @@ impl<'a> ast::Visitor<()> for SemanticAnalyzer<'a> {
                     )
                 }
             }
+            ast::Decl::Extern { name, .. } => assert!(
+                !name.starts_with(BUILTIN_PREFIX),
+                "Function name `{name}` is reserved for compiler builtins."
+            ),
             ast::Decl::Function {
-                return_type, body, ..
+                name,
+                return_type,
+                body,
+                linkage,
+                ..
             } => {
+                assert!(
+                    !name.starts_with(BUILTIN_PREFIX),
+                    "Function name `{name}` is reserved for compiler builtins."
+                );
+                assert!(
+                    !(name == "main" && *linkage == ast::Linkage::Internal),
+                    "Function `main` can't be declared static."
+                );
                 let mut has_return_stmt = false;
+                self.check_labels(*body);
+                if let Some(Stmt::Block(stmts)) = self.ast.get_stmt(*body) {
+                    self.check_declaration_order(stmts);
+                }
                 self.enter_scope();
                 match self.ast.get_stmt(*body) {
                     Some(Stmt::Block(stmts)) => {
@@ -943,22 +1407,595 @@ impl<'a> ast::Visitor<()> for SemanticAnalyzer<'a> {
     }
 }
 
+/// A local variable or argument in scope while linting a function.
+struct LintBinding {
+    name: String,
+    // Declaring statement.
+    decl: StmtRef,
+    // Function arguments are never reported as unused.
+    is_argument: bool,
+    // Whether the variable was read.
+    read: bool,
+    // Whether the variable holds a value assigned by the program, variables
+    // declared without an initializer hold the default value of their type.
+    assigned: bool,
+}
+
+/// `Linter` walks function bodies and reports the enabled warnings, it keeps
+/// its own scope stack since it runs after the program was validated.
+struct Linter<'a> {
+    ast: &'a ast::AST,
+    warnings: &'a Warnings,
+    // Stack of scopes of the function being linted.
+    scopes: Vec<Vec<LintBinding>>,
+    // Statement being linted, used to locate warnings about expressions.
+    current: Option<StmtRef>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    fn new(ast: &'a ast::AST, warnings: &'a Warnings) -> Self {
+        Self {
+            ast,
+            warnings,
+            scopes: vec![],
+            current: None,
+            diagnostics: vec![],
+        }
+    }
+
+    /// Report `warning` located at statement `stmt_ref` if it's enabled.
+    fn warn(&mut self, warning: Warning, message: &str, stmt_ref: StmtRef) {
+        if let Some(mut diagnostic) = self.warnings.warn(warning, message) {
+            if let Some(span) = self.ast.stmt_span(stmt_ref) {
+                if !span.is_empty() {
+                    diagnostic = diagnostic.with_span(span);
+                }
+            }
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    /// Returns the innermost binding of `name`.
+    fn binding(&mut self, name: &str) -> Option<&mut LintBinding> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|scope| scope.iter_mut().rev())
+            .find(|binding| binding.name == name)
+    }
+
+    /// Bind a local variable or argument in the innermost scope.
+    fn declare(&mut self, name: &str, decl: StmtRef, is_argument: bool) {
+        let depth = self.scopes.len().saturating_sub(1);
+        let shadows_local = self.scopes[..depth]
+            .iter()
+            .flatten()
+            .any(|binding| binding.name == name);
+        let shadows_global = self.ast.declarations().iter().any(|decl| {
+            matches!(decl, Decl::GlobalVariable { name: global, .. } if global == name)
+        });
+        if shadows_local {
+            let message =
+                format!("declaration of `{name}` shadows a local variable");
+            self.warn(Warning::Shadow, &message, decl);
+        } else if shadows_global {
+            let message =
+                format!("declaration of `{name}` shadows a global variable");
+            self.warn(Warning::Shadow, &message, decl);
+        }
+        let assigned = is_argument || !self.ast.is_default_initialized(decl);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(LintBinding {
+                name: name.to_string(),
+                decl,
+                is_argument,
+                read: false,
+                assigned,
+            });
+        }
+    }
+
+    /// Leave the innermost scope reporting the variables never read.
+    fn exit(&mut self) {
+        for binding in self.scopes.pop().unwrap_or_default() {
+            if !binding.read && !binding.is_argument {
+                let message = format!("unused variable `{}`", binding.name);
+                self.warn(Warning::Unused, &message, binding.decl);
+            }
+        }
+    }
+
+    /// Report the body of an `if` or `else` branch (`if (c);`) if it's an
+    /// empty statement, usually a stray semicolon.
+    fn empty_body(&mut self, body: StmtRef, branch: &str) {
+        if let Some(Stmt::Empty) = self.ast.get_stmt(body) {
+            let message = format!(
+                "suggest braces around empty body in an `{branch}` statement"
+            );
+            self.warn(Warning::EmptyBody, &message, body);
+        }
+    }
+
+    fn function(&mut self, args: &[StmtRef], body: StmtRef) {
+        // Arguments and the function body share a scope.
+        self.scopes.push(vec![]);
+        for arg_ref in args {
+            if let Some(Stmt::Argument { name, .. }) =
+                self.ast.get_stmt(*arg_ref)
+            {
+                self.declare(name, *arg_ref, true);
+            }
+        }
+        if let Some(Stmt::Block(stmts)) = self.ast.get_stmt(body) {
+            for stmt_ref in stmts {
+                self.stmt(*stmt_ref);
+            }
+        }
+        self.exit();
+    }
+
+    fn stmt(&mut self, stmt_ref: StmtRef) {
+        self.current = Some(stmt_ref);
+        match self.ast.get_stmt(stmt_ref) {
+            Some(Stmt::LocalVariable { name, value, .. }) => {
+                self.expr(*value);
+                self.declare(name, stmt_ref, false);
+            }
+            Some(Stmt::Expr(expr) | Stmt::Return(expr)) => self.expr(*expr),
+            Some(Stmt::Block(stmts)) => {
+                self.scopes.push(vec![]);
+                for stmt_ref in stmts {
+                    self.stmt(*stmt_ref);
+                }
+                self.exit();
+            }
+            Some(Stmt::If {
+                condition,
+                then_block,
+                else_block,
+            }) => {
+                self.expr(*condition);
+                self.empty_body(*then_block, "if");
+                self.stmt(*then_block);
+                if let Some(else_block) = else_block {
+                    self.empty_body(*else_block, "else");
+                    self.stmt(*else_block);
+                }
+            }
+            Some(Stmt::For {
+                init,
+                condition,
+                iteration,
+                body,
+            }) => {
+                for expr in [init, condition, iteration].into_iter().flatten() {
+                    self.expr(*expr);
+                }
+                self.stmt(*body);
+            }
+            Some(Stmt::While { condition, body }) => {
+                if let Some(condition) = condition {
+                    self.expr(*condition);
+                }
+                if let Some(body) = body {
+                    self.stmt(*body);
+                }
+            }
+            Some(Stmt::Switch { scrutinee, body }) => {
+                self.expr(*scrutinee);
+                self.stmt(*body);
+            }
+            _ => (),
+        }
+    }
+
+    fn expr(&mut self, expr_ref: ast::ExprRef) {
+        match self.ast.get_expr(expr_ref) {
+            Some(Expr::Named(name)) => {
+                let Some(binding) = self.binding(name) else {
+                    // Globals and functions.
+                    return;
+                };
+                binding.read = true;
+                if !binding.assigned {
+                    // Only report the first read.
+                    binding.assigned = true;
+                    let message = format!(
+                        "`{name}` is read before being assigned, it is implicitly initialized to its default value"
+                    );
+                    if let Some(current) = self.current {
+                        self.warn(Warning::Uninitialized, &message, current);
+                    }
+                }
+            }
+            Some(Expr::Assignment { name, value }) => {
+                self.expr(*value);
+                match self.ast.get_expr(*name) {
+                    Some(Expr::Named(name)) => {
+                        if let Some(binding) = self.binding(name) {
+                            binding.assigned = true;
+                        }
+                    }
+                    _ => self.expr(*name),
+                }
+            }
+            Some(
+                Expr::UnaryOp { operand: expr, .. }
+                | Expr::Cast { operand: expr, .. },
+            ) => self.expr(*expr),
+            Some(Expr::BinOp { left, right, .. }) => {
+                self.expr(*left);
+                self.expr(*right);
+            }
+            Some(Expr::Call { args, .. }) => {
+                for arg in args {
+                    self.expr(*arg);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Report the enabled warnings about the local variables of every function
+/// in `ast`, the AST is expected to have passed semantic analysis.
+#[must_use]
+pub fn lint(ast: &ast::AST, warnings: &Warnings) -> Vec<Diagnostic> {
+    let mut linter = Linter::new(ast, warnings);
+    for decl in ast.declarations() {
+        if let Decl::Function { args, body, .. } = decl {
+            linter.function(args, *body);
+        }
+    }
+    linter.diagnostics
+}
+
+/// Declaration of a symbol in the cross-reference index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Definition {
+    // Functions, external functions and global variables.
+    Global(DeclRef),
+    // Local variables and function arguments.
+    Local(StmtRef),
+}
+
+/// Reference to a symbol, every named expression resolving to the symbol
+/// (reads, assignments and calls) is a use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Use {
+    pub expr: ExprRef,
+    pub span: Option<Span>,
+}
+
+/// Cross-reference entry of a symbol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XRefEntry {
+    name: String,
+    definition: Definition,
+    // Source location of the declaration.
+    span: Option<Span>,
+    uses: Vec<Use>,
+}
+
+impl XRefEntry {
+    /// Returns the name of the symbol.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the declaration of the symbol.
+    #[must_use]
+    pub const fn definition(&self) -> Definition {
+        self.definition
+    }
+
+    /// Returns the source location of the declaration.
+    #[must_use]
+    pub const fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    /// Returns the uses of the symbol in source order.
+    #[must_use]
+    pub fn uses(&self) -> &[Use] {
+        &self.uses
+    }
+}
+
+/// `XRef` is the cross-reference index of a program, it maps every symbol to
+/// its declaration and uses for find-references, go-to-definition and
+/// unused symbol queries.
+///
+/// Like the linter it resolves names with its own scope stack, references
+/// to builtins aren't recorded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XRef {
+    entries: Vec<XRefEntry>,
+    // Entry of each declaration.
+    definitions: HashMap<Definition, usize>,
+    // Entry referenced by each use.
+    uses: HashMap<ExprRef, usize>,
+    // Entries of the bindings in scope while building the index.
+    scopes: Vec<Vec<usize>>,
+}
+
+impl XRef {
+    /// Build the cross-reference index of `ast`, the AST is expected to have
+    /// passed semantic analysis.
+    #[must_use]
+    pub fn new(ast: &ast::AST) -> Self {
+        let mut xref = Self::default();
+        // Functions can be called before they're declared.
+        xref.scopes.push(vec![]);
+        for (i, decl) in ast.declarations().iter().enumerate() {
+            let decl_ref = DeclRef::new(i);
+            let name = match decl {
+                Decl::Function { name, .. }
+                | Decl::Extern { name, .. }
+                | Decl::GlobalVariable { name, .. } => name,
+            };
+            xref.declare(
+                name,
+                Definition::Global(decl_ref),
+                ast.decl_span(decl_ref),
+            );
+        }
+        for decl in ast.declarations() {
+            match decl {
+                Decl::Function { args, body, .. } => {
+                    xref.scopes.push(vec![]);
+                    for arg_ref in args {
+                        if let Some(Stmt::Argument { name, .. }) =
+                            ast.get_stmt(*arg_ref)
+                        {
+                            xref.declare(
+                                name,
+                                Definition::Local(*arg_ref),
+                                ast.stmt_span(*arg_ref),
+                            );
+                        }
+                    }
+                    if let Some(Stmt::Block(stmts)) = ast.get_stmt(*body) {
+                        for stmt_ref in stmts {
+                            xref.stmt(ast, *stmt_ref);
+                        }
+                    }
+                    xref.scopes.pop();
+                }
+                Decl::GlobalVariable { value, .. } => xref.expr(ast, *value),
+                Decl::Extern { .. } => (),
+            }
+        }
+        xref.scopes.clear();
+        xref
+    }
+
+    /// Returns every entry in declaration order, globals first.
+    pub fn entries(&self) -> impl Iterator<Item = &XRefEntry> {
+        self.entries.iter()
+    }
+
+    /// Returns the entry of the symbol declared by `definition`.
+    #[must_use]
+    pub fn references(&self, definition: Definition) -> Option<&XRefEntry> {
+        self.definitions
+            .get(&definition)
+            .map(|index| &self.entries[*index])
+    }
+
+    /// Returns the entry of the symbol the named expression `expr_ref`
+    /// resolves to.
+    #[must_use]
+    pub fn definition(&self, expr_ref: ExprRef) -> Option<&XRefEntry> {
+        self.uses.get(&expr_ref).map(|index| &self.entries[*index])
+    }
+
+    /// Returns the entry of the symbol used or declared at the character
+    /// offset `position`, uses take precedence over the declarations
+    /// enclosing them and inner declarations over outer ones.
+    #[must_use]
+    pub fn at(&self, position: usize) -> Option<&XRefEntry> {
+        let covers = |span: Option<Span>| {
+            span.is_some_and(|span| {
+                span.start <= position && position < span.end
+            })
+        };
+        let used = self
+            .entries
+            .iter()
+            .find(|entry| entry.uses.iter().any(|u| covers(u.span)));
+        used.or_else(|| {
+            self.entries
+                .iter()
+                .filter(|entry| covers(entry.span))
+                .min_by_key(|entry| entry.span.map(|span| span.len()))
+        })
+    }
+
+    /// Returns the entries of the symbols that are never used.
+    pub fn unused(&self) -> impl Iterator<Item = &XRefEntry> {
+        self.entries.iter().filter(|entry| entry.uses.is_empty())
+    }
+
+    fn declare(
+        &mut self,
+        name: &str,
+        definition: Definition,
+        span: Option<Span>,
+    ) {
+        let index = self.entries.len();
+        self.entries.push(XRefEntry {
+            name: name.to_string(),
+            definition,
+            span,
+            uses: vec![],
+        });
+        self.definitions.insert(definition, index);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(index);
+        }
+    }
+
+    fn stmt(&mut self, ast: &ast::AST, stmt_ref: StmtRef) {
+        match ast.get_stmt(stmt_ref) {
+            Some(Stmt::LocalVariable { name, value, .. }) => {
+                // The initializer is resolved before the variable is in scope.
+                self.expr(ast, *value);
+                self.declare(
+                    name,
+                    Definition::Local(stmt_ref),
+                    ast.stmt_span(stmt_ref),
+                );
+            }
+            Some(Stmt::Expr(expr) | Stmt::Return(expr)) => {
+                self.expr(ast, *expr)
+            }
+            Some(Stmt::Block(stmts)) => {
+                self.scopes.push(vec![]);
+                for stmt_ref in stmts {
+                    self.stmt(ast, *stmt_ref);
+                }
+                self.scopes.pop();
+            }
+            Some(Stmt::If {
+                condition,
+                then_block,
+                else_block,
+            }) => {
+                self.expr(ast, *condition);
+                self.stmt(ast, *then_block);
+                if let Some(else_block) = else_block {
+                    self.stmt(ast, *else_block);
+                }
+            }
+            Some(Stmt::For {
+                init,
+                condition,
+                iteration,
+                body,
+            }) => {
+                for expr in [init, condition, iteration].into_iter().flatten() {
+                    self.expr(ast, *expr);
+                }
+                self.stmt(ast, *body);
+            }
+            Some(Stmt::While { condition, body }) => {
+                if let Some(condition) = condition {
+                    self.expr(ast, *condition);
+                }
+                if let Some(body) = body {
+                    self.stmt(ast, *body);
+                }
+            }
+            Some(Stmt::Switch { scrutinee, body }) => {
+                self.expr(ast, *scrutinee);
+                self.stmt(ast, *body);
+            }
+            _ => (),
+        }
+    }
+
+    fn expr(&mut self, ast: &ast::AST, expr_ref: ExprRef) {
+        match ast.get_expr(expr_ref) {
+            Some(Expr::Named(name)) => {
+                let binding = self
+                    .scopes
+                    .iter()
+                    .rev()
+                    .flat_map(|scope| scope.iter().rev())
+                    .find(|index| self.entries[**index].name == *name)
+                    .copied();
+                if let Some(index) = binding {
+                    self.entries[index].uses.push(Use {
+                        expr: expr_ref,
+                        span: ast.expr_span(expr_ref),
+                    });
+                    self.uses.insert(expr_ref, index);
+                }
+            }
+            Some(Expr::Assignment { name, value }) => {
+                self.expr(ast, *name);
+                self.expr(ast, *value);
+            }
+            Some(
+                Expr::UnaryOp { operand: expr, .. }
+                | Expr::Cast { operand: expr, .. },
+            ) => self.expr(ast, *expr),
+            Some(Expr::BinOp { left, right, .. }) => {
+                self.expr(ast, *left);
+                self.expr(ast, *right);
+            }
+            Some(Expr::Call { name, args }) => {
+                self.expr(ast, *name);
+                for arg in args {
+                    self.expr(ast, *arg);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
 /// Analyze the input AST and return the symbol table.
 pub fn analyze(ast: &ast::AST) -> SymbolTable {
+    analyze_with_lang(ast, Lang::default())
+}
+
+/// Analyze the input AST written in the language level `lang` and return the
+/// symbol table.
+pub fn analyze_with_lang(ast: &ast::AST, lang: Lang) -> SymbolTable {
     let mut decl_analyzer = DeclAnalyzer::new(ast);
-    ast::walk(ast, &mut decl_analyzer);
+    {
+        let _span = trace::span(trace::Level::Debug, "declarations", &[]);
+        ast::walk(ast, &mut decl_analyzer);
+    }
+    let _span = trace::span(trace::Level::Debug, "type-check", &[]);
     let mut semantic_analyzer =
         SemanticAnalyzer::new(ast, decl_analyzer.symbol_table());
+    semantic_analyzer.lang = lang;
     ast::walk(ast, &mut semantic_analyzer);
+    check_const_writes(ast);
     decl_analyzer.symbol_table().clone()
 }
 
+/// Reject assignments to `const` qualified variables.
+fn check_const_writes(ast: &ast::AST) {
+    let xref = XRef::new(ast);
+    let assigned = (0..)
+        .map(ExprRef::new)
+        .map_while(|expr_ref| ast.get_expr(expr_ref))
+        .filter_map(|expr| match expr {
+            Expr::Assignment { name, .. } => Some(*name),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    for entry in xref.entries() {
+        let node = match entry.definition() {
+            Definition::Global(decl_ref) => ast::AnyRef::Decl(decl_ref),
+            Definition::Local(stmt_ref) => ast::AnyRef::Stmt(stmt_ref),
+        };
+        assert!(
+            !ast.is_const(node)
+                || !entry.uses().iter().any(|u| assigned.contains(&u.expr)),
+            "Can't assign to const variable `{}`.",
+            entry.name()
+        );
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::ast;
-    use crate::parser::Parser;
+    use crate::diagnostics::{Severity, Warnings};
+    use crate::parser::{Lang, Parser};
     use crate::scanner::Scanner;
-    use crate::sema::{DeclAnalyzer, SemanticAnalyzer};
+    use crate::sema::{
+        analyze, analyze_with_lang, lint, DeclAnalyzer, Definition,
+        SemanticAnalyzer, Symbol, XRef, XRefEntry,
+    };
+    use crate::token::Span;
 
     // Macro to generate test cases.
     macro_rules! test_decl_analyzer {
@@ -1004,6 +2041,61 @@ mod tests {
         };
     }
 
+    #[test]
+    #[should_panic(expected = "Can't assign to const variable `n`.")]
+    fn can_reject_assignments_to_const_variables() {
+        let tokens =
+            Scanner::new("int main() { const int n = 1; n = 2; return n; }")
+                .scan()
+                .unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let _ = analyze(parser.ast());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Expected `printf` argument to be of type INT_TYPE."
+    )]
+    fn can_reject_mismatched_printf_arguments() {
+        let tokens =
+            Scanner::new(r#"int main() { return printf("%d", true); }"#)
+                .scan()
+                .unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let _ = analyze(parser.ast());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Memory order `acquire` is invalid for `__atomic_store`."
+    )]
+    fn can_reject_invalid_atomic_orders() {
+        let tokens = Scanner::new(
+            "int main() { int x = 0; return __atomic_store(x, 1, 2); }",
+        )
+        .scan()
+        .unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let _ = analyze(parser.ast());
+    }
+
+    #[test]
+    #[should_panic(expected = "`__builtin_expect` requires --lang=gnu.")]
+    fn can_gate_builtins_by_language_level() {
+        let tokens = Scanner::new(
+            "int main() { bool b = __builtin_expect(true, true); return __builtin_abs(-1); }",
+        )
+        .scan()
+        .unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let _ = analyze_with_lang(parser.ast(), Lang::Gnu);
+        let _ = analyze_with_lang(parser.ast(), Lang::C89Subset);
+    }
+
     test_decl_analyzer!(
         can_process_single_declarations,
         "int main() { int a = 0; return a;}"
@@ -1192,6 +2284,43 @@ int ifelses(int x,int y,int z) {
         "#
     );
 
+    test_decl_analyzer!(
+        can_process_goto_statements,
+        r#"
+        int main() {
+            int i = 0;
+            loop:
+            i = i + 1;
+            if (i < 10) {
+                goto loop;
+            }
+            goto exit;
+            exit:
+            return i;
+        }
+        "#
+    );
+
+    test_decl_analyzer!(
+        can_process_switch_statements,
+        r#"
+        int main() {
+            int x = 3;
+            switch (x) {
+            case 1:
+                x = 2;
+                break;
+            default:
+                {
+                    int y = x;
+                    x = y + 1;
+                }
+            }
+            return x;
+        }
+        "#
+    );
+
     test_semantic_analyzer!(
         can_deal_with_deeply_nested_scopes,
         r#"
@@ -1275,6 +2404,14 @@ int ifelses(int x,int y,int z) {
         can_find_invalid_bool_literal_assignment,
         "int main() { int a = true; }"
     );
+    test_semantic_analyzer!(
+        can_find_variable_read_after_its_block,
+        "int main() { int x = 1; { int z = 2; x = z; } return z; }"
+    );
+    test_semantic_analyzer!(
+        can_find_initializer_reading_a_later_declarator,
+        "int main() { int a = b + 1, b = 1; return a; }"
+    );
     test_semantic_analyzer!(
         can_find_invalid_call_assignment,
         "int f () { return -1; } int main() { char a = f(); }"
@@ -1315,4 +2452,258 @@ int ifelses(int x,int y,int z) {
         can_find_invalid_for_statement_with_non_boolean_condition,
         "int main() { int i; for(i = 0; i = i + 1; i = i + 1) {}}"
     );
+    test_semantic_analyzer!(
+        can_find_duplicate_label_definition,
+        "int main() { exit: exit: return 0; }"
+    );
+    test_semantic_analyzer!(
+        can_find_goto_to_undefined_label,
+        "int main() { goto exit; return 0; }"
+    );
+    test_semantic_analyzer!(
+        can_find_goto_into_nested_scope,
+        "int main() { goto inner; { int a = 1; inner: a = 2; } return 0; }"
+    );
+    test_semantic_analyzer!(
+        can_find_goto_past_variable_declaration,
+        "int main() { goto end; int a = 1; end: return a; }"
+    );
+    test_semantic_analyzer!(
+        can_find_switch_on_non_integer_expression,
+        "int main() { switch (true) { default: return 0; } return 1; }"
+    );
+    test_semantic_analyzer!(
+        can_find_duplicate_case_labels,
+        "int main() { int x = 1; switch (x) { case 1: case 1: x = 2; } return x; }"
+    );
+    test_semantic_analyzer!(
+        can_find_multiple_default_labels,
+        "int main() { int x = 1; switch (x) { default: default: x = 2; } return x; }"
+    );
+    test_semantic_analyzer!(
+        can_find_case_label_past_variable_declaration,
+        "int main() { int x = 1; switch (x) { int y = 2; case 1: x = y; } return x; }"
+    );
+    test_semantic_analyzer!(
+        can_find_case_label_outside_switch_body,
+        "int main() { int x = 1; switch (x) { { case 1: x = 2; } } return x; }"
+    );
+    test_semantic_analyzer!(
+        can_find_break_outside_loop_or_switch,
+        "int main() { break; return 0; }"
+    );
+    test_semantic_analyzer!(
+        can_find_builtin_call_with_wrong_arity,
+        "int main() { return __builtin_min(1); }"
+    );
+    test_semantic_analyzer!(
+        can_find_builtin_call_with_wrong_argument_type,
+        "int main() { return __builtin_abs(true); }"
+    );
+    test_semantic_analyzer!(
+        can_find_function_using_reserved_builtin_name,
+        "int __builtin_abs(int x) { return x; } int main() { return 0; }"
+    );
+    test_semantic_analyzer!(
+        can_find_extern_call_with_wrong_arity,
+        "extern int putchar(int c); int main() { return putchar(); }"
+    );
+    test_semantic_analyzer!(
+        can_find_static_main_function,
+        "static int main() { return 0; }"
+    );
+
+    #[test]
+    fn can_report_enabled_warnings() {
+        let source = r#"
+            int main() {
+                int a;
+                int b = 1;
+                int c;
+                c = 2;
+                if (a == c) {
+                    int d = 3;
+                }
+                return a;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan_with_spans().unwrap();
+        let mut parser = Parser::with_spans(&tokens);
+        parser.parse();
+        analyze(parser.ast());
+
+        assert!(lint(parser.ast(), &Warnings::default()).is_empty());
+        let mut warnings = Warnings::default();
+        warnings.apply_flag("-Wall");
+        let messages = lint(parser.ast(), &warnings)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "warning[W0002]: `a` is read before being assigned, it is implicitly initialized to its default value [-Wuninitialized]",
+                "warning[W0001]: unused variable `d` [-Wunused]",
+                "warning[W0001]: unused variable `b` [-Wunused]",
+            ]
+        );
+        warnings.apply_flag("-Werror");
+        assert!(lint(parser.ast(), &warnings)
+            .iter()
+            .all(|diagnostic| diagnostic.severity() == Severity::Error));
+    }
+
+    #[test]
+    fn can_report_shadowed_variables() {
+        let source = r#"
+            int g = 0;
+            int main() {
+                int c = 1;
+                {
+                    int c = 2;
+                    int g = c;
+                    c = g;
+                }
+                return c;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let mut symbol_table = analyze(parser.ast());
+        let mut warnings = Warnings::default();
+        warnings.apply_flag("-Wshadow");
+        let messages = lint(parser.ast(), &warnings)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "warning[W0003]: declaration of `c` shadows a local variable [-Wshadow]",
+                "warning[W0003]: declaration of `g` shadows a global variable [-Wshadow]",
+            ]
+        );
+
+        // The inner `c` is bound in the innermost scope.
+        let scope = 2;
+        assert!(symbol_table.rename("c", "c_inner", scope));
+        assert!(symbol_table.find("c", scope).is_some());
+        assert!(matches!(
+            symbol_table.find("c_inner", scope),
+            Some(Symbol::LocalVariable { name, .. }) if name == "c_inner"
+        ));
+        assert!(!symbol_table.rename("g_missing", "h", scope));
+    }
+
+    #[test]
+    fn can_report_empty_bodies() {
+        let source = r#"
+            int main() {
+                int c = 1;
+                if (c > 0);
+                if (c > 1) {
+                    c = 2;
+                } else;
+                return c;
+            }
+        "#;
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        analyze(parser.ast());
+        let mut warnings = Warnings::default();
+        assert!(lint(parser.ast(), &warnings).is_empty());
+        warnings.apply_flag("-Wempty-body");
+        let messages = lint(parser.ast(), &warnings)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                "warning[W0004]: suggest braces around empty body in an `if` statement [-Wempty-body]",
+                "warning[W0004]: suggest braces around empty body in an `else` statement [-Wempty-body]",
+            ]
+        );
+    }
+
+    #[test]
+    fn can_build_cross_references() {
+        let source = r#"int g = 1;
+int main() {
+    int c = g;
+    {
+        int c = 2;
+        c = c + 1;
+    }
+    return twice(c);
+}
+int twice(int a) {
+    return a + a;
+}"#;
+        let tokens = Scanner::new(source).scan_with_spans().unwrap();
+        let mut parser = Parser::with_spans(&tokens);
+        parser.parse();
+        let ast = parser.ast();
+        let xref = XRef::new(ast);
+        let text = |span: Option<Span>| {
+            let span = span.unwrap();
+            source
+                .chars()
+                .skip(span.start)
+                .take(span.len())
+                .collect::<String>()
+        };
+        let find = |name: &str, declaration: &str| {
+            xref.entries()
+                .find(|entry| {
+                    entry.name() == name && text(entry.span()) == declaration
+                })
+                .unwrap()
+        };
+        let uses = |entry: &XRefEntry| {
+            entry
+                .uses()
+                .iter()
+                .map(|u| ast.expr_span(u.expr).unwrap().start)
+                .collect::<Vec<_>>()
+        };
+        let positions = |needle: &str| {
+            source
+                .match_indices(needle)
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>()
+        };
+
+        let outer = find("c", "int c = g;");
+        let inner = find("c", "int c = 2;");
+        assert_eq!(uses(outer), vec![positions("(c)")[0] + 1]);
+        assert_eq!(
+            uses(inner),
+            vec![positions("c = c")[0], positions("c + 1")[0]]
+        );
+        assert_eq!(
+            uses(find("g", "int g = 1;")),
+            positions("= g;").iter().map(|i| i + 2).collect::<Vec<_>>()
+        );
+        assert_eq!(uses(find("a", "int a")).len(), 2);
+        assert_eq!(xref.references(outer.definition()), Some(outer));
+
+        // Calls resolve to functions declared later.
+        let call = positions("twice(c)")[0];
+        let twice = xref.at(call).unwrap();
+        assert!(matches!(twice.definition(), Definition::Global(_)));
+        assert_eq!(uses(twice), vec![call]);
+        assert_eq!(
+            xref.definition(twice.uses()[0].expr).map(XRefEntry::name),
+            Some("twice")
+        );
+        // Declarations are found from their location.
+        assert_eq!(xref.at(positions("int c = 2")[0]), Some(inner));
+        assert_eq!(
+            xref.unused().map(XRefEntry::name).collect::<Vec<_>>(),
+            vec!["main"]
+        );
+    }
 }
@@ -0,0 +1,332 @@
+//! AST simplification before IR generation.
+//!
+//! Expressions are simplified bottom up: operations on literals are folded
+//! with the constant evaluator and algebraic identities such as `x + 0` or
+//! `b && true` are rewritten to their operand. Identities dropping an operand
+//! (`x * 0`) only apply when the dropped operand has no side effects.
+//!
+//! Simplified expressions are pushed as new nodes since interned expressions
+//! can be shared, the replaced nodes are reclaimed by `AST::compact`.
+use crate::ast::{BinaryOperator, Expr, ExprRef, UnaryOperator, AST};
+use crate::consteval::{self, ArithmeticMode};
+use crate::conversions;
+use crate::ir::{Literal, OPCode};
+
+/// Simplify every expression of `ast`, returns the number of rewritten
+/// expressions.
+pub fn simplify(ast: &mut AST) -> usize {
+    let mut rewritten = 0;
+    ast.rewrite_exprs(|ast, expr_ref| expr(ast, expr_ref, &mut rewritten));
+    rewritten
+}
+
+/// Simplify the expression at `expr_ref` and return the reference of the
+/// simplified expression.
+fn expr(ast: &mut AST, expr_ref: ExprRef, rewritten: &mut usize) -> ExprRef {
+    let Some(node) = ast.get_expr(expr_ref).cloned() else {
+        return expr_ref;
+    };
+    match node {
+        Expr::BinOp {
+            left,
+            operator,
+            right,
+        } => {
+            let (lhs, rhs) =
+                (expr(ast, left, rewritten), expr(ast, right, rewritten));
+            if let Some(simplified) = binary(ast, lhs, operator, rhs) {
+                *rewritten += 1;
+                return simplified;
+            }
+            if (lhs, rhs) == (left, right) {
+                return expr_ref;
+            }
+            ast.push_expr(Expr::BinOp {
+                left: lhs,
+                operator,
+                right: rhs,
+            })
+        }
+        Expr::UnaryOp { operator, operand } => {
+            let simplified = expr(ast, operand, rewritten);
+            if let Some(simplified) = unary(ast, operator, simplified) {
+                *rewritten += 1;
+                return simplified;
+            }
+            if simplified == operand {
+                return expr_ref;
+            }
+            ast.push_expr(Expr::UnaryOp {
+                operator,
+                operand: simplified,
+            })
+        }
+        Expr::Cast { to, operand } => {
+            let simplified = expr(ast, operand, rewritten);
+            if let Some(literal) = literal(ast, simplified) {
+                *rewritten += 1;
+                return literal_expr(conversions::convert(literal, to))
+                    .map_or(expr_ref, |folded| ast.push_expr(folded));
+            }
+            if simplified == operand {
+                return expr_ref;
+            }
+            ast.push_expr(Expr::Cast {
+                to,
+                operand: simplified,
+            })
+        }
+        Expr::Assignment { name, value } => {
+            let simplified = expr(ast, value, rewritten);
+            if simplified == value {
+                return expr_ref;
+            }
+            ast.push_expr(Expr::Assignment {
+                name,
+                value: simplified,
+            })
+        }
+        Expr::Call { name, args } => {
+            let simplified = args
+                .iter()
+                .map(|arg| expr(ast, *arg, rewritten))
+                .collect::<Vec<_>>();
+            if simplified == args {
+                return expr_ref;
+            }
+            ast.push_expr(Expr::Call {
+                name,
+                args: simplified,
+            })
+        }
+        Expr::Named(_)
+        | Expr::IntLiteral(_)
+        | Expr::BoolLiteral(_)
+        | Expr::CharLiteral(_)
+        | Expr::StringLiteral(_) => expr_ref,
+    }
+}
+
+/// Simplify a binary operation on simplified operands, returns `None` if it
+/// can't be simplified.
+fn binary(
+    ast: &mut AST,
+    lhs: ExprRef,
+    operator: BinaryOperator,
+    rhs: ExprRef,
+) -> Option<ExprRef> {
+    let opcode = opcode(operator);
+    if let (Some(a), Some(b)) = (literal(ast, lhs), literal(ast, rhs)) {
+        let folded = consteval::binary(opcode, a, b, ArithmeticMode::Wrapping)
+            .ok()
+            .flatten()?;
+        return Some(ast.push_expr(literal_expr(folded)?));
+    }
+    let (a, b) = (literal(ast, lhs), literal(ast, rhs));
+    let int = Literal::Int;
+    let bool = Literal::Bool;
+    match operator {
+        BinaryOperator::Add if b == Some(int(0)) => Some(lhs),
+        BinaryOperator::Add if a == Some(int(0)) => Some(rhs),
+        BinaryOperator::Sub if b == Some(int(0)) => Some(lhs),
+        BinaryOperator::Mul | BinaryOperator::Div if b == Some(int(1)) => {
+            Some(lhs)
+        }
+        BinaryOperator::Mul if a == Some(int(1)) => Some(rhs),
+        BinaryOperator::Mul if b == Some(int(0)) && is_pure(ast, lhs) => {
+            Some(rhs)
+        }
+        BinaryOperator::Mul if a == Some(int(0)) && is_pure(ast, rhs) => {
+            Some(lhs)
+        }
+        BinaryOperator::And if b == Some(bool(true)) => Some(lhs),
+        BinaryOperator::And if a == Some(bool(true)) => Some(rhs),
+        BinaryOperator::And if b == Some(bool(false)) && is_pure(ast, lhs) => {
+            Some(rhs)
+        }
+        BinaryOperator::And if a == Some(bool(false)) && is_pure(ast, rhs) => {
+            Some(lhs)
+        }
+        BinaryOperator::Or if b == Some(bool(false)) => Some(lhs),
+        BinaryOperator::Or if a == Some(bool(false)) => Some(rhs),
+        BinaryOperator::Or if b == Some(bool(true)) && is_pure(ast, lhs) => {
+            Some(rhs)
+        }
+        BinaryOperator::Or if a == Some(bool(true)) && is_pure(ast, rhs) => {
+            Some(lhs)
+        }
+        // Operands of comparisons share a type once casts are inserted, so
+        // the other operand of a boolean literal is a boolean.
+        BinaryOperator::Eq | BinaryOperator::Neq => {
+            let (literal, operand) = match (a, b) {
+                (_, Some(Literal::Bool(value))) => (value, lhs),
+                (Some(Literal::Bool(value)), _) => (value, rhs),
+                _ => return None,
+            };
+            if literal == (operator == BinaryOperator::Eq) {
+                return Some(operand);
+            }
+            Some(ast.push_expr(Expr::UnaryOp {
+                operator: UnaryOperator::Not,
+                operand,
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// Simplify a unary operation on a simplified operand, returns `None` if it
+/// can't be simplified.
+fn unary(
+    ast: &mut AST,
+    operator: UnaryOperator,
+    operand: ExprRef,
+) -> Option<ExprRef> {
+    let opcode = match operator {
+        UnaryOperator::Neg => OPCode::Neg,
+        UnaryOperator::Not => OPCode::Not,
+    };
+    if let Some(a) = literal(ast, operand) {
+        let folded = consteval::unary(opcode, a, ArithmeticMode::Wrapping)
+            .ok()
+            .flatten()?;
+        return Some(ast.push_expr(literal_expr(folded)?));
+    }
+    // Double negations cancel out, `--x` wraps back to `x`.
+    match ast.get_expr(operand) {
+        Some(Expr::UnaryOp {
+            operator: inner,
+            operand,
+        }) if *inner == operator => Some(*operand),
+        _ => None,
+    }
+}
+
+/// Returns `true` if evaluating the expression has no side effects.
+fn is_pure(ast: &AST, expr_ref: ExprRef) -> bool {
+    match ast.get_expr(expr_ref) {
+        Some(Expr::UnaryOp { operand, .. }) => is_pure(ast, *operand),
+        Some(Expr::BinOp { left, right, .. }) => {
+            is_pure(ast, *left) && is_pure(ast, *right)
+        }
+        Some(expr) => expr.is_pure(),
+        None => false,
+    }
+}
+
+/// Returns the value of a literal expression.
+fn literal(ast: &AST, expr_ref: ExprRef) -> Option<Literal> {
+    match ast.get_expr(expr_ref)? {
+        Expr::IntLiteral(value) => Some(Literal::Int(*value)),
+        Expr::BoolLiteral(value) => Some(Literal::Bool(*value)),
+        Expr::CharLiteral(value) => Some(Literal::Char(*value)),
+        _ => None,
+    }
+}
+
+/// Returns the literal expression of a constant.
+const fn literal_expr(literal: Literal) -> Option<Expr> {
+    match literal {
+        Literal::Int(value) => Some(Expr::IntLiteral(value)),
+        Literal::Bool(value) => Some(Expr::BoolLiteral(value)),
+        Literal::Char(value) => Some(Expr::CharLiteral(value)),
+        Literal::Empty => None,
+    }
+}
+
+/// Returns the IR operation of a binary operator.
+pub(crate) const fn opcode(operator: BinaryOperator) -> OPCode {
+    match operator {
+        BinaryOperator::Add => OPCode::Add,
+        BinaryOperator::Sub => OPCode::Sub,
+        BinaryOperator::Mul => OPCode::Mul,
+        BinaryOperator::Div => OPCode::Div,
+        BinaryOperator::Eq => OPCode::Eq,
+        BinaryOperator::Neq => OPCode::Neq,
+        BinaryOperator::Gt => OPCode::Gt,
+        BinaryOperator::Gte => OPCode::Gte,
+        BinaryOperator::Lt => OPCode::Lt,
+        BinaryOperator::Lte => OPCode::Lte,
+        BinaryOperator::And => OPCode::And,
+        BinaryOperator::Or => OPCode::Or,
+        BinaryOperator::Shl => OPCode::Shl,
+        BinaryOperator::Shr => OPCode::Shr,
+        BinaryOperator::BitAnd => OPCode::BitAnd,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::IRBuilder;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::sema::analyze;
+    use crate::simplify::simplify;
+
+    // Macro to generate test cases.
+    macro_rules! test_simplify {
+        ($name:ident, $source:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                let tokens = Scanner::new($source).scan().unwrap();
+                let mut parser = Parser::new(&tokens);
+                parser.parse();
+                simplify(parser.ast_mut());
+                let ast = parser.ast().to_string();
+                assert!(ast.contains($expected), "{ast}");
+            }
+        };
+    }
+
+    test_simplify!(
+        can_fold_literal_subtrees,
+        "int main() { int x = 1; return (1 + 2) * x + (4 - 6); }",
+        "Return(Add(Mul(3, Named(x)), -2))"
+    );
+    test_simplify!(
+        can_simplify_arithmetic_identities,
+        "int main() { int x = 1; return ((x + 0) * 1) / 1 - 0; }",
+        "Return(Named(x))"
+    );
+    test_simplify!(
+        can_simplify_boolean_identities,
+        "int main() { bool b = true; return (b && true) || (false && b); }",
+        "Return(Named(b))"
+    );
+    test_simplify!(
+        can_simplify_boolean_comparisons,
+        "int main() { bool b = true; return (b == false) || (true != b == true); }",
+        "Return(Or(Not(Named(b)), Not(Named(b))))"
+    );
+    test_simplify!(
+        can_cancel_double_negations,
+        "int main() { bool b = true; int x = 1; return !!b == (--x == x); }",
+        "Return(Equal(Named(b), Equal(Named(x), Named(x))))"
+    );
+    test_simplify!(
+        can_not_drop_operands_with_side_effects,
+        "int f() { return 1; } int main() { return f() * 0; }",
+        "Return(Mul(Call(Named(f), Args()), 0))"
+    );
+    test_simplify!(
+        can_not_fold_division_by_zero,
+        "int main() { return 1 / 0; }",
+        "Return(Div(1, 0))"
+    );
+
+    #[test]
+    fn can_shrink_generated_ir() {
+        let source = "int main() { int a = (2 + 3) * 4; return a; }";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        assert_eq!(simplify(parser.ast_mut()), 2);
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+        assert_eq!(
+            irgen.functions()[0].to_string(),
+            "@main: int {\n   %v0: int = const 20\n   a: int = id %v0\n   ret a\n}\n"
+        );
+    }
+}
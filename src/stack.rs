@@ -0,0 +1,238 @@
+//! Static stack usage analysis.
+//!
+//! The analysis bounds the stack space every function needs, its own frame
+//! plus the deepest chain of frames of the functions it calls. There's no
+//! register allocator yet so every local and temporary is assumed to live in
+//! a stack slot, which makes the frame sizes an upper bound of what a backend
+//! emits. Recursive functions, and functions reaching one, have an unbounded
+//! stack usage.
+//!
+//! Calls to imported functions are not accounted for, their frames are not
+//! known to the compiler.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ir::{Function, GlobalValue, Instruction, Type, Value};
+use crate::target::Target;
+
+/// Alignment of the stack pointer at calls, the same on every target.
+const STACK_ALIGNMENT: usize = 16;
+
+/// Stack space needed to run a function, including its callees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StackUsage {
+    // Maximum number of bytes used.
+    Bounded(usize),
+    // The function can recurse.
+    Unbounded,
+}
+
+impl fmt::Display for StackUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bounded(bytes) => write!(f, "{bytes} bytes"),
+            Self::Unbounded => write!(f, "unbounded (recursive)"),
+        }
+    }
+}
+
+/// Stack usage of a single function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionStack {
+    /// Function name.
+    pub function: String,
+    /// Size of the function own frame in bytes.
+    pub frame: usize,
+    /// Stack usage along the deepest call chain starting at the function.
+    pub usage: StackUsage,
+}
+
+impl fmt::Display for FunctionStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "@{}: frame {} bytes, stack {}",
+            self.function, self.frame, self.usage
+        )
+    }
+}
+
+/// Returns the size in bytes of the stack slot of a value of type `t`.
+const fn slot_size(t: Type) -> usize {
+    match t {
+        Type::Unit => 0,
+        Type::Int => 4,
+        Type::Bool | Type::Char => 1,
+    }
+}
+
+/// Returns the frame size of `function` on `target` : the return address,
+/// the saved frame pointer and a slot for every argument, local and
+/// temporary, rounded up to the stack alignment. Statics and `globals` live
+/// outside the frame.
+#[must_use]
+pub fn frame_size(
+    function: &Function,
+    globals: &[GlobalValue],
+    target: &Target,
+) -> usize {
+    let outside = globals
+        .iter()
+        .map(GlobalValue::symbol)
+        .chain(function.statics())
+        .map(|symbol| symbol.name())
+        .collect::<HashSet<_>>();
+    let mut slots = function
+        .args()
+        .iter()
+        .map(|arg| (arg.name(), arg.t()))
+        .collect::<HashMap<_, _>>();
+    for inst in function.instructions() {
+        let symbols = inst.destination().into_iter().chain(
+            inst.uses().filter_map(|value| match value {
+                Value::StorageLocation(symbol) => Some(symbol),
+                Value::ConstantLiteral(..) => None,
+            }),
+        );
+        for symbol in symbols {
+            if !outside.contains(symbol.name()) {
+                slots.insert(symbol.name(), symbol.t());
+            }
+        }
+    }
+    // Slots laid out by decreasing size need no padding.
+    let locals = slots.values().map(|t| slot_size(*t)).sum::<usize>();
+    let linkage = 2 * target.pointer_width() / 8;
+    (locals + linkage).next_multiple_of(STACK_ALIGNMENT)
+}
+
+/// Returns the stack usage of every function in `functions`, in order.
+#[must_use]
+pub fn analyze(
+    functions: &[Function],
+    globals: &[GlobalValue],
+    target: &Target,
+) -> Vec<FunctionStack> {
+    let index = functions
+        .iter()
+        .enumerate()
+        .map(|(i, function)| (function.name(), i))
+        .collect::<HashMap<_, _>>();
+    let callees = functions
+        .iter()
+        .map(|function| {
+            function
+                .instructions()
+                .iter()
+                .filter_map(|inst| match inst {
+                    Instruction::Call(_, callee, _) => {
+                        index.get(callee.name()).copied()
+                    }
+                    _ => None,
+                })
+                .collect::<HashSet<_>>()
+        })
+        .collect::<Vec<_>>();
+    let frames = functions
+        .iter()
+        .map(|function| frame_size(function, globals, target))
+        .collect::<Vec<_>>();
+
+    let mut usages = vec![None; functions.len()];
+    let mut visiting = vec![false; functions.len()];
+    for i in 0..functions.len() {
+        usage(i, &callees, &frames, &mut usages, &mut visiting);
+    }
+    functions
+        .iter()
+        .zip(frames)
+        .zip(usages)
+        .map(|((function, frame), usage)| FunctionStack {
+            function: function.name().to_string(),
+            frame,
+            usage: usage.expect("every function is visited"),
+        })
+        .collect()
+}
+
+/// Returns the stack usage of the function at `i`, memoized in `usages`.
+/// Reaching a function being visited means the call graph has a cycle.
+fn usage(
+    i: usize,
+    callees: &[HashSet<usize>],
+    frames: &[usize],
+    usages: &mut [Option<StackUsage>],
+    visiting: &mut [bool],
+) -> StackUsage {
+    if let Some(usage) = usages[i] {
+        return usage;
+    }
+    if visiting[i] {
+        return StackUsage::Unbounded;
+    }
+    visiting[i] = true;
+    let mut deepest = 0;
+    let mut bounded = true;
+    for callee in &callees[i] {
+        match usage(*callee, callees, frames, usages, visiting) {
+            StackUsage::Bounded(bytes) => deepest = deepest.max(bytes),
+            StackUsage::Unbounded => bounded = false,
+        }
+    }
+    visiting[i] = false;
+    let result = if bounded {
+        StackUsage::Bounded(frames[i] + deepest)
+    } else {
+        StackUsage::Unbounded
+    };
+    usages[i] = Some(result);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::IRBuilder;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::sema::analyze;
+    use crate::stack::{self, StackUsage};
+    use crate::target::Target;
+
+    #[test]
+    fn can_analyze_stack_usage() {
+        let source = r"
+            int leaf(int a) { return a + 1; }
+            int twice(int a) { return leaf(a) + leaf(a); }
+            int down(int a) { return down(a); }
+            int main() { return twice(leaf(1)) + down(2); }
+        ";
+        let tokens = Scanner::new(source).scan().unwrap();
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        let symbol_table = analyze(parser.ast());
+        let mut irgen = IRBuilder::new(parser.ast(), &symbol_table);
+        irgen.build();
+
+        let target = Target::from_triple("riscv64-none").unwrap();
+        let stacks = stack::analyze(irgen.functions(), &[], &target);
+        let usages = stacks
+            .iter()
+            .map(|stack| (stack.function.as_str(), stack.usage))
+            .collect::<Vec<_>>();
+        // `leaf` holds `a` and two temporaries next to the linkage area.
+        assert_eq!(stacks[0].frame, 32);
+        assert_eq!(
+            usages,
+            vec![
+                ("leaf", StackUsage::Bounded(32)),
+                ("twice", StackUsage::Bounded(stacks[1].frame + 32)),
+                ("down", StackUsage::Unbounded),
+                ("main", StackUsage::Unbounded),
+            ]
+        );
+        assert_eq!(
+            stacks[2].to_string(),
+            "@down: frame 32 bytes, stack unbounded (recursive)"
+        );
+    }
+}
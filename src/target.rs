@@ -0,0 +1,241 @@
+//! Description of the machine the compiler generates code for.
+//!
+//! A `Target` is parsed from a triple string such as `x86_64-linux` or
+//! `aarch64-unknown-linux-gnu` and carries everything that depends on the
+//! target machine: pointer width, endianness, type sizes and alignments and
+//! the calling convention. Passes that care about the target query it rather
+//! than assuming the host is the target.
+use std::error::Error;
+use std::fmt;
+
+use crate::ast::DeclType;
+
+/// Instruction set architectures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Arch {
+    X86_64,
+    AArch64,
+    RiscV64,
+}
+
+/// Operating systems, `None` is used for freestanding targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Os {
+    Linux,
+    MacOS,
+    Windows,
+    None,
+}
+
+/// Byte order of multi-byte values in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+/// Calling conventions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Abi {
+    // System V AMD64 ABI.
+    SysV,
+    // Microsoft x64 ABI.
+    Win64,
+    // ARM 64-bit procedure call standard.
+    Aapcs64,
+    // RISC-V LP64 ABI.
+    Lp64,
+}
+
+/// Target error type is used to report invalid target triples.
+#[derive(Debug, Clone)]
+pub struct TargetError {
+    triple: String,
+}
+
+impl fmt::Display for TargetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unsupported target triple `{}`", self.triple)
+    }
+}
+
+impl Error for TargetError {}
+
+/// `Target` describes the machine code is generated for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Target {
+    arch: Arch,
+    os: Os,
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Self {
+            arch: Arch::X86_64,
+            os: Os::Linux,
+        }
+    }
+}
+
+impl Target {
+    /// Parse a target triple of the form `arch-[vendor-]os[-env]`, the
+    /// vendor and environment components are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the architecture or operating system isn't
+    /// supported.
+    pub fn from_triple(triple: &str) -> Result<Self, TargetError> {
+        let error = || TargetError {
+            triple: triple.to_string(),
+        };
+        let mut components = triple.split('-');
+        let arch = match components.next() {
+            Some("x86_64" | "amd64") => Arch::X86_64,
+            Some("aarch64" | "arm64") => Arch::AArch64,
+            Some("riscv64") => Arch::RiscV64,
+            _ => return Err(error()),
+        };
+        let os = components
+            .find_map(|component| match component {
+                "linux" => Some(Os::Linux),
+                "darwin" | "macos" => Some(Os::MacOS),
+                "windows" => Some(Os::Windows),
+                "none" | "elf" => Some(Os::None),
+                _ => None,
+            })
+            .ok_or_else(error)?;
+        Ok(Self { arch, os })
+    }
+
+    /// Returns the target architecture.
+    #[must_use]
+    pub const fn arch(&self) -> Arch {
+        self.arch
+    }
+
+    /// Returns the target operating system.
+    #[must_use]
+    pub const fn os(&self) -> Os {
+        self.os
+    }
+
+    /// Returns the width of pointers in bits.
+    #[must_use]
+    pub const fn pointer_width(&self) -> usize {
+        64
+    }
+
+    /// Returns the target byte order.
+    #[must_use]
+    pub const fn endianness(&self) -> Endianness {
+        Endianness::Little
+    }
+
+    /// Returns the calling convention used by the target.
+    #[must_use]
+    pub const fn abi(&self) -> Abi {
+        match (self.arch, self.os) {
+            (Arch::X86_64, Os::Windows) => Abi::Win64,
+            (Arch::X86_64, _) => Abi::SysV,
+            (Arch::AArch64, _) => Abi::Aapcs64,
+            (Arch::RiscV64, _) => Abi::Lp64,
+        }
+    }
+
+    /// Returns the size in bytes of values of type `decl_type`.
+    #[must_use]
+    pub const fn size_of(&self, decl_type: &DeclType) -> usize {
+        match decl_type {
+            DeclType::Int => 4,
+            DeclType::Char | DeclType::Bool => 1,
+        }
+    }
+
+    /// Returns the alignment in bytes of values of type `decl_type`.
+    #[must_use]
+    pub const fn align_of(&self, decl_type: &DeclType) -> usize {
+        self.size_of(decl_type)
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arch = match self.arch {
+            Arch::X86_64 => "x86_64",
+            Arch::AArch64 => "aarch64",
+            Arch::RiscV64 => "riscv64",
+        };
+        let os = match self.os {
+            Os::Linux => "linux",
+            Os::MacOS => "macos",
+            Os::Windows => "windows",
+            Os::None => "none",
+        };
+        write!(f, "{arch}-{os}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::DeclType;
+    use crate::target::{Abi, Arch, Os, Target};
+
+    // Macro to generate test cases.
+    macro_rules! test_target_triple {
+        ($name:ident, $triple:expr, $arch:expr, $os:expr, $abi:expr) => {
+            #[test]
+            fn $name() {
+                let target = Target::from_triple($triple)
+                    .expect("expected test case triple to be valid");
+                assert_eq!(target.arch(), $arch);
+                assert_eq!(target.os(), $os);
+                assert_eq!(target.abi(), $abi);
+            }
+        };
+    }
+
+    test_target_triple!(
+        can_parse_short_triple,
+        "x86_64-linux",
+        Arch::X86_64,
+        Os::Linux,
+        Abi::SysV
+    );
+    test_target_triple!(
+        can_parse_windows_triple,
+        "x86_64-pc-windows-msvc",
+        Arch::X86_64,
+        Os::Windows,
+        Abi::Win64
+    );
+    test_target_triple!(
+        can_parse_full_triple,
+        "aarch64-unknown-linux-gnu",
+        Arch::AArch64,
+        Os::Linux,
+        Abi::Aapcs64
+    );
+    test_target_triple!(
+        can_parse_freestanding_triple,
+        "riscv64-unknown-none-elf",
+        Arch::RiscV64,
+        Os::None,
+        Abi::Lp64
+    );
+
+    #[test]
+    fn can_reject_unsupported_triples() {
+        assert!(Target::from_triple("i686-linux").is_err());
+        assert!(Target::from_triple("x86_64").is_err());
+    }
+
+    #[test]
+    fn can_compute_type_layout() {
+        let target = Target::default();
+        assert_eq!(target.to_string(), "x86_64-linux");
+        assert_eq!(target.pointer_width(), 64);
+        assert_eq!(target.size_of(&DeclType::Int), 4);
+        assert_eq!(target.align_of(&DeclType::Bool), 1);
+    }
+}
@@ -1,12 +1,63 @@
 //! Implementation of language tokens.
 use std::fmt;
 
+use crate::builtins::escape_char;
+
 /// Language defined keywords.
 pub const KEYWORDS: &[&str] = &[
     "int", "char", "bool", "return", "const", "void", "if", "else", "while",
-    "for", "break", "true", "false",
+    "for", "break", "true", "false", "goto", "asm", "extern", "static",
+    "switch", "case", "default", "volatile",
 ];
 
+/// Span represents the location of a token in the source code as a half
+/// open range `[start, end)` of character offsets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    #[must_use]
+    pub const fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Returns the number of characters covered by the span.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Returns `true` if the span covers no characters.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Returns the span as a range of byte offsets, `offsets` holds the byte
+    /// offset of every character of the source (see `byte_offsets`).
+    #[must_use]
+    pub fn to_bytes(&self, offsets: &[usize]) -> Self {
+        let byte = |offset: usize| {
+            offsets.get(offset).or(offsets.last()).copied().unwrap_or(0)
+        };
+        Self::new(byte(self.start), byte(self.end))
+    }
+}
+
+/// Returns the byte offset of every character of `source` followed by the
+/// length of `source`, indexed by character offset.
+#[must_use]
+pub fn byte_offsets(source: &str) -> Vec<usize> {
+    source
+        .char_indices()
+        .map(|(offset, _)| offset)
+        .chain(std::iter::once(source.len()))
+        .collect()
+}
+
 /// Token represents the individual language tokens.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Token {
@@ -42,6 +93,8 @@ pub enum Token {
     BoolLiteral(bool),
     // Identifiers.
     Identifier(String),
+    // Comments are only produced when the scanner is asked to keep them.
+    Comment(String),
     // Keywords.
     Const,
     Void,
@@ -50,6 +103,14 @@ pub enum Token {
     While,
     For,
     Break,
+    Switch,
+    Case,
+    Default,
+    Goto,
+    Asm,
+    Extern,
+    Static,
+    Volatile,
     True,
     False,
     Return,
@@ -98,6 +159,7 @@ impl fmt::Display for Token {
             // Identifiers are wrapped in `IDENT()` to signify that they
             // are identifiers.
             Self::Identifier(str) => write!(f, "IDENT({str})"),
+            Self::Comment(str) => write!(f, "COMMENT({str})"),
             // Keywords are display in capital case.
             Self::Return => write!(f, "RETURN"),
             Self::Const => write!(f, "CONST"),
@@ -107,6 +169,14 @@ impl fmt::Display for Token {
             Self::While => write!(f, "WHILE"),
             Self::For => write!(f, "FOR"),
             Self::Break => write!(f, "BREAK"),
+            Self::Switch => write!(f, "SWITCH"),
+            Self::Case => write!(f, "CASE"),
+            Self::Default => write!(f, "DEFAULT"),
+            Self::Goto => write!(f, "GOTO"),
+            Self::Asm => write!(f, "ASM"),
+            Self::Extern => write!(f, "EXTERN"),
+            Self::Static => write!(f, "STATIC"),
+            Self::Volatile => write!(f, "VOLATILE"),
             Self::True => write!(f, "TRUE"),
             Self::False => write!(f, "FALSE"),
             // Types supported are shown with a `_T` to signify that this is
@@ -119,3 +189,62 @@ impl fmt::Display for Token {
         }
     }
 }
+
+impl Token {
+    /// Returns the token as it is spelled in source code.
+    #[must_use]
+    pub fn lexeme(&self) -> String {
+        match self {
+            Self::LParen => "(".to_string(),
+            Self::RParen => ")".to_string(),
+            Self::LBrace => "{".to_string(),
+            Self::RBrace => "}".to_string(),
+            Self::LBracket => "[".to_string(),
+            Self::RBracket => "]".to_string(),
+            Self::SemiColon => ";".to_string(),
+            Self::Colon => ":".to_string(),
+            Self::Comma => ",".to_string(),
+            Self::Equal => "=".to_string(),
+            Self::BangEqual => "!=".to_string(),
+            Self::EqualEqual => "==".to_string(),
+            Self::GreaterEqual => ">=".to_string(),
+            Self::LesserEqual => "<=".to_string(),
+            Self::Greater => ">".to_string(),
+            Self::Lesser => "<".to_string(),
+            Self::Plus => "+".to_string(),
+            Self::Minus => "-".to_string(),
+            Self::Slash => "/".to_string(),
+            Self::Star => "*".to_string(),
+            Self::And => "&&".to_string(),
+            Self::Or => "||".to_string(),
+            Self::Bang => "!".to_string(),
+            Self::IntLiteral(value) => value.to_string(),
+            Self::CharLiteral(value) => escape_char(*value),
+            Self::StringLiteral(value) => format!("\"{value}\""),
+            Self::BoolLiteral(value) => value.to_string(),
+            Self::Identifier(name) | Self::Comment(name) => name.clone(),
+            Self::Const => "const".to_string(),
+            Self::Void => "void".to_string(),
+            Self::If => "if".to_string(),
+            Self::Else => "else".to_string(),
+            Self::While => "while".to_string(),
+            Self::For => "for".to_string(),
+            Self::Break => "break".to_string(),
+            Self::Switch => "switch".to_string(),
+            Self::Case => "case".to_string(),
+            Self::Default => "default".to_string(),
+            Self::Goto => "goto".to_string(),
+            Self::Asm => "asm".to_string(),
+            Self::Extern => "extern".to_string(),
+            Self::Static => "static".to_string(),
+            Self::Volatile => "volatile".to_string(),
+            Self::True => "true".to_string(),
+            Self::False => "false".to_string(),
+            Self::Return => "return".to_string(),
+            Self::Int => "int".to_string(),
+            Self::Char => "char".to_string(),
+            Self::Bool => "bool".to_string(),
+            Self::Eof => String::new(),
+        }
+    }
+}
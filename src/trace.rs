@@ -0,0 +1,169 @@
+//! Structured trace events.
+//!
+//! Compilation phases, IR generation and optimizer passes open spans that
+//! are reported on the standard error when they close, along with their
+//! fields (the function being compiled, the pass name...) and duration. The
+//! API mirrors the `tracing` crate, spans are guards that close on drop, and
+//! tracing is disabled by default so instrumented code only pays for a level
+//! check.
+//!
+//! The level and format are process wide, the driver sets them once with
+//! `init` from `--log-level` and `--log-json`.
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::time::Instant;
+
+use crate::diagnostics::escape;
+
+// Most verbose level reported, 0 disables tracing.
+static LEVEL: AtomicU8 = AtomicU8::new(0);
+// Whether events are reported as JSON objects.
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Verbosity of a span, from the least to the most verbose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    /// Returns the level named `name`.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "error" => Some(Self::Error),
+            "warn" => Some(Self::Warn),
+            "info" => Some(Self::Info),
+            "debug" => Some(Self::Debug),
+            "trace" => Some(Self::Trace),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warn => write!(f, "warn"),
+            Self::Info => write!(f, "info"),
+            Self::Debug => write!(f, "debug"),
+            Self::Trace => write!(f, "trace"),
+        }
+    }
+}
+
+/// Report the spans up to `level`, `None` disables tracing. Events are
+/// reported as one JSON object per line if `json` is set.
+pub fn init(level: Option<Level>, json: bool) {
+    LEVEL.store(level.map_or(0, |level| level as u8), Ordering::Relaxed);
+    JSON.store(json, Ordering::Relaxed);
+}
+
+/// Returns `true` if spans at `level` are reported.
+#[must_use]
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Open a span named `name`, fields are only formatted if the level is
+/// enabled and the span is reported when the returned guard is dropped.
+#[must_use]
+pub fn span(
+    level: Level,
+    name: &'static str,
+    fields: &[(&'static str, &dyn fmt::Display)],
+) -> Option<SpanGuard> {
+    enabled(level).then(|| SpanGuard {
+        level,
+        name,
+        fields: fields
+            .iter()
+            .map(|(key, value)| (*key, value.to_string()))
+            .collect(),
+        start: Instant::now(),
+    })
+}
+
+/// `SpanGuard` is an open span, it is reported when dropped.
+#[derive(Debug)]
+pub struct SpanGuard {
+    level: Level,
+    name: &'static str,
+    fields: Vec<(&'static str, String)>,
+    start: Instant,
+}
+
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        let micros = self.start.elapsed().as_micros();
+        let event = if JSON.load(Ordering::Relaxed) {
+            json_event(self.level, self.name, &self.fields, micros)
+        } else {
+            human_event(self.level, self.name, &self.fields, micros)
+        };
+        eprintln!("{event}");
+    }
+}
+
+/// Returns a closed span formatted for humans.
+fn human_event(
+    level: Level,
+    name: &str,
+    fields: &[(&str, String)],
+    micros: u128,
+) -> String {
+    let mut event = format!("[{level}] {name}");
+    for (key, value) in fields {
+        event.push_str(&format!(" {key}={value}"));
+    }
+    event.push_str(&format!(" elapsed={micros}us"));
+    event
+}
+
+/// Returns a closed span formatted as a single line JSON object.
+fn json_event(
+    level: Level,
+    name: &str,
+    fields: &[(&str, String)],
+    micros: u128,
+) -> String {
+    let fields = fields
+        .iter()
+        .map(|(key, value)| format!("{}:{}", escape(key), escape(value)))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(
+        r#"{{"level":"{level}","span":{},"fields":{{{fields}}},"micros":{micros}}}"#,
+        escape(name)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::trace::{human_event, json_event, span, Level};
+
+    #[test]
+    fn can_format_trace_events() {
+        let fields = [
+            ("pass", "DCE".to_string()),
+            ("function", "main".to_string()),
+        ];
+        assert_eq!(
+            human_event(Level::Debug, "pass", &fields, 12),
+            "[debug] pass pass=DCE function=main elapsed=12us"
+        );
+        assert_eq!(
+            json_event(Level::Info, "optimize", &fields, 7),
+            r#"{"level":"info","span":"optimize","fields":{"pass":"DCE","function":"main"},"micros":7}"#
+        );
+        assert_eq!(Level::from_name("trace"), Some(Level::Trace));
+        assert!(Level::Error < Level::Trace);
+        // Tracing is disabled by default.
+        assert!(span(Level::Error, "scan", &[]).is_none());
+    }
+}